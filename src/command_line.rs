@@ -20,14 +20,40 @@ pub fn setup_app(version: &str) -> App
                     "\
 View a previously saved session or captured traffic in the Proxide UI."
                 ))
+                .config_options()
                 .json_options()
                 .decoder_options()
+                .ui_options()
                 .arg(
                     Arg::with_name("file")
                         .index(1)
                         .value_name("file")
-                        .required(true)
+                        .multiple_values(true)
+                        .required_unless_present("files")
                         .help("Specify the file to load"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .short('f')
+                        .long("file")
+                        .value_name("file")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .number_of_values(1)
+                        .help("Specify an additional file to load")
+                        .long_help(long!(
+                            "\
+Specify an additional file to load. Can be specified multiple times. The sessions in all the files
+are merged and the connections and requests are ordered by their start time. Useful for inspecting
+rotated captures or captures gathered from several hosts together."
+                        )),
+                )
+                .arg(
+                    Arg::with_name("har")
+                        .long("har")
+                        .value_name("file")
+                        .takes_value(true)
+                        .help("Export the session as a HAR file instead of opening the UI"),
                 ),
         )
         // Monitor subcommand.
@@ -40,9 +66,24 @@ Monitors network traffic using the Proxide UI. The UI displays all decoded traff
 
 The monitoring session can also be exported into a file for later analysis."
                 ))
+                .config_options()
                 .connection_options()
                 .json_options()
-                .decoder_options(),
+                .decoder_options()
+                .ui_options()
+                .arg(
+                    Arg::with_name("max-requests")
+                        .long("max-requests")
+                        .value_name("count")
+                        .takes_value(true)
+                        .help("Keep only the latest requests in memory")
+                        .long_help(long!(
+                            "\
+Keep only the given number of the latest requests in memory. The oldest requests are dropped as new
+ones arrive, along with the closed connections that have no requests left. Limits the memory use
+of long monitoring sessions. Captures started with ':capture' still receive all the traffic."
+                        )),
+                ),
         )
         // Capture subcommand.
         .subcommand(
@@ -54,13 +95,64 @@ Capture network traffic directly into a file. The file can be later analyzed wit
 command. The traffic is written to the output file in real time. This allows capturing traffic over
 long periods with the only limit being the disk usage."
                 ))
+                .config_options()
                 .connection_options()
                 .json_options()
+                // The decoders are used by the control API.
+                .decoder_options()
                 .arg(
                     Arg::with_name("file")
                         .short('f')
                         .value_name("file")
                         .help("Specify the output file. Defaults to 'capture-<timestamp>.bin'."),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("format")
+                        .takes_value(true)
+                        .possible_values(["msgpack", "json", "jsonl"])
+                        .conflicts_with("json")
+                        .help("Format of the capture file. Defaults to 'msgpack'.")
+                        .long_help(long!(
+                            "\
+Format of the capture file. Defaults to 'msgpack'.
+
+'jsonl' writes each event as a JSON object on a line of its own with the event type in the 'type'
+field and the event data in the 'event' field. The lines are written as the events happen so the output
+can be piped into tools such as 'jq'. Unlike the 'json' format, 'jsonl' captures can be opened with
+the 'view' command."
+                        )),
+                )
+                .arg(
+                    Arg::with_name("only-path")
+                        .long("only-path")
+                        .value_name("pattern")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Capture only the requests with a matching path, e.g. '/api/v1/*'")
+                        .long_help(long!(
+                            "\
+Capture only the requests whose path matches the wildcard pattern, such as '/api/v1/*'. The other
+requests are dropped before they are written to the capture file. Connections without any
+captured requests are omitted as well. Can be specified multiple times."
+                        )),
+                )
+                .arg(
+                    Arg::with_name("only-host")
+                        .long("only-host")
+                        .value_name("pattern")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Capture only the requests to a matching host, e.g. '*.example.com'")
+                        .long_help(long!(
+                            "\
+Capture only the requests whose target host matches the wildcard pattern, such as '*.example.com'.
+Combined with --only-path a request must match both options to be captured. Can be specified
+multiple times."
+                        )),
                 ),
         )
         // The config subcommands.
@@ -72,6 +164,11 @@ long periods with the only limit being the disk usage."
                     SubCommand::with_name("ca")
                         .about("Manage CA certificates required for debugging TLS traffic")
                         .cert_options(false)
+                        .arg(
+                            Arg::with_name("json")
+                                .long("json")
+                                .help("Output the result in JSON format for automation purposes."),
+                        )
                         .arg(
                             Arg::with_name("create")
                                 .long("create")
@@ -167,9 +264,58 @@ The ASN.1 format does not support arbitrary large dates so the user-specified va
 automatically capped to 2000 years."
                                 )),
                         )
+                        .arg(
+                            Arg::with_name("key-type")
+                                .long("key-type")
+                                .value_name("type")
+                                .possible_values(["rsa", "ecdsa"])
+                                .default_value_if("create", None, Some("rsa"))
+                                .requires("create")
+                                .help("The key algorithm for the new CA certificate. Defaults to rsa.")
+                                .long_help(long!(
+                                    "\
+Specifies the key algorithm for the new CA certificate. Defaults to rsa.
+
+The RSA key size is controlled with --rsa-bits. The ecdsa keys use the P-256 curve, which some
+client stacks validate faster."
+                                )),
+                        )
+                        .arg(
+                            Arg::with_name("rsa-bits")
+                                .long("rsa-bits")
+                                .value_name("bits")
+                                .possible_values(["2048", "3072", "4096"])
+                                .default_value_if("key-type", Some("rsa"), Some("2048"))
+                                .requires("create")
+                                .help("The RSA key size for the new CA certificate. Defaults to 2048.")
+                                .long_help(long!(
+                                    "\
+Specifies the RSA key size in bits for the new CA certificate. Defaults to 2048. Only applies
+when --key-type is rsa."
+                                )),
+                        )
+                        .arg(
+                            Arg::with_name("export-pkcs12")
+                                .long("export-pkcs12")
+                                .value_name("path")
+                                .help("Export the CA certificate and private key as PKCS#12.")
+                                .long_help(long!(
+                                    "\
+Exports the CA certificate and private key into a PKCS#12 (.p12) file for clients that can't use
+the PEM files directly, such as JVM keystores. Can be combined with --create to export the newly
+created CA certificate."
+                                )),
+                        )
+                        .arg(
+                            Arg::with_name("password")
+                                .long("password")
+                                .value_name("password")
+                                .requires("export-pkcs12")
+                                .help("The password for the exported PKCS#12 file. Defaults to an empty password."),
+                        )
                         .group(
                             ArgGroup::with_name("action")
-                                .args(&["create", "revoke", "trust"])
+                                .args(&["create", "revoke", "trust", "export-pkcs12"])
                                 .multiple(true)
                                 .required(true),
                         ),
@@ -195,7 +341,10 @@ trait AppEx<'a>: Sized
                     .long_help(long!(
                         "\
 Specify the port that Proxide will listen to. This is the port that clients must comment to, either
-directly (when using -t) or through a proxy setting (when using -p)."
+directly (when using -t) or through a proxy setting (when using -p).
+
+Use port 0 to let the operating system assign a free port. The port Proxide ends up listening to is
+reported once the listening sockets have been bound."
                     )),
             )
             .arg(
@@ -216,6 +365,291 @@ payloads, these are not modified and the target server will receive the details
 connect to Proxide instead."
                     )),
             )
+            .arg(
+                Arg::with_name("route")
+                    .long("route")
+                    .value_name("host=host:port")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Route direct connections to different servers by host name")
+                    .long_help(long!(
+                        "\
+Route the direct connections for the host to a different target server, such as
+'api.example.com=127.0.0.1:8002'. Can be specified multiple times. The host may contain '*' and '?'
+wildcards and the first matching route is used.
+
+The host is taken from the TLS SNI or the HTTP/1.1 Host-header of the first request. Connections
+that match none of the routes, including plaintext HTTP/2 connections, are redirected to the
+server given with -t."
+                    )),
+            )
+            .arg(
+                Arg::with_name("target-header-host")
+                    .long("target-header-host")
+                    .value_name("host[:port]")
+                    .takes_value(true)
+                    .help("Override the HTTP Host/authority sent to the server")
+                    .long_help(long!(
+                        "\
+Specify the HTTP Host-header and HTTP/2 authority value Proxide uses for the outgoing requests.
+
+By default Proxide rewrites the authority with the target server address when using -t. This
+option decouples the HTTP authority from the address Proxide connects to and from the TLS SNI,
+which is required when the server expects a specific host name that differs from both."
+                    )),
+            )
+            .arg(
+                Arg::with_name("upstream-proxy")
+                    .long("upstream-proxy")
+                    .value_name("host:port")
+                    .takes_value(true)
+                    .help("Connect to the servers through an upstream CONNECT proxy")
+                    .long_help(long!(
+                        "\
+Specify an HTTP proxy Proxide uses for connecting to the servers. Proxide will send a CONNECT
+request to the upstream proxy for each server connection instead of connecting to the server
+directly. This is required in networks where the external hosts are only reachable through an
+outbound proxy."
+                    )),
+            )
+            .arg(
+                Arg::with_name("upstream-proxy-auth")
+                    .long("upstream-proxy-auth")
+                    .value_name("user:password")
+                    .takes_value(true)
+                    .requires("upstream-proxy")
+                    .help("Specify the basic auth credentials for the upstream proxy"),
+            )
+            .arg(
+                Arg::with_name("inject-latency")
+                    .long("inject-latency")
+                    .value_name("duration")
+                    .takes_value(true)
+                    .help("Delay the responses, e.g. '200ms' or '2s'")
+                    .long_help(long!(
+                        "\
+Delay forwarding the responses to the clients by the given duration. The duration is given in
+milliseconds ('200ms') or seconds ('2s'). Useful for testing how the clients handle slow servers."
+                    )),
+            )
+            .arg(
+                Arg::with_name("inject-error-rate")
+                    .long("inject-error-rate")
+                    .value_name("fraction")
+                    .takes_value(true)
+                    .help("Fail a fraction of the requests, e.g. '0.1'")
+                    .long_help(long!(
+                        "\
+Answer the given fraction of the requests with an error response instead of forwarding them to the
+server. The status of the error response is specified with --inject-status."
+                    )),
+            )
+            .arg(
+                Arg::with_name("inject-status")
+                    .long("inject-status")
+                    .value_name("status")
+                    .takes_value(true)
+                    .help("Specify the status of the injected error responses. Defaults to 503.")
+                    .long_help(long!(
+                        "\
+Specify the status of the injected error responses. Defaults to 503. The status must be a 4xx or
+5xx status. Without --inject-error-rate every request fails with the status."
+                    )),
+            )
+            .arg(
+                Arg::with_name("inject-seed")
+                    .long("inject-seed")
+                    .value_name("seed")
+                    .takes_value(true)
+                    .help("Specify the seed for choosing the failed requests")
+                    .long_help(long!(
+                        "\
+Specify the seed for choosing the requests that fail due to --inject-error-rate. With the same seed
+the same requests fail as long as the requests are made in the same order."
+                    )),
+            )
+            .arg(
+                Arg::with_name("throttle-kbps")
+                    .long("throttle-kbps")
+                    .value_name("kbps")
+                    .takes_value(true)
+                    .help("Limit the bandwidth of each connection")
+                    .long_help(long!(
+                        "\
+Limit the rate data is forwarded in each direction of a connection to the given number of kilobits
+per second. Useful for emulating slow mobile networks."
+                    )),
+            )
+            .arg(
+                Arg::with_name("idle-timeout")
+                    .long("idle-timeout")
+                    .value_name("duration")
+                    .takes_value(true)
+                    .help("Close HTTP/2 connections that stay idle, e.g. '30s'")
+                    .long_help(long!(
+                        "\
+Close HTTP/2 connections that haven't forwarded any data in either direction for the given
+duration. The duration is given in milliseconds ('500ms') or seconds ('30s'). The connection is
+closed with the NO_ERROR reason and the requests still in progress are marked as failed."
+                    )),
+            )
+            .arg(
+                Arg::with_name("request-timeout")
+                    .long("request-timeout")
+                    .value_name("duration")
+                    .takes_value(true)
+                    .help("Fail HTTP/2 requests that take too long, e.g. '10s'")
+                    .long_help(long!(
+                        "\
+Fail HTTP/2 requests that haven't completed within the given duration. The duration is given in
+milliseconds ('500ms') or seconds ('10s'). The streams of the timed out requests are reset and the
+requests are shown as timed out in the UI."
+                    )),
+            )
+            .arg(
+                Arg::with_name("connect-retries")
+                    .long("connect-retries")
+                    .value_name("count")
+                    .takes_value(true)
+                    .help("Retry failed connections to the server")
+                    .long_help(long!(
+                        "\
+Retry connecting to the server the given number of times before failing the client connection.
+Useful when the server is restarted during the debugging session. The HTTP/2 handshake over
+plaintext connections is retried as well. Each failed attempt is logged and the connections view
+shows the number of attempts each connection took."
+                    )),
+            )
+            .arg(
+                Arg::with_name("connect-backoff")
+                    .long("connect-backoff")
+                    .value_name("duration")
+                    .takes_value(true)
+                    .help("Delay before the first connection retry, e.g. '500ms'")
+                    .long_help(long!(
+                        "\
+Delay before the first connection retry. The delay is doubled after each failed attempt. The
+duration is given in milliseconds ('500ms') or seconds ('1s'). The default is 200 milliseconds."
+                    )),
+            )
+            .arg(
+                Arg::with_name("redact-header")
+                    .long("redact-header")
+                    .value_name("name")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Redact the header values from the captured traffic")
+                    .long_help(long!(
+                        "\
+Replace the values of the given header with '***REDACTED***' in the captured traffic. The
+redaction happens before the traffic is stored so the secrets never end up in the capture or
+exported session files. Can be specified multiple times."
+                    )),
+            )
+            .arg(
+                Arg::with_name("redact-all-auth")
+                    .long("redact-all-auth")
+                    .help("Redact the common authentication headers and cookies"),
+            )
+            .arg(
+                Arg::with_name("redact-body-pattern")
+                    .long("redact-body-pattern")
+                    .value_name("regex")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Redact the matches of the pattern from the message bodies")
+                    .long_help(long!(
+                        "\
+Replace the matches of the regular expression with '***REDACTED***' in the request and response
+bodies. The pattern is matched against each received chunk of data separately. Can be specified
+multiple times."
+                    )),
+            )
+            .arg(
+                Arg::with_name("set-request-header")
+                    .long("set-request-header")
+                    .value_name("name=value")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Set a header on every forwarded request")
+                    .long_help(long!(
+                        "\
+Set the header on every forwarded request, such as 'authorization=Bearer token', replacing the
+existing values of the header. Can be specified multiple times. The headers are set after the ones
+given with --remove-request-header are removed."
+                    )),
+            )
+            .arg(
+                Arg::with_name("remove-request-header")
+                    .long("remove-request-header")
+                    .value_name("name")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Remove a header from every forwarded request")
+                    .long_help(long!(
+                        "\
+Remove the header from every forwarded request. Can be specified multiple times."
+                    )),
+            )
+            .arg(
+                Arg::with_name("set-response-header")
+                    .long("set-response-header")
+                    .value_name("name=value")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Set a header on every forwarded response")
+                    .long_help(long!(
+                        "\
+Set the header on every forwarded response, such as 'cache-control=no-store', replacing the
+existing values of the header. Can be specified multiple times. The headers are set after the ones
+given with --remove-response-header are removed."
+                    )),
+            )
+            .arg(
+                Arg::with_name("remove-response-header")
+                    .long("remove-response-header")
+                    .value_name("name")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Remove a header from every forwarded response")
+                    .long_help(long!(
+                        "\
+Remove the header from every forwarded response. Can be specified multiple times."
+                    )),
+            )
+            .arg(
+                Arg::with_name("max-body-bytes")
+                    .long("max-body-bytes")
+                    .value_name("bytes")
+                    .takes_value(true)
+                    .help("Limit the size of the message bodies kept in the session")
+                    .long_help(long!(
+                        "\
+Keep at most the given number of bytes of each request and response body in the session. The rest
+of the body is still forwarded but only its size is recorded. Protects against running out of
+memory when large files are transferred through Proxide."
+                    )),
+            )
+            .arg(
+                Arg::with_name("h2-window-size")
+                    .long("h2-window-size")
+                    .value_name("bytes")
+                    .takes_value(true)
+                    .help("Specify the HTTP/2 initial window size")
+                    .long_help(long!(
+                        "\
+Specify the HTTP/2 initial stream window size Proxide advertises to both the client and the server.
+The default is 1000000 bytes. Use the window sizes of the real deployment when debugging flow
+control sensitive streaming calls."
+                    )),
+            )
             .arg(
                 Arg::with_name("allow-remote")
                     .long("allow-remote")
@@ -231,6 +665,81 @@ CONNECT proxy ('-p') while allowing remote connections turns Proxide into an ope
 be used to connect to other hosts in the local network."
                     )),
             )
+            .arg(
+                Arg::with_name("control-listen")
+                    .long("control-listen")
+                    .value_name("port")
+                    .takes_value(true)
+                    .validator(|v| {
+                        v.parse::<u16>()
+                            .map_err(|_| String::from("port must be a number between 0 and 65535"))
+                            .map(|_| ())
+                    })
+                    .help("Serve a JSON/HTTP API for controlling Proxide on the port")
+                    .long_help(long!(
+                        "\
+Serve a JSON/HTTP API for automation on the given port. The API listens only to local connections
+regardless of --allow-remote and --bind. Use port 0 to let the operating system assign a free port.
+The API keeps the latest 10000 requests, or the number given with --max-requests when monitoring.
+Requests with an Origin header or a Host header other than localhost are rejected so that web pages
+open in a browser can't use the API.
+
+The API supports the following requests:
+  GET  /connections                  List the connections.
+  GET  /requests?path=..&host=..     List the requests, optionally filtered with wildcard patterns.
+  GET  /requests/<uuid>              Get the request with its headers and decoded bodies.
+  POST /session/save?file=..         Save the session recorded so far as a session file.
+  POST /session/clear                Forget the requests recorded so far.
+
+The API can't start or stop the capture or change the capture filters. Traffic is recorded for the
+whole run and the capture command takes its filters on the command line with --only-path and
+--only-host."
+                    )),
+            )
+            .arg(
+                Arg::with_name("bind")
+                    .long("bind")
+                    .value_name("address")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Specify the IP address to listen on")
+                    .long_help(long!(
+                        "\
+Specify the IP address Proxide listens on for incoming connections. Can be specified multiple times
+to listen on several addresses. Normally Proxide listens on both the IPv4 and IPv6 loopback
+addresses or, with '--allow-remote', on all addresses. Specifying '--bind' overrides this and
+Proxide only listens on the given addresses."
+                    )),
+            )
+            .arg(
+                Arg::with_name("listen-uds")
+                    .long("listen-uds")
+                    .value_name("path")
+                    .takes_value(true)
+                    .help("Listen on a Unix domain socket in addition to the TCP port")
+                    .long_help(long!(
+                        "\
+Listen for connections on a Unix domain socket at the given path in addition to the TCP port. The
+connections are handled the same way as the ones made to the TCP port. The socket file is removed
+when Proxide exits. Proxide refuses to start if the file already exists."
+                    )),
+            )
+            .arg(
+                Arg::with_name("client-filter")
+                    .long("client-filter")
+                    .value_name("cidr")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Decode only the connections from the given client addresses")
+                    .long_help(long!(
+                        "\
+Decode only the connections from clients within the given network, such as '10.0.0.0/24' or
+'fd00::/64'. Can be specified multiple times. Connections from other clients are passed to the
+server without decoding them."
+                    )),
+            )
             .arg(
                 Arg::with_name("proxy")
                     .short('p')
@@ -244,10 +753,12 @@ Enable using Proxide as a CONNECT proxy with an optional filter for limiting the
 are decoded by Proxide.
 
 The option accepts a comma separated list of filters to limit the decoded connections. Asterisk
-('*') can be used as a wildcard when specifying the hostname filter. If the filter is not
-specified, Proxide will decode all traffic.
+('*') can be used as a wildcard when specifying the hostname filter and the port may be given as a
+range. Filters starting with '!' exclude the matching connections even if another filter includes
+them. If the filter is not specified, Proxide will decode all traffic.
 
   > proxide monitor -l 1234 -p *.foo.com,api.bar.com:8080
+  > proxide monitor -l 1234 -p *.internal:8000-8100,!metrics.internal
 
 If neither -t or -p options are specified, Proxide will default to running as a CONNECT proxy (as
 if -p was specified with no filter). If -t option is specified, this default behaviour is skipped.
@@ -299,6 +810,28 @@ certificates."
         self.app().arg(cert).arg(key)
     }
 
+    fn config_options(self) -> App<'a>
+    {
+        self.app().arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("file")
+                .takes_value(true)
+                .help("Read the options from a TOML file")
+                .long_help(long!(
+                    "\
+Read the options from a TOML file. The keys in the file are the long names of the command line
+options. Options that take multiple values are given as arrays and flags as booleans. Options
+given on the command line override the ones in the file. Options that are not supported by the
+current command are ignored so the same file can be used with all commands.
+
+  listen = 8888
+  target = \"localhost:5000\"
+  grpc = [\"./protos/service.proto\"]"
+                )),
+        )
+    }
+
     fn json_options(self) -> App<'a>
     {
         self.app().arg(Arg::with_name("json").long("json").help(
@@ -310,6 +843,35 @@ certificates."
     {
         crate::decoders::setup_args(self.app())
     }
+
+    fn ui_options(self) -> App<'a>
+    {
+        self.app()
+            .arg(
+                Arg::with_name("replay-target")
+                    .long("replay-target")
+                    .value_name("host:port")
+                    .takes_value(true)
+                    .help("Specify the server requests are sent to with ':replay'")
+                    .long_help(long!(
+                        "\
+Specify the server the ':replay' command sends the requests to. The target can also be given to
+the command itself. If neither is specified, the request is sent to the authority in the original
+request URI."
+                    )),
+            )
+            .arg(
+                Arg::with_name("osc52")
+                    .long("osc52")
+                    .help("Copy to the clipboard through the terminal with OSC 52")
+                    .long_help(long!(
+                        "\
+Copy to the clipboard by sending the OSC 52 escape sequence to the terminal instead of using the
+system clipboard. This works over SSH, but requires support from the terminal emulator and is
+limited to 100 kB of text."
+                    )),
+            )
+    }
 }
 
 impl<'a> AppEx<'a> for App<'a>
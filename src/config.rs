@@ -1,4 +1,5 @@
 use clap::ArgMatches;
+use serde::Serialize;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
@@ -7,6 +8,45 @@ use super::Error;
 
 const CERT_COMMON_NAME: &str = "UNSAFE Proxide Root Certificate";
 
+/// Summary of the actions taken by the `config ca` command.
+///
+/// Printed as JSON when the user specifies `--json`. Otherwise the progress is reported as human
+/// readable text as the actions are performed.
+#[derive(Serialize, Default)]
+pub struct CaReport
+{
+    #[serde(skip)]
+    json: bool,
+
+    pub success: bool,
+    pub error: Option<String>,
+    pub files_written: Vec<String>,
+    pub revoked: Vec<&'static str>,
+    pub trusted: Vec<&'static str>,
+    pub commands: Vec<CommandOutput>,
+}
+
+/// Output of an external command run while modifying the certificate stores.
+#[derive(Serialize)]
+pub struct CommandOutput
+{
+    pub command: String,
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CaReport
+{
+    /// Reports progress to the user unless the output is meant for machines.
+    fn message(&self, msg: &str)
+    {
+        if !self.json {
+            println!("{}", msg);
+        }
+    }
+}
+
 pub fn run(matches: &ArgMatches) -> Result<(), Error>
 {
     match matches.subcommand() {
@@ -17,14 +57,37 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error>
 }
 
 pub fn run_ca(matches: &ArgMatches) -> Result<(), Error>
+{
+    let mut report = CaReport {
+        json: matches.is_present("json"),
+        ..Default::default()
+    };
+
+    let result = run_ca_actions(matches, &mut report);
+    if report.json {
+        report.success = result.is_ok();
+        report.error = result.as_ref().err().map(ToString::to_string);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Report is always serializable")
+        );
+    }
+
+    result
+}
+
+fn run_ca_actions(matches: &ArgMatches, report: &mut CaReport) -> Result<(), Error>
 {
     // Handle revoke first.
     if matches.is_present("revoke") || matches.is_present("trust") {
-        os::revoke_ca(matches)?;
+        os::revoke_ca(matches, report)?;
     }
 
     // If 'revoke' was the only command, we'll interrupt here.
-    if !(matches.is_present("create") || matches.is_present("trust")) {
+    if !(matches.is_present("create")
+        || matches.is_present("trust")
+        || matches.is_present("export-pkcs12"))
+    {
         return Ok(());
     }
 
@@ -35,21 +98,47 @@ pub fn run_ca(matches: &ArgMatches) -> Result<(), Error>
 
     if matches.is_present("create") {
         create_ca(matches, cert_file, key_file)?;
+        report.files_written.push(cert_file.to_string());
+        report.files_written.push(key_file.to_string());
+        report.message(&format!(
+            "Created CA certificate '{}' and private key '{}'",
+            cert_file, key_file
+        ));
     }
 
     // Trust the certificate if the user asked for that.
     if matches.is_present("trust") {
-        trust_ca(matches, cert_file, key_file)?;
+        trust_ca(matches, cert_file, key_file, report)?;
+    }
+
+    if let Some(pkcs12_file) = matches.value_of("export-pkcs12") {
+        export_pkcs12(matches, cert_file, key_file, pkcs12_file)?;
+        report.files_written.push(pkcs12_file.to_string());
+        report.message(&format!("Exported the CA certificate to '{}'", pkcs12_file));
     }
 
     Ok(())
 }
 
-fn trust_ca(matches: &ArgMatches, cert_file: &str, key_file: &str) -> Result<(), Error>
+fn trust_ca(
+    matches: &ArgMatches,
+    cert_file: &str,
+    key_file: &str,
+    report: &mut CaReport,
+) -> Result<(), Error>
 {
     // Technically if all the user wanted to do was '--create' we wouldn't really need to
     // do this check, but it doesn't really hurt either, unless you count the extra disk
     // access (which I don't!).
+    ensure_ca_files(cert_file, key_file)?;
+
+    os::trust_ca(cert_file, matches, report)?;
+
+    Ok(())
+}
+
+fn ensure_ca_files(cert_file: &str, key_file: &str) -> Result<(), Error>
+{
     for file in &[cert_file, key_file] {
         if !Path::new(file).is_file() {
             return Err(Error::ArgumentError {
@@ -61,7 +150,53 @@ fn trust_ca(matches: &ArgMatches, cert_file: &str, key_file: &str) -> Result<(),
         }
     }
 
-    os::trust_ca(cert_file, matches)?;
+    Ok(())
+}
+
+fn export_pkcs12(
+    matches: &ArgMatches,
+    cert_file: &str,
+    key_file: &str,
+    pkcs12_file: &str,
+) -> Result<(), Error>
+{
+    ensure_ca_files(cert_file, key_file)?;
+
+    // PKCS#12 wants the DER contents so we'll need to decode the PEM files first. The private
+    // key is stored in PKCS#8 format, which is what the PKCS#12 container expects as well.
+    let read_pem = |file: &str| {
+        std::fs::read_to_string(file)
+            .ok()
+            .and_then(|data| pem::parse(data).ok())
+            .map(|pem| pem.contents)
+            .ok_or_else(|| Error::ArgumentError {
+                msg: format!("Could not read PEM data from '{}'", file),
+            })
+    };
+    let cert = read_pem(cert_file)?;
+    let key = read_pem(key_file)?;
+
+    let password = matches.value_of("password").unwrap_or("");
+    let pfx = p12::PFX::new(&cert, &key, None, password, CERT_COMMON_NAME).ok_or_else(|| {
+        Error::RuntimeError {
+            msg: format!(
+                "Could not bundle '{}' and '{}' into PKCS#12",
+                cert_file, key_file
+            ),
+        }
+    })?;
+
+    File::create(pkcs12_file)
+        .map_err(|_| Error::ArgumentError {
+            msg: format!(
+                "Could not open the PKCS#12 file '{}' for writing",
+                pkcs12_file
+            ),
+        })?
+        .write_all(&pfx.to_der())
+        .map_err(|_| Error::ArgumentError {
+            msg: format!("Could not write PKCS#12 to '{}'", pkcs12_file),
+        })?;
 
     Ok(())
 }
@@ -83,6 +218,52 @@ fn create_ca(matches: &ArgMatches, cert_file: &str, key_file: &str) -> Result<()
         }
     }
 
+    let duration = matches
+        .value_of("duration")
+        .expect("Duration was missing") // Defaulted by clap for --create.
+        .parse::<u32>()
+        .expect("Duration was invalid"); // Validated to be u32 by clap.
+    let (alg, key_pair) = generate_key_pair(matches)?;
+    let ca_cert = ca_certificate(duration, alg, key_pair)?;
+
+    File::create(cert_file)
+        .map_err(|_| Error::ArgumentError {
+            msg: format!(
+                "Could not open the certificate file '{}' for writing",
+                cert_file
+            ),
+        })?
+        .write_all(
+            ca_cert
+                .serialize_pem()
+                .map_err(|e| Error::RuntimeError { msg: e.to_string() })?
+                .as_bytes(),
+        )
+        .map_err(|_| Error::ArgumentError {
+            msg: format!("Could not write certificate to '{}'", cert_file),
+        })?;
+    File::create(key_file)
+        .map_err(|_| Error::ArgumentError {
+            msg: format!(
+                "Could not open the private key file '{}' for writing",
+                key_file
+            ),
+        })?
+        .write_all(ca_cert.serialize_private_key_pem().as_bytes())
+        .map_err(|_| Error::ArgumentError {
+            msg: format!("Could not write private key to '{}'", key_file),
+        })?;
+
+    Ok(())
+}
+
+/// Creates the CA certificate valid for `duration` days starting from now.
+fn ca_certificate(
+    duration: u32,
+    alg: &'static rcgen::SignatureAlgorithm,
+    key_pair: rcgen::KeyPair,
+) -> Result<rcgen::Certificate, Error>
+{
     // Set up the rcgen certificate parameters for the new certificate.
     //
     // Note that at least on Windows the common name is used to later find and revoke
@@ -106,68 +287,145 @@ fn create_ca(matches: &ArgMatches, cert_file: &str, key_file: &str) -> Result<()
     key_usage.set_criticality(true);
     ca_params.custom_extensions = vec![key_usage];
     ca_params.distinguished_name = rcgen::DistinguishedName::new();
-    /*
-    ca_params.not_after = chrono::Utc::now()
+
+    // Backdate the certificate a bit so clients with a clock slightly behind ours don't reject
+    // it as not yet valid.
+    let now = chrono::Utc::now();
+    ca_params.not_before = now - chrono::Duration::hours(1);
+    ca_params.not_after = now
         + chrono::Duration::days(
-            matches
-                .value_of("duration")
-                .expect("Duration was missing") // Marked as required for clap.
-                .parse::<i64>()
-                .expect("Duration was invalid") // Validated to be u32 by clap.
-                .min(/* 365.2425 * 2000 = */ 730485), // ASN has trouble with giant dates so max out at 2000 years.
+            // ASN has trouble with giant dates so max out at 2000 years.
+            i64::from(duration).min(/* 365.2425 * 2000 = */ 730485),
         );
-        */
     ca_params
         .distinguished_name
         .push(rcgen::DnType::OrganizationName, "UNSAFE");
     ca_params
         .distinguished_name
         .push(rcgen::DnType::CommonName, CERT_COMMON_NAME); // See the comment above.
-    let ca_cert = rcgen::Certificate::from_params(ca_params).unwrap();
+    ca_params.alg = alg;
+    ca_params.key_pair = Some(key_pair);
+    rcgen::Certificate::from_params(ca_params)
+        .map_err(|e| Error::RuntimeError { msg: e.to_string() })
+}
 
-    File::create(cert_file)
-        .map_err(|_| Error::ArgumentError {
-            msg: format!(
-                "Could not open the certificate file '{}' for writing",
-                cert_file
-            ),
-        })?
-        .write_all(
-            ca_cert
-                .serialize_pem()
-                .map_err(|e| Error::RuntimeError { msg: e.to_string() })?
-                .as_bytes(),
-        )
-        .map_err(|_| Error::ArgumentError {
-            msg: format!("Could not write certificate to '{}'", cert_file),
-        })?;
-    File::create(key_file)
-        .map_err(|_| Error::ArgumentError {
-            msg: format!(
-                "Could not open the private key file '{}' for writing",
-                key_file
-            ),
-        })?
-        .write_all(ca_cert.serialize_private_key_pem().as_bytes())
-        .map_err(|_| Error::ArgumentError {
-            msg: format!("Could not write private key to '{}'", key_file),
-        })?;
+/// Generates the CA key pair based on the `--key-type` and `--rsa-bits` options.
+fn generate_key_pair(
+    matches: &ArgMatches,
+) -> Result<(&'static rcgen::SignatureAlgorithm, rcgen::KeyPair), Error>
+{
+    let key_type = matches.value_of("key-type").unwrap_or("rsa");
+    if key_type != "rsa" && matches.occurrences_of("rsa-bits") > 0 {
+        return Err(Error::ArgumentError {
+            msg: "--rsa-bits requires --key-type rsa".to_string(),
+        });
+    }
 
-    Ok(())
+    match key_type {
+        "ecdsa" => {
+            let alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+            let key_pair = rcgen::KeyPair::generate(alg)
+                .map_err(|e| Error::RuntimeError { msg: e.to_string() })?;
+            Ok((alg, key_pair))
+        }
+        "rsa" => {
+            // Ring, which rcgen uses under the hood, cannot generate RSA keys so the key is
+            // generated with the rsa crate and handed over to rcgen as PKCS#8.
+            use rsa::pkcs8::EncodePrivateKey;
+            let bits = matches
+                .value_of("rsa-bits")
+                .unwrap_or("2048")
+                .parse::<usize>()
+                .expect("Key size was invalid"); // Validated by clap possible values.
+            let key = rsa::RsaPrivateKey::new(&mut rand::rngs::OsRng, bits)
+                .map_err(|e| Error::RuntimeError { msg: e.to_string() })?;
+            let der = key
+                .to_pkcs8_der()
+                .map_err(|e| Error::RuntimeError { msg: e.to_string() })?;
+            let key_pair = rcgen::KeyPair::from_der(der.as_bytes())
+                .map_err(|e| Error::RuntimeError { msg: e.to_string() })?;
+            Ok((&rcgen::PKCS_RSA_SHA256, key_pair))
+        }
+        v => Err(Error::ArgumentError {
+            msg: format!("Invalid key type '{}'", v),
+        }),
+    }
+}
+
+/// The certificate store targeted by `--revoke` or the implicit revoke of `--trust`.
+fn revoke_store(matches: &ArgMatches) -> Result<&str, Error>
+{
+    // The revoke may happen either explicitly through --revoke or implicitly through --trust.
+    // Since the use of these two options conflict with each other, we need to only care about
+    // one of them here.
+    let store = matches
+        .value_of("revoke")
+        .or_else(|| matches.value_of("trust"))
+        .unwrap_or("user");
+    match store {
+        "user" | "system" | "all" => Ok(store),
+        v => Err(Error::ArgumentError {
+            msg: format!("Invalid certificate store '{}'", v),
+        }),
+    }
+}
+
+/// The certificate store targeted by `--trust`.
+fn trust_store(matches: &ArgMatches) -> Result<&str, Error>
+{
+    let trust = matches.value_of("trust").unwrap_or("user");
+    match trust {
+        "user" | "system" | "all" => Ok(trust),
+        v => Err(Error::ArgumentError {
+            msg: format!("Invalid --trust value '{}'", v),
+        }),
+    }
 }
 
-#[cfg(not(windows))]
+/// Run a command that modifies the certificate stores.
+///
+/// The output of the command is captured into the report so it won't mix with the JSON output.
+/// Returns whether the command succeeded. Failing to start the command is an error.
+#[cfg(any(windows, unix))]
+fn run_command(command: &mut std::process::Command, report: &mut CaReport) -> Result<bool, Error>
+{
+    let output = command.output().map_err(|e| Error::RuntimeError {
+        msg: format!("Failed to run {:?}: {}", command, e),
+    })?;
+
+    let output = CommandOutput {
+        command: format!("{:?}", command),
+        success: output.status.success(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    };
+    log::info!("{} exited with success: {}", output.command, output.success);
+    if !report.json {
+        print!("{}", output.stdout);
+        eprint!("{}", output.stderr);
+    }
+
+    let success = output.success;
+    report.commands.push(output);
+    Ok(success)
+}
+
+#[cfg(not(any(windows, unix)))]
 mod os
 {
     use super::*;
-    pub fn revoke_ca(_matches: &ArgMatches) -> Result<(), Error>
+    pub fn revoke_ca(_matches: &ArgMatches, _report: &mut CaReport) -> Result<(), Error>
     {
         Err(Error::RuntimeError {
             msg: "--revoke is not supported on this platform".to_string(),
         })
     }
 
-    pub fn trust_ca(_cert_file: &str, _matches: &ArgMatches) -> Result<(), Error>
+    pub fn trust_ca(
+        _cert_file: &str,
+        _matches: &ArgMatches,
+        _report: &mut CaReport,
+    ) -> Result<(), Error>
     {
         Err(Error::RuntimeError {
             msg: "--trust is not supported on this platform".to_string(),
@@ -175,100 +433,471 @@ mod os
     }
 }
 
-#[cfg(windows)]
+#[cfg(target_os = "macos")]
 mod os
 {
     use super::*;
-    pub fn revoke_ca(matches: &ArgMatches) -> Result<(), Error>
+    use std::process::Command;
+
+    const SYSTEM_KEYCHAIN: &str = "/Library/Keychains/System.keychain";
+
+    /// Upper bound for the certificates removed from a single keychain.
+    const MAX_DELETED_CERTIFICATES: usize = 100;
+
+    pub fn revoke_ca(matches: &ArgMatches, report: &mut CaReport) -> Result<(), Error>
     {
-        // The revoke may happen either explicitly through --revoke or implicitly through --trust.
-        // Since the use of these two options conflict with each other, we need to only care about
-        // one of them here.
-        let store = matches
-            .value_of("revoke")
-            .or_else(|| matches.value_of("trust"))
-            .unwrap_or("user");
-        match store {
-            "user" | "system" | "all" => (),
-            v => {
-                return Err(Error::ArgumentError {
-                    msg: format!("Invalid certificate store '{}'", v),
-                })
+        let store = revoke_store(matches)?;
+
+        if store == "all" || store == "system" {
+            report
+                .message("Removing the previous Proxide CA certificates from the System keychain");
+            delete_certificates(Some(SYSTEM_KEYCHAIN), report)?;
+            report.revoked.push("system");
+        }
+
+        if store == "all" || store == "user" {
+            report.message("Removing the previous Proxide CA certificates from the login keychain");
+            delete_certificates(None, report)?;
+            report.revoked.push("user");
+        }
+
+        Ok(())
+    }
+
+    /// Delete all the Proxide CA certificates from the keychain.
+    ///
+    /// `security delete-certificate` removes only one matching certificate per call so it is
+    /// repeated until it fails, which happens once there are no certificates left. The user
+    /// keychain is the default keychain, which is usually the login keychain.
+    fn delete_certificates(keychain: Option<&str>, report: &mut CaReport) -> Result<(), Error>
+    {
+        for _ in 0..MAX_DELETED_CERTIFICATES {
+            let mut command = Command::new("security");
+            command.args(["delete-certificate", "-t", "-c", CERT_COMMON_NAME]);
+            command.args(keychain);
+            if !run_command(&mut command, report)? {
+                return Ok(());
+            }
+        }
+
+        Err(Error::RuntimeError {
+            msg: format!(
+                "Failed to remove all the Proxide CA certificates after {} attempts",
+                MAX_DELETED_CERTIFICATES
+            ),
+        })
+    }
+
+    pub fn trust_ca(
+        cert_file: &str,
+        matches: &ArgMatches,
+        report: &mut CaReport,
+    ) -> Result<(), Error>
+    {
+        let trust = trust_store(matches)?;
+
+        if trust == "all" || trust == "system" {
+            report.message("Importing the Proxide CA certificate to the System keychain");
+            let success = run_command(
+                Command::new("security")
+                    .args(["add-trusted-cert", "-d", "-r", "trustRoot", "-k"])
+                    .arg(SYSTEM_KEYCHAIN)
+                    .arg(cert_file),
+                report,
+            )?;
+            if !success {
+                return Err(Error::RuntimeError {
+                    msg: "Failed to import the certificate to the System keychain".to_string(),
+                });
             }
+            report.trusted.push("system");
+        }
+
+        if trust == "all" || trust == "user" {
+            report.message("Importing the Proxide CA certificate to the login keychain");
+            let success = run_command(
+                Command::new("security")
+                    .args(["add-trusted-cert", "-r", "trustRoot"])
+                    .arg(cert_file),
+                report,
+            )?;
+            if !success {
+                return Err(Error::RuntimeError {
+                    msg: "Failed to import the certificate to the login keychain".to_string(),
+                });
+            }
+            report.trusted.push("user");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod os
+{
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    /// Location of the certificates picked up by `update-ca-certificates`.
+    const SYSTEM_CERT_FILE: &str = "/usr/local/share/ca-certificates/proxide_ca.crt";
+
+    /// Linux has no system wide user store. The NSS database in the home directory is used
+    /// by the browsers so that works as one.
+    fn user_nss_db() -> Result<String, Error>
+    {
+        let home = std::env::var_os("HOME").ok_or_else(|| Error::RuntimeError {
+            msg: "Could not resolve the home directory for the user store".to_string(),
+        })?;
+        let db = PathBuf::from(home).join(".pki").join("nssdb");
+        std::fs::create_dir_all(&db).map_err(|e| Error::RuntimeError {
+            msg: format!("Could not create '{}': {}", db.to_string_lossy(), e),
+        })?;
+        Ok(format!("sql:{}", db.to_string_lossy()))
+    }
+
+    fn update_ca_certificates(report: &mut CaReport) -> Result<(), Error>
+    {
+        match run_command(&mut Command::new("update-ca-certificates"), report)? {
+            true => Ok(()),
+            false => Err(Error::RuntimeError {
+                msg: "Failed to update the system certificates with update-ca-certificates"
+                    .to_string(),
+            }),
         }
+    }
+
+    pub fn revoke_ca(matches: &ArgMatches, report: &mut CaReport) -> Result<(), Error>
+    {
+        let store = revoke_store(matches)?;
 
         if store == "all" || store == "system" {
-            println!("Removing the previous Proxide CA certificates from the System store");
-            std::process::Command::new("certutil")
-                .arg("-delstore")
-                .arg("Root")
-                .arg(CERT_COMMON_NAME)
-                .spawn()
-                .and_then(|mut process| process.wait())
-                .map(|_| ())
-                .map_err(|e| Error::RuntimeError {
-                    msg: format!("Failed to revoke the certificates with certutil: {}", e),
+            report.message("Removing the previous Proxide CA certificates from the System store");
+            if Path::new(SYSTEM_CERT_FILE).is_file() {
+                std::fs::remove_file(SYSTEM_CERT_FILE).map_err(|e| Error::RuntimeError {
+                    msg: format!("Failed to remove '{}': {}", SYSTEM_CERT_FILE, e),
                 })?;
+                update_ca_certificates(report)?;
+            }
+            report.revoked.push("system");
         }
 
         if store == "all" || store == "user" {
-            println!("Removing the previous Proxide CA certificates from the User store");
-            std::process::Command::new("certutil")
-                .arg("-delstore")
-                .arg("-user")
-                .arg("Root")
-                .arg(CERT_COMMON_NAME)
-                .spawn()
-                .and_then(|mut process| process.wait())
-                .map(|_| ())
-                .map_err(|e| Error::RuntimeError {
-                    msg: format!("Failed to revoke the certificates with certutil: {}", e),
-                })?;
+            report.message("Removing the previous Proxide CA certificates from the User store");
+
+            // Missing certificates make certutil fail so the exit status is ignored here.
+            run_command(
+                Command::new("certutil").args([
+                    "-D",
+                    "-d",
+                    &user_nss_db()?,
+                    "-n",
+                    CERT_COMMON_NAME,
+                ]),
+                report,
+            )?;
+            report.revoked.push("user");
         }
 
         Ok(())
     }
 
-    pub fn trust_ca(cert_file: &str, matches: &ArgMatches) -> Result<(), Error>
+    pub fn trust_ca(
+        cert_file: &str,
+        matches: &ArgMatches,
+        report: &mut CaReport,
+    ) -> Result<(), Error>
     {
-        let trust = matches.value_of("trust").unwrap_or("user");
-        match trust {
-            "user" | "system" | "all" => (),
-            v => {
-                return Err(Error::ArgumentError {
-                    msg: format!("Invalid --trust value '{}'", v),
-                })
+        let trust = trust_store(matches)?;
+
+        if trust == "all" || trust == "system" {
+            report.message("Importing the Proxide CA certificate to the System store");
+            std::fs::copy(cert_file, SYSTEM_CERT_FILE).map_err(|e| Error::RuntimeError {
+                msg: format!(
+                    "Failed to copy the certificate to '{}': {}",
+                    SYSTEM_CERT_FILE, e
+                ),
+            })?;
+            update_ca_certificates(report)?;
+            report.trusted.push("system");
+        }
+
+        if trust == "all" || trust == "user" {
+            report.message("Importing the Proxide CA certificate to the User store");
+            let success = run_command(
+                Command::new("certutil").args([
+                    "-A",
+                    "-d",
+                    &user_nss_db()?,
+                    "-t",
+                    "C,,",
+                    "-n",
+                    CERT_COMMON_NAME,
+                    "-i",
+                    cert_file,
+                ]),
+                report,
+            )?;
+            if !success {
+                return Err(Error::RuntimeError {
+                    msg: "Failed to import the certificate to the User store with certutil"
+                        .to_string(),
+                });
             }
+            report.trusted.push("user");
         }
 
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod os
+{
+    use super::*;
+    use std::process::Command;
+
+    pub fn revoke_ca(matches: &ArgMatches, report: &mut CaReport) -> Result<(), Error>
+    {
+        let store = revoke_store(matches)?;
+
+        if store == "all" || store == "system" {
+            report.message("Removing the previous Proxide CA certificates from the System store");
+            // Missing certificates make certutil fail so the exit status is ignored here.
+            run_command(
+                Command::new("certutil")
+                    .arg("-delstore")
+                    .arg("Root")
+                    .arg(CERT_COMMON_NAME),
+                report,
+            )?;
+            report.revoked.push("system");
+        }
+
+        if store == "all" || store == "user" {
+            report.message("Removing the previous Proxide CA certificates from the User store");
+            // Missing certificates make certutil fail so the exit status is ignored here.
+            run_command(
+                Command::new("certutil")
+                    .arg("-delstore")
+                    .arg("-user")
+                    .arg("Root")
+                    .arg(CERT_COMMON_NAME),
+                report,
+            )?;
+            report.revoked.push("user");
+        }
+
+        Ok(())
+    }
+
+    pub fn trust_ca(
+        cert_file: &str,
+        matches: &ArgMatches,
+        report: &mut CaReport,
+    ) -> Result<(), Error>
+    {
+        let trust = trust_store(matches)?;
+
         if trust == "all" || trust == "system" {
-            println!("Add system");
-            std::process::Command::new("certutil")
-                .arg("-addstore")
-                .arg("-v")
-                .arg("Root")
-                .arg(cert_file)
-                .spawn()
-                .and_then(|mut process| process.wait())
-                .map_err(|e| Error::RuntimeError {
-                    msg: format!("Failed to import the certificate with certutil: {}", e),
-                })?;
+            report.message("Importing the Proxide CA certificate to the System store");
+            let success = run_command(
+                Command::new("certutil")
+                    .arg("-addstore")
+                    .arg("-v")
+                    .arg("Root")
+                    .arg(cert_file),
+                report,
+            )?;
+            if !success {
+                return Err(Error::RuntimeError {
+                    msg: "Failed to import the certificate to the System store with certutil"
+                        .to_string(),
+                });
+            }
+            report.trusted.push("system");
         }
 
         if trust == "all" || trust == "user" {
-            println!("Add user");
-            std::process::Command::new("certutil")
-                .arg("-addstore")
-                .arg("-user")
-                .arg("Root")
-                .arg(cert_file)
-                .spawn()
-                .and_then(|mut process| process.wait())
-                .map_err(|e| Error::RuntimeError {
-                    msg: format!("Failed to import the certificate with certutil: {}", e),
-                })?;
+            report.message("Importing the Proxide CA certificate to the User store");
+            let success = run_command(
+                Command::new("certutil")
+                    .arg("-addstore")
+                    .arg("-user")
+                    .arg("Root")
+                    .arg(cert_file),
+                report,
+            )?;
+            if !success {
+                return Err(Error::RuntimeError {
+                    msg: "Failed to import the certificate to the User store with certutil"
+                        .to_string(),
+                });
+            }
+            report.trusted.push("user");
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn validity(duration: u32) -> (i64, i64)
+    {
+        let alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+        let key_pair = rcgen::KeyPair::generate(alg).unwrap();
+        let der = ca_certificate(duration, alg, key_pair)
+            .unwrap()
+            .serialize_der()
+            .unwrap();
+        let (_, cert) = x509_parser::parse_x509_certificate(&der).unwrap();
+        (
+            cert.validity().not_before.timestamp(),
+            cert.validity().not_after.timestamp(),
+        )
+    }
+
+    #[test]
+    fn ca_validity_matches_duration()
+    {
+        let now = chrono::Utc::now().timestamp();
+        let (not_before, not_after) = validity(30);
+        assert!(not_before <= now);
+        assert!((not_after - now - 30 * 24 * 60 * 60).abs() < 60);
+    }
+
+    #[test]
+    fn ca_validity_is_capped()
+    {
+        let now = chrono::Utc::now().timestamp();
+        let (_, not_after) = validity(u32::MAX);
+        assert!((not_after - now - 730485 * 24 * 60 * 60).abs() < 60);
+    }
+
+    /// Parses the `config ca` arguments.
+    fn ca_matches(args: &[&str]) -> ArgMatches
+    {
+        let matches = crate::command_line::setup_app("test")
+            .get_matches_from(["proxide", "config", "ca"].iter().chain(args));
+        let (_, config) = matches.subcommand().unwrap();
+        let (_, ca) = config.subcommand().unwrap();
+        ca.clone()
+    }
+
+    fn temp_dir() -> std::path::PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("proxide-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn created_ca(args: &[&str]) -> crate::CADetails
+    {
+        let dir = temp_dir();
+        let cert_file = dir.join("ca.crt").to_string_lossy().into_owned();
+        let key_file = dir.join("ca.key").to_string_lossy().into_owned();
+        let mut args = args.to_vec();
+        args.push("--create");
+        create_ca(&ca_matches(&args), &cert_file, &key_file).unwrap();
+
+        let ca = crate::CADetails {
+            certificate: std::fs::read_to_string(&cert_file).unwrap(),
+            key: std::fs::read_to_string(&key_file).unwrap(),
+        };
+        std::fs::remove_dir_all(dir).unwrap();
+        ca
+    }
+
+    /// Signs a leaf certificate the way TLS connections do and verifies it against the CA.
+    ///
+    /// Returns the OID of the CA public key algorithm and the size of the key in bits.
+    fn sign_leaf(ca: &crate::CADetails) -> (String, usize)
+    {
+        let (leaf, key) = crate::connection::get_certificate("example.com", ca);
+        assert!(rustls::sign::any_supported_type(&key).is_ok());
+
+        let ca_der = pem::parse(&ca.certificate).unwrap().contents;
+        let (_, ca_cert) = x509_parser::parse_x509_certificate(&ca_der).unwrap();
+        let (_, leaf_cert) = x509_parser::parse_x509_certificate(&leaf[0].0).unwrap();
+        assert_eq!(leaf_cert.issuer(), ca_cert.subject());
+        leaf_cert
+            .verify_signature(Some(ca_cert.public_key()))
+            .unwrap();
+
+        let spki = ca_cert.public_key();
+        (
+            spki.algorithm.algorithm.to_id_string(),
+            spki.subject_public_key.data.len() * 8,
+        )
+    }
+
+    #[test]
+    fn rsa_ca_signs_certificates()
+    {
+        let (alg, bits) = sign_leaf(&created_ca(&["--key-type", "rsa", "--rsa-bits", "2048"]));
+        assert_eq!(alg, "1.2.840.113549.1.1.1"); // rsaEncryption
+
+        // The RSAPublicKey structure wraps the modulus with a few bytes of DER.
+        assert!((2048..2048 + 128).contains(&bits));
+
+        // RSA stays the default key type.
+        let matches = ca_matches(&["--create"]);
+        assert_eq!(matches.value_of("key-type"), Some("rsa"));
+        assert_eq!(matches.value_of("rsa-bits"), Some("2048"));
+    }
+
+    #[test]
+    fn ecdsa_ca_signs_certificates()
+    {
+        let (alg, _) = sign_leaf(&created_ca(&["--key-type", "ecdsa"]));
+        assert_eq!(alg, "1.2.840.10045.2.1"); // ecPublicKey
+    }
+
+    #[test]
+    fn rsa_bits_require_rsa_keys()
+    {
+        let matches = ca_matches(&["--create", "--key-type", "ecdsa", "--rsa-bits", "2048"]);
+        assert!(generate_key_pair(&matches).is_err());
+    }
+
+    #[test]
+    fn pkcs12_export_round_trips()
+    {
+        let dir = temp_dir();
+        let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let (cert_file, key_file, pkcs12_file) = (file("ca.crt"), file("ca.key"), file("ca.p12"));
+        create_ca(
+            &ca_matches(&["--create", "--key-type", "ecdsa"]),
+            &cert_file,
+            &key_file,
+        )
+        .unwrap();
+        export_pkcs12(
+            &ca_matches(&["--export-pkcs12", &pkcs12_file, "--password", "secret"]),
+            &cert_file,
+            &key_file,
+            &pkcs12_file,
+        )
+        .unwrap();
+
+        let pem_contents = |file: &str| pem::parse(std::fs::read(file).unwrap()).unwrap().contents;
+        let pfx = p12::PFX::parse(&std::fs::read(&pkcs12_file).unwrap()).unwrap();
+        assert!(pfx.verify_mac("secret"));
+        assert!(!pfx.verify_mac("wrong"));
+        assert_eq!(
+            pfx.cert_x509_bags("secret").unwrap(),
+            vec![pem_contents(&cert_file)]
+        );
+        assert_eq!(
+            pfx.key_bags("secret").unwrap(),
+            vec![pem_contents(&key_file)]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+use clap::{App, Arg};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+
+use crate::Error;
+
+/// Options read from the file given with `--config`.
+///
+/// The keys are the long names of the command line options. Options given on the command line
+/// take precedence over the ones in the file.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config
+{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    listen: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_header_host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    route: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxy: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_filter: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream_proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream_proxy_auth: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_remote: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bind: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    listen_uds: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    control_listen: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    h2_window_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ca_cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ca_key: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inject_latency: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inject_error_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inject_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inject_seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    throttle_kbps: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idle_timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connect_retries: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connect_backoff: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_body_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    set_request_header: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remove_request_header: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    set_response_header: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remove_response_header: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redact_header: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redact_body_pattern: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redact_all_auth: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grpc: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grpc_reflection: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protobuf_type: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decode_base64: Option<bool>,
+    #[serde(rename = "json.max-depth", skip_serializing_if = "Option::is_none")]
+    json_max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default_decoder: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disable_decoder: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replay_target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    osc52: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_requests: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    only_path: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    only_host: Option<Vec<String>>,
+}
+
+/// Add the options from the config file to the command line arguments.
+///
+/// The options are appended after the arguments given by the user, skipping the ones the user has
+/// already given, so clap ends up seeing each option only once.
+pub fn apply(app: &App, args: Vec<OsString>) -> Result<Vec<OsString>, Error>
+{
+    let (subcommand, path) = match find_config(&args) {
+        Some(c) => c,
+        None => return Ok(args),
+    };
+
+    // Leave reporting an unknown subcommand to clap.
+    let subcommand_app = match app.get_subcommands().find(|s| s.get_name() == subcommand) {
+        Some(app) => app,
+        None => return Ok(args),
+    };
+
+    let content = std::fs::read_to_string(&path).map_err(|e| Error::ArgumentError {
+        msg: format!("Could not read config file '{}': {}", path, e),
+    })?;
+    let config: Config = toml::from_str(&content).map_err(|e| Error::ArgumentError {
+        msg: format!("Invalid config file '{}': {}", path, e),
+    })?;
+    Ok(to_args(&config, subcommand_app, args))
+}
+
+fn to_args(config: &Config, app: &App, mut args: Vec<OsString>) -> Vec<OsString>
+{
+    // Everything after '--' is positional so the options must be inserted before it.
+    let mut insert_at = args.iter().position(|a| a == "--").unwrap_or(args.len());
+
+    let values = match toml::Value::try_from(config).expect("Config is always serializable") {
+        toml::Value::Table(table) => table,
+        _ => unreachable!("Config is serialized as a table"),
+    };
+    for (key, value) in values {
+        // The same file may be used with different commands so options the current command
+        // doesn't support are ignored.
+        let arg = match app
+            .get_arguments()
+            .find(|a| a.get_long() == Some(&key) || (a.get_long().is_none() && a.get_id() == key))
+        {
+            Some(arg) => arg,
+            None => continue,
+        };
+        if is_given(arg, &args[..insert_at]) {
+            continue;
+        }
+
+        let option = match (arg.get_long(), arg.get_short()) {
+            (Some(long), _) => format!("--{}", long),
+            (None, Some(short)) => format!("-{}", short),
+            (None, None) => unreachable!("Config only contains named options"),
+        };
+        let mut new_args = vec![];
+        match value {
+            toml::Value::Boolean(true) => new_args.push(option),
+            toml::Value::Boolean(false) => {}
+            toml::Value::Array(values) => {
+                let values = values.iter().map(value_to_string);
+                match arg.is_multiple_occurrences_set() {
+                    true => {
+                        for v in values {
+                            new_args.push(option.clone());
+                            new_args.push(v);
+                        }
+                    }
+                    false => {
+                        new_args.push(option);
+                        new_args.extend(values);
+                    }
+                }
+            }
+            other => {
+                new_args.push(option);
+                new_args.push(value_to_string(&other));
+            }
+        }
+
+        for a in new_args {
+            args.insert(insert_at, a.into());
+            insert_at += 1;
+        }
+    }
+
+    args
+}
+
+/// Find the subcommand and the config file from the raw command line arguments.
+///
+/// The config file must be read before clap parses the arguments as the required options may be
+/// given only in the file.
+fn find_config(args: &[OsString]) -> Option<(String, String)>
+{
+    let subcommand = args.get(1)?.to_str()?;
+    let mut iter = args.iter().skip(2).map(|a| a.to_string_lossy());
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            return Some((subcommand.to_string(), iter.next()?.into_owned()));
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some((subcommand.to_string(), path.to_string()));
+        }
+    }
+    None
+}
+
+/// Check whether the user has given the option on the command line.
+fn is_given(arg: &Arg, args: &[OsString]) -> bool
+{
+    args.iter().skip(2).map(|a| a.to_string_lossy()).any(|a| {
+        let long = arg.get_long().map(|long| {
+            a.strip_prefix("--")
+                .map(|a| a == long || a.starts_with(&format!("{}=", long)))
+                .unwrap_or(false)
+        });
+        let short = arg.get_short().map(|short| {
+            !a.starts_with("--")
+                && a.strip_prefix('-').and_then(|a| a.chars().next()) == Some(short)
+        });
+        long.unwrap_or(false) || short.unwrap_or(false)
+    })
+}
+
+fn value_to_string(value: &toml::Value) -> String
+{
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString>
+    {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn command_line_overrides_config()
+    {
+        let app = crate::command_line::setup_app("test");
+        let config: Config = toml::from_str(
+            r#"
+                listen = 5555
+                target = "example.com:80"
+                grpc = ["a.proto", "b.proto"]
+                allow-remote = true
+                "json.max-depth" = 3
+            "#,
+        )
+        .unwrap();
+
+        let monitor = app
+            .get_subcommands()
+            .find(|s| s.get_name() == "monitor")
+            .unwrap();
+        let result = to_args(
+            &config,
+            monitor,
+            args(&["proxide", "monitor", "--config", "x", "-t", "localhost:80"]),
+        );
+        assert_eq!(
+            result,
+            args(&[
+                "proxide",
+                "monitor",
+                "--config",
+                "x",
+                "-t",
+                "localhost:80",
+                "--allow-remote",
+                "--grpc",
+                "a.proto",
+                "--grpc",
+                "b.proto",
+                "--json.max-depth",
+                "3",
+                "-l",
+                "5555",
+            ])
+        );
+
+        let unknown: Result<Config, _> = toml::from_str("listne = 5555");
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn unknown_subcommand_is_left_to_clap()
+    {
+        let app = crate::command_line::setup_app("test");
+        let given = args(&["proxide", "moniter", "--config", "missing.toml"]);
+        assert_eq!(apply(&app, given.clone()).unwrap(), given);
+    }
+
+    #[test]
+    fn config_supports_all_options()
+    {
+        let app = crate::command_line::setup_app("test");
+        for subcommand in app.get_subcommands() {
+            for arg in subcommand.get_arguments() {
+                // The output options choose what a single run produces so they are left to the
+                // command line.
+                let long = match arg.get_long() {
+                    Some("config") | Some("help") | Some("version") | None => continue,
+                    Some("json") | Some("har") | Some("file") => continue,
+                    Some(long) => long,
+                };
+
+                // Only the key matters here. The types of the values are not checked.
+                let result: Result<Config, _> = toml::from_str(&format!("\"{}\" = 0", long));
+                if let Err(e) = result {
+                    assert!(
+                        !e.to_string().contains("unknown field"),
+                        "--{} of {} is missing from the config: {}",
+                        long,
+                        subcommand.get_name(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
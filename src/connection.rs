@@ -3,18 +3,35 @@ use std::net::SocketAddr;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio::net::TcpStream;
 use uuid::Uuid;
 
 use crate::session::events::*;
 use crate::session::*;
-use crate::{CADetails, ConnectionOptions};
+use crate::{CADetails, ConnectionOptions, Route};
 
+mod body;
 mod connect;
 mod demux;
+mod faults;
+mod headers;
+mod http1;
 mod http2;
+mod idle;
+mod replay;
 mod stream;
+mod throttle;
 mod tls;
+mod websocket;
+
+use body::BodyRecorder;
+pub use faults::{Fault, FaultOptions};
+pub use headers::HeaderRules;
+use idle::IdleTimeout;
+pub use replay::{replay, ReplayRequest};
+use throttle::{Throttle, Throttles};
+#[cfg(test)]
+pub(crate) use tls::get_certificate;
+pub(crate) use tls::NoVerify;
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
@@ -33,6 +50,10 @@ pub enum ConfigurationErrorKind
     {
         source: http::uri::InvalidUriParts,
     },
+    HttpError
+    {
+        source: http::Error,
+    },
     NoSource {},
 }
 
@@ -63,6 +84,12 @@ pub enum EndpointError
     {
         reason: &'static str
     },
+
+    #[snafu(display("timed out after {:?}", duration))]
+    TimeoutError
+    {
+        duration: std::time::Duration
+    },
 }
 
 #[derive(Debug, Snafu)]
@@ -108,8 +135,15 @@ pub struct ConnectionDetails
     /// server than Proxide is redirecting it to. This might result in the need to rewrite
     /// Host/authority headers, etc. in the outgoing requests.
     pub opaque_redirect: Option<String>,
+
+    /// Number of attempts it took to connect to the server.
+    pub connect_attempts: u32,
 }
 
+/// Opens a new connection to the server for retrying a failed handshake.
+pub type Reconnect<TServer> =
+    Box<dyn Fn() -> futures::future::BoxFuture<'static, Result<TServer>> + Send + Sync>;
+
 pub struct Streams<TClient, TServer>
 {
     pub client: TClient,
@@ -132,17 +166,20 @@ impl<TClient, TServer> Streams<TClient, TServer>
 ///
 /// Avoiding having to return the streams allows us to avoid dynamic dispatch in the stream
 /// handling.
-pub async fn run(
-    client: TcpStream,
+pub async fn run<TClient>(
+    client: TClient,
     src_addr: SocketAddr,
     options: Arc<ConnectionOptions>,
     ui: Sender<SessionEvent>,
 ) -> Result<()>
+where
+    TClient: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
     let details = ConnectionDetails {
         uuid: Uuid::new_v4(),
         protocol_stack: vec![],
         opaque_redirect: None,
+        connect_attempts: 0,
     };
     connect_phase(details, client, src_addr, options, ui).await
 }
@@ -151,15 +188,22 @@ pub async fn run(
 ///
 /// The server may be either a hard coded one as specified by the user or one specified through a
 /// CONNECT proxy request by the client.
-pub async fn connect_phase(
+pub async fn connect_phase<TClient>(
     mut details: ConnectionDetails,
-    client: TcpStream,
+    client: TClient,
     src_addr: SocketAddr,
     options: Arc<ConnectionOptions>,
     ui: Sender<SessionEvent>,
 ) -> Result<()>
+where
+    TClient: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
     log::info!("{} - New connection from {:?}", details.uuid, src_addr);
+    let decode_client = options.client_filter.is_empty()
+        || options
+            .client_filter
+            .iter()
+            .any(|f| f.contains(src_addr.ip()));
 
     // Resolve the top-level protocol.
     let (protocol, client) =
@@ -186,10 +230,11 @@ pub async fn connect_phase(
         };
 
         details.protocol_stack.push(Protocol::Connect);
-        let connect_data = connect::handle_connect(client).await?;
+        let connect_data = connect::handle_connect(client, &options).await?;
+        details.connect_attempts = connect_data.connect_attempts;
 
         // Check what to do with the CONNECT target.
-        if connect::check_filter(connect_filter, &connect_data.target_server) {
+        if decode_client && connect::check_filter(connect_filter, &connect_data.target_server) {
             log::info!("{} - Intercepting CONNECT", details.uuid);
 
             // The connection matches filter and should be decoded.
@@ -204,10 +249,12 @@ pub async fn connect_phase(
                 })?;
             log::debug!("{} - Next protocol: {:?}", details.uuid, protocol);
 
+            let reconnect = connect::reconnect(connect_data.target_server.clone(), options.clone());
             handle_protocol(
                 details,
                 protocol,
                 Streams::new(client_stream, connect_data.server_stream),
+                Some(reconnect),
                 src_addr,
                 connect_data.target_server,
                 options,
@@ -225,7 +272,14 @@ pub async fn connect_phase(
             Ok(())
         }
     } else {
-        let target_server = match &options.target_server {
+        // Routing the connection by host requires peeking at the client data. The stream is
+        // wrapped even without routes to keep its type the same.
+        let (host, client) = match options.routes.is_empty() {
+            true => (None, stream::PrefixedStream::new(vec![], client)),
+            false => connect::peek_host(&protocol, client).await?,
+        };
+        let route = host.and_then(|host| Route::find(&options.routes, &host));
+        let target_server = match route.or(options.target_server.as_deref()) {
             Some(t) => t,
             None => {
                 return Err(EndpointError::ProxideError {
@@ -241,17 +295,24 @@ pub async fn connect_phase(
         // redirect the whole client stream there.
         details.opaque_redirect = Some(target_server.to_string());
         log::trace!("Connecting directly to {}", target_server);
-        let server = TcpStream::connect(target_server)
-            .await
-            .context(IoError {})
-            .context(ServerError {
-                scenario: "connecting",
-            })?;
+        let (server, connect_attempts) = connect::connect_server(target_server, &options).await?;
+        details.connect_attempts = connect_attempts;
 
+        if !decode_client {
+            log::info!("{} - Proxying connection without decoding", details.uuid);
+            let (server_read, server_write) = server.into_split();
+            let (client_read, client_write) = tokio::io::split(client);
+            pipe_stream(client_read, server_write);
+            pipe_stream(server_read, client_write);
+            return Ok(());
+        }
+
+        let reconnect = connect::reconnect(target_server.to_string(), options.clone());
         handle_protocol(
             details,
             protocol,
             Streams::new(client, server),
+            Some(reconnect),
             src_addr,
             target_server.to_string(),
             options,
@@ -261,10 +322,15 @@ pub async fn connect_phase(
     }
 }
 
+/// Handles the protocol of the connection.
+///
+/// The `reconnect` is used to retry the plaintext HTTP/2 handshake with the server.
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_protocol<TClient, TServer>(
     mut details: ConnectionDetails,
     protocol: demux::Protocol,
     streams: Streams<TClient, TServer>,
+    reconnect: Option<Reconnect<TServer>>,
     src_addr: SocketAddr,
     target: String,
     options: Arc<ConnectionOptions>,
@@ -277,9 +343,26 @@ where
     let ui_clone = ui.clone();
     if protocol == demux::Protocol::Tls {
         let streams = tls::handle(&mut details, streams, options.clone(), target).await?;
-        http2::handle(details, src_addr, streams, ui_clone).await?;
+
+        // The decrypted stream may carry either HTTP/2 or HTTP/1.1.
+        let (protocol, client) = demux::recognize(streams.client)
+            .await
+            .context(IoError {})
+            .context(ClientError {
+                scenario: "demuxing stream",
+            })?;
+        log::debug!("{} - TLS protocol: {:?}", details.uuid, protocol);
+        let streams = Streams::new(client, streams.server);
+        match protocol {
+            demux::Protocol::Http1 => {
+                http1::handle(details, src_addr, streams, options, ui_clone).await?
+            }
+            _ => http2::handle(details, src_addr, streams, None, options, ui_clone).await?,
+        }
+    } else if protocol == demux::Protocol::Http1 {
+        http1::handle(details, src_addr, streams, options, ui_clone).await?;
     } else {
-        http2::handle(details, src_addr, streams, ui_clone).await?;
+        http2::handle(details, src_addr, streams, reconnect, options, ui_clone).await?;
     }
 
     Ok(())
@@ -0,0 +1,85 @@
+use bytes::Bytes;
+use std::sync::mpsc::Sender;
+use uuid::Uuid;
+
+use crate::session::events::{MessageDataEvent, SessionEvent};
+use crate::session::RequestPart;
+
+/// Passes the message content to the UI, keeping at most `--max-body-bytes` of it.
+///
+/// The content beyond the limit is still forwarded to the peer but only its size is reported to
+/// the UI so large downloads don't end up buffered in memory.
+pub struct BodyRecorder
+{
+    ui: Sender<SessionEvent>,
+    uuid: Uuid,
+    pub part: RequestPart,
+
+    /// Largest number of bytes kept. `None` keeps the whole body.
+    max: Option<usize>,
+    recorded: usize,
+}
+
+impl BodyRecorder
+{
+    pub fn new(ui: &Sender<SessionEvent>, uuid: Uuid, part: RequestPart, max: Option<usize>)
+        -> Self
+    {
+        Self {
+            ui: ui.clone(),
+            uuid,
+            part,
+            max,
+            recorded: 0,
+        }
+    }
+
+    pub fn record(&mut self, data: &Bytes)
+    {
+        let keep = match self.max {
+            Some(max) => max.saturating_sub(self.recorded).min(data.len()),
+            None => data.len(),
+        };
+        self.recorded += keep;
+        self.ui
+            .send(SessionEvent::MessageData(MessageDataEvent {
+                uuid: self.uuid,
+                data: data.slice(..keep),
+                part: self.part,
+                truncated: data.len() - keep,
+            }))
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn content_beyond_limit_is_truncated()
+    {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut recorder = BodyRecorder::new(&tx, Uuid::new_v4(), RequestPart::Response, Some(5));
+        recorder.record(&Bytes::from_static(b"abc"));
+        recorder.record(&Bytes::from_static(b"defg"));
+        recorder.record(&Bytes::from_static(b"hij"));
+
+        let received: Vec<_> = rx
+            .try_iter()
+            .map(|e| match e {
+                SessionEvent::MessageData(e) => (e.data, e.truncated),
+                _ => panic!("Unexpected event"),
+            })
+            .collect();
+        assert_eq!(
+            received,
+            vec![
+                (Bytes::from_static(b"abc"), 0),
+                (Bytes::from_static(b"de"), 2),
+                (Bytes::new(), 3),
+            ]
+        );
+    }
+}
@@ -1,20 +1,27 @@
+use futures::prelude::*;
 use snafu::ResultExt;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
 
+use super::demux::Protocol;
 use super::stream::PrefixedStream;
-use super::{ClientError, ConnectError, IoError, Result, ServerError};
-use crate::ProxyFilter;
+use super::tls::{resolve_client_hello, HelloResult};
+use super::{ClientError, ConnectError, EndpointError, IoError, Reconnect, Result, ServerError};
+use crate::{ConnectionOptions, ProxyFilter, UpstreamProxy};
 
 pub struct ConnectData<TClient>
 {
     pub client_stream: PrefixedStream<TClient>,
     pub server_stream: TcpStream,
     pub target_server: String,
+    pub connect_attempts: u32,
 }
 
 pub async fn handle_connect<T: AsyncRead + AsyncWrite + Unpin>(
     mut client: T,
+    options: &ConnectionOptions,
 ) -> Result<ConnectData<T>>
 {
     let mut buffer = Vec::new();
@@ -43,12 +50,7 @@ pub async fn handle_connect<T: AsyncRead + AsyncWrite + Unpin>(
     };
 
     let host = AsRef::<str>::as_ref(&host);
-    let server = TcpStream::connect(host)
-        .await
-        .context(IoError {})
-        .context(ServerError {
-            scenario: "connecting",
-        })?;
+    let (server, connect_attempts) = connect_server(host, options).await?;
     client
         .write(b"HTTP/1.1 200 OK\r\n\r\n")
         .await
@@ -61,9 +63,249 @@ pub async fn handle_connect<T: AsyncRead + AsyncWrite + Unpin>(
         client_stream: PrefixedStream::new(remainder, client),
         server_stream: server,
         target_server: host.to_string(),
+        connect_attempts,
     })
 }
 
+/// Largest HTTP/1.1 request head read while looking for the Host header.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Find the host name the client is connecting to for routing the direct connections.
+///
+/// The host is read from the TLS SNI or the Host header of the first HTTP/1.1 request. The data
+/// read from the client is returned with the stream so it is still processed as usual.
+pub async fn peek_host<T: AsyncRead + AsyncWrite + Unpin>(
+    protocol: &Protocol,
+    mut client: T,
+) -> Result<(Option<String>, PrefixedStream<T>)>
+{
+    let (host, data) =
+        match protocol {
+            Protocol::Tls => {
+                let HelloResult { data, sni, .. } = resolve_client_hello(&mut client).await?;
+                (Some(sni), data)
+            }
+            Protocol::Http1 => {
+                let mut data = Vec::new();
+                let host =
+                    loop {
+                        let mut chunk = [0_u8; 1024];
+                        let count = client.read(&mut chunk).await.context(IoError {}).context(
+                            ClientError {
+                                scenario: "reading request",
+                            },
+                        )?;
+                        if count == 0 {
+                            break None;
+                        }
+                        data.extend(chunk[..count].iter().copied());
+
+                        // Parse errors are left for the HTTP/1.1 handling to report.
+                        let mut headers = [httparse::EMPTY_HEADER; 64];
+                        let mut req = httparse::Request::new(&mut headers);
+                        match req.parse(&data) {
+                            Ok(httparse::Status::Complete(_)) => {
+                                break req
+                                    .headers
+                                    .iter()
+                                    .find(|h| h.name.eq_ignore_ascii_case("host"))
+                                    .and_then(|h| std::str::from_utf8(h.value).ok())
+                                    .map(|host| match host.rsplit_once(':') {
+                                        Some((host, port)) if port.parse::<u16>().is_ok() => {
+                                            host.to_string()
+                                        }
+                                        _ => host.to_string(),
+                                    })
+                            }
+                            Ok(httparse::Status::Partial) if data.len() < MAX_HEAD_SIZE => {}
+                            _ => break None,
+                        }
+                    };
+                (host, data)
+            }
+
+            // HTTP/2 carries the authority in compressed headers so those connections can't be
+            // routed without decoding the connection.
+            Protocol::Http2 | Protocol::Connect => (None, vec![]),
+        };
+
+    Ok((host, PrefixedStream::new(data, client)))
+}
+
+/// Delay before the first connection retry unless the user specifies one with
+/// `--connect-backoff`.
+const DEFAULT_CONNECT_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Opens the connection to the server, retrying up to `--connect-retries` times.
+///
+/// Returns the number of attempts it took along with the stream.
+pub async fn connect_server(target: &str, options: &ConnectionOptions) -> Result<(TcpStream, u32)>
+{
+    retry(
+        target,
+        options.connect_retries,
+        options,
+        |_| {},
+        || try_connect_server(target, options),
+    )
+    .await
+}
+
+/// Runs the connection step, retrying it up to `retries` times.
+///
+/// The delay between the attempts doubles after each failed attempt. The `on_retry` is called
+/// with the number of the upcoming attempt before each retry. Returns the number of attempts it
+/// took along with the result.
+pub async fn retry<T, F, Fut>(
+    target: &str,
+    retries: u32,
+    options: &ConnectionOptions,
+    mut on_retry: impl FnMut(u32),
+    mut attempt_fn: F,
+) -> Result<(T, u32)>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = options.connect_backoff.unwrap_or(DEFAULT_CONNECT_BACKOFF);
+    let mut attempt = 1;
+    loop {
+        match attempt_fn().await {
+            Ok(result) => return Ok((result, attempt)),
+            Err(e) if attempt <= retries => {
+                log::warn!(
+                    "Connection attempt {} to {} failed, retrying in {:?}: {}",
+                    attempt,
+                    target,
+                    backoff,
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+                on_retry(attempt);
+            }
+            Err(e) => {
+                if attempt > 1 {
+                    log::error!("Could not connect to {} in {} attempts", target, attempt);
+                }
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Creates the function that opens a new connection to the server for retrying a failed
+/// handshake.
+pub fn reconnect(target: String, options: Arc<ConnectionOptions>) -> Reconnect<TcpStream>
+{
+    Box::new(move || {
+        let target = target.clone();
+        let options = options.clone();
+        async move { try_connect_server(&target, &options).await }.boxed()
+    })
+}
+
+/// Opens the connection to the server, either directly or through the upstream proxy.
+async fn try_connect_server(target: &str, options: &ConnectionOptions) -> Result<TcpStream>
+{
+    let upstream = match &options.upstream_proxy {
+        Some(upstream) => upstream,
+        None => {
+            return TcpStream::connect(target)
+                .await
+                .context(IoError {})
+                .context(ServerError {
+                    scenario: "connecting",
+                })
+        }
+    };
+
+    log::trace!("Connecting to {} through {}", target, upstream.address);
+    let mut server = TcpStream::connect(&upstream.address)
+        .await
+        .context(IoError {})
+        .context(ServerError {
+            scenario: "connecting to upstream proxy",
+        })?;
+    upstream_connect(&mut server, target, upstream).await?;
+    Ok(server)
+}
+
+async fn upstream_connect<T: AsyncRead + AsyncWrite + Unpin>(
+    server: &mut T,
+    target: &str,
+    upstream: &UpstreamProxy,
+) -> Result<()>
+{
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+    if let Some(auth) = &upstream.authorization {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", auth));
+    }
+    request.push_str("\r\n");
+    server
+        .write_all(request.as_bytes())
+        .await
+        .context(IoError {})
+        .context(ServerError {
+            scenario: "sending CONNECT to upstream proxy",
+        })?;
+
+    // The proxy won't send anything after the response before the tunnel is used so the response
+    // is the only thing in the buffer.
+    let mut buffer = Vec::new();
+    let status = loop {
+        let mut chunk = [0_u8; 256];
+        let count = server
+            .read(&mut chunk)
+            .await
+            .context(IoError {})
+            .context(ServerError {
+                scenario: "reading upstream proxy response",
+            })?;
+        if count == 0 {
+            return Err(EndpointError::ProxideError {
+                reason: "upstream proxy closed the connection",
+            })
+            .context(ServerError {
+                scenario: "reading upstream proxy response",
+            });
+        }
+        buffer.extend(chunk[..count].iter().copied());
+
+        let mut headers = [httparse::EMPTY_HEADER; 16];
+        let mut response = httparse::Response::new(&mut headers);
+        let res = response
+            .parse(&buffer)
+            .context(ConnectError {})
+            .context(ServerError {
+                scenario: "parsing upstream proxy response",
+            })?;
+        if let httparse::Status::Complete(_) = res {
+            break response.code.unwrap_or_default();
+        }
+    };
+
+    match status {
+        200..=299 => Ok(()),
+        407 => Err(EndpointError::ProxideError {
+            reason: "upstream proxy requires authentication",
+        })
+        .context(ServerError {
+            scenario: "connecting through upstream proxy",
+        }),
+        _ => {
+            log::error!("Upstream proxy rejected CONNECT to {}: {}", target, status);
+            Err(EndpointError::ProxideError {
+                reason: "upstream proxy rejected the CONNECT request",
+            })
+            .context(ServerError {
+                scenario: "connecting through upstream proxy",
+            })
+        }
+    }
+}
+
 pub fn check_filter(filter: &[ProxyFilter], target: &str) -> bool
 {
     // If there are no filters, everything ought to be accepted.
@@ -76,20 +318,99 @@ pub fn check_filter(filter: &[ProxyFilter], target: &str) -> bool
     let host = split.next().unwrap();
     let port: u16 = split.next().unwrap().parse().unwrap();
 
-    for f in filter {
-        if !f.host_filter.is_match(host) {
-            continue;
-        }
+    let is_match = |f: &&ProxyFilter| {
+        f.host_filter.is_match(host)
+            && f.port_filter
+                .as_ref()
+                .map(|range| range.contains(&port))
+                .unwrap_or(true)
+    };
 
-        if let Some(port_filter) = f.port_filter {
-            if port != port_filter.get() {
-                continue;
-            }
+    // Without any including filters everything that isn't excluded is accepted.
+    let mut includes = filter.iter().filter(|f| !f.exclude).peekable();
+    if includes.peek().is_some() && !includes.any(|f| is_match(&f)) {
+        return false;
+    }
+
+    // The excluding filters take precedence over the including ones.
+    !filter.iter().filter(|f| f.exclude).any(|f| is_match(&f))
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn check(filter: &str, target: &str) -> bool
+    {
+        check_filter(&ProxyFilter::parse(filter).unwrap(), target)
+    }
+
+    #[test]
+    fn proxy_filters()
+    {
+        assert!(check("*.internal:8000-8100", "api.internal:8000"));
+        assert!(check("*.internal:8000-8100", "api.internal:8100"));
+        assert!(!check("*.internal:8000-8100", "api.internal:8101"));
+        assert!(!check("*.internal:8000-8100", "api.external:8050"));
+
+        // Exclusions alone accept everything else.
+        assert!(!check("!metrics.svc", "metrics.svc:443"));
+        assert!(check("!metrics.svc", "api.svc:443"));
+
+        // Exclusions are evaluated after the inclusions regardless of the order.
+        for &filter in &[
+            "!metrics.internal,*.internal",
+            "*.internal,!metrics.internal",
+        ] {
+            assert!(check(filter, "api.internal:80"));
+            assert!(!check(filter, "metrics.internal:80"));
+            assert!(!check(filter, "api.external:80"));
         }
 
-        // None of the filters rejected this.
-        return true;
+        // Overlapping ranges.
+        let filter = "*:8000-8100,*:8050-8200,!*:8080-8090";
+        assert!(check(filter, "host:8000"));
+        assert!(check(filter, "host:8150"));
+        assert!(check(filter, "host:8079"));
+        assert!(!check(filter, "host:8085"));
+        assert!(!check(filter, "host:8201"));
+
+        assert!(ProxyFilter::parse("*:8100-8000").is_err());
+        assert!(ProxyFilter::parse("*:80-").is_err());
+        assert!(ProxyFilter::parse("*:80:80").is_err());
     }
 
-    false
+    #[tokio::test]
+    async fn connect_retries_with_backoff()
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let options = ConnectionOptions {
+            connect_retries: 2,
+            connect_backoff: Some(Duration::from_millis(1)),
+            ..Default::default()
+        };
+        assert!(connect_server(&target, &options).await.is_err());
+
+        // The server comes up while Proxide is waiting for the next attempt.
+        let server = {
+            let target = target.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let listener = tokio::net::TcpListener::bind(target).await.unwrap();
+                listener.accept().await.unwrap();
+            })
+        };
+        let options = ConnectionOptions {
+            connect_retries: 10,
+            connect_backoff: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let (_, attempts) = connect_server(&target, &options).await.unwrap();
+        assert!(attempts > 1);
+        server.await.unwrap();
+    }
 }
@@ -9,27 +9,206 @@ pub enum Protocol
     Http2,
     Connect,
     Tls,
+    Http1,
 }
 
+/// Connection preface of the HTTP/2 clients, which is sent as is over plaintext connections
+/// (h2c) when the client has prior knowledge of the server supporting HTTP/2.
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Start of a TLS ClientHello record. `None` matches any byte.
+const CLIENT_HELLO: [Option<u8>; 10] = [
+    // Content type: Handshake (22)
+    Some(22),
+    // TLS version: 1.x (3, _)
+    Some(3),
+    None,
+    // Length: _, _
+    None,
+    None,
+    // Handshake type: ClientHello (1)
+    Some(1),
+    // Handshake length: _, _, _
+    None,
+    None,
+    None,
+    // Protocol version: 1.x (3, _)
+    Some(3),
+];
+
+/// Longest request method recognized as HTTP/1.x. The registered methods are far shorter.
+const MAX_METHOD_LENGTH: usize = 32;
+
 pub async fn recognize(
     mut stream: impl AsyncWrite + AsyncRead + Unpin + 'static,
 ) -> Result<(Protocol, impl AsyncWrite + AsyncRead + Unpin + 'static)>
 {
-    let mut buffer = [0_u8; 10];
-    stream.read_exact(&mut buffer).await?;
-
-    let protocol = match &buffer {
-        // Content type: Handshake (22)
-        // TLS version: 1.x (3, _)
-        // Length: _, _
-        // Handshake type: ClientHello (1)
-        // Handshake length: _, _, _
-        // Protocol version: 1.x (3, _)
-        &[22, 3, _, _, _, 1, _, _, _, 3] => Protocol::Tls,
-        b"PRI * HTTP" => Protocol::Http2,
-        &[b'C', b'O', b'N', b'N', b'E', b'C', b'T', b' ', _, _] => Protocol::Connect,
+    // Read until the data is recognized. Reading stops at the first byte that doesn't match any
+    // of the protocols or at the end of the stream so short requests are not stalled here.
+    let mut buffer = Vec::with_capacity(H2_PREFACE.len());
+    while is_partial_client_hello(&buffer)
+        || is_partial_h2_preface(&buffer)
+        || is_partial_http1_request(&buffer)
+    {
+        if stream.read_buf(&mut buffer).await? == 0 {
+            break;
+        }
+    }
+
+    let protocol = match &buffer[..] {
+        [] => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+        b if is_client_hello(b) => Protocol::Tls,
+
+        // The start of the preface looks like an HTTP/1.x request line with a PRI method so the
+        // whole preface is needed to tell them apart.
+        b if b.starts_with(H2_PREFACE) => Protocol::Http2,
+        b if b.starts_with(b"CONNECT ") => Protocol::Connect,
+        b if is_http1_request(b) => Protocol::Http1,
         _ => return Err(std::io::ErrorKind::InvalidData.into()),
     };
 
-    Ok((protocol, PrefixedStream::new(buffer.to_vec(), stream)))
+    Ok((protocol, PrefixedStream::new(buffer, stream)))
+}
+
+fn is_client_hello(buffer: &[u8]) -> bool
+{
+    buffer.len() >= CLIENT_HELLO.len() && matches_client_hello(buffer)
+}
+
+fn is_partial_client_hello(buffer: &[u8]) -> bool
+{
+    buffer.len() < CLIENT_HELLO.len() && matches_client_hello(buffer)
+}
+
+fn matches_client_hello(buffer: &[u8]) -> bool
+{
+    buffer
+        .iter()
+        .zip(CLIENT_HELLO)
+        .all(|(b, expected)| expected.unwrap_or(*b) == *b)
+}
+
+fn is_partial_h2_preface(buffer: &[u8]) -> bool
+{
+    buffer.len() < H2_PREFACE.len() && H2_PREFACE.starts_with(buffer)
+}
+
+/// Check whether the buffer starts with an HTTP/1.x request line: a token method followed by a
+/// space, such as "GET /" or "PROPPATCH /".
+fn is_http1_request(buffer: &[u8]) -> bool
+{
+    match buffer
+        .iter()
+        .take(MAX_METHOD_LENGTH + 1)
+        .position(|b| *b == b' ')
+    {
+        Some(0) | None => false,
+        Some(end) => buffer[..end].iter().all(|b| b.is_ascii_uppercase()),
+    }
+}
+
+/// Check whether the buffer could still become an HTTP/1.x request line once more data arrives.
+fn is_partial_http1_request(buffer: &[u8]) -> bool
+{
+    buffer.len() <= MAX_METHOD_LENGTH && buffer.iter().all(|b| b.is_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+
+    /// Recognize the protocol of the data, checking that the data is still readable afterwards.
+    async fn recognize_data(data: &[u8]) -> Result<Protocol>
+    {
+        let (mut client, proxy) = tokio::io::duplex(1024);
+        client.write_all(data).await?;
+        let (protocol, mut stream) =
+            tokio::time::timeout(Duration::from_secs(10), recognize(proxy))
+                .await
+                .expect("Recognizing the protocol stalled")?;
+
+        let mut read = vec![0_u8; data.len()];
+        stream.read_exact(&mut read).await?;
+        assert_eq!(read, data);
+        Ok(protocol)
+    }
+
+    #[tokio::test]
+    async fn recognize_protocols()
+    {
+        // The preface followed by an empty SETTINGS frame.
+        let mut h2c = H2_PREFACE.to_vec();
+        h2c.extend_from_slice(&[0, 0, 0, 4, 0, 0, 0, 0, 0]);
+        assert_eq!(recognize_data(&h2c).await.unwrap(), Protocol::Http2);
+
+        assert_eq!(
+            recognize_data(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")
+                .await
+                .unwrap(),
+            Protocol::Http1
+        );
+        assert_eq!(
+            recognize_data(b"PROPPATCH /file HTTP/1.1\r\n\r\n")
+                .await
+                .unwrap(),
+            Protocol::Http1
+        );
+        assert_eq!(
+            recognize_data(b"MKCALENDAR /calendars/user/ HTTP/1.1\r\n\r\n")
+                .await
+                .unwrap(),
+            Protocol::Http1
+        );
+        assert_eq!(
+            recognize_data(b"PRI * HTTP/1.1\r\nHost: example.com\r\n\r\n")
+                .await
+                .unwrap(),
+            Protocol::Http1
+        );
+        assert_eq!(
+            recognize_data(b"CONNECT example.com:443 HTTP/1.1\r\n\r\n")
+                .await
+                .unwrap(),
+            Protocol::Connect
+        );
+        assert_eq!(
+            recognize_data(&[22, 3, 1, 0, 200, 1, 0, 0, 196, 3, 3])
+                .await
+                .unwrap(),
+            Protocol::Tls
+        );
+        assert!(recognize_data(b"\x00\x01garbage data").await.is_err());
+
+        // The client is waiting for a response so there is no more data to wait for.
+        assert_eq!(
+            recognize_data(b"GET /\r\n\r\n").await.unwrap(),
+            Protocol::Http1
+        );
+        assert_eq!(
+            recognize_data(b"PRI / HTTP/1.1\r\n").await.unwrap(),
+            Protocol::Http1
+        );
+        assert!(recognize_data(&[b'A'; 64]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn recognize_at_end_of_stream()
+    {
+        // A truncated preface is still a valid HTTP/1.x request line.
+        let (mut client, proxy) = tokio::io::duplex(1024);
+        client.write_all(&H2_PREFACE[..16]).await.unwrap();
+        drop(client);
+        let (protocol, _) = recognize(proxy).await.unwrap();
+        assert_eq!(protocol, Protocol::Http1);
+
+        let (client, proxy) = tokio::io::duplex(1024);
+        drop(client);
+        assert_eq!(
+            recognize(proxy).await.err().unwrap().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
 }
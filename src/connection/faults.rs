@@ -0,0 +1,104 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::ConnectionOptions;
+
+/// Faults configured with the `--inject-*` options.
+#[derive(Default)]
+pub struct FaultOptions
+{
+    /// Delay added before forwarding the response.
+    pub latency: Option<Duration>,
+
+    /// Fraction of requests answered with `status` instead of forwarding them to the server.
+    pub error_rate: f64,
+    pub status: u16,
+
+    pub seed: u64,
+
+    /// Number of requests the faults have been decided for.
+    pub counter: AtomicU64,
+}
+
+/// The faults injected into a single request.
+#[derive(Default, Clone, Copy)]
+pub struct Fault
+{
+    pub latency: Option<Duration>,
+    pub status: Option<u16>,
+}
+
+impl Fault
+{
+    pub fn decide(options: &ConnectionOptions) -> Fault
+    {
+        let faults = match &options.faults {
+            Some(f) => f,
+            None => return Fault::default(),
+        };
+
+        // The decision depends only on the seed and the request order so the same sequence of
+        // requests gets the same faults with the same seed.
+        let n = faults.counter.fetch_add(1, Ordering::Relaxed);
+        let roll = (splitmix64(faults.seed ^ splitmix64(n)) >> 11) as f64 / (1u64 << 53) as f64;
+        Fault {
+            latency: faults.latency,
+            status: match roll < faults.error_rate {
+                true => Some(faults.status),
+                false => None,
+            },
+        }
+    }
+
+    pub fn is_injected(&self) -> bool
+    {
+        self.latency.is_some() || self.status.is_some()
+    }
+
+    /// Wait for the injected latency.
+    pub async fn delay(&self)
+    {
+        if let Some(latency) = self.latency {
+            tokio::time::sleep(latency).await;
+        }
+    }
+}
+
+fn splitmix64(x: u64) -> u64
+{
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn statuses(seed: u64) -> Vec<Option<u16>>
+    {
+        let options = ConnectionOptions {
+            faults: Some(FaultOptions {
+                error_rate: 0.5,
+                status: 503,
+                seed,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        (0..64).map(|_| Fault::decide(&options).status).collect()
+    }
+
+    #[test]
+    fn faults_are_deterministic_with_seed()
+    {
+        let first = statuses(42);
+        assert_eq!(first, statuses(42));
+        assert_ne!(first, statuses(43));
+
+        let errors = first.iter().filter(|s| s.is_some()).count();
+        assert!(errors > 16 && errors < 48, "{} errors", errors);
+    }
+}
@@ -0,0 +1,88 @@
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// Header changes configured with `--set-*-header` and `--remove-*-header`.
+///
+/// The removals are applied before the values are set so a header can be replaced by removing
+/// all of its values and setting a new one.
+#[derive(Default)]
+pub struct HeaderRules
+{
+    pub remove: Vec<HeaderName>,
+
+    /// Headers that replace the existing values of the same name.
+    pub set: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl HeaderRules
+{
+    /// Parse the rules from `NAME=VALUE` pairs to set and names to remove.
+    pub fn parse<'a>(
+        set: impl IntoIterator<Item = &'a str>,
+        remove: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, String>
+    {
+        let name = |name: &str| {
+            HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| format!("Invalid header name '{}'", name))
+        };
+        let set = set
+            .into_iter()
+            .map(|rule| {
+                let (n, value) = rule
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid header '{}', expected NAME=VALUE", rule))?;
+                let value = HeaderValue::from_str(value)
+                    .map_err(|_| format!("Invalid value for header '{}'", n))?;
+                Ok((name(n)?, value))
+            })
+            .collect::<Result<_, String>>()?;
+        let remove = remove.into_iter().map(name).collect::<Result<_, _>>()?;
+        Ok(Self { remove, set })
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap)
+    {
+        for name in &self.remove {
+            headers.remove(name);
+        }
+        for (name, value) in &self.set {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn remove_before_set()
+    {
+        let rules = HeaderRules::parse(
+            vec!["Authorization=Bearer token", "x-debug=1=2"],
+            vec!["authorization", "cookie"],
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Basic abc"));
+        headers.append("cookie", HeaderValue::from_static("a=1"));
+        headers.append("cookie", HeaderValue::from_static("b=2"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        rules.apply(&mut headers);
+
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers["authorization"], "Bearer token");
+        assert_eq!(headers["x-debug"], "1=2");
+        assert_eq!(headers["accept"], "*/*");
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected()
+    {
+        assert!(HeaderRules::parse(vec!["missing-value"], vec![]).is_err());
+        assert!(HeaderRules::parse(vec!["bad name=1"], vec![]).is_err());
+        assert!(HeaderRules::parse(vec![], vec!["bad name"]).is_err());
+    }
+}
@@ -0,0 +1,1343 @@
+use bytes::BytesMut;
+use http::{HeaderMap, HeaderValue, Method, Uri};
+use log::error;
+use snafu::ResultExt;
+use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use uuid::Uuid;
+
+use super::websocket;
+use super::*;
+
+/// Maximum size of the request or response head.
+///
+/// This protects against clients or servers that never terminate the headers.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Maximum number of headers in a single request or response.
+const MAX_HEADERS: usize = 128;
+
+pub async fn handle<TClient, TServer>(
+    mut details: ConnectionDetails,
+    client_addr: SocketAddr,
+    streams: Streams<TClient, TServer>,
+    options: Arc<ConnectionOptions>,
+    ui: Sender<SessionEvent>,
+) -> Result<()>
+where
+    TClient: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    TServer: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let Streams { client, server } = streams;
+    details.protocol_stack.push(Protocol::Http1);
+    let scheme = match details
+        .protocol_stack
+        .iter()
+        .any(|p| matches!(p, Protocol::Tls))
+    {
+        true => "https",
+        false => "http",
+    };
+
+    ui.send(SessionEvent::NewConnection(NewConnectionEvent {
+        uuid: details.uuid,
+        protocol_stack: details.protocol_stack,
+        client_addr,
+        timestamp: SystemTime::now(),
+        connect_attempts: details.connect_attempts,
+    }))
+    .unwrap();
+
+    let throttles = Arc::new(Throttles::new(&options));
+    let (client_read, client_write) = tokio::io::split(client);
+    let (server_read, server_write) = tokio::io::split(server);
+    let mut connection = Http1Connection {
+        uuid: details.uuid,
+        scheme,
+
+        // The user may override the authority explicitly. Otherwise we'll rewrite it only if the
+        // connection was redirected to a server the client didn't know about.
+        authority: options
+            .target_header_host
+            .clone()
+            .or(details.opaque_redirect),
+        client_read: MessageReader::new(client_read),
+        client_write,
+        server_read: MessageReader::new(server_read),
+        server_write,
+        throttles: throttles.clone(),
+        options,
+        ui: ui.clone(),
+    };
+
+    // HTTP/1.1 connections process one request at a time. Keep going until either side closes
+    // the connection.
+    let r = async {
+        while connection.handle_request().await? {}
+        Ok(())
+    };
+    let r = throttles.report(details.uuid, &ui, r).await;
+
+    ui.send(SessionEvent::ConnectionDone(ConnectionDoneEvent {
+        uuid: details.uuid,
+        status: match r {
+            Ok(_) => Status::Succeeded,
+            Err(_) => Status::Failed,
+        },
+        timestamp: SystemTime::now(),
+        reason: None,
+    }))
+    .unwrap();
+    r
+}
+
+struct Http1Connection<TClientRead, TClientWrite, TServerRead, TServerWrite>
+{
+    uuid: Uuid,
+    scheme: &'static str,
+    authority: Option<String>,
+    client_read: MessageReader<TClientRead>,
+    client_write: TClientWrite,
+    server_read: MessageReader<TServerRead>,
+    server_write: TServerWrite,
+    options: Arc<ConnectionOptions>,
+    throttles: Arc<Throttles>,
+    ui: Sender<SessionEvent>,
+}
+
+/// How the end of the message body is determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyFraming
+{
+    None,
+    ContentLength(u64),
+    Chunked,
+
+    /// The body continues until the sender closes the connection.
+    UntilClose,
+}
+
+impl<TClientRead, TClientWrite, TServerRead, TServerWrite>
+    Http1Connection<TClientRead, TClientWrite, TServerRead, TServerWrite>
+where
+    TClientRead: AsyncRead + Unpin + Send + 'static,
+    TClientWrite: AsyncWrite + Unpin + Send + 'static,
+    TServerRead: AsyncRead + Unpin + Send + 'static,
+    TServerWrite: AsyncWrite + Unpin + Send + 'static,
+{
+    /// Proxy a single request. Returns `false` once the connection should be closed.
+    async fn handle_request(&mut self) -> Result<bool>
+    {
+        let head = match self
+            .client_read
+            .read_head()
+            .await
+            .context(IoError {})
+            .context(ClientError {
+                scenario: "reading request",
+            })? {
+            Some(head) => head,
+            None => return Ok(false),
+        };
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+        request
+            .parse(&head)
+            .context(ConnectError {})
+            .context(ClientError {
+                scenario: "parsing request",
+            })?;
+
+        let method = Method::from_bytes(request.method.unwrap_or("GET").as_bytes())
+            .map_err(|_| EndpointError::ProxideError {
+                reason: "invalid request method",
+            })
+            .context(ClientError {
+                scenario: "parsing request",
+            })?;
+        let path = request.path.unwrap_or("/").to_string();
+        let version = request.version.unwrap_or(1);
+        let mut request_headers = to_header_map(request.headers);
+
+        // Rewrite the Host header if the connection was redirected.
+        if let Some(authority) = &self.authority {
+            if let Ok(value) = HeaderValue::from_str(authority) {
+                request_headers.insert(http::header::HOST, value);
+            }
+        }
+        self.options.request_headers.apply(&mut request_headers);
+
+        let uri = self.resolve_uri(&path, &request_headers)?;
+        let uuid = Uuid::new_v4();
+        let fault = Fault::decide(&self.options);
+        log::debug!(
+            "{}:{} - HTTP/1.1 request {} {}",
+            self.uuid,
+            uuid,
+            method,
+            uri
+        );
+
+        self.ui
+            .send(SessionEvent::NewRequest(NewRequestEvent {
+                connection_uuid: self.uuid,
+                uuid,
+                uri,
+                method: method.clone(),
+                headers: request_headers.clone(),
+                timestamp: SystemTime::now(),
+                injected_fault: fault.is_injected(),
+            }))
+            .unwrap();
+
+        let request_framing = request_framing(&request_headers);
+        let websocket_requested = is_websocket(&request_headers);
+        let mut keep_alive = is_keep_alive(version, &request_headers);
+
+        if let Some(status) = fault.status {
+            let r = self
+                .inject_error(uuid, request_framing, version, status, fault)
+                .await;
+            let status = match &r {
+                Ok(_) => Status::Succeeded,
+                Err(_) => Status::Failed,
+            };
+            notify_request_done(&self.ui, uuid, status);
+            r?;
+            return Ok(keep_alive);
+        }
+
+        // Forward the request head and body to the server.
+        let request_line = format!("{} {} HTTP/1.{}\r\n", method, path, version);
+        let r = async {
+            write_head(
+                &mut self.server_write,
+                &request_line,
+                &request_headers,
+                RequestPart::Request,
+            )
+            .await?;
+            pipe_body(
+                &mut self.client_read,
+                &mut self.server_write,
+                &self.throttles.request,
+                request_framing,
+                &mut BodyRecorder::new(
+                    &self.ui,
+                    uuid,
+                    RequestPart::Request,
+                    self.options.max_body_bytes,
+                ),
+            )
+            .await
+        }
+        .await;
+
+        // WebSocket frames sent by the client are part of the request message so the request
+        // remains in progress until we know whether the server accepted the upgrade.
+        if r.is_err() || !websocket_requested {
+            notify_message_done(&self.ui, uuid, &r, RequestPart::Request);
+        }
+        if let Err(e) = r {
+            notify_request_done(&self.ui, uuid, Status::Failed);
+            return Err(e);
+        }
+
+        let r = self
+            .handle_response(uuid, &method, version, websocket_requested, fault)
+            .await;
+        let status = match &r {
+            Ok(_) => Status::Succeeded,
+            Err(_) => Status::Failed,
+        };
+        notify_request_done(&self.ui, uuid, status);
+
+        keep_alive &= r?;
+        Ok(keep_alive)
+    }
+
+    /// Proxy the response. Returns `false` if the response requires closing the connection.
+    async fn handle_response(
+        &mut self,
+        uuid: Uuid,
+        method: &Method,
+        version: u8,
+        websocket_requested: bool,
+        fault: Fault,
+    ) -> Result<bool>
+    {
+        // Informational responses precede the final response. These are forwarded as is.
+        let (status_line, status, response_version, response_headers) = loop {
+            let head = self
+                .server_read
+                .read_head()
+                .await
+                .context(IoError {})
+                .context(ServerError {
+                    scenario: "reading response",
+                })?
+                .ok_or(EndpointError::ProxideError {
+                    reason: "connection closed before response",
+                })
+                .context(ServerError {
+                    scenario: "reading response",
+                })?;
+
+            let (status_line, status, response_version, response_headers) =
+                parse_response_head(&head, version)?;
+
+            // 101 Switching Protocols is informational as well but it ends the HTTP traffic.
+            if (100..200).contains(&status) && status != 101 {
+                write_head(
+                    &mut self.client_write,
+                    &status_line,
+                    &response_headers,
+                    RequestPart::Response,
+                )
+                .await?;
+                continue;
+            }
+
+            break (status_line, status, response_version, response_headers);
+        };
+        let mut response_headers = response_headers;
+        self.options.response_headers.apply(&mut response_headers);
+        fault.delay().await;
+
+        self.ui
+            .send(SessionEvent::NewResponse(NewResponseEvent {
+                uuid,
+                connection_uuid: self.uuid,
+                headers: response_headers.clone(),
+                timestamp: SystemTime::now(),
+                status: Some(status),
+            }))
+            .unwrap();
+
+        let websocket = websocket_requested && status == 101 && is_websocket(&response_headers);
+        if websocket_requested && !websocket {
+            notify_message_done(&self.ui, uuid, &Ok(None), RequestPart::Request);
+        }
+
+        let framing = response_framing(method, status, &response_headers);
+        let r = async {
+            write_head(
+                &mut self.client_write,
+                &status_line,
+                &response_headers,
+                RequestPart::Response,
+            )
+            .await?;
+            if status == 101 {
+                return Ok(None);
+            }
+
+            pipe_body(
+                &mut self.server_read,
+                &mut self.client_write,
+                &self.throttles.response,
+                framing,
+                &mut BodyRecorder::new(
+                    &self.ui,
+                    uuid,
+                    RequestPart::Response,
+                    self.options.max_body_bytes,
+                ),
+            )
+            .await
+        }
+        .await;
+        if r.is_err() || !websocket {
+            notify_message_done(&self.ui, uuid, &r, RequestPart::Response);
+        }
+        r?;
+
+        if websocket {
+            log::info!("{}:{} - Switching to WebSocket", self.uuid, uuid);
+            let r = websocket::pipe(
+                &mut self.client_read,
+                &mut self.client_write,
+                &mut self.server_read,
+                &mut self.server_write,
+                &self.throttles,
+                &mut BodyRecorder::new(
+                    &self.ui,
+                    uuid,
+                    RequestPart::Request,
+                    self.options.max_body_bytes,
+                ),
+                &mut BodyRecorder::new(
+                    &self.ui,
+                    uuid,
+                    RequestPart::Response,
+                    self.options.max_body_bytes,
+                ),
+            )
+            .await
+            .map(|_| None);
+            notify_message_done(&self.ui, uuid, &r, RequestPart::Request);
+            notify_message_done(&self.ui, uuid, &r, RequestPart::Response);
+            r?;
+            return Ok(false);
+        }
+
+        if status == 101 {
+            log::info!(
+                "{}:{} - Switching protocols; piping streams",
+                self.uuid,
+                uuid
+            );
+            self.pipe_upgraded().await?;
+            return Ok(false);
+        }
+
+        Ok(
+            framing != BodyFraming::UntilClose
+                && is_keep_alive(response_version, &response_headers),
+        )
+    }
+
+    /// Answer the request with the injected error status without forwarding it to the server.
+    async fn inject_error(
+        &mut self,
+        uuid: Uuid,
+        request_framing: BodyFraming,
+        version: u8,
+        status: u16,
+        fault: Fault,
+    ) -> Result<()>
+    {
+        let r = pipe_body(
+            &mut self.client_read,
+            &mut tokio::io::sink(),
+            &self.throttles.request,
+            request_framing,
+            &mut BodyRecorder::new(
+                &self.ui,
+                uuid,
+                RequestPart::Request,
+                self.options.max_body_bytes,
+            ),
+        )
+        .await;
+        notify_message_done(&self.ui, uuid, &r, RequestPart::Request);
+        r?;
+
+        fault.delay().await;
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::CONTENT_LENGTH, HeaderValue::from(0));
+        self.ui
+            .send(SessionEvent::NewResponse(NewResponseEvent {
+                uuid,
+                connection_uuid: self.uuid,
+                headers: headers.clone(),
+                timestamp: SystemTime::now(),
+                status: Some(status),
+            }))
+            .unwrap();
+
+        let reason = http::StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("Injected Error");
+        let status_line = format!("HTTP/1.{} {} {}\r\n", version, status, reason);
+        let r = write_head(
+            &mut self.client_write,
+            &status_line,
+            &headers,
+            RequestPart::Response,
+        )
+        .await
+        .map(|_| None);
+        notify_message_done(&self.ui, uuid, &r, RequestPart::Response);
+        r.map(|_| ())
+    }
+
+    /// Pipe the raw streams together after a protocol upgrade.
+    async fn pipe_upgraded(&mut self) -> Result<()>
+    {
+        let Self {
+            client_read,
+            client_write,
+            server_read,
+            server_write,
+            ..
+        } = self;
+        let client_leftover = client_read.buffer.split();
+        let server_leftover = server_read.buffer.split();
+
+        let upstream = async {
+            server_write.write_all(&client_leftover).await?;
+            tokio::io::copy(&mut client_read.read, server_write).await?;
+            server_write.shutdown().await
+        };
+        let downstream = async {
+            client_write.write_all(&server_leftover).await?;
+            tokio::io::copy(&mut server_read.read, client_write).await?;
+            client_write.shutdown().await
+        };
+
+        futures::try_join!(upstream, downstream)
+            .context(IoError {})
+            .context(ServerError {
+                scenario: "piping upgraded connection",
+            })?;
+        Ok(())
+    }
+
+    fn resolve_uri(&self, path: &str, headers: &HeaderMap) -> Result<Uri>
+    {
+        // Requests sent to a proxy use the absolute form that already includes the authority.
+        let uri = match path.starts_with('/') {
+            false => path.to_string(),
+            true => match headers
+                .get(http::header::HOST)
+                .and_then(|h| h.to_str().ok())
+            {
+                Some(host) => format!("{}://{}{}", self.scheme, host, path),
+                None => path.to_string(),
+            },
+        };
+
+        uri.parse::<Uri>()
+            .context(UriError {})
+            .context(ConfigurationError {
+                reason: "invalid request URI",
+            })
+    }
+}
+
+/// Send a previously captured request to the server.
+pub(super) async fn replay<TServer>(
+    server: TServer,
+    connection_uuid: Uuid,
+    request: ReplayRequest,
+    ui: &Sender<SessionEvent>,
+) -> Result<()>
+where
+    TServer: AsyncRead + AsyncWrite + Unpin,
+{
+    let ReplayRequest {
+        method,
+        uri,
+        mut headers,
+        body,
+        ..
+    } = request;
+    let (server_read, mut server_write) = tokio::io::split(server);
+    let mut server_read = MessageReader::new(server_read);
+
+    // The whole body is known up front so it's sent with a content-length instead of the
+    // original framing.
+    headers.remove(http::header::TRANSFER_ENCODING);
+    if !body.is_empty() || headers.contains_key(http::header::CONTENT_LENGTH) {
+        headers.insert(http::header::CONTENT_LENGTH, body.len().into());
+    }
+    if !headers.contains_key(http::header::HOST) {
+        if let Some(authority) = uri.authority() {
+            if let Ok(host) = HeaderValue::from_str(authority.as_str()) {
+                headers.insert(http::header::HOST, host);
+            }
+        }
+    }
+
+    let uuid = Uuid::new_v4();
+    ui.send(SessionEvent::NewRequest(NewRequestEvent {
+        connection_uuid,
+        uuid,
+        uri: uri.clone(),
+        method: method.clone(),
+        headers: headers.clone(),
+        timestamp: SystemTime::now(),
+        injected_fault: false,
+    }))
+    .unwrap();
+
+    let r = async {
+        let request_line = format!(
+            "{} {} HTTP/1.1\r\n",
+            method,
+            uri.path_and_query().map(|p| p.as_str()).unwrap_or("/")
+        );
+        let request_result = async {
+            write_head(
+                &mut server_write,
+                &request_line,
+                &headers,
+                RequestPart::Request,
+            )
+            .await?;
+            if !body.is_empty() {
+                server_write
+                    .write_all(&body)
+                    .await
+                    .map_err(|e| target_error(RequestPart::Request, "writing content", e))?;
+                server_write
+                    .flush()
+                    .await
+                    .map_err(|e| target_error(RequestPart::Request, "writing content", e))?;
+                ui.send(SessionEvent::MessageData(MessageDataEvent {
+                    uuid,
+                    data: body.clone(),
+                    part: RequestPart::Request,
+                    truncated: 0,
+                }))
+                .unwrap();
+            }
+            Ok(None)
+        }
+        .await;
+        notify_message_done(ui, uuid, &request_result, RequestPart::Request);
+        request_result?;
+
+        let response_result = async {
+            // Informational responses are skipped as there is no client to forward them to.
+            let (status, response_headers) = loop {
+                let head = server_read
+                    .read_head()
+                    .await
+                    .context(IoError {})
+                    .context(ServerError {
+                        scenario: "reading response",
+                    })?
+                    .ok_or(EndpointError::ProxideError {
+                        reason: "connection closed before response",
+                    })
+                    .context(ServerError {
+                        scenario: "reading response",
+                    })?;
+                let (_, status, _, response_headers) = parse_response_head(&head, 1)?;
+                if !(100..200).contains(&status) || status == 101 {
+                    break (status, response_headers);
+                }
+            };
+
+            ui.send(SessionEvent::NewResponse(NewResponseEvent {
+                uuid,
+                connection_uuid,
+                headers: response_headers.clone(),
+                timestamp: SystemTime::now(),
+                status: Some(status),
+            }))
+            .unwrap();
+
+            if status == 101 {
+                return Ok(None);
+            }
+            let framing = response_framing(&method, status, &response_headers);
+            pipe_body(
+                &mut server_read,
+                &mut tokio::io::sink(),
+                &Throttle::unlimited(),
+                framing,
+                &mut BodyRecorder::new(ui, uuid, RequestPart::Response, None),
+            )
+            .await
+        }
+        .await;
+        notify_message_done(ui, uuid, &response_result, RequestPart::Response);
+        response_result.map(|_| ())
+    }
+    .await;
+
+    notify_request_done(
+        ui,
+        uuid,
+        match r {
+            Ok(_) => Status::Succeeded,
+            Err(_) => Status::Failed,
+        },
+    );
+    r
+}
+
+/// Parse the response head into the status line, status code, HTTP version and headers.
+fn parse_response_head(head: &[u8], default_version: u8) -> Result<(String, u16, u8, HeaderMap)>
+{
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut headers);
+    response
+        .parse(head)
+        .context(ConnectError {})
+        .context(ServerError {
+            scenario: "parsing response",
+        })?;
+
+    let status = response.code.unwrap_or(200);
+    let version = response.version.unwrap_or(default_version);
+    let status_line = format!(
+        "HTTP/1.{} {} {}\r\n",
+        version,
+        status,
+        response.reason.unwrap_or("")
+    );
+    Ok((
+        status_line,
+        status,
+        version,
+        to_header_map(response.headers),
+    ))
+}
+
+fn request_framing(headers: &HeaderMap) -> BodyFraming
+{
+    if is_chunked(headers) {
+        return BodyFraming::Chunked;
+    }
+
+    match content_length(headers) {
+        Some(0) | None => BodyFraming::None,
+        Some(len) => BodyFraming::ContentLength(len),
+    }
+}
+
+fn response_framing(method: &Method, status: u16, headers: &HeaderMap) -> BodyFraming
+{
+    if method == Method::HEAD || status == 204 || status == 304 || (100..200).contains(&status) {
+        return BodyFraming::None;
+    }
+
+    if is_chunked(headers) {
+        return BodyFraming::Chunked;
+    }
+
+    match content_length(headers) {
+        Some(0) => BodyFraming::None,
+        Some(len) => BodyFraming::ContentLength(len),
+        None => BodyFraming::UntilClose,
+    }
+}
+
+fn is_chunked(headers: &HeaderMap) -> bool
+{
+    headers
+        .get_all(http::header::TRANSFER_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case("chunked"))
+}
+
+fn is_websocket(headers: &HeaderMap) -> bool
+{
+    headers
+        .get(http::header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().eq_ignore_ascii_case("websocket"))
+        .unwrap_or(false)
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64>
+{
+    headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+fn is_keep_alive(version: u8, headers: &HeaderMap) -> bool
+{
+    let connection = headers
+        .get_all(http::header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+
+    // HTTP/1.1 defaults to persistent connections while HTTP/1.0 requires an explicit keep-alive.
+    match version {
+        0 => connection.iter().any(|v| v == "keep-alive"),
+        _ => !connection.iter().any(|v| v == "close"),
+    }
+}
+
+fn to_header_map(headers: &[httparse::Header]) -> HeaderMap
+{
+    let mut map = HeaderMap::new();
+    for h in headers {
+        match (
+            http::header::HeaderName::from_bytes(h.name.as_bytes()),
+            HeaderValue::from_bytes(h.value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                map.append(name, value);
+            }
+            _ => log::warn!("Skipping invalid header '{}'", h.name),
+        }
+    }
+    map
+}
+
+async fn write_head<TWrite>(
+    target: &mut TWrite,
+    start_line: &str,
+    headers: &HeaderMap,
+    part: RequestPart,
+) -> Result<()>
+where
+    TWrite: AsyncWrite + Unpin,
+{
+    let mut head = Vec::from(start_line.as_bytes());
+    for (k, v) in headers {
+        head.extend_from_slice(k.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(v.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+
+    target
+        .write_all(&head)
+        .await
+        .map_err(|e| target_error(part, "writing head", e))?;
+    target
+        .flush()
+        .await
+        .map_err(|e| target_error(part, "writing head", e))
+}
+
+/// Pipe the message body from the source to the target.
+///
+/// The body is forwarded as is, including the chunked encoding, while the UI is notified with
+/// the decoded content.
+async fn pipe_body<TRead, TWrite>(
+    source: &mut MessageReader<TRead>,
+    target: &mut TWrite,
+    throttle: &Throttle,
+    framing: BodyFraming,
+    body: &mut BodyRecorder,
+) -> Result<Option<HeaderMap>>
+where
+    TRead: AsyncRead + Unpin,
+    TWrite: AsyncWrite + Unpin,
+{
+    let part = body.part;
+    let read_err = |e| source_error(part, "reading content", e);
+    let write_err = |e| target_error(part, "writing content", e);
+
+    match framing {
+        BodyFraming::None => {}
+        BodyFraming::ContentLength(len) => {
+            pipe_bytes(source, target, throttle, Some(len), body).await?;
+        }
+        BodyFraming::UntilClose => {
+            pipe_bytes(source, target, throttle, None, body).await?;
+        }
+        BodyFraming::Chunked => loop {
+            let size_line = source.read_line().await.map_err(read_err)?;
+            target.write_all(&size_line).await.map_err(write_err)?;
+
+            // Chunk extensions follow the size after a ';'.
+            let size = std::str::from_utf8(&size_line)
+                .ok()
+                .and_then(|line| line.split(';').next())
+                .and_then(|size| u64::from_str_radix(size.trim(), 16).ok())
+                .ok_or_else(|| read_err(std::io::ErrorKind::InvalidData.into()))?;
+
+            if size == 0 {
+                // The last chunk is followed by optional trailers and an empty line.
+                let mut trailer_data = BytesMut::new();
+                loop {
+                    let line = source.read_line().await.map_err(read_err)?;
+                    target.write_all(&line).await.map_err(write_err)?;
+                    trailer_data.extend_from_slice(&line);
+                    if &line[..] == b"\r\n" {
+                        break;
+                    }
+                }
+                target.flush().await.map_err(write_err)?;
+                return Ok(parse_trailers(&trailer_data));
+            }
+
+            pipe_bytes(source, target, throttle, Some(size), body).await?;
+            let line_end = source.read_line().await.map_err(read_err)?;
+            target.write_all(&line_end).await.map_err(write_err)?;
+        },
+    }
+
+    target.flush().await.map_err(write_err)?;
+    Ok(None)
+}
+
+/// Parse the trailer section of a chunked body, including the terminating empty line.
+///
+/// Invalid trailers are skipped since they have already been forwarded as they were.
+fn parse_trailers(data: &[u8]) -> Option<HeaderMap>
+{
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    match httparse::parse_headers(data, &mut headers) {
+        Ok(httparse::Status::Complete((_, headers))) if !headers.is_empty() => {
+            Some(to_header_map(headers))
+        }
+        Ok(_) => None,
+        Err(e) => {
+            log::warn!("Skipping invalid trailers: {}", e);
+            None
+        }
+    }
+}
+
+/// Pipe raw bytes from the source to the target.
+///
+/// If the length is not given, the bytes are piped until the source is closed.
+pub(super) async fn pipe_bytes<TRead, TWrite>(
+    source: &mut MessageReader<TRead>,
+    target: &mut TWrite,
+    throttle: &Throttle,
+    len: Option<u64>,
+    body: &mut BodyRecorder,
+) -> Result<()>
+where
+    TRead: AsyncRead + Unpin,
+    TWrite: AsyncWrite + Unpin,
+{
+    let part = body.part;
+    let mut remaining = len.unwrap_or(u64::MAX);
+    while remaining > 0 {
+        let data = source
+            .read_some(remaining.min(usize::MAX as u64) as usize)
+            .await
+            .map_err(|e| source_error(part, "reading content", e))?;
+        if data.is_empty() {
+            return match len {
+                None => Ok(()),
+                Some(_) => Err(source_error(
+                    part,
+                    "reading content",
+                    std::io::ErrorKind::UnexpectedEof.into(),
+                )),
+            };
+        }
+        remaining -= data.len() as u64;
+
+        throttle.consume(data.len()).await;
+        target
+            .write_all(&data)
+            .await
+            .map_err(|e| target_error(part, "writing content", e))?;
+
+        body.record(&data.freeze());
+    }
+
+    Ok(())
+}
+
+/// Error from the endpoint that is sending the message part.
+pub(super) fn source_error(
+    part: RequestPart,
+    scenario: &'static str,
+    source: std::io::Error,
+) -> Error
+{
+    let source = EndpointError::IoError { source };
+    match part {
+        RequestPart::Request => Error::ClientError { scenario, source },
+        RequestPart::Response => Error::ServerError { scenario, source },
+    }
+}
+
+/// Error from the endpoint that is receiving the message part.
+pub(super) fn target_error(
+    part: RequestPart,
+    scenario: &'static str,
+    source: std::io::Error,
+) -> Error
+{
+    let source = EndpointError::IoError { source };
+    match part {
+        RequestPart::Request => Error::ServerError { scenario, source },
+        RequestPart::Response => Error::ClientError { scenario, source },
+    }
+}
+
+fn notify_message_done(
+    ui: &Sender<SessionEvent>,
+    uuid: Uuid,
+    r: &Result<Option<HeaderMap>>,
+    part: RequestPart,
+)
+{
+    let (status, trailers, error) = match r {
+        Ok(trailers) => (Status::Succeeded, trailers.clone(), None),
+        Err(e) => {
+            error!("{} - {:?} failed: {}", uuid, part, e);
+            (Status::Failed, None, Some(e.to_string()))
+        }
+    };
+
+    ui.send(SessionEvent::MessageDone(MessageDoneEvent {
+        uuid,
+        part,
+        status,
+        timestamp: SystemTime::now(),
+        trailers,
+        error,
+    }))
+    .unwrap();
+}
+
+fn notify_request_done(ui: &Sender<SessionEvent>, uuid: Uuid, status: Status)
+{
+    ui.send(SessionEvent::RequestDone(RequestDoneEvent {
+        uuid,
+        status,
+        timestamp: SystemTime::now(),
+        timed_out: false,
+        reason: None,
+    }))
+    .unwrap();
+}
+
+/// Buffered reader for the HTTP/1.1 message framing.
+pub(super) struct MessageReader<TRead>
+{
+    read: TRead,
+    buffer: BytesMut,
+}
+
+impl<TRead: AsyncRead + Unpin> MessageReader<TRead>
+{
+    pub(super) fn new(read: TRead) -> Self
+    {
+        Self {
+            read,
+            buffer: BytesMut::new(),
+        }
+    }
+
+    async fn fill(&mut self) -> std::io::Result<usize>
+    {
+        self.buffer.reserve(8 * 1024);
+        self.read.read_buf(&mut self.buffer).await
+    }
+
+    /// Read the request or response head including the terminating empty line.
+    ///
+    /// Returns `None` if the stream was closed before any data was received.
+    async fn read_head(&mut self) -> std::io::Result<Option<BytesMut>>
+    {
+        loop {
+            if let Some(pos) = find(&self.buffer, b"\r\n\r\n") {
+                return Ok(Some(self.buffer.split_to(pos + 4)));
+            }
+            if self.buffer.len() > MAX_HEAD_SIZE {
+                return Err(std::io::ErrorKind::InvalidData.into());
+            }
+            if self.fill().await? == 0 {
+                return match self.buffer.is_empty() {
+                    true => Ok(None),
+                    false => Err(std::io::ErrorKind::UnexpectedEof.into()),
+                };
+            }
+        }
+    }
+
+    /// Read a single line including the line terminator.
+    async fn read_line(&mut self) -> std::io::Result<BytesMut>
+    {
+        loop {
+            if let Some(pos) = find(&self.buffer, b"\r\n") {
+                return Ok(self.buffer.split_to(pos + 2));
+            }
+            if self.buffer.len() > MAX_HEAD_SIZE {
+                return Err(std::io::ErrorKind::InvalidData.into());
+            }
+            if self.fill().await? == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// Read exactly `len` bytes.
+    ///
+    /// Returns `None` if the stream was closed before any data was received.
+    pub(super) async fn read_exact(&mut self, len: usize) -> std::io::Result<Option<BytesMut>>
+    {
+        while self.buffer.len() < len {
+            if self.fill().await? == 0 {
+                return match self.buffer.is_empty() {
+                    true => Ok(None),
+                    false => Err(std::io::ErrorKind::UnexpectedEof.into()),
+                };
+            }
+        }
+        Ok(Some(self.buffer.split_to(len)))
+    }
+
+    /// Read up to `max` bytes. Returns an empty buffer once the stream has been closed.
+    async fn read_some(&mut self, max: usize) -> std::io::Result<BytesMut>
+    {
+        if self.buffer.is_empty() && self.fill().await? == 0 {
+            return Ok(BytesMut::new());
+        }
+
+        let len = self.buffer.len().min(max);
+        Ok(self.buffer.split_to(len))
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize>
+{
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use std::sync::mpsc::Receiver;
+    use std::time::{Duration, Instant};
+    use tokio::io::DuplexStream;
+
+    /// Start proxying a connection. Returns the client and the server ends of it.
+    fn start_proxy() -> (DuplexStream, DuplexStream, Receiver<SessionEvent>)
+    {
+        let (client, proxy_client) = tokio::io::duplex(64 * 1024);
+        let (proxy_server, server) = tokio::io::duplex(64 * 1024);
+        let (ui_tx, ui_rx) = std::sync::mpsc::channel();
+        let details = ConnectionDetails {
+            uuid: Uuid::new_v4(),
+            protocol_stack: vec![],
+            opaque_redirect: None,
+            connect_attempts: 1,
+        };
+        tokio::spawn(handle(
+            details,
+            "127.0.0.1:1234".parse().unwrap(),
+            Streams {
+                client: proxy_client,
+                server: proxy_server,
+            },
+            Arc::new(ConnectionOptions::default()),
+            ui_tx,
+        ));
+        (client, server, ui_rx)
+    }
+
+    /// Collect the session events until the given number of requests have been completed.
+    async fn collect_session(ui_rx: Receiver<SessionEvent>, requests: usize) -> Session
+    {
+        // The UI channel is synchronous so it's read on a blocking thread to keep the proxy
+        // running.
+        tokio::task::spawn_blocking(move || {
+            let mut session = Session::default();
+            let mut done = 0;
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+                match ui_rx.recv_timeout(timeout) {
+                    Ok(e) => {
+                        if let SessionEvent::RequestDone(_) = e {
+                            done += 1;
+                        }
+                        session.handle(e);
+                        if done == requests {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+            session
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Read from the stream until the data ends with the suffix.
+    async fn read_until(stream: &mut (impl AsyncRead + Unpin), suffix: &[u8]) -> Vec<u8>
+    {
+        let mut data = vec![];
+        while !data.ends_with(suffix) {
+            let mut buffer = [0_u8; 1024];
+            let count = stream.read(&mut buffer).await.unwrap();
+            assert_ne!(count, 0, "Stream closed before {:?}", data);
+            data.extend_from_slice(&buffer[..count]);
+        }
+        data
+    }
+
+    #[tokio::test]
+    async fn content_length_bodies_are_forwarded()
+    {
+        let (mut client, server, ui_rx) = start_proxy();
+
+        let server = tokio::spawn(async move {
+            let (server_read, mut server_write) = tokio::io::split(server);
+            let mut server_read = MessageReader::new(server_read);
+            let head = server_read.read_head().await.unwrap().unwrap();
+            assert!(head.starts_with(b"POST /upload HTTP/1.1\r\n"));
+            let body = server_read.read_exact(4).await.unwrap().unwrap();
+            assert_eq!(&body[..], b"ping");
+            server_write
+                .write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 4\r\n\r\npong")
+                .await
+                .unwrap();
+        });
+
+        client
+            .write_all(
+                b"POST /upload HTTP/1.1\r\n\
+                Host: localhost\r\n\
+                Content-Length: 4\r\n\
+                Connection: close\r\n\
+                \r\n\
+                ping",
+            )
+            .await
+            .unwrap();
+
+        // The proxy closes the connection after the response because the client asked for it.
+        let mut response = vec![];
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 201 Created\r\n"));
+        assert!(response.ends_with(b"\r\n\r\npong"));
+        server.await.unwrap();
+
+        let session = collect_session(ui_rx, 1).await;
+        let request = session.requests.iter().next().expect("No request recorded");
+        assert_eq!(request.request_data.method, Method::POST);
+        assert_eq!(request.request_data.status, Status::Succeeded);
+        assert_eq!(&request.request_msg.content[..], b"ping");
+        assert_eq!(&request.response_msg.content[..], b"pong");
+    }
+
+    #[tokio::test]
+    async fn chunked_bodies_are_forwarded()
+    {
+        let (mut client, server, ui_rx) = start_proxy();
+
+        let server = tokio::spawn(async move {
+            let (server_read, mut server_write) = tokio::io::split(server);
+            let mut server_read = MessageReader::new(server_read);
+            server_read.read_head().await.unwrap();
+
+            // The chunks are forwarded as they were sent.
+            let body = server_read.read_exact(19).await.unwrap().unwrap();
+            assert_eq!(&body[..], b"2\r\npi\r\n2\r\nng\r\n0\r\n\r\n");
+            server_write
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\n\
+                    Transfer-Encoding: chunked\r\n\
+                    \r\n\
+                    3\r\npon\r\n1;ext=1\r\ng\r\n0\r\n\r\n",
+                )
+                .await
+                .unwrap();
+        });
+
+        client
+            .write_all(
+                b"POST /upload HTTP/1.1\r\n\
+                Host: localhost\r\n\
+                Transfer-Encoding: chunked\r\n\
+                Connection: close\r\n\
+                \r\n\
+                2\r\npi\r\n2\r\nng\r\n0\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut response = vec![];
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.ends_with(b"\r\n\r\n3\r\npon\r\n1;ext=1\r\ng\r\n0\r\n\r\n"));
+        server.await.unwrap();
+
+        // The recorded content has the chunked encoding removed.
+        let session = collect_session(ui_rx, 1).await;
+        let request = session.requests.iter().next().expect("No request recorded");
+        assert_eq!(&request.request_msg.content[..], b"ping");
+        assert_eq!(&request.response_msg.content[..], b"pong");
+    }
+
+    #[tokio::test]
+    async fn keep_alive_connections_carry_multiple_requests()
+    {
+        let (mut client, server, ui_rx) = start_proxy();
+
+        let server = tokio::spawn(async move {
+            let (server_read, mut server_write) = tokio::io::split(server);
+            let mut server_read = MessageReader::new(server_read);
+            for body in ["first", "second"] {
+                server_read.read_head().await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                server_write.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        // HTTP/1.1 connections are kept alive unless either side asks otherwise.
+        client
+            .write_all(b"GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        read_until(&mut client, b"first").await;
+        client
+            .write_all(b"GET /second HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = vec![];
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.ends_with(b"second"));
+        server.await.unwrap();
+
+        let session = collect_session(ui_rx, 2).await;
+        let requests: Vec<_> = session.requests.iter().collect();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].request_data.uri.path(), "/first");
+        assert_eq!(requests[1].request_data.uri.path(), "/second");
+        assert_eq!(&requests[0].response_msg.content[..], b"first");
+        assert_eq!(&requests[1].response_msg.content[..], b"second");
+        assert_eq!(
+            requests[0].request_data.connection_uuid,
+            requests[1].request_data.connection_uuid
+        );
+        assert_eq!(session.connections.iter().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn chunked_trailers_are_recorded()
+    {
+        let (mut client, server, ui_rx) = start_proxy();
+
+        tokio::spawn(async move {
+            let (server_read, mut server_write) = tokio::io::split(server);
+            let mut server_read = MessageReader::new(server_read);
+            server_read.read_head().await.unwrap();
+            server_write
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\n\
+                    Transfer-Encoding: chunked\r\n\
+                    Trailer: grpc-status\r\n\
+                    \r\n\
+                    5\r\nhello\r\n\
+                    0\r\n\
+                    grpc-status: 0\r\n\
+                    grpc-message: ok\r\n\
+                    \r\n",
+                )
+                .await
+                .unwrap();
+        });
+
+        client
+            .write_all(b"GET /test HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let session = collect_session(ui_rx, 1).await;
+        let request = session.requests.iter().next().expect("No request recorded");
+        assert_eq!(&request.response_msg.content[..], b"hello");
+        assert_eq!(request.response_msg.trailers["grpc-status"], "0");
+        assert_eq!(request.response_msg.trailers["grpc-message"], "ok");
+        assert!(request.request_msg.trailers.is_empty());
+    }
+}
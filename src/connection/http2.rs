@@ -3,23 +3,38 @@ use futures::{prelude::*, try_join};
 use h2::{
     client::{self, ResponseFuture},
     server::{self, SendResponse},
-    Reason, RecvStream, SendStream,
+    FlowControl, Reason, RecvStream, SendStream,
 };
 use http::{HeaderMap, Request, Response};
 use log::error;
 use snafu::ResultExt;
 use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::oneshot;
 use uuid::Uuid;
 
 use super::*;
 
+/// Initial window size used unless the user specifies one with `--h2-window-size`.
+const DEFAULT_WINDOW_SIZE: u32 = 1_000_000;
+
+/// How often the settings and the flow-control windows are checked for changes.
+const SETTINGS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Proxy the HTTP/2 connection.
+///
+/// A failed server handshake is retried up to `--connect-retries` times when the connection can
+/// be reopened with `reconnect`.
 pub async fn handle<TClient, TServer>(
     mut details: ConnectionDetails,
     client_addr: SocketAddr,
     streams: Streams<TClient, TServer>,
+    reconnect: Option<Reconnect<TServer>>,
+    options: Arc<ConnectionOptions>,
     ui: Sender<SessionEvent>,
 ) -> Result<()>
 where
@@ -31,82 +46,202 @@ where
 
     // This is a debugging proxy so we don't need to be supporting hundreds of concurrent
     // requests. We can opt for a bit larger window size to avoid slowing down the connection.
+    let window_size = options.h2_window_size.unwrap_or(DEFAULT_WINDOW_SIZE);
     let mut client_connection = server::Builder::new()
-        .initial_window_size(1_000_000)
+        .initial_window_size(window_size)
         .handshake(client)
         .await
         .context(H2Error {})
         .context(ClientError {
             scenario: "client handshake",
         })?;
-    let (server_stream, server_connection) = client::handshake(server)
-        .await
-        .context(H2Error {})
-        .context(ServerError {
-            scenario: "server handshake",
-        })?;
-
-    // The connection futures are responsible for driving the network communication.
-    // Spawn them into a new task to take care of that.
-    tokio::spawn({
-        let uuid = details.uuid;
-        async move {
-            match server_connection.await {
-                Ok(..) => {}
-                Err(e) => error!("Server connection failed for connection {}; {}", uuid, e),
-            }
-        }
-    });
-
-    let mut server_stream =
-        server_stream
-            .ready()
-            .await
-            .context(H2Error {})
-            .context(ServerError {
-                scenario: "starting stream",
-            })?;
 
+    // The connection is shown before the server handshake so the UI can show the retries.
     ui.send(SessionEvent::NewConnection(NewConnectionEvent {
         uuid: details.uuid,
         protocol_stack: details.protocol_stack,
         client_addr,
         timestamp: SystemTime::now(),
+        connect_attempts: details.connect_attempts,
+    }))
+    .unwrap();
+
+    let uuid = details.uuid;
+    let connect_attempts = details.connect_attempts;
+    let target = details.opaque_redirect.as_deref().unwrap_or("server");
+    let retries = match reconnect {
+        Some(_) => options.connect_retries,
+        None => 0,
+    };
+    let mut server = Some(server);
+    let result = connect::retry(
+        target,
+        retries,
+        &options,
+        |attempt| {
+            let _ = ui.send(SessionEvent::ConnectAttempt(ConnectAttemptEvent {
+                uuid,
+                connect_attempts: connect_attempts + attempt - 1,
+            }));
+        },
+        || {
+            // The first attempt uses the stream the connection was set up with.
+            let server = server.take();
+            let reconnect = &reconnect;
+            async move {
+                let server = match (server, reconnect) {
+                    (Some(server), _) => server,
+                    (None, Some(reconnect)) => reconnect().await?,
+                    (None, None) => unreachable!("Handshake retried without reconnecting"),
+                };
+                server_handshake(server, window_size, uuid).await
+            }
+        },
+    )
+    .await;
+    let (mut server_stream, mut server_error, server_streams) = match result {
+        Ok((handshake, _)) => handshake,
+        Err(e) => {
+            ui.send(SessionEvent::ConnectionDone(ConnectionDoneEvent {
+                uuid,
+                status: Status::Failed,
+                timestamp: SystemTime::now(),
+                reason: None,
+            }))
+            .unwrap();
+            return Err(e);
+        }
+    };
+    let windows = Arc::new(StreamWindows::new());
+    let mut settings = h2_settings(window_size, &client_connection, &server_streams, &windows);
+    ui.send(SessionEvent::ConnectionSettings(ConnectionSettingsEvent {
+        uuid: details.uuid,
+        settings,
     }))
     .unwrap();
 
     // We'll wrap all of this into an `async` block to act as a try/catch for handling errors
     // at the end of the function.
+    let throttles = Arc::new(Throttles::new(&options));
     let r = {
         let ui = ui.clone();
         let client_connection = &mut client_connection;
         let server_stream = &mut server_stream;
         let uuid = details.uuid;
-        let authority = details.opaque_redirect;
+        let throttles = throttles.clone();
+        let idle = Arc::new(IdleTimeout::new(options.idle_timeout));
+        let windows = windows.clone();
+
+        // The user may override the authority explicitly. Otherwise we'll rewrite it only if the
+        // connection was redirected to a server the client didn't know about.
+        let authority = options
+            .target_header_host
+            .clone()
+            .or(details.opaque_redirect);
         async move {
             // The client_connection will produce individual HTTP request that we'll accept.
             // These requests will be handled in parallel by spawning them into their own
             // tasks.
-            while let Some(request) = client_connection.accept().await {
+            let mut idle_expired = false;
+            let mut settings_interval = tokio::time::interval(SETTINGS_INTERVAL);
+            loop {
+                let accepted = tokio::select! {
+                    request = client_connection.accept() => Some(request),
+                    _ = idle.expired(), if !idle_expired => None,
+
+                    // The peers may update their settings during the connection and the windows
+                    // change as the data flows. There is no notification of either so they are
+                    // checked periodically.
+                    _ = settings_interval.tick() => {
+                        let current =
+                            h2_settings(window_size, client_connection, &server_streams, &windows);
+                        if current != settings {
+                            settings = current;
+                            ui.send(SessionEvent::ConnectionSettings(ConnectionSettingsEvent {
+                                uuid,
+                                settings,
+                            }))
+                            .unwrap();
+                        }
+                        continue;
+                    }
+                };
+                let request = match accepted {
+                    Some(Some(request)) => request,
+                    Some(None) => break,
+                    None => {
+                        // Keep accepting until the client has received the GOAWAY and the
+                        // connection closes.
+                        log::info!("{} - Closing idle connection", uuid);
+                        client_connection.abrupt_shutdown(Reason::NO_ERROR);
+                        idle_expired = true;
+                        continue;
+                    }
+                };
+                idle.touch();
+
                 let (client_request, client_response) =
                     request.context(H2Error {}).context(ClientError {
                         scenario: "processing request",
                     })?;
                 log::debug!("Request: {:?}", client_request);
 
+                let fault = Fault::decide(&options);
+                if fault.status.is_some() {
+                    let ui = ui.clone();
+                    let throttles = throttles.clone();
+                    let idle = idle.clone();
+                    let max_body_bytes = options.max_body_bytes;
+                    tokio::spawn(async move {
+                        match inject_error(
+                            uuid,
+                            client_request,
+                            client_response,
+                            fault,
+                            &throttles,
+                            &idle,
+                            max_body_bytes,
+                            ui,
+                        )
+                        .await
+                        {
+                            Ok(_) => {}
+                            Err(e) => error!("Request error for request {}; {}", uuid, e),
+                        }
+                    });
+                    continue;
+                }
+
                 let request = ProxyRequest::new(
                     uuid,
                     authority.clone(),
                     client_request,
                     client_response,
                     server_stream,
+                    fault,
+                    &options,
                     &ui,
                 )?;
 
                 let ui = ui.clone();
+                let throttles = throttles.clone();
+                let idle = idle.clone();
+                let windows = windows.clone();
+                let request_timeout = options.request_timeout;
+                let max_body_bytes = options.max_body_bytes;
                 tokio::spawn(async move {
                     let ui = ui;
-                    match request.execute(ui).await {
+                    match request
+                        .execute(
+                            ui,
+                            throttles,
+                            idle,
+                            windows,
+                            request_timeout,
+                            max_body_bytes,
+                        )
+                        .await
+                    {
                         Ok(_) => {}
                         Err(e) => error!("Request error for request {}; {}", uuid, e),
                     }
@@ -115,12 +250,21 @@ where
 
             Ok(())
         }
-    }
-    .await;
+    };
+    let r = throttles.report(details.uuid, &ui, r).await;
 
     // Once the ´while client_connection.accept()` loop ends, the connection will close (or
     // alternatively an error happened and we'll terminate it). The final status value depends
     // on whether there was an error or not.
+    //
+    // The server connection is driven by its own task so its errors, such as a GOAWAY from the
+    // server, are only known if that task has finished already.
+    let reason = h2_reason(&r).or_else(|| {
+        server_error
+            .try_recv()
+            .ok()
+            .and_then(|e| h2_error_reason(&e))
+    });
     ui.send(SessionEvent::ConnectionDone(ConnectionDoneEvent {
         uuid: details.uuid,
         status: match r {
@@ -128,6 +272,281 @@ where
             Err(_) => Status::Failed,
         },
         timestamp: SystemTime::now(),
+        reason,
+    }))
+    .unwrap();
+    r
+}
+
+/// Flow-control windows of the most recent streams in both directions.
+///
+/// h2 only exposes the windows of the individual streams, not the window of the connection.
+pub struct StreamWindows
+{
+    /// Data the client may still send on its most recent request stream.
+    request: AtomicUsize,
+
+    /// Data the server may still send on its most recent response stream.
+    response: AtomicUsize,
+}
+
+impl StreamWindows
+{
+    fn new() -> Self
+    {
+        // The windows are unknown until data has been received.
+        Self {
+            request: AtomicUsize::new(usize::MAX),
+            response: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    fn record(&self, part: RequestPart, flow_control: &FlowControl)
+    {
+        let window = match part {
+            RequestPart::Request => &self.request,
+            RequestPart::Response => &self.response,
+        };
+        window.store(
+            flow_control.available_capacity().max(0) as usize,
+            Ordering::Relaxed,
+        );
+    }
+}
+
+/// Collect the HTTP/2 settings of the connection.
+fn h2_settings<TClient>(
+    window_size: u32,
+    client_connection: &server::Connection<TClient, Bytes>,
+    server_streams: &AtomicUsize,
+    windows: &StreamWindows,
+) -> H2Settings
+where
+    TClient: AsyncRead + AsyncWrite + Unpin,
+{
+    // h2 reports the streams as unlimited with usize::MAX. The windows use it for ones that
+    // aren't known yet.
+    let limit = |value: usize| match value {
+        usize::MAX => None,
+        n => Some(n),
+    };
+    H2Settings {
+        initial_window_size: window_size,
+        client_max_concurrent_streams: limit(client_connection.max_concurrent_send_streams()),
+        server_max_concurrent_streams: limit(server_streams.load(Ordering::Relaxed)),
+        client_stream_window: limit(windows.request.load(Ordering::Relaxed)),
+        server_stream_window: limit(windows.response.load(Ordering::Relaxed)),
+    }
+}
+
+/// Establish the HTTP/2 connection to the server.
+///
+/// The returned receiver gets the error the server connection fails with. The counter tracks the
+/// number of concurrent streams the server allows.
+async fn server_handshake<TServer>(
+    server: TServer,
+    window_size: u32,
+    connection_uuid: Uuid,
+) -> Result<(
+    client::SendRequest<Bytes>,
+    oneshot::Receiver<h2::Error>,
+    Arc<AtomicUsize>,
+)>
+where
+    TServer: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (server_stream, mut server_connection) = client::Builder::new()
+        .initial_window_size(window_size)
+        .handshake(server)
+        .await
+        .context(H2Error {})
+        .context(ServerError {
+            scenario: "server handshake",
+        })?;
+
+    // The connection futures are responsible for driving the network communication.
+    // Spawn them into a new task to take care of that.
+    //
+    // The connection can't be inspected once it has been moved into the task so the stream limit
+    // of the server is recorded every time the connection is polled.
+    let (error_tx, error_rx) = oneshot::channel();
+    let server_streams = Arc::new(AtomicUsize::new(
+        server_connection.max_concurrent_send_streams(),
+    ));
+    let streams = server_streams.clone();
+    tokio::spawn(async move {
+        let result = future::poll_fn(|cx| {
+            let poll = Pin::new(&mut server_connection).poll(cx);
+            streams.store(
+                server_connection.max_concurrent_send_streams(),
+                Ordering::Relaxed,
+            );
+            poll
+        })
+        .await;
+        match result {
+            Ok(..) => {}
+            Err(e) => {
+                error!(
+                    "Server connection failed for connection {}; {}",
+                    connection_uuid, e
+                );
+                let _ = error_tx.send(e);
+            }
+        }
+    });
+
+    let server_stream = server_stream
+        .ready()
+        .await
+        .context(H2Error {})
+        .context(ServerError {
+            scenario: "starting stream",
+        })?;
+    Ok((server_stream, error_rx, server_streams))
+}
+
+/// Send a previously captured request to the server.
+pub(super) async fn replay<TServer>(
+    server: TServer,
+    connection_uuid: Uuid,
+    request: ReplayRequest,
+    ui: &Sender<SessionEvent>,
+) -> Result<()>
+where
+    TServer: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let ReplayRequest {
+        method,
+        uri,
+        headers,
+        body,
+        trailers,
+        ..
+    } = request;
+    let (mut server_stream, ..) =
+        server_handshake(server, DEFAULT_WINDOW_SIZE, connection_uuid).await?;
+
+    let uuid = Uuid::new_v4();
+    ui.send(SessionEvent::NewRequest(NewRequestEvent {
+        connection_uuid,
+        uuid,
+        uri: uri.clone(),
+        method: method.clone(),
+        headers: headers.clone(),
+        timestamp: SystemTime::now(),
+        injected_fault: false,
+    }))
+    .unwrap();
+
+    let r = async {
+        let mut server_request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .context(HttpError {})
+            .context(ConfigurationError {
+                reason: "invalid replay request",
+            })?;
+        *server_request.headers_mut() = headers;
+
+        let has_trailers = !trailers.is_empty();
+        let end_stream = body.is_empty() && !has_trailers;
+        let (server_response, mut server_request) = server_stream
+            .send_request(server_request, end_stream)
+            .context(H2Error {})
+            .context(ServerError {
+                scenario: "sending request",
+            })?;
+
+        let request_result = async {
+            if end_stream {
+                return Ok(None);
+            }
+
+            ui.send(SessionEvent::MessageData(MessageDataEvent {
+                uuid,
+                data: body.clone(),
+                part: RequestPart::Request,
+                truncated: 0,
+            }))
+            .unwrap();
+            server_request
+                .send_data(body, !has_trailers)
+                .context(H2Error {})
+                .context(ServerError {
+                    scenario: "writing content",
+                })?;
+
+            if !has_trailers {
+                return Ok(None);
+            }
+            server_request
+                .send_trailers(trailers.clone())
+                .context(H2Error {})
+                .context(ServerError {
+                    scenario: "sending trailers",
+                })?;
+            Ok(Some(trailers))
+        }
+        .await;
+        notify_message_done(ui.clone(), uuid, request_result, RequestPart::Request).await?;
+
+        let response_result = async {
+            let response = server_response
+                .await
+                .context(H2Error {})
+                .context(ServerError {
+                    scenario: "waiting for response",
+                })?;
+
+            let (response_head, mut response_body) = response.into_parts();
+            ui.send(SessionEvent::NewResponse(NewResponseEvent {
+                uuid,
+                connection_uuid,
+                timestamp: SystemTime::now(),
+                headers: response_head.headers,
+                status: Some(response_head.status.as_u16()),
+            }))
+            .unwrap();
+
+            while let Some(data) = response_body.data().await {
+                let data = data.context(H2Error {}).context(ServerError {
+                    scenario: "reading content",
+                })?;
+                let size = data.len();
+                ui.send(SessionEvent::MessageData(MessageDataEvent {
+                    uuid,
+                    data,
+                    part: RequestPart::Response,
+                    truncated: 0,
+                }))
+                .unwrap();
+                let _ = response_body.flow_control().release_capacity(size);
+            }
+
+            response_body
+                .trailers()
+                .await
+                .context(H2Error {})
+                .context(ServerError {
+                    scenario: "receiving trailers",
+                })
+        }
+        .await;
+        notify_message_done(ui.clone(), uuid, response_result, RequestPart::Response).await
+    }
+    .await;
+
+    ui.send(SessionEvent::RequestDone(RequestDoneEvent {
+        uuid,
+        status: match is_fatal_error(&r) {
+            true => Status::Failed,
+            false => Status::Succeeded,
+        },
+        timestamp: SystemTime::now(),
+        timed_out: false,
+        reason: h2_reason(&r),
     }))
     .unwrap();
     r
@@ -141,16 +560,21 @@ pub struct ProxyRequest
     client_response: SendResponse<Bytes>,
     server_request: SendStream<Bytes>,
     server_response: ResponseFuture,
+    fault: Fault,
+    options: Arc<ConnectionOptions>,
 }
 
 impl ProxyRequest
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         connection_uuid: Uuid,
         authority: Option<String>,
         client_request: Request<RecvStream>,
         client_response: SendResponse<Bytes>,
         server_stream: &mut client::SendRequest<Bytes>,
+        fault: Fault,
+        options: &Arc<ConnectionOptions>,
         ui: &Sender<SessionEvent>,
     ) -> Result<ProxyRequest>
     {
@@ -180,6 +604,7 @@ impl ProxyRequest
                     reason: "invalid target server",
                 })?;
         }
+        options.request_headers.apply(&mut client_head.headers);
 
         ui.send(SessionEvent::NewRequest(NewRequestEvent {
             connection_uuid,
@@ -188,6 +613,7 @@ impl ProxyRequest
             method: client_head.method.clone(),
             headers: client_head.headers.clone(),
             timestamp: SystemTime::now(),
+            injected_fault: fault.is_injected(),
         }))
         .unwrap();
 
@@ -208,10 +634,20 @@ impl ProxyRequest
             client_response,
             server_request,
             server_response,
+            fault,
+            options: options.clone(),
         })
     }
 
-    pub async fn execute(self, ui: Sender<SessionEvent>) -> Result<()>
+    pub async fn execute(
+        self,
+        ui: Sender<SessionEvent>,
+        throttles: Arc<Throttles>,
+        idle: Arc<IdleTimeout>,
+        windows: Arc<StreamWindows>,
+        request_timeout: Option<std::time::Duration>,
+        max_body_bytes: Option<usize>,
+    ) -> Result<()>
     {
         // Acquire futures that are responsible for streaming the request and the response. These
         // are set up in their own futures to allow parallel request/response streaming to occur.
@@ -224,6 +660,9 @@ impl ProxyRequest
         let uuid = self.uuid;
         let client_request = self.client_request;
         let mut server_request = self.server_request;
+        let request_throttles = throttles.clone();
+        let request_idle = idle.clone();
+        let request_windows = windows.clone();
         let ui_temp = ui.clone();
         let request_future = async move {
             if client_request.is_end_stream() {
@@ -233,9 +672,10 @@ impl ProxyRequest
                 let trailers = pipe_stream(
                     client_request,
                     &mut server_request,
-                    ui,
-                    uuid,
-                    RequestPart::Request,
+                    &request_throttles,
+                    &request_idle,
+                    &request_windows,
+                    BodyRecorder::new(&ui, uuid, RequestPart::Request, max_body_bytes),
                 )
                 .await?;
 
@@ -265,6 +705,9 @@ impl ProxyRequest
         let mut client_response = self.client_response;
         let server_response = self.server_response;
         let connection_uuid = self.connection_uuid;
+        let fault = self.fault;
+        let options = self.options;
+        let response_idle = idle.clone();
         let ui_temp = ui.clone();
         let response_future = async move {
             let ui = ui_temp;
@@ -274,13 +717,16 @@ impl ProxyRequest
                 .context(ServerError {
                     scenario: "waiting for response",
                 })?;
+            fault.delay().await;
 
-            let (response_head, response_body) = response.into_parts();
+            let (mut response_head, response_body) = response.into_parts();
+            options.response_headers.apply(&mut response_head.headers);
             ui.send(SessionEvent::NewResponse(NewResponseEvent {
                 uuid,
                 connection_uuid,
                 timestamp: SystemTime::now(),
                 headers: response_head.headers.clone(),
+                status: Some(response_head.status.as_u16()),
             }))
             .unwrap();
 
@@ -302,9 +748,10 @@ impl ProxyRequest
                 let trailers = pipe_stream(
                     response_body,
                     &mut client_stream,
-                    ui,
-                    uuid,
-                    RequestPart::Response,
+                    &throttles,
+                    &response_idle,
+                    &windows,
+                    BodyRecorder::new(&ui, uuid, RequestPart::Response, max_body_bytes),
                 )
                 .await?;
                 log::info!("{}: Server stream ended", uuid);
@@ -326,8 +773,17 @@ impl ProxyRequest
             move |r| notify_message_done(ui, uuid, r, RequestPart::Response)
         });
 
-        // Now handle both futures in parallel.
-        let r = try_join!(request_future, response_future);
+        // Now handle both futures in parallel. If the request times out, the futures are dropped,
+        // which resets the streams.
+        let r = tokio::select! {
+            r = async { try_join!(request_future, response_future) } => r,
+            duration = idle.request_expired(request_timeout) => {
+                log::info!("{}: Request timed out", uuid);
+                Err(EndpointError::TimeoutError { duration }).context(ServerError {
+                    scenario: "forwarding request",
+                })
+            }
+        };
         ui.send(SessionEvent::RequestDone(RequestDoneEvent {
             uuid: self.uuid,
             status: match is_fatal_error(&r) {
@@ -335,18 +791,118 @@ impl ProxyRequest
                 false => Status::Succeeded,
             },
             timestamp: SystemTime::now(),
+            timed_out: is_timeout(&r),
+            reason: h2_reason(&r),
         }))
         .unwrap();
         r.map(|_| ())
     }
 }
 
+/// Answer the request with the injected error status without forwarding it to the server.
+#[allow(clippy::too_many_arguments)]
+async fn inject_error(
+    connection_uuid: Uuid,
+    client_request: Request<RecvStream>,
+    mut client_response: SendResponse<Bytes>,
+    fault: Fault,
+    throttles: &Throttles,
+    idle: &IdleTimeout,
+    max_body_bytes: Option<usize>,
+    ui: Sender<SessionEvent>,
+) -> Result<()>
+{
+    let uuid = Uuid::new_v4();
+    let (client_head, mut client_request) = client_request.into_parts();
+    ui.send(SessionEvent::NewRequest(NewRequestEvent {
+        connection_uuid,
+        uuid,
+        uri: client_head.uri,
+        method: client_head.method,
+        headers: client_head.headers,
+        timestamp: SystemTime::now(),
+        injected_fault: true,
+    }))
+    .unwrap();
+
+    let r = async {
+        // The request is read in full even though it's not forwarded anywhere to keep the client
+        // from stalling on flow control.
+        let request_result = async {
+            let mut body = BodyRecorder::new(&ui, uuid, RequestPart::Request, max_body_bytes);
+            while let Some(data) = client_request.data().await {
+                let b = data.context(H2Error {}).context(ClientError {
+                    scenario: "reading content",
+                })?;
+                let size = b.len();
+                body.record(&b);
+                throttles.request.consume(size).await;
+                idle.touch();
+                client_request
+                    .flow_control()
+                    .release_capacity(size)
+                    .unwrap();
+            }
+            client_request
+                .trailers()
+                .await
+                .context(H2Error {})
+                .context(ClientError {
+                    scenario: "receiving trailers",
+                })
+        }
+        .await;
+        notify_message_done(ui.clone(), uuid, request_result, RequestPart::Request).await?;
+
+        fault.delay().await;
+        let status = fault.status.unwrap_or(503);
+        ui.send(SessionEvent::NewResponse(NewResponseEvent {
+            uuid,
+            connection_uuid,
+            timestamp: SystemTime::now(),
+            headers: HeaderMap::new(),
+            status: Some(status),
+        }))
+        .unwrap();
+        let response = Response::builder()
+            .status(status)
+            .body(())
+            .context(HttpError {})
+            .context(ConfigurationError {
+                reason: "invalid injected status",
+            })?;
+        let response_result = client_response
+            .send_response(response, true)
+            .map(|_| None)
+            .context(H2Error {})
+            .context(ClientError {
+                scenario: "sending response",
+            });
+        notify_message_done(ui.clone(), uuid, response_result, RequestPart::Response).await
+    }
+    .await;
+
+    ui.send(SessionEvent::RequestDone(RequestDoneEvent {
+        uuid,
+        status: match is_fatal_error(&r) {
+            true => Status::Failed,
+            false => Status::Succeeded,
+        },
+        timestamp: SystemTime::now(),
+        timed_out: false,
+        reason: h2_reason(&r),
+    }))
+    .unwrap();
+    r
+}
+
 async fn pipe_stream(
     mut source: RecvStream,
     target: &mut SendStream<Bytes>,
-    ui: Sender<SessionEvent>,
-    uuid: Uuid,
-    part: RequestPart,
+    throttles: &Throttles,
+    idle: &IdleTimeout,
+    windows: &StreamWindows,
+    mut body: BodyRecorder,
 ) -> Result<Option<HeaderMap>>
 {
     while let Some(data) = source.data().await {
@@ -363,15 +919,14 @@ async fn pipe_stream(
             }
         };
 
-        // Send a notification to the UI.
-        ui.send(SessionEvent::MessageData(MessageDataEvent {
-            uuid,
-            data: b.clone(),
-            part,
-        }))
-        .unwrap();
+        // Send a notification to the UI. The data is forwarded in full even if the UI gets only
+        // a part of it.
+        body.record(&b);
 
+        // Capacity is released only after the data is forwarded, which keeps the client from
+        // sending more than the throttle lets through.
         let size = b.len();
+        throttles.get(body.part).consume(size).await;
         target
             .send_data(b, source.is_end_stream())
             .context(H2Error {})
@@ -379,6 +934,8 @@ async fn pipe_stream(
                 scenario: "writing content",
             })?;
         source.flow_control().release_capacity(size).unwrap();
+        windows.record(body.part, source.flow_control());
+        idle.touch();
     }
 
     let t = source
@@ -406,15 +963,17 @@ async fn notify_message_done(
                 status: Status::Succeeded,
                 timestamp: SystemTime::now(),
                 trailers,
+                error: None,
             }))
             .unwrap(),
         Err(e) => {
             ui.send(SessionEvent::MessageDone(MessageDoneEvent {
                 uuid,
                 part,
-                status: Status::Succeeded,
+                status: Status::Failed,
                 timestamp: SystemTime::now(),
                 trailers: None,
+                error: Some(e.to_string()),
             }))
             .unwrap();
             return Err(e);
@@ -423,6 +982,41 @@ async fn notify_message_done(
     Ok(())
 }
 
+fn is_timeout<S>(r: &Result<S, Error>) -> bool
+{
+    matches!(
+        r,
+        Err(Error::ServerError {
+            source: EndpointError::TimeoutError { .. },
+            ..
+        })
+    )
+}
+
+/// The HTTP/2 error code the request or the connection failed with.
+fn h2_reason<S>(r: &Result<S, Error>) -> Option<H2Reason>
+{
+    match r {
+        Err(Error::ServerError {
+            source: EndpointError::H2Error { source },
+            ..
+        })
+        | Err(Error::ClientError {
+            source: EndpointError::H2Error { source },
+            ..
+        }) => h2_error_reason(source),
+        _ => None,
+    }
+}
+
+fn h2_error_reason(e: &h2::Error) -> Option<H2Reason>
+{
+    e.reason().map(|reason| H2Reason {
+        code: reason.into(),
+        go_away: e.is_go_away(),
+    })
+}
+
 fn is_fatal_error<S>(r: &Result<S, Error>) -> bool
 {
     match r {
@@ -440,3 +1034,246 @@ fn is_fatal_error<S>(r: &Result<S, Error>) -> bool
         },
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn reset_request_is_reported_failed()
+    {
+        let (client, proxy_client) = tokio::io::duplex(64 * 1024);
+        let (proxy_server, server) = tokio::io::duplex(64 * 1024);
+        let (ui_tx, ui_rx) = std::sync::mpsc::channel();
+        let details = ConnectionDetails {
+            uuid: Uuid::new_v4(),
+            protocol_stack: vec![],
+            opaque_redirect: None,
+            connect_attempts: 1,
+        };
+        tokio::spawn(handle(
+            details,
+            "127.0.0.1:1234".parse().unwrap(),
+            Streams {
+                client: proxy_client,
+                server: proxy_server,
+            },
+            None,
+            Arc::new(ConnectionOptions::default()),
+            ui_tx,
+        ));
+
+        // The server never responds so the request fails only because of the client reset.
+        let (received_tx, received_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut connection = server::handshake(server).await.unwrap();
+            let (request, _respond) = connection.accept().await.unwrap().unwrap();
+            let mut body = request.into_body();
+            tokio::spawn(async move {
+                body.data().await;
+                let _ = received_tx.send(());
+                while let Some(Ok(_)) = body.data().await {}
+            });
+            while connection.accept().await.is_some() {}
+        });
+
+        let (mut send_request, connection) = client::handshake(client).await.unwrap();
+        tokio::spawn(connection);
+        let request = Request::builder()
+            .uri("http://localhost/test")
+            .body(())
+            .unwrap();
+        let (_response, mut stream) = send_request.send_request(request, false).unwrap();
+        stream
+            .send_data(Bytes::from_static(b"partial"), false)
+            .unwrap();
+
+        // Resetting the stream before the headers are sent would be a connection error.
+        received_rx.await.unwrap();
+        stream.send_reset(Reason::INTERNAL_ERROR);
+
+        // The UI channel is synchronous so it's read on a blocking thread to keep the proxy
+        // running.
+        let done = tokio::task::spawn_blocking(move || {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+                match ui_rx.recv_timeout(timeout) {
+                    Ok(SessionEvent::MessageDone(e)) if e.part == RequestPart::Request => {
+                        return Some(e)
+                    }
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+            None
+        })
+        .await
+        .unwrap()
+        .expect("The request MessageDone was not sent");
+
+        assert_eq!(done.status, Status::Failed);
+        assert!(done.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn server_handshake_is_retried()
+    {
+        let (client, proxy_client) = tokio::io::duplex(64 * 1024);
+        let (retry_server, server) = tokio::io::duplex(64 * 1024);
+        let (ui_tx, ui_rx) = std::sync::mpsc::channel();
+        let details = ConnectionDetails {
+            uuid: Uuid::new_v4(),
+            protocol_stack: vec![],
+            opaque_redirect: None,
+            connect_attempts: 1,
+        };
+
+        // The first server connection is closed before the handshake.
+        let (proxy_server, closed_server) = tokio::io::duplex(64 * 1024);
+        drop(closed_server);
+        let retry_server = std::sync::Mutex::new(Some(retry_server));
+        let reconnect: Reconnect<tokio::io::DuplexStream> = Box::new(move || {
+            let server = retry_server.lock().unwrap().take();
+            future::ready(Ok(server.expect("Reconnected more than once"))).boxed()
+        });
+        let options = ConnectionOptions {
+            connect_retries: 2,
+            connect_backoff: Some(Duration::from_millis(1)),
+            ..Default::default()
+        };
+        tokio::spawn(handle(
+            details,
+            "127.0.0.1:1234".parse().unwrap(),
+            Streams {
+                client: proxy_client,
+                server: proxy_server,
+            },
+            Some(reconnect),
+            Arc::new(options),
+            ui_tx,
+        ));
+
+        tokio::spawn(async move {
+            let mut connection = server::handshake(server).await.unwrap();
+            while let Some(Ok((_request, mut respond))) = connection.accept().await {
+                respond.send_response(Response::new(()), true).unwrap();
+            }
+        });
+
+        let (mut send_request, connection) = client::handshake(client).await.unwrap();
+        tokio::spawn(connection);
+        let request = Request::builder()
+            .uri("http://localhost/test")
+            .body(())
+            .unwrap();
+        let (response, _) = send_request.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), 200);
+
+        // The UI channel is synchronous so it's read on a blocking thread to keep the proxy
+        // running.
+        let session = tokio::task::spawn_blocking(move || {
+            let mut session = crate::session::Session::default();
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+                match ui_rx.recv_timeout(timeout) {
+                    Ok(e @ SessionEvent::RequestDone(_)) => {
+                        session.handle(e);
+                        break;
+                    }
+                    Ok(e) => {
+                        session.handle(e);
+                    }
+                    Err(_) => break,
+                }
+            }
+            session
+        })
+        .await
+        .unwrap();
+
+        let connection = session.connections.iter().next().unwrap();
+        assert_eq!(connection.connect_attempts, 2);
+        let request = session.requests.iter().next().unwrap();
+        assert_eq!(request.request_data.status, Status::Succeeded);
+    }
+
+    #[tokio::test]
+    async fn stream_windows_are_reported()
+    {
+        let (client, proxy_client) = tokio::io::duplex(64 * 1024);
+        let (proxy_server, server) = tokio::io::duplex(64 * 1024);
+        let (ui_tx, ui_rx) = std::sync::mpsc::channel();
+        let details = ConnectionDetails {
+            uuid: Uuid::new_v4(),
+            protocol_stack: vec![],
+            opaque_redirect: None,
+            connect_attempts: 1,
+        };
+        tokio::spawn(handle(
+            details,
+            "127.0.0.1:1234".parse().unwrap(),
+            Streams {
+                client: proxy_client,
+                server: proxy_server,
+            },
+            None,
+            Arc::new(ConnectionOptions::default()),
+            ui_tx,
+        ));
+
+        tokio::spawn(async move {
+            let mut connection = server::handshake(server).await.unwrap();
+            while let Some(Ok((request, mut respond))) = connection.accept().await {
+                tokio::spawn(async move {
+                    let mut body = request.into_body();
+                    while let Some(Ok(data)) = body.data().await {
+                        let _ = body.flow_control().release_capacity(data.len());
+                    }
+                    let mut stream = respond.send_response(Response::new(()), false).unwrap();
+                    stream.send_data(Bytes::from(vec![0; 1000]), true).unwrap();
+                });
+            }
+        });
+
+        let (mut send_request, connection) = client::handshake(client).await.unwrap();
+        tokio::spawn(connection);
+        let request = Request::builder()
+            .uri("http://localhost/test")
+            .body(())
+            .unwrap();
+        let (response, mut stream) = send_request.send_request(request, false).unwrap();
+        stream.send_data(Bytes::from(vec![0; 1000]), true).unwrap();
+        let mut body = response.await.unwrap().into_body();
+        while let Some(Ok(data)) = body.data().await {
+            let _ = body.flow_control().release_capacity(data.len());
+        }
+
+        // The windows are checked periodically while the connection stays open.
+        let settings = tokio::task::spawn_blocking(move || {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+                match ui_rx.recv_timeout(timeout) {
+                    Ok(SessionEvent::ConnectionSettings(e))
+                        if e.settings.client_stream_window.is_some()
+                            && e.settings.server_stream_window.is_some() =>
+                    {
+                        return Some(e.settings)
+                    }
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+            None
+        })
+        .await
+        .unwrap()
+        .expect("The stream windows were not reported");
+        drop(send_request);
+
+        assert_eq!(settings.initial_window_size, DEFAULT_WINDOW_SIZE);
+        assert!(settings.client_stream_window.unwrap() <= DEFAULT_WINDOW_SIZE as usize);
+        assert!(settings.server_stream_window.unwrap() <= DEFAULT_WINDOW_SIZE as usize);
+    }
+}
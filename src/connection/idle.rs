@@ -0,0 +1,65 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Tracks the time data was last forwarded on a connection for the `--idle-timeout`.
+pub struct IdleTimeout
+{
+    /// Allowed idle time. `None` disables the timeout.
+    timeout: Option<Duration>,
+    last: Mutex<Instant>,
+}
+
+impl IdleTimeout
+{
+    pub fn new(timeout: Option<Duration>) -> Self
+    {
+        Self {
+            timeout,
+            last: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn touch(&self)
+    {
+        *self.last.lock().unwrap() = Instant::now();
+    }
+
+    /// Wait until there has been no activity for the timeout.
+    ///
+    /// Never completes if there is no timeout.
+    pub async fn expired(&self) -> Duration
+    {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return futures::future::pending().await,
+        };
+
+        // The activity may be touched while sleeping so the deadline is checked again after
+        // waking up.
+        loop {
+            let deadline = *self.last.lock().unwrap() + timeout;
+            if Instant::now() >= deadline {
+                return timeout;
+            }
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+    }
+
+    /// Wait until the request exceeds the request timeout or the connection becomes idle.
+    pub async fn request_expired(&self, request_timeout: Option<Duration>) -> Duration
+    {
+        let request = async {
+            match request_timeout {
+                Some(timeout) => {
+                    tokio::time::sleep(timeout).await;
+                    timeout
+                }
+                None => futures::future::pending().await,
+            }
+        };
+        tokio::select! {
+            timeout = request => timeout,
+            timeout = self.expired() => timeout,
+        }
+    }
+}
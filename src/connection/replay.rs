@@ -0,0 +1,130 @@
+use bytes::Bytes;
+use http::{HeaderMap, Method, Uri};
+use rustls::{client::ServerName, ClientConfig};
+use snafu::ResultExt;
+use std::convert::TryFrom;
+use std::sync::mpsc::Sender;
+use std::time::SystemTime;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use super::*;
+
+/// A captured request that is sent to the server again.
+pub struct ReplayRequest
+{
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub trailers: HeaderMap,
+
+    /// The protocol used by the original request. Either `Protocol::Http1` or `Protocol::Http2`.
+    pub protocol: Protocol,
+
+    /// Use TLS for the connection to the server.
+    pub tls: bool,
+}
+
+/// Send the request to the target server over a new connection.
+///
+/// The connection and the request are reported to the UI as if they had been captured normally.
+pub async fn replay(request: ReplayRequest, target: &str, ui: Sender<SessionEvent>) -> Result<()>
+{
+    let server = TcpStream::connect(target)
+        .await
+        .context(IoError {})
+        .context(ServerError {
+            scenario: "connecting",
+        })?;
+    let local_addr = server
+        .local_addr()
+        .context(IoError {})
+        .context(ServerError {
+            scenario: "connecting",
+        })?;
+
+    let uuid = Uuid::new_v4();
+    let mut protocol_stack = vec![];
+    if request.tls {
+        protocol_stack.push(Protocol::Tls);
+    }
+    let http2 = matches!(request.protocol, Protocol::Http2);
+    protocol_stack.push(match http2 {
+        true => Protocol::Http2,
+        false => Protocol::Http1,
+    });
+    ui.send(SessionEvent::NewConnection(NewConnectionEvent {
+        uuid,
+        protocol_stack,
+        client_addr: local_addr,
+        timestamp: SystemTime::now(),
+        connect_attempts: 1,
+    }))
+    .unwrap();
+
+    let r = async {
+        if !request.tls {
+            return replay_protocol(server, uuid, request, http2, &ui).await;
+        }
+
+        let host = target
+            .rsplit_once(':')
+            .map(|(host, _)| host)
+            .unwrap_or(target);
+
+        // Replaying is meant for debugging so the server certificate isn't validated, the same
+        // way it isn't validated when proxying.
+        let mut config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(NoVerify))
+            .with_no_client_auth();
+        config.alpn_protocols = vec![match http2 {
+            true => b"h2".to_vec(),
+            false => b"http/1.1".to_vec(),
+        }];
+        let server_name =
+            ServerName::try_from(host)
+                .context(DNSError {})
+                .context(ConfigurationError {
+                    reason: "Invalid target server",
+                })?;
+        let server = TlsConnector::from(Arc::new(config))
+            .connect(server_name, server)
+            .await
+            .context(IoError {})
+            .context(ServerError {
+                scenario: "connecting TLS",
+            })?;
+        replay_protocol(server, uuid, request, http2, &ui).await
+    }
+    .await;
+
+    ui.send(SessionEvent::ConnectionDone(ConnectionDoneEvent {
+        uuid,
+        status: match r {
+            Ok(_) => Status::Succeeded,
+            Err(_) => Status::Failed,
+        },
+        timestamp: SystemTime::now(),
+        reason: None,
+    }))
+    .unwrap();
+    r
+}
+
+async fn replay_protocol<TServer>(
+    server: TServer,
+    connection_uuid: Uuid,
+    request: ReplayRequest,
+    http2: bool,
+    ui: &Sender<SessionEvent>,
+) -> Result<()>
+where
+    TServer: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    match http2 {
+        true => http2::replay(server, connection_uuid, request, ui).await,
+        false => http1::replay(server, connection_uuid, request, ui).await,
+    }
+}
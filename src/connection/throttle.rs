@@ -0,0 +1,224 @@
+use std::future::Future;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::session::events::{ConnectionThroughputEvent, SessionEvent};
+use crate::session::{RequestPart, Throughput};
+use crate::ConnectionOptions;
+
+/// How often the throughput of the connection is reported to the UI.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Token bucket limiting the rate data is forwarded in one direction.
+pub struct Throttle
+{
+    /// Allowed rate in bytes per second. `None` disables the throttling.
+    rate: Option<f64>,
+    bucket: Mutex<Bucket>,
+    transfer: Mutex<Transfer>,
+}
+
+struct Bucket
+{
+    tokens: f64,
+    last: Instant,
+}
+
+/// The data that has passed through the throttle.
+#[derive(Default)]
+struct Transfer
+{
+    bytes: u64,
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+/// Throttles for both directions of a connection.
+pub struct Throttles
+{
+    pub request: Throttle,
+    pub response: Throttle,
+}
+
+impl Throttle
+{
+    pub fn new(kbps: Option<u64>) -> Self
+    {
+        Self {
+            rate: kbps.map(|kbps| kbps as f64 * 1000.0 / 8.0),
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                last: Instant::now(),
+            }),
+            transfer: Default::default(),
+        }
+    }
+
+    pub fn unlimited() -> Self
+    {
+        Self::new(None)
+    }
+
+    /// Wait until the bytes may be forwarded and record them in the throughput.
+    pub async fn consume(&self, bytes: usize)
+    {
+        let start = Instant::now();
+        self.wait(bytes).await;
+
+        let mut transfer = self.transfer.lock().unwrap();
+        transfer.bytes += bytes as u64;
+        transfer.first.get_or_insert(start);
+        transfer.last = Some(Instant::now());
+    }
+
+    /// Wait until the bytes may be forwarded.
+    ///
+    /// Chunks larger than the bucket are allowed through once the bucket has been refilled for
+    /// the whole chunk so the throttle never stalls on large data frames.
+    async fn wait(&self, bytes: usize)
+    {
+        let rate = match self.rate {
+            Some(rate) => rate,
+            None => return,
+        };
+
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+
+            // Allow bursts of up to 100 ms worth of data.
+            let elapsed = now.duration_since(bucket.last).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(rate / 10.0);
+            bucket.last = now;
+            bucket.tokens -= bytes as f64;
+            match bucket.tokens < 0.0 {
+                true => Duration::from_secs_f64(-bucket.tokens / rate),
+                false => Duration::from_secs(0),
+            }
+        };
+
+        if wait > Duration::from_secs(0) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// The data forwarded so far and the time it took, including the time spent throttled.
+    pub fn throughput(&self) -> Throughput
+    {
+        let transfer = self.transfer.lock().unwrap();
+        Throughput {
+            bytes: transfer.bytes,
+            duration: match (transfer.first, transfer.last) {
+                (Some(first), Some(last)) => last.duration_since(first),
+                _ => Duration::from_secs(0),
+            },
+        }
+    }
+}
+
+impl Throttles
+{
+    pub fn new(options: &ConnectionOptions) -> Self
+    {
+        Self {
+            request: Throttle::new(options.throttle_kbps),
+            response: Throttle::new(options.throttle_kbps),
+        }
+    }
+
+    pub fn get(&self, part: RequestPart) -> &Throttle
+    {
+        match part {
+            RequestPart::Request => &self.request,
+            RequestPart::Response => &self.response,
+        }
+    }
+
+    /// Drive the connection, reporting its throughput to the UI periodically and once more when
+    /// the connection ends.
+    pub async fn report<F: Future>(
+        &self,
+        uuid: Uuid,
+        ui: &Sender<SessionEvent>,
+        connection: F,
+    ) -> F::Output
+    {
+        let mut reported = (Throughput::default(), Throughput::default());
+        let mut report = || {
+            let current = (self.request.throughput(), self.response.throughput());
+            if current != reported {
+                reported = current;
+                let _ = ui.send(SessionEvent::ConnectionThroughput(
+                    ConnectionThroughputEvent {
+                        uuid,
+                        request: current.0,
+                        response: current.1,
+                    },
+                ));
+            }
+        };
+
+        tokio::pin!(connection);
+        let mut interval = tokio::time::interval(REPORT_INTERVAL);
+        loop {
+            tokio::select! {
+                r = &mut connection => {
+                    report();
+                    return r;
+                }
+                _ = interval.tick() => report(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[tokio::test]
+    async fn throughput_includes_throttled_time()
+    {
+        // 80 kbps is 10 kB/s so 5 kB takes about half a second.
+        let throttle = Throttle::new(Some(80));
+        for _ in 0..5 {
+            throttle.consume(1000).await;
+        }
+
+        let throughput = throttle.throughput();
+        assert_eq!(throughput.bytes, 5000);
+        assert!(throughput.duration >= Duration::from_millis(400));
+        assert!(throughput.duration < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn throughput_is_reported()
+    {
+        let (ui_tx, ui_rx) = std::sync::mpsc::channel();
+        let throttles = Throttles::new(&ConnectionOptions::default());
+        let uuid = Uuid::new_v4();
+        let result = throttles
+            .report(uuid, &ui_tx, async {
+                throttles.request.consume(100).await;
+                throttles.response.consume(200).await;
+                "done"
+            })
+            .await;
+        assert_eq!(result, "done");
+
+        let reported: Vec<_> = ui_rx
+            .try_iter()
+            .map(|e| match e {
+                SessionEvent::ConnectionThroughput(e) => e,
+                _ => panic!("Unexpected event"),
+            })
+            .collect();
+        let last = reported.last().expect("The throughput was not reported");
+        assert_eq!(last.uuid, uuid);
+        assert_eq!(last.request.bytes, 100);
+        assert_eq!(last.response.bytes, 200);
+    }
+}
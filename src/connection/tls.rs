@@ -162,7 +162,7 @@ impl ResolvesServerCert for ClientHelloCapture
     }
 }
 
-struct NoVerify;
+pub(crate) struct NoVerify;
 impl ServerCertVerifier for NoVerify
 {
     fn verify_server_cert(
@@ -179,14 +179,14 @@ impl ServerCertVerifier for NoVerify
     }
 }
 
-struct HelloResult
+pub(super) struct HelloResult
 {
-    data: Vec<u8>,
-    sni: String,
-    alpn: Vec<Vec<u8>>,
+    pub data: Vec<u8>,
+    pub sni: String,
+    pub alpn: Vec<Vec<u8>>,
 }
 
-async fn resolve_client_hello<TStream>(client: &mut TStream) -> Result<HelloResult>
+pub(super) async fn resolve_client_hello<TStream>(client: &mut TStream) -> Result<HelloResult>
 where
     TStream: AsyncRead + Unpin,
 {
@@ -268,7 +268,7 @@ where
     })
 }
 
-fn get_certificate(
+pub(crate) fn get_certificate(
     common_name: &str,
     ca: &CADetails,
 ) -> (Vec<rustls::Certificate>, rustls::PrivateKey)
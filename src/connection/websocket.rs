@@ -0,0 +1,192 @@
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use super::http1::{pipe_bytes, source_error, target_error, MessageReader};
+use super::*;
+
+/// Pipe WebSocket frames between the client and the server after a successful upgrade.
+///
+/// The frames are forwarded as is. Frames sent by the client extend the request message and
+/// frames sent by the server the response message. The frame payloads are forwarded and reported
+/// in chunks as they arrive so a large frame is never buffered in whole. The frames are subject
+/// to the same throttling and `--max-body-bytes` limit as the HTTP bodies.
+pub async fn pipe<TClientRead, TClientWrite, TServerRead, TServerWrite>(
+    client_read: &mut MessageReader<TClientRead>,
+    client_write: &mut TClientWrite,
+    server_read: &mut MessageReader<TServerRead>,
+    server_write: &mut TServerWrite,
+    throttles: &Throttles,
+    request_body: &mut BodyRecorder,
+    response_body: &mut BodyRecorder,
+) -> Result<()>
+where
+    TClientRead: AsyncRead + Unpin,
+    TClientWrite: AsyncWrite + Unpin,
+    TServerRead: AsyncRead + Unpin,
+    TServerWrite: AsyncWrite + Unpin,
+{
+    let upstream = pipe_frames(client_read, server_write, &throttles.request, request_body);
+    let downstream = pipe_frames(
+        server_read,
+        client_write,
+        &throttles.response,
+        response_body,
+    );
+    futures::try_join!(upstream, downstream)?;
+    Ok(())
+}
+
+async fn pipe_frames<TRead, TWrite>(
+    source: &mut MessageReader<TRead>,
+    target: &mut TWrite,
+    throttle: &Throttle,
+    body: &mut BodyRecorder,
+) -> Result<()>
+where
+    TRead: AsyncRead + Unpin,
+    TWrite: AsyncWrite + Unpin,
+{
+    let part = body.part;
+    while let Some((header, payload_len)) = read_frame_header(source)
+        .await
+        .map_err(|e| source_error(part, "reading frame", e))?
+    {
+        throttle.consume(header.len()).await;
+        target
+            .write_all(&header)
+            .await
+            .map_err(|e| target_error(part, "writing frame", e))?;
+        body.record(&header.freeze());
+
+        pipe_bytes(source, target, throttle, Some(payload_len), body).await?;
+        target
+            .flush()
+            .await
+            .map_err(|e| target_error(part, "writing frame", e))?;
+    }
+
+    // The source closed its side of the connection. Propagate it to the target.
+    target
+        .shutdown()
+        .await
+        .map_err(|e| target_error(part, "closing stream", e))
+}
+
+/// Read the header of a frame, returning it together with the length of the payload that
+/// follows it.
+///
+/// Returns `None` if the stream was closed on a frame boundary.
+async fn read_frame_header<TRead>(
+    source: &mut MessageReader<TRead>,
+) -> std::io::Result<Option<(BytesMut, u64)>>
+where
+    TRead: AsyncRead + Unpin,
+{
+    let mut header = match source.read_exact(2).await? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    // The 7-bit payload length may be followed by a 16-bit or a 64-bit extended length and
+    // frames sent by the client carry a 4 byte masking key. See RFC 6455, section 5.2.
+    let masked = header[1] & 0x80 != 0;
+    let extended_len = match header[1] & 0x7f {
+        126 => 2,
+        127 => 8,
+        _ => 0,
+    };
+    let mask_len = if masked { 4 } else { 0 };
+    let extension = source
+        .read_exact(extended_len + mask_len)
+        .await?
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+
+    let payload_len = match extended_len {
+        0 => u64::from(header[1] & 0x7f),
+        _ => extension[..extended_len]
+            .iter()
+            .fold(0, |len, b| (len << 8) | u64::from(*b)),
+    };
+    header.extend_from_slice(&extension);
+    Ok(Some((header, payload_len)))
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn payload_is_forwarded_before_the_frame_completes()
+    {
+        let (mut client, source) = tokio::io::duplex(1024);
+        let (mut target, mut server) = tokio::io::duplex(1024);
+        let (ui, _ui_rx) = std::sync::mpsc::channel();
+        tokio::spawn(async move {
+            let mut source = MessageReader::new(source);
+            let mut body = BodyRecorder::new(&ui, Uuid::new_v4(), RequestPart::Response, None);
+            let throttle = Throttle::unlimited();
+            let _ = pipe_frames(&mut source, &mut target, &throttle, &mut body).await;
+        });
+
+        // Binary frame claiming a 1 TiB payload.
+        let header = [0x82, 127, 0, 0, 1, 0, 0, 0, 0, 0];
+        client.write_all(&header).await.unwrap();
+        client.write_all(b"abc").await.unwrap();
+
+        let mut received = [0u8; 13];
+        tokio::time::timeout(Duration::from_secs(10), server.read_exact(&mut received))
+            .await
+            .expect("The payload was not forwarded")
+            .unwrap();
+        assert_eq!(&received[..10], &header);
+        assert_eq!(&received[10..], b"abc");
+    }
+
+    #[tokio::test]
+    async fn recorded_frames_are_limited()
+    {
+        let (mut client, proxy_client) = tokio::io::duplex(1024);
+        let (proxy_server, mut server) = tokio::io::duplex(1024);
+        let (ui, ui_rx) = std::sync::mpsc::channel();
+        let proxy = tokio::spawn(async move {
+            let (client_read, mut client_write) = tokio::io::split(proxy_client);
+            let (server_read, mut server_write) = tokio::io::split(proxy_server);
+            let uuid = Uuid::new_v4();
+            let _ = pipe(
+                &mut MessageReader::new(client_read),
+                &mut client_write,
+                &mut MessageReader::new(server_read),
+                &mut server_write,
+                &Throttles::new(&ConnectionOptions::default()),
+                &mut BodyRecorder::new(&ui, uuid, RequestPart::Request, Some(4)),
+                &mut BodyRecorder::new(&ui, uuid, RequestPart::Response, Some(4)),
+            )
+            .await;
+        });
+
+        // Unmasked text frame with a 5 byte payload sent by the server.
+        let frame = b"\x81\x05hello";
+        server.write_all(frame).await.unwrap();
+        let mut received = [0u8; 7];
+        client.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, frame);
+        drop(server);
+        drop(client);
+        proxy.await.unwrap();
+
+        let (recorded, truncated) = ui_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                SessionEvent::MessageData(e) => Some((e.data.len(), e.truncated)),
+                _ => None,
+            })
+            .fold((0, 0), |(d, t), (data, truncated)| {
+                (d + data, t + truncated)
+            });
+        assert_eq!((recorded, truncated), (4, 3));
+    }
+}
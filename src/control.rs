@@ -0,0 +1,505 @@
+//! JSON/HTTP API for driving Proxide from scripts, enabled with `--control-listen`.
+//!
+//! The API keeps its own copy of the session, built from the same events the UI or the capture
+//! file receive, so it works with both the `monitor` and the `capture` commands.
+//!
+//! The API only reads and saves the recorded traffic. Starting and stopping the capture and the
+//! capture filters remain command line options.
+
+use clap::ArgMatches;
+use serde_json::json;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::decoders::{self, Decoders};
+use crate::session::events::SessionEvent;
+use crate::session::serialization::OutputFormat;
+use crate::session::{EncodedRequest, Session};
+use crate::Error;
+
+/// Largest request head the API accepts. The API doesn't take request bodies.
+const MAX_REQUEST_SIZE: usize = 16 * 1024;
+
+/// Number of requests the API keeps when `--max-requests` isn't given.
+///
+/// The capture command streams the traffic to disk so the API's copy of the session must not grow
+/// without bounds in long captures.
+const DEFAULT_MAX_REQUESTS: usize = 10_000;
+
+/// How long a client may stall while sending its request or receiving the response.
+///
+/// The requests are served one at a time so a client that never finishes its request would block
+/// everyone else.
+const IO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Start serving the API on the local port.
+///
+/// Returns the sender the session events should be sent to. The events are recorded for the API
+/// and passed on to the target. Returns the port the API ended up listening to as well since the
+/// given port may be 0. Only the latest `max_requests` requests are kept for the API.
+pub fn serve(
+    port: u16,
+    max_requests: Option<usize>,
+    matches: &ArgMatches,
+    target: Sender<SessionEvent>,
+) -> Result<(Sender<SessionEvent>, u16), Error>
+{
+    // The API is meant for local automation so it's never exposed to the network.
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| Error::RuntimeError {
+        msg: format!("Could not listen on control port {}: {}", port, e),
+    })?;
+    let port = listener
+        .local_addr()
+        .map(|addr| addr.port())
+        .unwrap_or(port);
+
+    let max_requests = max_requests.unwrap_or(DEFAULT_MAX_REQUESTS);
+    let session = Arc::new(Mutex::new(Session::default()));
+    let (tx, rx) = std::sync::mpsc::channel::<SessionEvent>();
+    std::thread::spawn({
+        let session = session.clone();
+        move || {
+            while let Ok(e) = rx.recv() {
+                {
+                    let mut session = session.lock().unwrap();
+                    session.handle(e.clone());
+                    session.evict_requests(max_requests);
+                }
+                if target.send(e).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let matches = matches.clone();
+    std::thread::spawn(move || {
+        // The decoders aren't thread safe so they are created on the thread that uses them.
+        let decoders = decoders::get_decoders(&matches).unwrap_or_else(|e| {
+            log::error!("Control API could not set up the decoders: {}", e);
+            Decoders::new(vec![])
+        });
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = handle_connection(stream, &session, &decoders) {
+                        log::warn!("Control API request failed: {}", e);
+                    }
+                }
+                Err(e) => log::warn!("Control API connection failed: {}", e),
+            }
+        }
+    });
+
+    Ok((tx, port))
+}
+
+struct Response
+{
+    status: u16,
+    body: serde_json::Value,
+}
+
+impl Response
+{
+    fn ok(body: serde_json::Value) -> Self
+    {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, msg: &str) -> Self
+    {
+        Self {
+            status,
+            body: json!({ "error": msg }),
+        }
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    session: &Mutex<Session>,
+    decoders: &Decoders,
+) -> std::io::Result<()>
+{
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    // Read the request head. Requests are handled one at a time and the connection is closed
+    // after the response so there is no need to care about anything after the head.
+    let mut data = vec![];
+    let mut buffer = [0; 1024];
+    let (method, target, local) = loop {
+        let count = stream.read(&mut buffer)?;
+        if count == 0 {
+            return Ok(());
+        }
+        data.extend_from_slice(&buffer[..count]);
+
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut request = httparse::Request::new(&mut headers);
+        match request.parse(&data) {
+            Ok(httparse::Status::Complete(_)) => {
+                break (
+                    request.method.unwrap_or("").to_string(),
+                    request.path.unwrap_or("").to_string(),
+                    is_local_request(request.headers),
+                )
+            }
+            Ok(httparse::Status::Partial) if data.len() < MAX_REQUEST_SIZE => {}
+            _ => return write_response(&mut stream, Response::error(400, "Invalid HTTP request")),
+        }
+    };
+
+    if !local {
+        return write_response(
+            &mut stream,
+            Response::error(403, "Requests from web pages are not allowed"),
+        );
+    }
+
+    let response = route(&method, &target, session, decoders);
+    write_response(&mut stream, response)
+}
+
+/// Check the request came from a local tool instead of a web page.
+///
+/// Listening only on the loopback interface isn't enough since any web page the user has open can
+/// send requests to it. Browsers include the `Origin` header in cross-origin requests and a page
+/// relying on DNS rebinding has its own host name in the `Host` header so such requests are
+/// rejected.
+fn is_local_request(headers: &[httparse::Header]) -> bool
+{
+    if headers
+        .iter()
+        .any(|h| h.name.eq_ignore_ascii_case("origin"))
+    {
+        return false;
+    }
+
+    let host = match headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("host"))
+        .and_then(|h| std::str::from_utf8(h.value).ok())
+    {
+        Some(host) => host.trim().to_ascii_lowercase(),
+        None => return false,
+    };
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !host.ends_with(']') && port.bytes().all(|b| b.is_ascii_digit()) => {
+            name
+        }
+        _ => &host,
+    };
+    matches!(name, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+fn write_response(stream: &mut TcpStream, response: Response) -> std::io::Result<()>
+{
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let body = serde_json::to_string_pretty(&response.body).expect("JSON is always serializable");
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn route(method: &str, target: &str, session: &Mutex<Session>, decoders: &Decoders) -> Response
+{
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (target, ""),
+    };
+    let query: Vec<_> = decoders::form::parse_pairs(query.as_bytes()).collect();
+    let param = |name: &str| {
+        query
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+
+    let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let mut session = session.lock().unwrap();
+    match (method, segments.as_slice()) {
+        ("GET", ["connections"]) => Response::ok(json!(session.connections.items)),
+        ("GET", ["requests"]) => {
+            let path_filter = param("path").map(wildmatch::WildMatch::new);
+            let host_filter = param("host").map(wildmatch::WildMatch::new);
+            let requests: Vec<_> = session
+                .requests
+                .iter()
+                .filter(|r| {
+                    let uri = &r.request_data.uri;
+                    let host = uri
+                        .host()
+                        .or_else(|| {
+                            let host = r.request_msg.headers.get("host")?.to_str().ok()?;
+                            host.split(':').next()
+                        })
+                        .unwrap_or("");
+                    path_filter
+                        .as_ref()
+                        .map(|f| f.is_match(uri.path()))
+                        .unwrap_or(true)
+                        && host_filter
+                            .as_ref()
+                            .map(|f| f.is_match(host))
+                            .unwrap_or(true)
+                })
+                .map(request_summary)
+                .collect();
+            Response::ok(json!(requests))
+        }
+        ("GET", ["requests", uuid]) => {
+            let request = match Uuid::parse_str(uuid)
+                .ok()
+                .and_then(|uuid| session.requests.get_by_uuid(uuid))
+            {
+                Some(request) => request,
+                None => return Response::error(404, "Request not found"),
+            };
+            Response::ok(json!({
+                "request_data": request.request_data,
+                "request": crate::json::get_message_data(
+                    &request.request_data,
+                    &request.request_msg,
+                    decoders
+                ),
+                "response": crate::json::get_message_data(
+                    &request.request_data,
+                    &request.response_msg,
+                    decoders
+                ),
+            }))
+        }
+        ("POST", ["session", "save"]) => {
+            let file = match param("file") {
+                Some(file) => file,
+                None => return Response::error(400, "Missing 'file' parameter"),
+            };
+            let format = match param("format") {
+                None | Some("msgpack") => OutputFormat::MessagePack,
+                Some("json") => OutputFormat::Json,
+                Some(_) => return Response::error(400, "Unsupported format"),
+            };
+            match session.write_to_file(file, format) {
+                Ok(_) => Response::ok(json!({ "file": file })),
+                Err(e) => Response::error(500, &e.to_string()),
+            }
+        }
+        ("POST", ["session", "clear"]) => {
+            *session = Session::default();
+            Response::ok(json!({}))
+        }
+        (_, ["connections"])
+        | (_, ["requests"])
+        | (_, ["requests", _])
+        | (_, ["session", "save"])
+        | (_, ["session", "clear"]) => Response::error(405, "Method not allowed"),
+        _ => Response::error(404, "Not found"),
+    }
+}
+
+fn request_summary(request: &EncodedRequest) -> serde_json::Value
+{
+    let data = &request.request_data;
+    json!({
+        "uuid": data.uuid,
+        "connection_uuid": data.connection_uuid,
+        "method": data.method.as_str(),
+        "uri": data.uri.to_string(),
+        "status": data.status,
+        "response_status": data.response_status,
+        "start_timestamp": data.start_timestamp,
+        "end_timestamp": data.end_timestamp,
+    })
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::session::events::*;
+    use crate::session::Protocol;
+
+    fn session(uris: &[&str]) -> (Mutex<Session>, Vec<Uuid>)
+    {
+        let connection = Uuid::new_v4();
+        let mut session = Session::default();
+        session.handle(SessionEvent::NewConnection(NewConnectionEvent {
+            uuid: connection,
+            protocol_stack: vec![Protocol::Http1],
+            client_addr: "127.0.0.1:1234".parse().unwrap(),
+            timestamp: std::time::SystemTime::now(),
+            connect_attempts: 1,
+        }));
+
+        let uuids = uris
+            .iter()
+            .map(|uri| {
+                let uuid = Uuid::new_v4();
+                session.handle(SessionEvent::NewRequest(NewRequestEvent {
+                    connection_uuid: connection,
+                    uuid,
+                    uri: uri.parse().unwrap(),
+                    method: http::Method::GET,
+                    headers: Default::default(),
+                    timestamp: std::time::SystemTime::now(),
+                    injected_fault: false,
+                }));
+                uuid
+            })
+            .collect();
+        (Mutex::new(session), uuids)
+    }
+
+    fn get(session: &Mutex<Session>, target: &str) -> Response
+    {
+        route("GET", target, session, &Decoders::new(vec![]))
+    }
+
+    fn listed_uris(response: Response) -> Vec<String>
+    {
+        assert_eq!(response.status, 200);
+        response
+            .body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["uri"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn requests_are_listed()
+    {
+        let (session, _) = session(&["http://example.com/api/a", "http://other.com/b"]);
+        assert_eq!(
+            listed_uris(get(&session, "/requests")),
+            vec!["http://example.com/api/a", "http://other.com/b"]
+        );
+
+        let connections = get(&session, "/connections");
+        assert_eq!(connections.status, 200);
+        assert_eq!(connections.body.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn requests_are_filtered()
+    {
+        let (session, _) = session(&[
+            "http://example.com/api/a",
+            "http://example.com/b",
+            "http://other.com/api/c",
+        ]);
+        assert_eq!(
+            listed_uris(get(&session, "/requests?path=/api/*")),
+            vec!["http://example.com/api/a", "http://other.com/api/c"]
+        );
+        assert_eq!(
+            listed_uris(get(&session, "/requests?host=example.*")),
+            vec!["http://example.com/api/a", "http://example.com/b"]
+        );
+        assert_eq!(
+            listed_uris(get(&session, "/requests?path=/api/*&host=other.com")),
+            vec!["http://other.com/api/c"]
+        );
+    }
+
+    #[test]
+    fn requests_are_found_by_uuid()
+    {
+        let (session, uuids) = session(&["http://example.com/a", "http://example.com/b"]);
+        let response = get(&session, &format!("/requests/{}", uuids[1]));
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.body["request_data"]["uuid"],
+            json!(uuids[1].to_string())
+        );
+        assert!(response.body.get("request").is_some());
+        assert!(response.body.get("response").is_some());
+
+        let response = get(&session, &format!("/requests/{}", Uuid::new_v4()));
+        assert_eq!(response.status, 404);
+        assert_eq!(get(&session, "/requests/not-a-uuid").status, 404);
+    }
+
+    #[test]
+    fn unknown_requests_are_rejected()
+    {
+        let (session, _) = session(&["http://example.com/a"]);
+        let decoders = Decoders::new(vec![]);
+        assert_eq!(get(&session, "/unknown").status, 404);
+        assert_eq!(get(&session, "/requests/a/b").status, 404);
+        assert_eq!(
+            route("DELETE", "/requests", &session, &decoders).status,
+            405
+        );
+        assert_eq!(
+            route("POST", "/connections", &session, &decoders).status,
+            405
+        );
+        assert_eq!(get(&session, "/session/save").status, 405);
+        assert_eq!(get(&session, "/session/clear").status, 405);
+    }
+
+    #[test]
+    fn session_is_cleared()
+    {
+        let (session, _) = session(&["http://example.com/a"]);
+        let response = route("POST", "/session/clear", &session, &Decoders::new(vec![]));
+        assert_eq!(response.status, 200);
+        assert!(listed_uris(get(&session, "/requests")).is_empty());
+    }
+
+    fn header<'a>(name: &'a str, value: &'a str) -> httparse::Header<'a>
+    {
+        httparse::Header {
+            name,
+            value: value.as_bytes(),
+        }
+    }
+
+    #[test]
+    fn only_local_requests_are_allowed()
+    {
+        for host in &[
+            "localhost",
+            "localhost:5556",
+            "127.0.0.1:5556",
+            "[::1]",
+            "[::1]:5556",
+        ] {
+            assert!(is_local_request(&[header("Host", host)]), "{}", host);
+        }
+
+        for host in &[
+            "example.com",
+            "example.com:5556",
+            "localhost.example.com",
+            "127.0.0.2",
+        ] {
+            assert!(!is_local_request(&[header("Host", host)]), "{}", host);
+        }
+        assert!(!is_local_request(&[]));
+        assert!(!is_local_request(&[
+            header("Host", "localhost:5556"),
+            header("Origin", "http://example.com"),
+        ]));
+    }
+}
@@ -1,11 +1,29 @@
 use http::header::{HeaderName, HeaderValue};
 use snafu::Snafu;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
 use tui::text::{Span, Spans, Text};
+use uuid::Uuid;
 
-use crate::session::{MessageData, RequestData};
+use crate::session::{MessageData, RequestData, RequestPart};
 
+/// Name of a decoder option namespaced with the decoder name, such as `json.max-depth`.
+macro_rules! decoder_option {
+    ($decoder:literal, $option:literal) => {
+        concat!($decoder, ".", $option)
+    };
+}
+
+pub mod compression;
+pub mod form;
 pub mod grpc;
+pub mod json;
+pub mod multipart;
 pub mod raw;
+pub mod sse;
+pub mod websocket;
+pub mod xml;
 
 #[derive(Debug, Snafu)]
 pub enum Error
@@ -28,21 +46,112 @@ pub enum Error
 
 type Result<S, E = Error> = std::result::Result<S, E>;
 
+/// Names of the decoders that can be chosen with `--default-decoder` or `--disable-decoder`.
+const DECODER_NAMES: &[&str] = &[
+    "hex",
+    "json",
+    "form",
+    "multipart",
+    "xml",
+    "sse",
+    "websocket",
+    "grpc",
+    grpc::TEXT_FORMAT_DECODER,
+    "protobuf",
+];
+
 pub fn setup_args(app: clap::App) -> clap::App
 {
-    grpc::setup_args(app)
+    json::setup_args(grpc::setup_args(app))
+        .arg(
+            clap::Arg::with_name("default-decoder")
+                .long("default-decoder")
+                .value_name("NAME")
+                .possible_values(DECODER_NAMES)
+                .help("Show the messages with the decoder by default when it supports the message")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("disable-decoder")
+                .long("disable-decoder")
+                .value_name("NAME")
+                .possible_values(DECODER_NAMES)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Disable the decoder. The hex decoder is still used if no other decoder supports the message")
+                .takes_value(true),
+        )
 }
 
 pub fn get_decoders(matches: &clap::ArgMatches) -> Result<Decoders, Error>
 {
-    let decoders = vec![raw::initialize(matches)?, grpc::initialize(matches)?];
+    let args = |decoder| DecoderArgs { decoder, matches };
+    let mut decoders: Vec<_> = vec![
+        raw::initialize(&args("hex"))?,
+        json::initialize(&args("json"))?,
+        form::initialize(&args("form"))?,
+        multipart::initialize(&args("multipart"))?,
+        xml::initialize(&args("xml"))?,
+        sse::initialize(&args("sse"))?,
+        websocket::initialize(&args("websocket"))?,
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    decoders.extend(grpc::initialize(&args("grpc"))?);
+
+    let mut decoders = Decoders::new(decoders);
+    decoders.default_decoder = matches.value_of("default-decoder").map(String::from);
+    decoders.disabled = matches
+        .values_of("disable-decoder")
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect();
+    Ok(decoders)
+}
 
-    Ok(Decoders::new(decoders.into_iter().flatten()))
+/// Command line options of a single decoder.
+///
+/// The decoders register their options namespaced with the decoder name using
+/// `decoder_option!`, such as `--json.max-depth`, and look them up here without the namespace.
+pub struct DecoderArgs<'a>
+{
+    decoder: &'static str,
+    matches: &'a clap::ArgMatches,
+}
+
+impl<'a> DecoderArgs<'a>
+{
+    fn id(&self, option: &str) -> String
+    {
+        format!("{}.{}", self.decoder, option)
+    }
+
+    pub fn value_of(&self, option: &str) -> Option<&'a str>
+    {
+        self.matches.value_of(self.id(option))
+    }
+
+    /// All the options, including the ones that predate the namespaces, such as `--grpc`.
+    pub fn matches(&self) -> &'a clap::ArgMatches
+    {
+        self.matches
+    }
 }
 
 pub struct Decoders
 {
     factories: Vec<Box<dyn DecoderFactory>>,
+
+    /// Decoder preferred over the others when it supports the message.
+    pub default_decoder: Option<String>,
+
+    /// Decoders that are never used, unless the message can't be decoded otherwise.
+    pub disabled: Vec<String>,
+
+    /// Content of the recent messages decompressed based on their `content-encoding`.
+    content: ContentCache,
 }
 
 impl Decoders
@@ -51,22 +160,64 @@ impl Decoders
     {
         Self {
             factories: decoders.into_iter().collect(),
+            default_decoder: None,
+            disabled: vec![],
+            content: ContentCache::default(),
         }
     }
 
+    /// Get the decoders that support the message.
+    ///
+    /// The decoders are listed from the lowest priority, so the last one is the preferred one.
     pub fn get_decoders<'a>(
         &'a self,
         request: &'a RequestData,
         message: &'a MessageData,
     ) -> impl Iterator<Item = Box<dyn Decoder>> + 'a
     {
-        self.factories
+        let decoded = self.content.get(request, message);
+        let all: Vec<_> = self
+            .factories
             .iter()
-            .filter_map(move |d| d.try_create(request, message))
+            .filter_map(move |d| match &decoded {
+                None => d.try_create(request, message),
+                Some(decoded) => d.try_create(request, decoded).map(|inner| {
+                    Box::new(ContentEncodingDecoder {
+                        inner,
+                        message: decoded.clone(),
+                    }) as Box<dyn Decoder>
+                }),
+            })
+            .collect();
+
+        // The hex decoder supports all messages so keeping it ensures there is always at least
+        // one decoder even if it was disabled.
+        let (mut enabled, disabled): (Vec<_>, Vec<_>) = all
+            .into_iter()
+            .partition(|d| !self.disabled.iter().any(|name| name == d.name()));
+        if enabled.is_empty() {
+            enabled.extend(disabled.into_iter().filter(|d| d.name() == "hex"));
+        }
+
+        if let Some(name) = &self.default_decoder {
+            if let Some(idx) = enabled.iter().position(|d| d.name() == name) {
+                let preferred = enabled.remove(idx);
+                enabled.push(preferred);
+            }
+        }
+        enabled.into_iter()
+    }
+
+    /// Whether the decoder was chosen with `--default-decoder`.
+    pub fn is_default(&self, name: &str) -> bool
+    {
+        self.default_decoder.as_deref() == Some(name)
     }
 
     pub fn index(&self, request: &RequestData, message: &MessageData) -> Vec<String>
     {
+        let decoded = self.content.get(request, message);
+        let message = decoded.as_deref().unwrap_or(message);
         self.factories
             .iter()
             .filter_map(|d| d.try_create(request, message))
@@ -75,6 +226,147 @@ impl Decoders
     }
 }
 
+/// Number of messages the decompressed content is cached for.
+const CONTENT_CACHE_SIZE: usize = 8;
+
+/// Decompressed content of the recently displayed messages.
+///
+/// The decoders are looked up again on every redraw. Caching the content keeps it from being
+/// decompressed each time.
+#[derive(Default)]
+struct ContentCache
+{
+    /// Most recently used entry first.
+    entries: RefCell<Vec<CachedContent>>,
+}
+
+struct CachedContent
+{
+    request: Uuid,
+    part: RequestPart,
+
+    /// Length of the content the entry was decompressed from. The content only grows while the
+    /// message is being received.
+    length: usize,
+    complete: bool,
+    decoded: Option<Rc<MessageData>>,
+}
+
+impl ContentCache
+{
+    fn get(&self, request: &RequestData, message: &MessageData) -> Option<Rc<MessageData>>
+    {
+        let mut entries = self.entries.borrow_mut();
+        let cached = entries
+            .iter()
+            .position(|e| e.request == request.uuid && e.part == message.part)
+            .map(|idx| entries.remove(idx))
+            .filter(|e| {
+                e.length == message.content.len() && e.complete == message.end_timestamp.is_some()
+            });
+        let entry = cached.unwrap_or_else(|| CachedContent {
+            request: request.uuid,
+            part: message.part,
+            length: message.content.len(),
+            complete: message.end_timestamp.is_some(),
+            decoded: decode_content(message).map(Rc::new),
+        });
+
+        let decoded = entry.decoded.clone();
+        entries.insert(0, entry);
+        entries.truncate(CONTENT_CACHE_SIZE);
+        decoded
+    }
+}
+
+/// Decompress the message content based on the `content-encoding` header.
+///
+/// Returns `None` if the content isn't encoded or it can't be decompressed, such as when the
+/// message is still being received. In that case the decoders work on the content as is.
+fn decode_content(message: &MessageData) -> Option<MessageData>
+{
+    let encodings = message
+        .headers
+        .get_all("content-encoding")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(str::trim)
+        .filter(|e| !e.is_empty() && !e.eq_ignore_ascii_case("identity"))
+        .collect::<Vec<_>>();
+    if encodings.is_empty() {
+        return None;
+    }
+
+    // The encodings are listed in the order they were applied.
+    let mut content = message.content.to_vec();
+    for encoding in encodings.iter().rev() {
+        content = match compression::decompress(encoding, &content) {
+            Ok(content) => content,
+            Err(e @ compression::Error::TooLarge { .. }) => {
+                log::warn!("Could not decode content: {}", e);
+                return None;
+            }
+            Err(e) => {
+                log::debug!("Could not decode content: {}", e);
+                return None;
+            }
+        };
+    }
+
+    Some(MessageData {
+        headers: message.headers.clone(),
+        trailers: message.trailers.clone(),
+        content: content.as_slice().into(),
+        start_timestamp: message.start_timestamp,
+        end_timestamp: message.end_timestamp,
+        part: message.part,
+        error: message.error.clone(),
+        truncated: message.truncated,
+    })
+}
+
+/// Decoder that passes the decompressed content to the inner decoder instead of the encoded
+/// content it is invoked with.
+struct ContentEncodingDecoder
+{
+    inner: Box<dyn Decoder>,
+    message: Rc<MessageData>,
+}
+
+impl Decoder for ContentEncodingDecoder
+{
+    fn name(&self) -> &'static str
+    {
+        self.inner.name()
+    }
+
+    fn decode(&self, _msg: &MessageData) -> Text<'_>
+    {
+        self.inner.decode(&self.message)
+    }
+
+    fn index(&self, _msg: &MessageData) -> Vec<String>
+    {
+        self.inner.index(&self.message)
+    }
+
+    fn is_opt_in(&self) -> bool
+    {
+        self.inner.is_opt_in()
+    }
+
+    fn line_count(&self, _msg: &MessageData) -> Option<usize>
+    {
+        self.inner.line_count(&self.message)
+    }
+
+    fn decode_lines(&self, _msg: &MessageData, lines: Range<usize>) -> Text<'_>
+    {
+        self.inner.decode_lines(&self.message, lines)
+    }
+}
+
 /// A factory for constructing decoders.
 pub trait DecoderFactory
 {
@@ -88,6 +380,107 @@ pub trait Decoder
     fn name(&self) -> &'static str;
     fn decode(&self, msg: &MessageData) -> Text;
     fn index(&self, msg: &MessageData) -> Vec<String>;
+
+    /// Whether the decoder is used only when it is chosen explicitly or made the default.
+    ///
+    /// Such decoders are never picked as the preferred decoder of the messages they support.
+    fn is_opt_in(&self) -> bool
+    {
+        false
+    }
+
+    /// Number of lines in the decoded output if the decoder can decode only some of the lines.
+    ///
+    /// Allows drawing large messages without decoding the parts that aren't visible.
+    fn line_count(&self, _msg: &MessageData) -> Option<usize>
+    {
+        None
+    }
+
+    /// Decode the given lines of the message. Only called if `line_count` is known.
+    fn decode_lines(&self, msg: &MessageData, _lines: Range<usize>) -> Text<'_>
+    {
+        self.decode(msg)
+    }
+}
+
+/// Decode percent-encoded bytes.
+///
+/// Invalid escapes are kept as they are as the value is only displayed.
+fn percent_decode(value: &[u8]) -> String
+{
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut output = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        match (
+            value[i],
+            value.get(i + 1).copied().and_then(hex),
+            value.get(i + 2).copied().and_then(hex),
+        ) {
+            (b'%', Some(high), Some(low)) => {
+                output.push(high << 4 | low);
+                i += 3;
+            }
+            (b, _, _) => {
+                output.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+/// Write the data as `offset  hex bytes  ascii` lines.
+fn hex_dump(data: &[u8], builder: &mut TextBuilder)
+{
+    for (i, chunk) in data.chunks(16).enumerate() {
+        let hex = chunk
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii: String = chunk
+            .iter()
+            .map(|b| match b.is_ascii_graphic() || *b == b' ' {
+                true => *b as char,
+                false => '.',
+            })
+            .collect();
+        builder.push(Span::raw(format!(
+            "{:08x}  {:<47}  {}\n",
+            i * 16,
+            hex,
+            ascii
+        )));
+    }
+}
+
+#[derive(Default)]
+struct TextBuilder<'a>
+{
+    last_line: Vec<Span<'a>>,
+    lines: Vec<Spans<'a>>,
+}
+
+impl<'a> TextBuilder<'a>
+{
+    fn push(&mut self, span: Span<'a>)
+    {
+        let has_linebreak = span.content.contains('\n');
+        self.last_line.push(span);
+        if has_linebreak {
+            let mut line = vec![];
+            std::mem::swap(&mut line, &mut self.last_line);
+            self.lines.push(Spans::from(line));
+        }
+    }
+
+    fn build(mut self) -> Text<'a>
+    {
+        self.lines.push(Spans::from(self.last_line));
+        Text::from(self.lines)
+    }
 }
 
 struct HeaderDecoder;
@@ -141,3 +534,124 @@ impl HeaderDecoder
         output
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::session::{RequestPart, Status};
+    use http::HeaderMap;
+
+    #[test]
+    fn decode_gzip_content_encoding()
+    {
+        // "Hello, Proxide!" compressed with Python's zlib module.
+        let data = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xf3, 0x48, 0xcd, 0xc9,
+            0xc9, 0xd7, 0x51, 0x08, 0x28, 0xca, 0xaf, 0xc8, 0x4c, 0x49, 0x55, 0x04, 0x00, 0xfa,
+            0x64, 0xb0, 0x34, 0x0f, 0x00, 0x00, 0x00,
+        ];
+
+        let mut headers = HeaderMap::new();
+        headers.insert("content-encoding", HeaderValue::from_static("gzip"));
+        let mut msg = MessageData::new(RequestPart::Response).with_headers(headers);
+        msg.content.extend_from_slice(&data);
+
+        let decoded = decode_content(&msg).unwrap();
+        assert_eq!(&decoded.content[..], b"Hello, Proxide!");
+
+        // Partial content is left as is.
+        msg.content.truncate(20);
+        assert!(decode_content(&msg).is_none());
+    }
+
+    fn test_request() -> RequestData
+    {
+        RequestData {
+            uuid: Uuid::new_v4(),
+            connection_uuid: Uuid::new_v4(),
+            method: http::Method::GET,
+            uri: "/".parse().unwrap(),
+            start_timestamp: chrono::Local::now(),
+            end_timestamp: None,
+            status: Status::InProgress,
+            response_status: None,
+            injected_fault: false,
+            timed_out: false,
+            reason: None,
+            alias: None,
+        }
+    }
+
+    #[test]
+    fn decompressed_content_is_cached()
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-encoding", HeaderValue::from_static("deflate"));
+        let mut msg = MessageData::new(RequestPart::Response).with_headers(headers);
+        let request = test_request();
+
+        // Incomplete data isn't decoded but becomes decodable once the rest arrives.
+        let data = [
+            0x78, 0x9c, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x08, 0x28, 0xca, 0xaf, 0xc8,
+            0x4c, 0x49, 0x55, 0x04, 0x00, 0x2a, 0x41, 0x05, 0x3d,
+        ];
+        let cache = ContentCache::default();
+        msg.content.extend_from_slice(&data[..10]);
+        assert!(cache.get(&request, &msg).is_none());
+        msg.content.extend_from_slice(&data[10..]);
+        let decoded = cache.get(&request, &msg).unwrap();
+        assert_eq!(&decoded.content[..], b"Hello, Proxide!");
+        assert!(Rc::ptr_eq(&decoded, &cache.get(&request, &msg).unwrap()));
+    }
+
+    struct OptInDecoder;
+    impl DecoderFactory for OptInDecoder
+    {
+        fn try_create(&self, _req: &RequestData, _msg: &MessageData) -> Option<Box<dyn Decoder>>
+        {
+            Some(Box::new(OptInDecoder))
+        }
+    }
+
+    impl Decoder for OptInDecoder
+    {
+        fn name(&self) -> &'static str
+        {
+            "opt-in"
+        }
+
+        fn decode(&self, _msg: &MessageData) -> Text
+        {
+            Text::raw("")
+        }
+
+        fn index(&self, _msg: &MessageData) -> Vec<String>
+        {
+            vec![]
+        }
+
+        fn is_opt_in(&self) -> bool
+        {
+            true
+        }
+    }
+
+    #[test]
+    fn decoders_of_encoded_content_stay_opt_in()
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-encoding", HeaderValue::from_static("deflate"));
+        let mut msg = MessageData::new(RequestPart::Response).with_headers(headers);
+        msg.content.extend_from_slice(&[
+            0x78, 0x9c, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x08, 0x28, 0xca, 0xaf, 0xc8,
+            0x4c, 0x49, 0x55, 0x04, 0x00, 0x2a, 0x41, 0x05, 0x3d,
+        ]);
+        let request = test_request();
+
+        let decoders = Decoders::new(vec![Box::new(OptInDecoder) as Box<dyn DecoderFactory>]);
+        let found: Vec<_> = decoders.get_decoders(&request, &msg).collect();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].is_opt_in());
+    }
+}
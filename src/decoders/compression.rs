@@ -0,0 +1,191 @@
+//! Decompression of message payloads.
+//!
+//! Handles the gzip (RFC 1952), zlib (RFC 1950), raw deflate (RFC 1951) and brotli (RFC 7932) data
+//! used by gRPC and HTTP content encodings. Only decompression is needed as Proxide never
+//! re-encodes the traffic it displays.
+
+use brotli_decompressor::Decompressor;
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use snafu::{ResultExt, Snafu};
+use std::io::Read;
+
+/// Maximum size of the decompressed data.
+///
+/// The compressed data comes from the monitored peers so a small payload could otherwise expand
+/// into gigabytes in memory.
+pub const MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Snafu)]
+pub enum Error
+{
+    #[snafu(display("Unsupported encoding '{}'", encoding))]
+    UnsupportedEncoding
+    {
+        encoding: String
+    },
+
+    #[snafu(display("The data is compressed but its encoding is not known"))]
+    UnknownEncoding,
+
+    #[snafu(display("Invalid {} data: {}", encoding, source))]
+    InvalidData
+    {
+        encoding: &'static str,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The decompressed data exceeds the limit of {} bytes", limit))]
+    TooLarge
+    {
+        limit: u64
+    },
+}
+
+type Result<S, E = Error> = std::result::Result<S, E>;
+
+/// Decompress the data based on the encoding name used in `grpc-encoding` or `content-encoding`.
+pub fn decompress(encoding: &str, data: &[u8]) -> Result<Vec<u8>>
+{
+    match encoding.trim().to_ascii_lowercase().as_str() {
+        "identity" => Ok(data.to_vec()),
+        "gzip" | "x-gzip" => gunzip(data),
+        "deflate" => zlib(data).or_else(|_| inflate(data)),
+        "br" => unbrotli(data),
+        other => Err(Error::UnsupportedEncoding {
+            encoding: other.to_string(),
+        }),
+    }
+}
+
+/// Decompress gzip data, verifying the CRC and the size in the trailer.
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>>
+{
+    read_limited("gzip", GzDecoder::new(data), MAX_DECOMPRESSED_SIZE)
+}
+
+/// Decompress zlib wrapped deflate data, verifying the Adler-32 checksum.
+pub fn zlib(data: &[u8]) -> Result<Vec<u8>>
+{
+    read_limited("zlib", ZlibDecoder::new(data), MAX_DECOMPRESSED_SIZE)
+}
+
+/// Decompress raw deflate data.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>>
+{
+    read_limited("deflate", DeflateDecoder::new(data), MAX_DECOMPRESSED_SIZE)
+}
+
+/// Decompress brotli data.
+pub fn unbrotli(data: &[u8]) -> Result<Vec<u8>>
+{
+    read_limited(
+        "brotli",
+        Decompressor::new(data, 4096),
+        MAX_DECOMPRESSED_SIZE,
+    )
+}
+
+/// Read the decompressed data, failing if there is more than `limit` bytes of it.
+pub fn read_limited(encoding: &'static str, reader: impl Read, limit: u64) -> Result<Vec<u8>>
+{
+    // Reading one byte past the limit tells whether the data would have continued.
+    let mut output = vec![];
+    reader
+        .take(limit + 1)
+        .read_to_end(&mut output)
+        .context(InvalidData { encoding })?;
+    match output.len() as u64 > limit {
+        true => Err(Error::TooLarge { limit }),
+        false => Ok(output),
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    // Test vectors have been compressed with Python's zlib and gzip modules.
+    const TEXT: &[u8] = b"Hello, Proxide! Hello, Proxide! Hello, Proxide!";
+    const GZIP: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9,
+        0xd7, 0x51, 0x08, 0x28, 0xca, 0xaf, 0xc8, 0x4c, 0x49, 0x55, 0x54, 0xf0, 0xc0, 0xcf, 0x07,
+        0x00, 0x2e, 0x01, 0x37, 0xf5, 0x2f, 0x00, 0x00, 0x00,
+    ];
+    const ZLIB: &[u8] = &[
+        0x78, 0x9c, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x08, 0x28, 0xca, 0xaf, 0xc8, 0x4c,
+        0x49, 0x55, 0x54, 0xf0, 0xc0, 0xcf, 0x07, 0x00, 0x80, 0x14, 0x0f, 0xf5,
+    ];
+
+    #[test]
+    fn decompress_gzip()
+    {
+        assert_eq!(decompress("gzip", GZIP).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn decompress_deflate()
+    {
+        assert_eq!(decompress("deflate", ZLIB).unwrap(), TEXT);
+
+        // Some servers send raw deflate data without the zlib wrapper.
+        assert_eq!(
+            decompress("deflate", &ZLIB[2..ZLIB.len() - 4]).unwrap(),
+            TEXT
+        );
+    }
+
+    #[test]
+    fn decompress_brotli()
+    {
+        // Compressed with the brotli crate at quality 11.
+        let data = [
+            0x1b, 0x2e, 0x00, 0xf8, 0x9d, 0x09, 0x76, 0x0c, 0x65, 0x4a, 0x49, 0xbb, 0xa1, 0x12,
+            0x04, 0x09, 0xaa, 0xca, 0xe4, 0xba, 0x2c, 0x8d, 0xed, 0x4d, 0x87, 0xb0, 0x90, 0xe1,
+            0x89, 0xa6, 0x62, 0x00, 0x93, 0x5a, 0x7f, 0x03,
+        ];
+        assert_eq!(decompress("br", &data).unwrap(), TEXT);
+
+        // A stream that is still being received is not complete brotli data.
+        assert!(decompress("br", &data[..20]).is_err());
+    }
+
+    #[test]
+    fn checksums_are_verified()
+    {
+        let mut gzip = GZIP.to_vec();
+        let crc = gzip.len() - 8;
+        gzip[crc] ^= 0xff;
+        assert!(matches!(
+            decompress("gzip", &gzip),
+            Err(Error::InvalidData { .. })
+        ));
+
+        let mut zlib_data = ZLIB.to_vec();
+        let adler = zlib_data.len() - 1;
+        zlib_data[adler] ^= 0xff;
+        assert!(matches!(zlib(&zlib_data), Err(Error::InvalidData { .. })));
+    }
+
+    #[test]
+    fn output_size_is_limited()
+    {
+        assert_eq!(
+            read_limited("gzip", GzDecoder::new(GZIP), TEXT.len() as u64).unwrap(),
+            TEXT
+        );
+        assert!(matches!(
+            read_limited("gzip", GzDecoder::new(GZIP), TEXT.len() as u64 - 1),
+            Err(Error::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn unsupported_encoding()
+    {
+        assert!(matches!(
+            decompress("compress", b""),
+            Err(Error::UnsupportedEncoding { .. })
+        ));
+    }
+}
@@ -0,0 +1,127 @@
+use tui::style::{Color, Style};
+use tui::text::{Span, Text};
+
+use super::{percent_decode, Decoder, DecoderArgs, DecoderFactory, Result, TextBuilder};
+use crate::session::{MessageData, RequestData};
+
+pub fn initialize(_args: &DecoderArgs) -> Result<Option<Box<dyn DecoderFactory>>>
+{
+    Ok(Some(Box::new(FormDecoderFactory)))
+}
+
+pub struct FormDecoderFactory;
+impl DecoderFactory for FormDecoderFactory
+{
+    fn try_create(&self, _request: &RequestData, msg: &MessageData) -> Option<Box<dyn Decoder>>
+    {
+        let content_type = msg.headers.get("content-type")?.to_str().ok()?;
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match mime == "application/x-www-form-urlencoded" {
+            true => Some(Box::new(FormUrlEncodedDecoder)),
+            false => None,
+        }
+    }
+}
+
+/// Decoder that lists the `key = value` pairs of `application/x-www-form-urlencoded` content.
+pub struct FormUrlEncodedDecoder;
+impl Decoder for FormUrlEncodedDecoder
+{
+    fn name(&self) -> &'static str
+    {
+        "form"
+    }
+
+    fn decode(&self, msg: &MessageData) -> Text<'_>
+    {
+        let key_style = Style::default().fg(Color::Cyan);
+        let value_style = Style::default().fg(Color::Green);
+
+        let mut builder = TextBuilder::default();
+        if !msg.headers.is_empty() {
+            builder.push(Span::raw("Headers\n"));
+            for (k, v) in &msg.headers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        for (key, value) in parse_pairs(&msg.content) {
+            builder.push(Span::styled(key, key_style));
+            builder.push(Span::raw(" = "));
+            builder.push(Span::styled(value, value_style));
+            builder.push(Span::raw("\n"));
+        }
+
+        if !msg.trailers.is_empty() {
+            builder.push(Span::raw("\nTrailers\n"));
+            for (k, v) in &msg.trailers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+        }
+        builder.build()
+    }
+
+    fn index(&self, msg: &MessageData) -> Vec<String>
+    {
+        parse_pairs(&msg.content)
+            .flat_map(|(k, v)| vec![k, v])
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+}
+
+/// Split the content into percent-decoded key-value pairs.
+///
+/// Repeated keys are kept as separate pairs in their original order. Keys without a `=` have an
+/// empty value.
+pub fn parse_pairs(content: &[u8]) -> impl Iterator<Item = (String, String)> + '_
+{
+    content
+        .split(|&b| b == b'&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, |&b| b == b'=');
+            let key = parts.next().unwrap_or_default();
+            let value = parts.next().unwrap_or_default();
+            (form_decode(key), form_decode(value))
+        })
+}
+
+/// Percent-decode a form component, where `+` stands for a space.
+fn form_decode(value: &[u8]) -> String
+{
+    let value: Vec<u8> = value
+        .iter()
+        .map(|&b| match b {
+            b'+' => b' ',
+            b => b,
+        })
+        .collect();
+    percent_decode(&value)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn parse_form_pairs()
+    {
+        let pairs: Vec<_> =
+            parse_pairs(b"user=jane+doe&tag=a&tag=b%26c&empty=&flag&&note=100%25+sure%2B")
+                .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("user".to_string(), "jane doe".to_string()),
+                ("tag".to_string(), "a".to_string()),
+                ("tag".to_string(), "b&c".to_string()),
+                ("empty".to_string(), "".to_string()),
+                ("flag".to_string(), "".to_string()),
+                ("note".to_string(), "100% sure+".to_string()),
+            ]
+        );
+    }
+}
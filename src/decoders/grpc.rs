@@ -1,16 +1,80 @@
-use clap::{App, Arg, ArgMatches};
-use protofish::{context::MessageRef, Context, MessageValue};
+use clap::{App, Arg};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use protofish::context::{Constant, MessageField, MessageRef};
+use protofish::decode::Value;
+use protofish::{Context, MessageValue};
 use snafu::ResultExt;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::io::Read;
 use std::rc::Rc;
-use tui::text::{Span, Spans, Text};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Text};
+use uuid::Uuid;
 
-use super::{ConfigurationError, ConfigurationValueError, Decoder, DecoderFactory, Result};
-use crate::session::{MessageData, RequestData, RequestPart};
+use super::compression;
+use super::{
+    percent_decode, ConfigurationError, ConfigurationValueError, Decoder, DecoderArgs,
+    DecoderFactory, Result, TextBuilder,
+};
+use crate::session::{EncodedRequest, MessageData, RequestData, RequestPart};
+
+mod reflection;
+mod text_format;
+
+use reflection::Reflection;
 
 pub struct GrpcDecoderFactory
 {
     ctx: Rc<protofish::Context>,
+
+    /// Schemas fetched from the servers when `--grpc-reflection` is enabled.
+    reflection: Option<Rc<Reflection>>,
+
+    /// Message types for the plain protobuf bodies given with `--protobuf-type`.
+    protobuf_types: Rc<Vec<ProtobufType>>,
+
+    /// Produce protobuf text format instead of the pretty output.
+    text_format: bool,
+
+    /// Frames decoded from the recent messages. Shared between the pretty and text format
+    /// decoders.
+    frames: Rc<FrameCache>,
+}
+
+/// Number of messages the decoded frames are cached for.
+const FRAME_CACHE_SIZE: usize = 8;
+
+/// Frames decoded from the recently displayed gRPC messages.
+///
+/// Streaming calls keep appending frames to the message, which is redrawn after each new frame.
+/// Caching the frames keeps the redraw from decoding the whole stream again each time.
+#[derive(Default)]
+pub struct FrameCache
+{
+    /// Most recently used entry first.
+    entries: RefCell<Vec<CachedFrames>>,
+}
+
+struct CachedFrames
+{
+    request: Uuid,
+    part: RequestPart,
+    msg_ref: MessageRef,
+    ctx: Rc<Context>,
+
+    /// Length of the content the frames were decoded from. Content past this is either an
+    /// incomplete frame or not yet received.
+    consumed: usize,
+    messages: Rc<Vec<GrpcMessage>>,
+}
+
+/// Message type mapping for the protobuf bodies that aren't gRPC calls.
+struct ProtobufType
+{
+    /// Request paths the mapping applies to. `None` applies to all paths.
+    path: Option<wildmatch::WildMatch>,
+    message: String,
 }
 
 pub fn setup_args(app: App) -> App
@@ -23,17 +87,37 @@ pub fn setup_args(app: App) -> App
             .help("Specify .proto file for decoding Protobuf messages")
             .takes_value(true),
     )
+    .arg(
+        Arg::with_name("grpc-reflection")
+            .long("grpc-reflection")
+            .help("Fetch the Protobuf schemas from the servers using gRPC server reflection"),
+    )
+    .arg(
+        Arg::with_name("protobuf-type")
+            .long("protobuf-type")
+            .value_name("[PATH=]MESSAGE")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Specify the message type of the application/protobuf bodies, such as 'package.Message'. PATH limits the type to matching request paths")
+            .takes_value(true),
+    )
 }
 
-pub fn initialize(matches: &ArgMatches) -> Result<Option<Box<dyn DecoderFactory>>>
+pub fn initialize(args: &DecoderArgs) -> Result<Vec<Box<dyn DecoderFactory>>>
 {
+    // The gRPC options predate the decoder option namespaces.
+    let matches = args.matches();
+
     // Avoid initialization if the grpc arguments arent given on the command line.
+    let use_reflection = matches.is_present("grpc-reflection");
     let globs = match matches.values_of("grpc") {
-        Some(globs) => globs,
-        None => return Ok(None),
+        Some(globs) => globs.collect(),
+        None if use_reflection => vec![],
+        None => return Ok(vec![]),
     };
 
     // Read all proto files.
+    let mut paths = Vec::new();
     let mut content = Vec::new();
     for g in globs {
         let files = glob::glob(g)
@@ -59,18 +143,72 @@ pub fn initialize(matches: &ArgMatches) -> Result<Option<Box<dyn DecoderFactory>
                     msg: format!("Failed to read '{}'", path.to_string_lossy()),
                 })?;
             content.push(proto_file);
+            paths.push(path.to_string_lossy().replace('\\', "/"));
         }
     }
 
     let content_ref: Vec<_> = content.iter().map(|s| s.as_str()).collect();
 
-    let context = Context::parse(content_ref)
-        .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
-        .context(ConfigurationError { option: "grpc" })?;
+    // All the files are parsed into a single context so the types are resolved across the files.
+    // A type from a file that wasn't given fails the parsing on the first field referring to it,
+    // which is better explained by the missing file.
+    let context = match Context::parse(content_ref) {
+        Ok(context) => context,
+        Err(e) => {
+            let e = Box::new(e) as Box<dyn std::error::Error + Send>;
+            return match missing_imports(&paths, &content).into_iter().next() {
+                Some((file, import)) => Err(e).context(ConfigurationValueError {
+                    option: "grpc",
+                    msg: format!(
+                        "'{}' imports '{}', which was not given with --grpc",
+                        file, import
+                    ),
+                }),
+                None => Err(e).context(ConfigurationError { option: "grpc" }),
+            };
+        }
+    };
 
-    Ok(Some(Box::new(GrpcDecoderFactory {
-        ctx: Rc::new(context),
-    })))
+    // The text format decoder is registered first so the pretty decoder remains the default one.
+    let ctx = Rc::new(context);
+    let frames = Rc::new(FrameCache::default());
+    let reflection = match use_reflection {
+        true => Some(Rc::new(Reflection::new(content))),
+        false => None,
+    };
+    let protobuf_types: Rc<Vec<_>> = Rc::new(
+        matches
+            .values_of("protobuf-type")
+            .into_iter()
+            .flatten()
+            .map(|value| match value.rsplit_once('=') {
+                Some((path, message)) => ProtobufType {
+                    path: Some(wildmatch::WildMatch::new(path)),
+                    message: message.to_string(),
+                },
+                None => ProtobufType {
+                    path: None,
+                    message: value.to_string(),
+                },
+            })
+            .collect(),
+    );
+    Ok(vec![
+        Box::new(GrpcDecoderFactory {
+            ctx: ctx.clone(),
+            reflection: reflection.clone(),
+            protobuf_types: protobuf_types.clone(),
+            text_format: true,
+            frames: frames.clone(),
+        }),
+        Box::new(GrpcDecoderFactory {
+            ctx,
+            reflection,
+            protobuf_types,
+            text_format: false,
+            frames,
+        }),
+    ])
 }
 
 impl DecoderFactory for GrpcDecoderFactory
@@ -78,29 +216,93 @@ impl DecoderFactory for GrpcDecoderFactory
     fn try_create(&self, request: &RequestData, msg: &MessageData) -> Option<Box<dyn Decoder>>
     {
         log::info!("Acquiring gRPC decoder: {:?}", msg.headers);
-        match msg.headers.get("content-type")?.to_str() {
-            Ok("application/grpc") => {}
-            _ => return None,
+        let content_type = msg.headers.get("content-type")?.to_str().ok()?;
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match mime.as_str() {
+            "application/grpc"
+            | "application/grpc-web"
+            | "application/grpc-web+proto"
+            | "application/grpc-web-text"
+            | "application/grpc-web-text+proto" => {
+                let (ty, ctx) = self.rpc_message(request, msg, true)?;
+                let mut decoder = GrpcDecoder::new(ty, ctx).with_cache(
+                    self.frames.clone(),
+                    request.uuid,
+                    msg.part,
+                );
+                if mime.starts_with("application/grpc-web-text") {
+                    decoder = decoder.with_web_text();
+                }
+                Some(match self.text_format {
+                    true => Box::new(GrpcTextFormatDecoder(decoder)),
+                    false => Box::new(decoder),
+                })
+            }
+            "application/protobuf" | "application/x-protobuf" if !self.text_format => {
+                // Plain HTTP paths rarely name a gRPC service so the reflection isn't used for
+                // guessing the type from the path.
+                let (ty, ctx) = self
+                    .rpc_message(request, msg, false)
+                    .or_else(|| self.mapped_message(request))?;
+                Some(Box::new(ProtobufDecoder(GrpcDecoder::new(ty, ctx))))
+            }
+            _ => None,
         }
+    }
+}
 
+impl GrpcDecoderFactory
+{
+    /// Resolve the message type from the `/package.Service/Function` request path.
+    fn rpc_message(
+        &self,
+        request: &RequestData,
+        msg: &MessageData,
+        use_reflection: bool,
+    ) -> Option<(MessageRef, Rc<Context>)>
+    {
         let mut path = request.uri.path().rsplit('/');
         let function = path.next().unwrap();
-        let service = path.next().unwrap();
-        let service = match self.ctx.get_service(service) {
-            None => return None,
-            Some(s) => s,
+        let service = path.next()?;
+
+        // Fall back to the reflected schema when the --grpc files don't know the service. Until
+        // the schema has been fetched the message is left for the other decoders.
+        let ctx = match (self.ctx.get_service(service), &self.reflection) {
+            (Some(_), _) => self.ctx.clone(),
+            (None, Some(reflection)) if use_reflection => {
+                reflection.get_context(request, service)?
+            }
+            (None, _) => return None,
         };
+        let service = ctx.get_service(service)?;
         let function = match service.rpcs.iter().find(|f| f.name == function) {
             None => return None,
             Some(f) => f,
         };
 
         let ty = match msg.part {
-            RequestPart::Request => &function.input.message,
-            RequestPart::Response => &function.output.message,
+            RequestPart::Request => function.input.message,
+            RequestPart::Response => function.output.message,
         };
+        Some((ty, ctx))
+    }
 
-        Some(Box::new(GrpcDecoder::new(*ty, self.ctx.clone())))
+    /// Resolve the message type from the `--protobuf-type` mappings.
+    fn mapped_message(&self, request: &RequestData) -> Option<(MessageRef, Rc<Context>)>
+    {
+        let path = request.uri.path();
+        let mapping = self
+            .protobuf_types
+            .iter()
+            .find(|t| t.path.as_ref().map(|p| p.is_match(path)).unwrap_or(true))?;
+
+        let ctx = match (self.ctx.get_message(&mapping.message), &self.reflection) {
+            (Some(_), _) => self.ctx.clone(),
+            (None, Some(reflection)) => reflection.get_context(request, &mapping.message)?,
+            (None, None) => return None,
+        };
+        let ty = ctx.get_message(&mapping.message)?.self_ref;
+        Some((ty, ctx))
     }
 }
 
@@ -108,24 +310,107 @@ pub struct GrpcDecoder
 {
     msg_ref: MessageRef,
     ctx: Rc<Context>,
+
+    /// Cache for the decoded frames and the message they are cached for.
+    frames: Option<(Rc<FrameCache>, Uuid, RequestPart)>,
+
+    /// The content is base64 encoded as with `application/grpc-web-text`.
+    web_text: bool,
 }
 
+/// Name of the decoder that produces the protobuf text format.
+pub const TEXT_FORMAT_DECODER: &str = "grpc-text";
+
+/// gRPC decoder that produces the `protoc --decode` compatible text format.
+pub struct GrpcTextFormatDecoder(GrpcDecoder);
+
+/// Decoder for protobuf bodies that consist of a single message without the gRPC framing.
+pub struct ProtobufDecoder(GrpcDecoder);
+
 impl GrpcDecoder
 {
     pub fn new(msg_ref: MessageRef, rc: Rc<Context>) -> Self
     {
-        Self { msg_ref, ctx: rc }
+        Self {
+            msg_ref,
+            ctx: rc,
+            frames: None,
+            web_text: false,
+        }
     }
 
-    fn get_messages(&self, b: &[u8]) -> Vec<MessageValue>
+    pub fn with_cache(mut self, frames: Rc<FrameCache>, request: Uuid, part: RequestPart) -> Self
+    {
+        self.frames = Some((frames, request, part));
+        self
+    }
+
+    pub fn with_web_text(mut self) -> Self
+    {
+        self.web_text = true;
+        self
+    }
+
+    fn get_messages(&self, msg: &MessageData) -> Rc<Vec<GrpcMessage>>
+    {
+        let content = match self.web_text {
+            true => Cow::Owned(decode_web_text(&msg.content)),
+            false => Cow::Borrowed(&msg.content[..]),
+        };
+        let (frames, request, part) = match &self.frames {
+            Some(frames) => frames,
+            None => return Rc::new(self.decode_frames(msg, &content, 0).1),
+        };
+
+        // The content only grows while the message is being received. If it's shorter than what
+        // was decoded or the message type has changed, such as when the schema was fetched
+        // through reflection, the frames are decoded again.
+        let mut entries = frames.entries.borrow_mut();
+        let cached = entries
+            .iter()
+            .position(|e| e.request == *request && e.part == *part)
+            .map(|idx| entries.remove(idx))
+            .filter(|e| {
+                e.msg_ref == self.msg_ref
+                    && Rc::ptr_eq(&e.ctx, &self.ctx)
+                    && e.consumed <= content.len()
+            });
+        let mut entry = cached.unwrap_or_else(|| CachedFrames {
+            request: *request,
+            part: *part,
+            msg_ref: self.msg_ref,
+            ctx: self.ctx.clone(),
+            consumed: 0,
+            messages: Rc::new(vec![]),
+        });
+
+        if entry.consumed < content.len() {
+            let (consumed, messages) = self.decode_frames(msg, &content, entry.consumed);
+            if !messages.is_empty() {
+                Rc::make_mut(&mut entry.messages).extend(messages);
+            }
+            entry.consumed = consumed;
+        }
+
+        let messages = entry.messages.clone();
+        entries.insert(0, entry);
+        entries.truncate(FRAME_CACHE_SIZE);
+        messages
+    }
+
+    /// Decode the complete frames of the content starting from the cursor.
+    ///
+    /// Returns the position after the last complete frame together with the decoded messages.
+    fn decode_frames(
+        &self,
+        msg: &MessageData,
+        b: &[u8],
+        mut cursor: usize,
+    ) -> (usize, Vec<GrpcMessage>)
     {
-        let mut cursor = 0;
         let mut values = vec![];
         while b.len() >= cursor + 5 {
             let compressed = b[cursor];
-            if compressed != 0 {
-                return vec![];
-            }
 
             let len = ((b[cursor + 1] as usize) << 24)
                 + ((b[cursor + 2] as usize) << 16)
@@ -137,12 +422,95 @@ impl GrpcDecoder
             }
             cursor += 5;
 
-            values.push(self.msg_ref.decode(&b[cursor..cursor + len], &self.ctx));
+            let payload = &b[cursor..cursor + len];
             cursor += len;
+
+            // gRPC-Web sends the trailers in a frame of their own marked with the MSB.
+            if compressed & 0x80 != 0 {
+                values.push(GrpcMessage::Trailers(parse_web_trailers(payload)));
+                continue;
+            }
+            if compressed == 0 {
+                values.push(GrpcMessage::Decoded(
+                    self.msg_ref.decode(payload, &self.ctx),
+                ));
+                continue;
+            }
+
+            values.push(match Self::decompress(msg, payload) {
+                Ok(data) => GrpcMessage::Decoded(self.msg_ref.decode(&data, &self.ctx)),
+                Err(e) => GrpcMessage::Undecodable(e.to_string()),
+            });
         }
 
-        values
+        (cursor, values)
     }
+
+    fn decompress(msg: &MessageData, payload: &[u8]) -> Result<Vec<u8>, compression::Error>
+    {
+        // Only the grpc-encoding header tells how the message was compressed. The
+        // grpc-accept-encoding lists what the peer would accept, not what was used.
+        match msg
+            .headers
+            .get("grpc-encoding")
+            .and_then(|h| h.to_str().ok())
+        {
+            Some(encoding) => compression::decompress(encoding, payload),
+            None => Err(compression::Error::UnknownEncoding),
+        }
+    }
+}
+
+/// A single message frame in the gRPC stream.
+#[derive(Clone)]
+enum GrpcMessage
+{
+    Decoded(MessageValue),
+
+    /// The message couldn't be decoded, such as when it uses an unsupported compression.
+    Undecodable(String),
+
+    /// The trailers of a gRPC-Web call.
+    Trailers(HeaderMap),
+}
+
+/// Decode the base64 content of `application/grpc-web-text` messages.
+///
+/// Each 4 character group is decoded separately as the frames may be encoded one by one with
+/// padding in the middle of the content. An incomplete group at the end is still being received.
+fn decode_web_text(content: &[u8]) -> Vec<u8>
+{
+    let text: Vec<u8> = content
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    let mut data = vec![];
+    for group in text.chunks_exact(4) {
+        match base64::decode(group) {
+            Ok(bytes) => data.extend(bytes),
+            Err(_) => break,
+        }
+    }
+    data
+}
+
+/// Parse the `name: value` lines of a gRPC-Web trailer frame.
+fn parse_web_trailers(payload: &[u8]) -> HeaderMap
+{
+    let mut trailers = HeaderMap::new();
+    for line in String::from_utf8_lossy(payload).lines() {
+        let (name, value) = match line.split_once(':') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let name = HeaderName::from_bytes(name.trim().as_bytes());
+        let value = HeaderValue::from_str(value.trim());
+        if let (Ok(name), Ok(value)) = (name, value) {
+            trailers.append(name, value);
+        }
+    }
+    trailers
 }
 
 impl Decoder for GrpcDecoder
@@ -163,57 +531,265 @@ impl Decoder for GrpcDecoder
             builder.push(Span::raw("\n"));
         }
 
-        for v in &self.get_messages(&msg.content) {
-            v.to_text(&self.ctx, 0, &mut builder);
+        let messages = self.get_messages(msg);
+        for v in messages.iter() {
+            match v {
+                GrpcMessage::Decoded(v) => v.to_text(&self.ctx, 0, &mut builder),
+                GrpcMessage::Undecodable(reason) => builder.push(Span::styled(
+                    format!("<Undecodable message: {}>", reason),
+                    Style::default().fg(Color::LightRed),
+                )),
+                GrpcMessage::Trailers(trailers) => {
+                    builder.push(Span::raw("Trailers\n"));
+                    for (k, v) in trailers {
+                        builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+                    }
+                }
+            }
             builder.push(Span::raw("\n"));
         }
 
         if !msg.trailers.is_empty() {
             builder.push(Span::raw("\n"));
             builder.push(Span::raw("\nTrailers\n"));
-            for (k, v) in &msg.headers {
+            for (k, v) in &msg.trailers {
                 builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
             }
         }
+
+        if let Some((status, message)) = web_status(&messages).or_else(|| grpc_status(msg)) {
+            let style = match status {
+                0 => Style::default().fg(Color::LightGreen),
+                _ => Style::default().fg(Color::LightRed),
+            };
+            let name = status_name(status).unwrap_or("UNKNOWN");
+            builder.push(Span::raw("\nStatus: "));
+            builder.push(Span::styled(format!("{} ({})", name, status), style));
+            if let Some(message) = message {
+                builder.push(Span::raw(format!(": {}", message)));
+            }
+            builder.push(Span::raw("\n"));
+        }
         builder.build()
     }
 
     fn index(&self, msg: &MessageData) -> Vec<String>
     {
-        self.get_messages(&msg.content)
-            .into_iter()
-            .flat_map(|msg| msg.to_index(&self.ctx))
+        self.get_messages(msg)
+            .iter()
+            .flat_map(|msg| match msg {
+                GrpcMessage::Decoded(msg) => msg.to_index(&self.ctx),
+                GrpcMessage::Undecodable(_) | GrpcMessage::Trailers(_) => vec![],
+            })
             .collect()
     }
 }
 
-#[derive(Default)]
-struct TextBuilder<'a>
+impl Decoder for ProtobufDecoder
 {
-    last_line: Vec<Span<'a>>,
-    lines: Vec<Spans<'a>>,
+    fn name(&self) -> &'static str
+    {
+        "protobuf"
+    }
+
+    fn decode(&self, msg: &MessageData) -> Text<'_>
+    {
+        let mut builder = TextBuilder::default();
+        if !msg.headers.is_empty() {
+            builder.push(Span::raw("Headers\n"));
+            for (k, v) in &msg.headers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        let decoder = &self.0;
+        decoder
+            .msg_ref
+            .decode(&msg.content, &decoder.ctx)
+            .to_text(&decoder.ctx, 0, &mut builder);
+        builder.push(Span::raw("\n"));
+        builder.build()
+    }
+
+    fn index(&self, msg: &MessageData) -> Vec<String>
+    {
+        let decoder = &self.0;
+        decoder
+            .msg_ref
+            .decode(&msg.content, &decoder.ctx)
+            .to_index(&decoder.ctx)
+    }
 }
 
-impl<'a> TextBuilder<'a>
+impl Decoder for GrpcTextFormatDecoder
 {
-    fn push(&mut self, span: Span<'a>)
+    fn name(&self) -> &'static str
+    {
+        TEXT_FORMAT_DECODER
+    }
+
+    fn is_opt_in(&self) -> bool
+    {
+        true
+    }
+
+    fn decode(&self, msg: &MessageData) -> Text<'_>
     {
-        let has_linebreak = span.content.contains('\n');
-        self.last_line.push(span);
-        if has_linebreak {
-            let mut line = vec![];
-            std::mem::swap(&mut line, &mut self.last_line);
-            self.lines.push(Spans::from(line));
+        // Headers and trailers are written as comments to keep the output valid text format.
+        let mut output = String::new();
+        for (k, v) in &msg.headers {
+            output.push_str(&format!("# {}: {:?}\n", k, v));
+        }
+
+        let messages = self.0.get_messages(msg);
+        for (idx, v) in messages.iter().enumerate() {
+            if idx > 0 || !output.is_empty() {
+                output.push('\n');
+            }
+            match v {
+                GrpcMessage::Decoded(v) => {
+                    text_format::message_to_text_format(v, &self.0.ctx, 0, &mut output)
+                }
+                GrpcMessage::Undecodable(reason) => {
+                    output.push_str(&format!("# Undecodable message: {}\n", reason))
+                }
+                GrpcMessage::Trailers(trailers) => {
+                    for (k, v) in trailers {
+                        output.push_str(&format!("# {}: {:?}\n", k, v));
+                    }
+                }
+            }
         }
+
+        if !msg.trailers.is_empty() {
+            output.push('\n');
+            for (k, v) in &msg.trailers {
+                output.push_str(&format!("# {}: {:?}\n", k, v));
+            }
+        }
+        if let Some((status, message)) = web_status(&messages).or_else(|| grpc_status(msg)) {
+            let name = status_name(status).unwrap_or("UNKNOWN");
+            output.push_str(&format!("# Status: {} ({})", name, status));
+            if let Some(message) = message {
+                output.push_str(&format!(": {}", message));
+            }
+            output.push('\n');
+        }
+        Text::raw(output)
     }
 
-    fn build(mut self) -> Text<'a>
+    fn index(&self, _msg: &MessageData) -> Vec<String>
     {
-        self.lines.push(Spans::from(self.last_line));
-        Text::from(self.lines)
+        // The pretty decoder already indexes the same messages.
+        vec![]
+    }
+}
+
+/// Share of the deadline above which the request is considered to have been close to it.
+pub const DEADLINE_WARNING: f64 = 0.9;
+
+/// Parse the `grpc-timeout` header value, such as `100m` for 100 milliseconds.
+///
+/// The value is at most 8 digits followed by the unit: `H`, `M`, `S`, `m`, `u` or `n` for
+/// hours, minutes, seconds, milli-, micro- and nanoseconds.
+pub fn parse_timeout(value: &str) -> Option<std::time::Duration>
+{
+    let unit = value.chars().last()?;
+    let digits = &value[..value.len() - unit.len_utf8()];
+    if digits.is_empty() || digits.len() > 8 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let amount: u64 = digits.parse().ok()?;
+    Some(match unit {
+        'H' => std::time::Duration::from_secs(amount * 60 * 60),
+        'M' => std::time::Duration::from_secs(amount * 60),
+        'S' => std::time::Duration::from_secs(amount),
+        'm' => std::time::Duration::from_millis(amount),
+        'u' => std::time::Duration::from_micros(amount),
+        'n' => std::time::Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
+
+/// The timeout the client gave for the request with the `grpc-timeout` header.
+pub fn request_timeout(request: &EncodedRequest) -> Option<chrono::Duration>
+{
+    let value = request.request_msg.headers.get("grpc-timeout")?;
+    let timeout = parse_timeout(value.to_str().ok()?)?;
+    chrono::Duration::from_std(timeout).ok()
+}
+
+/// Share of the `grpc-timeout` the completed request took. Above 1.0 if the request didn't
+/// complete before the deadline.
+pub fn deadline_usage(request: &EncodedRequest) -> Option<f64>
+{
+    let timeout = request_timeout(request)?;
+    let end = request.request_data.end_timestamp?;
+    let duration = end - request.request_data.start_timestamp;
+    match timeout.num_microseconds()? {
+        0 => Some(f64::INFINITY),
+        t => Some(duration.num_microseconds()? as f64 / t as f64),
     }
 }
 
+/// Read the `grpc-status` and the `grpc-message` of the call.
+///
+/// The status is normally in the trailers, but calls that fail before sending any messages may
+/// respond with the status in the headers instead.
+fn grpc_status(msg: &MessageData) -> Option<(u32, Option<String>)>
+{
+    let headers = match msg.trailers.contains_key("grpc-status") {
+        true => &msg.trailers,
+        false => &msg.headers,
+    };
+    headers_status(headers)
+}
+
+/// Read the status from the trailer frame of a gRPC-Web call.
+fn web_status(messages: &[GrpcMessage]) -> Option<(u32, Option<String>)>
+{
+    messages.iter().find_map(|m| match m {
+        GrpcMessage::Trailers(trailers) => headers_status(trailers),
+        _ => None,
+    })
+}
+
+fn headers_status(headers: &HeaderMap) -> Option<(u32, Option<String>)>
+{
+    let status = headers.get("grpc-status")?.to_str().ok()?.parse().ok()?;
+    let message = headers
+        .get("grpc-message")
+        .map(|m| percent_decode(m.as_bytes()))
+        .filter(|m| !m.is_empty());
+    Some((status, message))
+}
+
+fn status_name(status: u32) -> Option<&'static str>
+{
+    Some(match status {
+        0 => "OK",
+        1 => "CANCELLED",
+        2 => "UNKNOWN",
+        3 => "INVALID_ARGUMENT",
+        4 => "DEADLINE_EXCEEDED",
+        5 => "NOT_FOUND",
+        6 => "ALREADY_EXISTS",
+        7 => "PERMISSION_DENIED",
+        8 => "RESOURCE_EXHAUSTED",
+        9 => "FAILED_PRECONDITION",
+        10 => "ABORTED",
+        11 => "OUT_OF_RANGE",
+        12 => "UNIMPLEMENTED",
+        13 => "INTERNAL",
+        14 => "UNAVAILABLE",
+        15 => "DATA_LOSS",
+        16 => "UNAUTHENTICATED",
+        _ => return None,
+    })
+}
+
 trait ToText
 {
     fn to_text<'a>(&self, ctx: &'a Context, indent: usize, builder: &mut TextBuilder<'a>);
@@ -227,15 +803,28 @@ impl ToText for protofish::decode::MessageValue
     {
         // Panic here should indicate that msg_ref is for a different context.
         let msg = ctx.resolve_message(self.msg_ref);
+        if well_known_to_text(self, &msg.full_name, ctx, indent, builder) {
+            return;
+        }
 
         builder.push(Span::raw(format!("{} {{\n", msg.name)));
         indent += 1;
         for f in &self.fields {
             builder.push(Span::raw("  ".repeat(indent)));
-            builder.push(match msg.fields.get(&f.number) {
-                Some(f) => Span::raw(&f.name),
-                None => Span::raw(format!("[#{}]", f.number)),
-            });
+            match msg.fields.get(&f.number) {
+                Some(f) if is_deprecated(f) => {
+                    builder.push(Span::styled(
+                        &f.name,
+                        Style::default().add_modifier(Modifier::CROSSED_OUT),
+                    ));
+                    builder.push(Span::styled(
+                        " (deprecated)",
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+                Some(f) => builder.push(Span::raw(&f.name)),
+                None => builder.push(Span::raw(format!("[#{}]", f.number))),
+            }
             builder.push(Span::raw(": "));
             f.value.to_text(ctx, indent, builder);
             builder.push(Span::raw("\n"));
@@ -247,18 +836,240 @@ impl ToText for protofish::decode::MessageValue
     fn to_index(&self, ctx: &Context) -> Vec<String>
     {
         let msg = ctx.resolve_message(self.msg_ref);
+        if msg.full_name == "google.protobuf.Any" {
+            if let Some((_, inner)) = decode_any(self, ctx) {
+                return inner.to_index(ctx);
+            }
+        }
+
         std::iter::once(msg.name.clone())
             .chain(self.fields.iter().flat_map(|field| {
-                msg.fields
+                // The JSON name is indexed as well since that's the name the users of the JSON
+                // APIs know the field by.
+                let names = msg
+                    .fields
                     .get(&field.number)
-                    .map(|f| f.name.clone())
-                    .into_iter()
-                    .chain(field.value.to_index(ctx))
+                    .map(|f| std::iter::once(f.name.clone()).chain(json_name(f).map(String::from)));
+                names.into_iter().flatten().chain(field.value.to_index(ctx))
             }))
             .collect()
     }
 }
 
+/// Render the Protobuf well-known types in a more readable form than their fields.
+///
+/// Returns `false` if the message isn't one of the supported types or its content can't be
+/// rendered, in which case the message is rendered as any other message.
+fn well_known_to_text<'a>(
+    msg: &MessageValue,
+    full_name: &str,
+    ctx: &'a Context,
+    indent: usize,
+    builder: &mut TextBuilder<'a>,
+) -> bool
+{
+    let text = match full_name {
+        "google.protobuf.Timestamp" => {
+            let (seconds, nanos) = seconds_and_nanos(msg);
+            match chrono::NaiveDateTime::from_timestamp_opt(seconds, nanos as u32) {
+                Some(time) if nanos >= 0 => {
+                    format!("Timestamp({})", time.format("%Y-%m-%dT%H:%M:%S%.fZ"))
+                }
+                _ => return false,
+            }
+        }
+        "google.protobuf.Duration" => {
+            let (seconds, nanos) = seconds_and_nanos(msg);
+            let sign = match seconds < 0 || nanos < 0 {
+                true => "-",
+                false => "",
+            };
+            let fraction = format!("{:09}", nanos.unsigned_abs());
+            let fraction = fraction.trim_end_matches('0');
+            match fraction.is_empty() {
+                true => format!("Duration({}{}s)", sign, seconds.unsigned_abs()),
+                false => format!("Duration({}{}.{}s)", sign, seconds.unsigned_abs(), fraction),
+            }
+        }
+        "google.protobuf.Any" => {
+            return match decode_any(msg, ctx) {
+                Some((type_url, inner)) => {
+                    builder.push(Span::styled(
+                        format!("Any({}) ", type_url),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                    inner.to_text(ctx, indent, builder);
+                    true
+                }
+                None => false,
+            }
+        }
+        "google.protobuf.Struct" | "google.protobuf.Value" | "google.protobuf.ListValue" => {
+            let json = match well_known_to_json(msg, ctx) {
+                Some(json) => json,
+                None => return false,
+            };
+            let name = &ctx.resolve_message(msg.msg_ref).name;
+            let json = serde_json::to_string_pretty(&json).expect("JSON is always serializable");
+            let mut lines = json.lines();
+            builder.push(Span::raw(format!(
+                "{} {}",
+                name,
+                lines.next().unwrap_or_default()
+            )));
+            for line in lines {
+                builder.push(Span::raw("\n"));
+                builder.push(Span::raw(format!("{}{}", "  ".repeat(indent), line)));
+            }
+            return true;
+        }
+        _ => return false,
+    };
+
+    builder.push(Span::raw(text));
+    true
+}
+
+/// The `seconds` and `nanos` fields of a Timestamp or a Duration.
+fn seconds_and_nanos(msg: &MessageValue) -> (i64, i32)
+{
+    let seconds = match get_field(msg, 1) {
+        Some(Value::Int64(v)) => *v,
+        _ => 0,
+    };
+    let nanos = match get_field(msg, 2) {
+        Some(Value::Int32(v)) => *v,
+        _ => 0,
+    };
+    (seconds, nanos)
+}
+
+/// Decode the message packed in an Any if the type of the message is known.
+fn decode_any<'a>(msg: &'a MessageValue, ctx: &Context) -> Option<(&'a str, MessageValue)>
+{
+    let type_url = match get_field(msg, 1)? {
+        Value::String(s) => s,
+        _ => return None,
+    };
+    let value = match get_field(msg, 2) {
+        Some(Value::Bytes(b)) => &b[..],
+        None => &[],
+        _ => return None,
+    };
+
+    // The type is the part of the URL after the last slash.
+    let type_name = type_url.rsplit('/').next().unwrap_or_default();
+    let inner = ctx.get_message(type_name)?.self_ref.decode(value, ctx);
+    Some((type_url, inner))
+}
+
+/// Convert a Struct, a Value or a ListValue into the JSON value it represents.
+fn well_known_to_json(msg: &MessageValue, ctx: &Context) -> Option<serde_json::Value>
+{
+    let info = ctx.resolve_message(msg.msg_ref);
+    let nested = |number| match get_field(msg, number) {
+        Some(Value::Message(m)) => well_known_to_json(m, ctx),
+        _ => None,
+    };
+    Some(match info.full_name.as_str() {
+        "google.protobuf.Struct" => {
+            let mut map = serde_json::Map::new();
+            for field in msg.fields.iter().filter(|f| f.number == 1) {
+                let entry = match &field.value {
+                    Value::Message(entry) => entry,
+                    _ => return None,
+                };
+                let key = match get_field(entry, 1) {
+                    Some(Value::String(key)) => key.clone(),
+                    _ => String::new(),
+                };
+                let value = match get_field(entry, 2) {
+                    Some(Value::Message(value)) => well_known_to_json(value, ctx)?,
+                    _ => serde_json::Value::Null,
+                };
+                map.insert(key, value);
+            }
+            serde_json::Value::Object(map)
+        }
+        "google.protobuf.ListValue" => serde_json::Value::Array(
+            msg.fields
+                .iter()
+                .filter(|f| f.number == 1)
+                .map(|f| match &f.value {
+                    Value::Message(value) => well_known_to_json(value, ctx),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+        ),
+        "google.protobuf.Value" => match msg.fields.last().map(|f| (f.number, &f.value)) {
+            None | Some((1, _)) => serde_json::Value::Null,
+            Some((2, Value::Double(v))) => serde_json::json!(v),
+            Some((3, Value::String(v))) => serde_json::json!(v),
+            Some((4, Value::Bool(v))) => serde_json::json!(v),
+            Some((5, _)) => nested(5)?,
+            Some((6, _)) => nested(6)?,
+            Some(_) => return None,
+        },
+        _ => return None,
+    })
+}
+
+/// The value of the field. The last one wins if the field is repeated, same as when parsing
+/// Protobuf messages normally.
+fn get_field(msg: &MessageValue, number: u64) -> Option<&Value>
+{
+    msg.fields
+        .iter()
+        .rev()
+        .find(|f| f.number == number)
+        .map(|f| &f.value)
+}
+
+/// Find the imports that don't match any of the given files.
+fn missing_imports(paths: &[String], content: &[String]) -> Vec<(String, String)>
+{
+    let import = regex::Regex::new(r#"(?m)^\s*import\s+(?:public\s+|weak\s+)?"([^"]+)"\s*;"#)
+        .expect("Invalid import pattern");
+    paths
+        .iter()
+        .zip(content)
+        .flat_map(|(path, content)| {
+            import
+                .captures_iter(content)
+                .map(|c| c[1].to_string())
+                .filter(|name| {
+                    !paths
+                        .iter()
+                        .any(|p| p == name || p.ends_with(&format!("/{}", name)))
+                })
+                .map(move |name| (path.clone(), name))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn is_deprecated(field: &MessageField) -> bool
+{
+    field
+        .options
+        .iter()
+        .any(|o| o.name == "deprecated" && o.value == Constant::Bool(true))
+}
+
+/// The `json_name` option of the field if it differs from the field name.
+fn json_name(field: &MessageField) -> Option<&str>
+{
+    field
+        .options
+        .iter()
+        .find(|o| o.name == "json_name")
+        .and_then(|o| match &o.value {
+            Constant::String(s) => std::str::from_utf8(s).ok(),
+            _ => None,
+        })
+        .filter(|name| *name != field.name)
+}
+
 impl ToText for protofish::decode::EnumValue
 {
     fn to_text<'a>(&self, ctx: &'a Context, _indent: usize, builder: &mut TextBuilder<'a>)
@@ -392,3 +1203,370 @@ impl ToText for protofish::decode::PackedArray
         v
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    const PROTO: &str = r#"
+        syntax = "proto3";
+        package test;
+        message Request { string name = 1; int32 id = 2; }
+    "#;
+
+    // Request { name: "proxide", id: 42 } compressed with gzip in a single gRPC frame.
+    const GZIP_FRAME: &[u8] = &[
+        0x01, 0x00, 0x00, 0x00, 0x1f, 0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03,
+        0xe3, 0x62, 0x2f, 0x28, 0xca, 0xaf, 0xc8, 0x4c, 0x49, 0x15, 0xd0, 0x02, 0x00, 0xad, 0xb3,
+        0xae, 0x10, 0x0b, 0x00, 0x00, 0x00,
+    ];
+
+    fn decoder() -> GrpcDecoder
+    {
+        let ctx = Context::parse([PROTO]).unwrap();
+        let msg_ref = ctx.get_message("test.Request").unwrap().self_ref;
+        GrpcDecoder::new(msg_ref, Rc::new(ctx))
+    }
+
+    fn message(encoding: Option<&'static str>, content: &[u8]) -> MessageData
+    {
+        let mut headers = HeaderMap::new();
+        if let Some(encoding) = encoding {
+            headers.insert("grpc-encoding", HeaderValue::from_static(encoding));
+        }
+        let mut msg = MessageData::new(RequestPart::Request).with_headers(headers);
+        msg.content.extend_from_slice(content);
+        msg
+    }
+
+    #[test]
+    fn decode_gzip_frame()
+    {
+        let index = decoder().index(&message(Some("gzip"), GZIP_FRAME));
+        assert!(index.contains(&"\"proxide\"".to_string()), "{:?}", index);
+        assert!(index.contains(&"42".to_string()), "{:?}", index);
+    }
+
+    #[test]
+    fn decode_uncompressed_frame()
+    {
+        let payload = [
+            0x0a, 0x07, b'p', b'r', b'o', b'x', b'i', b'd', b'e', 0x10, 0x2a,
+        ];
+        let mut frame = vec![0x00, 0x00, 0x00, 0x00, payload.len() as u8];
+        frame.extend_from_slice(&payload);
+
+        let index = decoder().index(&message(None, &frame));
+        assert!(index.contains(&"\"proxide\"".to_string()), "{:?}", index);
+    }
+
+    #[test]
+    fn streamed_frames_are_decoded_once()
+    {
+        // Request { name: "proxide", id: N } frames.
+        let frame = |id: u8| {
+            let mut frame = vec![0x00, 0x00, 0x00, 0x00, 11];
+            frame.extend_from_slice(&[
+                0x0a, 0x07, b'p', b'r', b'o', b'x', b'i', b'd', b'e', 0x10, id,
+            ]);
+            frame
+        };
+
+        let request = Uuid::new_v4();
+        let frames = Rc::new(FrameCache::default());
+        let decoder = decoder().with_cache(frames, request, RequestPart::Response);
+        let mut msg = message(None, &[]);
+        for id in 1..=100 {
+            msg.content.extend_from_slice(&frame(id));
+            assert_eq!(decoder.get_messages(&msg).len(), id as usize);
+        }
+
+        // Overwrite the first frame. The cached frames are still used for the already decoded
+        // content so the change only shows up if the whole stream is decoded again.
+        msg.content[15] = 0x7f;
+        msg.content.extend_from_slice(&frame(101)[..8]);
+        let messages = decoder.get_messages(&msg);
+        assert_eq!(messages.len(), 100);
+        let index = match &messages[0] {
+            GrpcMessage::Decoded(v) => v.to_index(&decoder.ctx),
+            GrpcMessage::Undecodable(e) => panic!("{}", e),
+            GrpcMessage::Trailers(_) => panic!("Unexpected trailers"),
+        };
+        assert!(index.contains(&"1".to_string()), "{:?}", index);
+
+        // The incomplete frame is decoded once the rest of it arrives.
+        msg.content.extend_from_slice(&frame(101)[8..]);
+        assert_eq!(decoder.get_messages(&msg).len(), 101);
+
+        // Shorter content means the message was replaced.
+        let msg = message(None, &frame(1));
+        assert_eq!(decoder.get_messages(&msg).len(), 1);
+    }
+
+    #[test]
+    fn status_is_read_from_trailers()
+    {
+        let mut msg = message(None, &[]);
+        msg.headers
+            .insert("grpc-status", HeaderValue::from_static("0"));
+        msg.trailers
+            .insert("grpc-status", HeaderValue::from_static("5"));
+        msg.trailers.insert(
+            "grpc-message",
+            HeaderValue::from_static("User%20%22foo%22%20not%20found%E2%9C%97%2"),
+        );
+        let text: String = decoder()
+            .decode(&msg)
+            .lines
+            .iter()
+            .flat_map(|line| line.0.iter().map(|s| s.content.to_string()))
+            .collect();
+        assert!(text.contains(" - grpc-status: \"5\""), "{}", text);
+        assert!(
+            text.contains("Status: NOT_FOUND (5): User \"foo\" not found\u{2717}%2"),
+            "{}",
+            text
+        );
+
+        // Trailers-only responses carry the status in the headers.
+        let mut msg = message(None, &[]);
+        msg.headers
+            .insert("grpc-status", HeaderValue::from_static("14"));
+        assert_eq!(grpc_status(&msg), Some((14, None)));
+    }
+
+    #[test]
+    fn decode_grpc_web_text()
+    {
+        // Request { name: "proxide", id: 42 } and the trailer frame, each encoded separately.
+        let mut frame = vec![0x00, 0x00, 0x00, 0x00, 11];
+        frame.extend_from_slice(&[
+            0x0a, 0x07, b'p', b'r', b'o', b'x', b'i', b'd', b'e', 0x10, 0x2a,
+        ]);
+        let trailers = b"grpc-status: 5\r\ngrpc-message: Not%20found\r\n";
+        let mut trailer_frame = vec![0x80, 0x00, 0x00, 0x00, trailers.len() as u8];
+        trailer_frame.extend_from_slice(trailers);
+        let content = format!(
+            "{}{}",
+            base64::encode(&frame),
+            base64::encode(&trailer_frame)
+        );
+
+        let decoder = decoder().with_web_text();
+        let msg = message(None, content.as_bytes());
+        let index = decoder.index(&msg);
+        assert!(index.contains(&"\"proxide\"".to_string()), "{:?}", index);
+
+        let text: String = decoder
+            .decode(&msg)
+            .lines
+            .iter()
+            .flat_map(|line| line.0.iter().map(|s| s.content.to_string()))
+            .collect();
+        assert!(text.contains(" - grpc-status: \"5\""), "{}", text);
+        assert!(
+            text.contains("Status: NOT_FOUND (5): Not found"),
+            "{}",
+            text
+        );
+
+        // The incomplete base64 group of a frame still being received is ignored.
+        let msg = message(None, &content.as_bytes()[..10]);
+        assert!(decoder.get_messages(&msg).is_empty());
+    }
+
+    #[test]
+    fn decode_unframed_protobuf()
+    {
+        let payload = [
+            0x0a, 0x07, b'p', b'r', b'o', b'x', b'i', b'd', b'e', 0x10, 0x2a,
+        ];
+
+        let index = ProtobufDecoder(decoder()).index(&message(None, &payload));
+        assert!(index.contains(&"\"proxide\"".to_string()), "{:?}", index);
+        assert!(index.contains(&"42".to_string()), "{:?}", index);
+    }
+
+    #[test]
+    fn field_options_are_rendered()
+    {
+        let ctx = Context::parse([r#"
+            syntax = "proto3";
+            package test;
+            message Request {
+                string name = 1 [deprecated = true, json_name = "fooBar"];
+                int32 id = 2;
+            }
+        "#])
+        .unwrap();
+        let msg_ref = ctx.get_message("test.Request").unwrap().self_ref;
+        let decoder = ProtobufDecoder(GrpcDecoder::new(msg_ref, Rc::new(ctx)));
+        let payload = [
+            0x0a, 0x07, b'p', b'r', b'o', b'x', b'i', b'd', b'e', 0x10, 0x2a,
+        ];
+        let msg = message(None, &payload);
+
+        let index = decoder.index(&msg);
+        assert!(index.contains(&"fooBar".to_string()), "{:?}", index);
+
+        let text = decoder.decode(&msg);
+        let name = text
+            .lines
+            .iter()
+            .flat_map(|line| line.0.iter())
+            .find(|s| s.content == "name")
+            .unwrap();
+        assert!(name.style.add_modifier.contains(Modifier::CROSSED_OUT));
+    }
+
+    #[test]
+    fn types_are_resolved_across_files()
+    {
+        let a = r#"
+            syntax = "proto3";
+            package a;
+            import public "proto/b.proto";
+            message A { b.B inner = 1; }
+        "#;
+        let b = r#"
+            syntax = "proto3";
+            package b;
+            message B { string name = 1; }
+        "#;
+        let ctx = Context::parse([a, b]).unwrap();
+        let msg = ctx.get_message("a.A").unwrap();
+        assert_eq!(msg.fields.len(), 1);
+
+        let paths = ["src/a.proto".to_string(), "src/proto/b.proto".to_string()];
+        let content = [a.to_string(), b.to_string()];
+        assert!(missing_imports(&paths, &content).is_empty());
+        assert_eq!(
+            missing_imports(&paths[..1], &content[..1]),
+            vec![("src/a.proto".to_string(), "proto/b.proto".to_string())]
+        );
+    }
+
+    #[test]
+    fn unsupported_encoding_is_marked()
+    {
+        let msg = message(Some("snappy"), GZIP_FRAME);
+        let decoder = decoder();
+        let text: String = decoder
+            .decode(&msg)
+            .lines
+            .iter()
+            .flat_map(|line| line.0.iter().map(|s| s.content.to_string()))
+            .collect();
+        assert!(text.contains("Unsupported encoding 'snappy'"), "{}", text);
+    }
+
+    #[test]
+    fn well_known_types_are_formatted()
+    {
+        let ctx = Context::parse([
+            r#"
+            syntax = "proto3";
+            package google.protobuf;
+            message Timestamp { int64 seconds = 1; int32 nanos = 2; }
+            message Duration { int64 seconds = 1; int32 nanos = 2; }
+            message Any { string type_url = 1; bytes value = 2; }
+            message Struct {
+                message FieldsEntry { string key = 1; Value value = 2; }
+                repeated FieldsEntry fields = 1;
+            }
+            enum NullValue { NULL_VALUE = 0; }
+            message Value {
+                NullValue null_value = 1;
+                double number_value = 2;
+                string string_value = 3;
+                bool bool_value = 4;
+                Struct struct_value = 5;
+                ListValue list_value = 6;
+            }
+            message ListValue { repeated Value values = 1; }
+        "#,
+            r#"
+            syntax = "proto3";
+            package test;
+            import "google/protobuf/well_known.proto";
+            message Inner { string name = 1; }
+            message Event {
+                google.protobuf.Timestamp time = 1;
+                google.protobuf.Duration took = 2;
+                google.protobuf.Any detail = 3;
+                google.protobuf.Any other = 4;
+                google.protobuf.Struct meta = 5;
+            }
+        "#,
+        ])
+        .unwrap();
+        let msg_ref = ctx.get_message("test.Event").unwrap().self_ref;
+        let decoder = ProtobufDecoder(GrpcDecoder::new(msg_ref, Rc::new(ctx)));
+
+        // Timestamp 2020-09-13T12:26:40.5Z, Duration 1.25s, Any with a known and an unknown type
+        // and Struct { "a": 1.5, "b": [true, null] }
+        let payload = [
+            0x0a, 0x0c, 0x08, 0x80, 0xa0, 0xf8, 0xfa, 0x05, 0x10, 0x80, 0xca, 0xb5, 0xee, 0x01,
+            0x12, 0x07, 0x08, 0x01, 0x10, 0x80, 0xe5, 0x9a, 0x77, 0x1a, 0x25, 0x0a, 0x1e, 0x74,
+            0x79, 0x70, 0x65, 0x2e, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x61, 0x70, 0x69, 0x73,
+            0x2e, 0x63, 0x6f, 0x6d, 0x2f, 0x74, 0x65, 0x73, 0x74, 0x2e, 0x49, 0x6e, 0x6e, 0x65,
+            0x72, 0x12, 0x03, 0x0a, 0x01, 0x78, 0x22, 0x26, 0x0a, 0x20, 0x74, 0x79, 0x70, 0x65,
+            0x2e, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x61, 0x70, 0x69, 0x73, 0x2e, 0x63, 0x6f,
+            0x6d, 0x2f, 0x74, 0x65, 0x73, 0x74, 0x2e, 0x4d, 0x69, 0x73, 0x73, 0x69, 0x6e, 0x67,
+            0x12, 0x02, 0x08, 0x01, 0x2a, 0x21, 0x0a, 0x0e, 0x0a, 0x01, 0x61, 0x12, 0x09, 0x11,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf8, 0x3f, 0x0a, 0x0f, 0x0a, 0x01, 0x62, 0x12,
+            0x0a, 0x32, 0x08, 0x0a, 0x02, 0x20, 0x01, 0x0a, 0x02, 0x08, 0x00,
+        ];
+        let msg = message(None, &payload);
+
+        let text: String = decoder
+            .decode(&msg)
+            .lines
+            .iter()
+            .flat_map(|line| line.0.iter().map(|s| s.content.to_string()))
+            .collect();
+        assert!(
+            text.contains("time: Timestamp(2020-09-13T12:26:40.500Z)"),
+            "{}",
+            text
+        );
+        assert!(text.contains("took: Duration(1.25s)"), "{}", text);
+        assert!(
+            text.contains("detail: Any(type.googleapis.com/test.Inner) Inner {"),
+            "{}",
+            text
+        );
+        assert!(text.contains("other: Any {"), "{}", text);
+        assert!(
+            text.contains("meta: Struct {\n    \"a\": 1.5,\n    \"b\": [\n      true,\n      null\n    ]\n  }"),
+            "{}",
+            text
+        );
+
+        assert!(decoder.index(&msg).contains(&"\"x\"".to_string()));
+    }
+
+    #[test]
+    fn parse_grpc_timeout()
+    {
+        use std::time::Duration;
+        assert_eq!(parse_timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_timeout("3M"), Some(Duration::from_secs(180)));
+        assert_eq!(parse_timeout("10S"), Some(Duration::from_secs(10)));
+        assert_eq!(parse_timeout("250m"), Some(Duration::from_millis(250)));
+        assert_eq!(
+            parse_timeout("99999999u"),
+            Some(Duration::from_micros(99_999_999))
+        );
+        assert_eq!(parse_timeout("1n"), Some(Duration::from_nanos(1)));
+
+        assert_eq!(parse_timeout(""), None);
+        assert_eq!(parse_timeout("S"), None);
+        assert_eq!(parse_timeout("100"), None);
+        assert_eq!(parse_timeout("100x"), None);
+        assert_eq!(parse_timeout("-1S"), None);
+        assert_eq!(parse_timeout("123456789m"), None);
+        assert_eq!(parse_timeout("1ä"), None);
+    }
+}
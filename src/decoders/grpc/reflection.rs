@@ -0,0 +1,581 @@
+use bytes::Bytes;
+use http::Uri;
+use protofish::decode::{MessageValue, Value};
+use protofish::Context;
+use rustls::{client::ServerName, ClientConfig};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use crate::connection::NoVerify;
+use crate::session::RequestData;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// File names and the proto sources of the fetched files.
+type FetchResult = Result<Vec<(String, String)>, String>;
+
+const REFLECTION_PATH: &str = "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo";
+
+/// The subset of `reflection.proto` and `descriptor.proto` needed to read the reflection
+/// responses.
+///
+/// Enums are declared as `int32` since only their numeric values are needed.
+const REFLECTION_PROTO: &str = r#"
+    syntax = "proto3";
+    package proxide.reflection;
+
+    message ServerReflectionResponse {
+        oneof message_response {
+            FileDescriptorResponse file_descriptor_response = 4;
+            ErrorResponse error_response = 7;
+        }
+    }
+    message FileDescriptorResponse { repeated bytes file_descriptor_proto = 1; }
+    message ErrorResponse { int32 error_code = 1; string error_message = 2; }
+
+    message FileDescriptorProto {
+        string name = 1;
+        string package = 2;
+        repeated DescriptorProto message_type = 4;
+        repeated EnumDescriptorProto enum_type = 5;
+        repeated ServiceDescriptorProto service = 6;
+    }
+    message DescriptorProto {
+        string name = 1;
+        repeated FieldDescriptorProto field = 2;
+        repeated DescriptorProto nested_type = 3;
+        repeated EnumDescriptorProto enum_type = 4;
+        repeated OneofDescriptorProto oneof_decl = 8;
+    }
+    message FieldDescriptorProto {
+        string name = 1;
+        int32 number = 3;
+        int32 label = 4;
+        int32 type = 5;
+        string type_name = 6;
+        int32 oneof_index = 9;
+        bool proto3_optional = 17;
+    }
+    message OneofDescriptorProto { string name = 1; }
+    message EnumDescriptorProto { string name = 1; repeated EnumValueDescriptorProto value = 2; }
+    message EnumValueDescriptorProto { string name = 1; int32 number = 2; }
+    message ServiceDescriptorProto { string name = 1; repeated MethodDescriptorProto method = 2; }
+    message MethodDescriptorProto {
+        string name = 1;
+        string input_type = 2;
+        string output_type = 3;
+        bool client_streaming = 5;
+        bool server_streaming = 6;
+    }
+"#;
+
+/// Proto schemas fetched from the servers using the gRPC server reflection.
+pub struct Reflection
+{
+    /// Sources of the proto files given on the command line. These are merged with the fetched
+    /// files.
+    sources: Vec<String>,
+
+    authorities: RefCell<HashMap<String, AuthorityState>>,
+}
+
+#[derive(Default)]
+struct AuthorityState
+{
+    context: Option<Rc<Context>>,
+
+    /// Fetched proto files by their file name.
+    files: HashMap<String, String>,
+
+    /// Symbols that have been requested from the server already.
+    requested: HashSet<String>,
+    pending: Option<Receiver<FetchResult>>,
+}
+
+impl Reflection
+{
+    pub fn new(sources: Vec<String>) -> Self
+    {
+        Self {
+            sources,
+            authorities: Default::default(),
+        }
+    }
+
+    /// Get a context that contains the service or message.
+    ///
+    /// The schema is requested from the server on the first call. Until it arrives, `None` is
+    /// returned.
+    pub fn get_context(&self, request: &RequestData, symbol: &str) -> Option<Rc<Context>>
+    {
+        let authority = request.uri.authority()?.to_string();
+        let mut authorities = self.authorities.borrow_mut();
+        let state = authorities.entry(authority).or_default();
+
+        if let Some(pending) = &state.pending {
+            match pending.try_recv() {
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => state.pending = None,
+                Ok(Err(e)) => {
+                    log::warn!("gRPC reflection failed for '{}': {}", symbol, e);
+                    state.pending = None;
+                }
+                Ok(Ok(files)) => {
+                    state.pending = None;
+                    state.files.extend(files);
+                    state.context = self.build_context(&state.files);
+                }
+            }
+        }
+
+        if let Some(ctx) = &state.context {
+            if ctx.get_service(symbol).is_some() || ctx.get_message(symbol).is_some() {
+                return Some(ctx.clone());
+            }
+        }
+
+        // Each symbol is requested only once to avoid hammering servers that don't support the
+        // reflection.
+        if state.pending.is_none() && state.requested.insert(symbol.to_string()) {
+            let (tx, rx) = mpsc::channel();
+            let uri = request.uri.clone();
+            let symbol = symbol.to_string();
+            std::thread::spawn(move || {
+                let _ = tx.send(fetch(&uri, &symbol).map_err(|e| e.to_string()));
+            });
+            state.pending = Some(rx);
+        }
+
+        None
+    }
+
+    fn build_context(&self, files: &HashMap<String, String>) -> Option<Rc<Context>>
+    {
+        let merged = self
+            .sources
+            .iter()
+            .chain(files.values())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        match Context::parse(merged) {
+            Ok(ctx) => return Some(Rc::new(ctx)),
+            Err(e) => log::warn!(
+                "Failed to merge reflected schema with the --grpc files: {}",
+                e
+            ),
+        }
+
+        // The command line files may define the same types as the fetched ones.
+        match Context::parse(files.values()) {
+            Ok(ctx) => Some(Rc::new(ctx)),
+            Err(e) => {
+                log::error!("Failed to parse reflected schema: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Fetch the proto files that define the symbol and its dependencies.
+///
+/// Returns the file names and the proto sources generated from the file descriptors.
+fn fetch(uri: &Uri, symbol: &str) -> Result<Vec<(String, String)>, BoxError>
+{
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let data = runtime.block_on(request_descriptors(uri, symbol))?;
+
+    let ctx = Context::parse([REFLECTION_PROTO])?;
+    let response_ref = ctx
+        .get_message("proxide.reflection.ServerReflectionResponse")
+        .unwrap()
+        .self_ref;
+    let file_ref = ctx
+        .get_message("proxide.reflection.FileDescriptorProto")
+        .unwrap()
+        .self_ref;
+
+    let mut files = vec![];
+    for frame in grpc_frames(&data) {
+        let response = response_ref.decode(frame, &ctx);
+        if let Some(error) = message(&response, 7) {
+            return Err(string(error, 2).unwrap_or("Unknown error").into());
+        }
+        for descriptor in message(&response, 4).into_iter().flat_map(|r| bytes(r, 1)) {
+            let file = file_ref.decode(descriptor, &ctx);
+            let name = string(&file, 1).unwrap_or_default().to_string();
+            files.push((name, proto_source(&file)));
+        }
+    }
+    Ok(files)
+}
+
+async fn request_descriptors(uri: &Uri, symbol: &str) -> Result<Vec<u8>, BoxError>
+{
+    let authority = uri.authority().ok_or("Request has no authority")?;
+    let is_https = uri.scheme_str() == Some("https");
+    let port = authority
+        .port_u16()
+        .unwrap_or(if is_https { 443 } else { 80 });
+    let server = TcpStream::connect((authority.host(), port)).await?;
+
+    match is_https {
+        true => {
+            let mut config = ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(NoVerify))
+                .with_no_client_auth();
+            config.alpn_protocols = vec![b"h2".to_vec()];
+            let server = TlsConnector::from(Arc::new(config))
+                .connect(ServerName::try_from(authority.host())?, server)
+                .await?;
+            request_descriptors_h2(server, uri, symbol).await
+        }
+        false => request_descriptors_h2(server, uri, symbol).await,
+    }
+}
+
+async fn request_descriptors_h2<T>(server: T, uri: &Uri, symbol: &str) -> Result<Vec<u8>, BoxError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (client, connection) = h2::client::handshake(server).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::warn!("gRPC reflection connection failed: {}", e);
+        }
+    });
+
+    let authority = uri.authority().ok_or("Request has no authority")?;
+    let request = http::Request::builder()
+        .method("POST")
+        .uri(format!(
+            "{}://{}{}",
+            uri.scheme_str().unwrap_or("http"),
+            authority,
+            REFLECTION_PATH
+        ))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(())?;
+
+    // ServerReflectionRequest { host = 1, file_containing_symbol = 4 }
+    let mut payload = vec![];
+    encode_string(1, authority.host(), &mut payload);
+    encode_string(4, symbol, &mut payload);
+    let mut frame = vec![0];
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend(payload);
+
+    let mut client = client.ready().await?;
+    let (response, mut send_stream) = client.send_request(request, false)?;
+    send_stream.send_data(Bytes::from(frame), true)?;
+
+    let response = response.await?;
+    if response.status() != http::StatusCode::OK {
+        return Err(format!("Server responded with {}", response.status()).into());
+    }
+
+    let mut body = response.into_body();
+    let mut data = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+fn encode_string(field: u64, value: &str, output: &mut Vec<u8>)
+{
+    encode_varint(field << 3 | 2, output);
+    encode_varint(value.len() as u64, output);
+    output.extend_from_slice(value.as_bytes());
+}
+
+fn encode_varint(mut value: u64, output: &mut Vec<u8>)
+{
+    while value >= 0x80 {
+        output.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+/// Split the uncompressed gRPC message frames.
+fn grpc_frames(mut data: &[u8]) -> Vec<&[u8]>
+{
+    let mut frames = vec![];
+    while data.len() >= 5 {
+        let len = u32::from_be_bytes([data[1], data[2], data[3], data[4]]) as usize;
+        if data.len() < 5 + len {
+            break;
+        }
+        frames.push(&data[5..5 + len]);
+        data = &data[5 + len..];
+    }
+    frames
+}
+
+/// Generate the proto source for a `FileDescriptorProto`.
+fn proto_source(file: &MessageValue) -> String
+{
+    let mut output = String::from("syntax = \"proto3\";\n");
+    if let Some(package) = string(file, 2).filter(|p| !p.is_empty()) {
+        output.push_str(&format!("package {};\n", package));
+    }
+    for msg in messages(file, 4) {
+        message_source(msg, 0, &mut output);
+    }
+    for e in messages(file, 5) {
+        enum_source(e, 0, &mut output);
+    }
+    for service in messages(file, 6) {
+        output.push_str(&format!(
+            "service {} {{\n",
+            string(service, 1).unwrap_or_default()
+        ));
+        for method in messages(service, 2) {
+            let stream = |field| match flag(method, field) {
+                true => "stream ",
+                false => "",
+            };
+            output.push_str(&format!(
+                "  rpc {} ({}{}) returns ({}{});\n",
+                string(method, 1).unwrap_or_default(),
+                stream(5),
+                string(method, 2).unwrap_or_default(),
+                stream(6),
+                string(method, 3).unwrap_or_default(),
+            ));
+        }
+        output.push_str("}\n");
+    }
+    output
+}
+
+fn message_source(msg: &MessageValue, indent: usize, output: &mut String)
+{
+    let pad = "  ".repeat(indent);
+    output.push_str(&format!(
+        "{}message {} {{\n",
+        pad,
+        string(msg, 1).unwrap_or_default()
+    ));
+
+    // Fields in the oneofs are written within the oneof blocks. The synthetic oneofs of the proto3
+    // optional fields are ignored as the optional fields decode the same way as the normal ones.
+    let fields: Vec<_> = messages(msg, 2).collect();
+    let oneof_index = |f: &MessageValue| match flag(f, 17) {
+        true => None,
+        false => int(f, 9),
+    };
+    for f in fields.iter().filter(|f| oneof_index(f).is_none()) {
+        field_source(f, true, indent + 1, output);
+    }
+    for (idx, oneof) in messages(msg, 8).enumerate() {
+        let oneof_fields: Vec<_> = fields
+            .iter()
+            .filter(|f| oneof_index(f) == Some(idx as i32))
+            .collect();
+        if oneof_fields.is_empty() {
+            continue;
+        }
+        output.push_str(&format!(
+            "{}  oneof {} {{\n",
+            pad,
+            string(oneof, 1).unwrap_or_default()
+        ));
+        for f in oneof_fields {
+            field_source(f, false, indent + 2, output);
+        }
+        output.push_str(&format!("{}  }}\n", pad));
+    }
+
+    // Map fields are kept as the repeated entry messages, which decode the same way.
+    for nested in messages(msg, 3) {
+        message_source(nested, indent + 1, output);
+    }
+    for e in messages(msg, 4) {
+        enum_source(e, indent + 1, output);
+    }
+    output.push_str(&format!("{}}}\n", pad));
+}
+
+fn field_source(field: &MessageValue, allow_repeated: bool, indent: usize, output: &mut String)
+{
+    let ty = match int(field, 5).unwrap_or_default() {
+        1 => "double",
+        2 => "float",
+        3 => "int64",
+        4 => "uint64",
+        5 => "int32",
+        6 => "fixed64",
+        7 => "fixed32",
+        8 => "bool",
+        9 => "string",
+        12 => "bytes",
+        13 => "uint32",
+        15 => "sfixed32",
+        16 => "sfixed64",
+        17 => "sint32",
+        18 => "sint64",
+        _ => string(field, 6).unwrap_or_default(),
+    };
+    let repeated = match allow_repeated && int(field, 4) == Some(3) {
+        true => "repeated ",
+        false => "",
+    };
+    output.push_str(&format!(
+        "{}{}{} {} = {};\n",
+        "  ".repeat(indent),
+        repeated,
+        ty,
+        string(field, 1).unwrap_or_default(),
+        int(field, 3).unwrap_or_default()
+    ));
+}
+
+fn enum_source(e: &MessageValue, indent: usize, output: &mut String)
+{
+    let pad = "  ".repeat(indent);
+    output.push_str(&format!(
+        "{}enum {} {{\n",
+        pad,
+        string(e, 1).unwrap_or_default()
+    ));
+    for value in messages(e, 2) {
+        output.push_str(&format!(
+            "{}  {} = {};\n",
+            pad,
+            string(value, 1).unwrap_or_default(),
+            int(value, 2).unwrap_or_default()
+        ));
+    }
+    output.push_str(&format!("{}}}\n", pad));
+}
+
+fn values(msg: &MessageValue, number: u64) -> impl Iterator<Item = &Value>
+{
+    msg.fields
+        .iter()
+        .filter(move |f| f.number == number)
+        .map(|f| &f.value)
+}
+
+fn messages(msg: &MessageValue, number: u64) -> impl Iterator<Item = &MessageValue>
+{
+    values(msg, number).filter_map(|v| match v {
+        Value::Message(m) => Some(m.as_ref()),
+        _ => None,
+    })
+}
+
+fn message(msg: &MessageValue, number: u64) -> Option<&MessageValue>
+{
+    messages(msg, number).last()
+}
+
+fn bytes(msg: &MessageValue, number: u64) -> impl Iterator<Item = &[u8]>
+{
+    values(msg, number).filter_map(|v| match v {
+        Value::Bytes(b) => Some(b.as_ref()),
+        _ => None,
+    })
+}
+
+fn string(msg: &MessageValue, number: u64) -> Option<&str>
+{
+    values(msg, number)
+        .filter_map(|v| match v {
+            Value::String(s) => Some(s.as_str()),
+            _ => None,
+        })
+        .last()
+}
+
+fn int(msg: &MessageValue, number: u64) -> Option<i32>
+{
+    values(msg, number)
+        .filter_map(|v| match v {
+            Value::Int32(i) => Some(*i),
+            _ => None,
+        })
+        .last()
+}
+
+fn flag(msg: &MessageValue, number: u64) -> bool
+{
+    values(msg, number).any(|v| matches!(v, Value::Bool(true)))
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn encode_message(field: u64, value: &[u8], output: &mut Vec<u8>)
+    {
+        encode_varint(field << 3 | 2, output);
+        encode_varint(value.len() as u64, output);
+        output.extend_from_slice(value);
+    }
+
+    fn encode_int(field: u64, value: u64, output: &mut Vec<u8>)
+    {
+        encode_varint(field << 3, output);
+        encode_varint(value, output);
+    }
+
+    #[test]
+    fn descriptor_to_proto_source()
+    {
+        // message Foo { repeated string bar = 1; }
+        let mut bar = vec![];
+        encode_string(1, "bar", &mut bar);
+        encode_int(3, 1, &mut bar);
+        encode_int(4, 3, &mut bar);
+        encode_int(5, 9, &mut bar);
+        let mut foo = vec![];
+        encode_string(1, "Foo", &mut foo);
+        encode_message(2, &bar, &mut foo);
+
+        // service Svc { rpc Get (.test.Foo) returns (stream .test.Foo); }
+        let mut get = vec![];
+        encode_string(1, "Get", &mut get);
+        encode_string(2, ".test.Foo", &mut get);
+        encode_string(3, ".test.Foo", &mut get);
+        encode_int(6, 1, &mut get);
+        let mut svc = vec![];
+        encode_string(1, "Svc", &mut svc);
+        encode_message(2, &get, &mut svc);
+
+        let mut file = vec![];
+        encode_string(1, "test.proto", &mut file);
+        encode_string(2, "test", &mut file);
+        encode_message(4, &foo, &mut file);
+        encode_message(6, &svc, &mut file);
+
+        let bootstrap = Context::parse([REFLECTION_PROTO]).unwrap();
+        let file = bootstrap
+            .get_message("proxide.reflection.FileDescriptorProto")
+            .unwrap()
+            .self_ref
+            .decode(&file, &bootstrap);
+
+        let source = proto_source(&file);
+        let ctx = Context::parse([source.as_str()]).unwrap();
+        let service = ctx.get_service("test.Svc").expect(&source);
+        assert!(service.rpcs[0].output.stream, "{}", source);
+        let foo = ctx.get_message("test.Foo").expect(&source);
+        assert_eq!(foo.fields[&1].name, "bar");
+    }
+}
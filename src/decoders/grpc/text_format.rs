@@ -0,0 +1,259 @@
+//! Protobuf text format output.
+//!
+//! Produces the same `field_name: value` / `nested { ... }` syntax as `protoc --decode`. This
+//! allows diffing the decoded messages against other tools or feeding them back to
+//! `protoc --encode`.
+
+use protofish::decode::{EnumValue, MessageValue, PackedArray, UnknownValue, Value};
+use protofish::Context;
+
+/// Render the message fields in text format.
+///
+/// The top level message isn't wrapped in braces, which matches the `protoc` output.
+pub fn message_to_text_format(msg: &MessageValue, ctx: &Context, indent: usize, out: &mut String)
+{
+    // Panic here should indicate that msg_ref is for a different context.
+    let info = ctx.resolve_message(msg.msg_ref);
+
+    for field in &msg.fields {
+        let name = match info.fields.get(&field.number) {
+            Some(f) => f.name.clone(),
+            None => field.number.to_string(),
+        };
+        value_to_text_format(&name, &field.value, ctx, indent, out);
+    }
+}
+
+fn value_to_text_format(name: &str, value: &Value, ctx: &Context, indent: usize, out: &mut String)
+{
+    let prefix = "  ".repeat(indent);
+    let scalar = match value {
+        Value::Double(v) => float(*v),
+        Value::Float(v) => float(*v as f64),
+        Value::Int32(v) => v.to_string(),
+        Value::Int64(v) => v.to_string(),
+        Value::UInt32(v) => v.to_string(),
+        Value::UInt64(v) => v.to_string(),
+        Value::SInt32(v) => v.to_string(),
+        Value::SInt64(v) => v.to_string(),
+        Value::Fixed32(v) => v.to_string(),
+        Value::Fixed64(v) => v.to_string(),
+        Value::SFixed32(v) => v.to_string(),
+        Value::SFixed64(v) => v.to_string(),
+        Value::Bool(v) => v.to_string(),
+        Value::String(v) => escape(v.as_bytes()),
+        Value::Bytes(v) => escape(v),
+        Value::Enum(v) => enum_value(v, ctx),
+        Value::Unknown(UnknownValue::Varint(v)) => v.to_string(),
+        Value::Unknown(UnknownValue::Fixed64(v)) => format!("0x{:016x}", v),
+        Value::Unknown(UnknownValue::Fixed32(v)) => format!("0x{:08x}", v),
+        Value::Unknown(UnknownValue::VariableLength(v)) => escape(v),
+        Value::Unknown(other) => {
+            out.push_str(&format!("{}# {}: {:?}\n", prefix, name, other));
+            return;
+        }
+        Value::Incomplete(bytes) => {
+            out.push_str(&format!(
+                "{}# {}: incomplete data {:X}\n",
+                prefix, name, bytes
+            ));
+            return;
+        }
+
+        // Packed repeated fields are written as one entry per value, same as any other repeated
+        // field.
+        Value::Packed(packed) => {
+            for v in packed_values(packed) {
+                out.push_str(&format!("{}{}: {}\n", prefix, name, v));
+            }
+            return;
+        }
+
+        Value::Message(msg) => {
+            out.push_str(&format!("{}{} {{\n", prefix, name));
+            message_to_text_format(msg, ctx, indent + 1, out);
+            out.push_str(&format!("{}}}\n", prefix));
+            return;
+        }
+    };
+
+    out.push_str(&format!("{}{}: {}\n", prefix, name, scalar));
+}
+
+fn enum_value(value: &EnumValue, ctx: &Context) -> String
+{
+    // Panic here should indicate that enum_ref is for a different context.
+    let e = ctx.resolve_enum(value.enum_ref);
+    match e.field_by_value(value.value) {
+        Some(field) => field.name.clone(),
+        None => value.value.to_string(),
+    }
+}
+
+fn packed_values(packed: &PackedArray) -> Vec<String>
+{
+    match packed {
+        PackedArray::Double(v) => v.iter().map(|v| float(*v)).collect(),
+        PackedArray::Float(v) => v.iter().map(|v| float(*v as f64)).collect(),
+        PackedArray::Int32(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::Int64(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::UInt32(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::UInt64(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::SInt32(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::SInt64(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::Fixed32(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::Fixed64(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::SFixed32(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::SFixed64(v) => v.iter().map(ToString::to_string).collect(),
+        PackedArray::Bool(v) => v.iter().map(ToString::to_string).collect(),
+    }
+}
+
+fn float(v: f64) -> String
+{
+    match v {
+        v if v.is_nan() => "nan".to_string(),
+        v if v.is_infinite() && v > 0.0 => "inf".to_string(),
+        v if v.is_infinite() => "-inf".to_string(),
+        v => v.to_string(),
+    }
+}
+
+/// Quote and escape the data the same way `protoc` does.
+///
+/// Valid UTF-8 sequences are kept as is while any other non-printable bytes are written as octal
+/// escapes.
+fn escape(data: &[u8]) -> String
+{
+    let mut out = String::with_capacity(data.len() + 2);
+    out.push('"');
+
+    let mut remaining = data;
+    while !remaining.is_empty() {
+        let (valid, invalid) = match std::str::from_utf8(remaining) {
+            Ok(s) => (s, &[][..]),
+            Err(e) => {
+                let (valid, rest) = remaining.split_at(e.valid_up_to());
+                let invalid_len = e.error_len().unwrap_or(rest.len());
+                (
+                    std::str::from_utf8(valid).expect("Validated above"),
+                    &rest[..invalid_len],
+                )
+            }
+        };
+
+        for c in valid.chars() {
+            match c {
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                '"' => out.push_str("\\\""),
+                '\'' => out.push_str("\\'"),
+                '\\' => out.push_str("\\\\"),
+                c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                    out.push_str(&format!("\\{:03o}", c as u32))
+                }
+                c => out.push(c),
+            }
+        }
+        for b in invalid {
+            out.push_str(&format!("\\{:03o}", b));
+        }
+
+        remaining = &remaining[valid.len() + invalid.len()..];
+    }
+
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    const PROTO: &str = r#"
+        syntax = "proto3";
+        package test;
+        enum Kind { UNKNOWN = 0; FIRST = 1; }
+        message Inner { string text = 1; bytes data = 2; }
+        message Outer {
+            int32 id = 1;
+            Inner inner = 2;
+            repeated Inner items = 3;
+            repeated int32 numbers = 4;
+            Kind kind = 5;
+            repeated string tags = 6;
+        }
+    "#;
+
+    fn to_text_format(data: &[u8]) -> String
+    {
+        let ctx = Context::parse([PROTO]).unwrap();
+        let msg = ctx.get_message("test.Outer").unwrap().decode(data, &ctx);
+        let mut output = String::new();
+        message_to_text_format(&msg, &ctx, 0, &mut output);
+        output
+    }
+
+    #[test]
+    fn matches_protoc_output()
+    {
+        let data = [
+            // id: 150
+            &[0x08, 0x96, 0x01][..],
+            // inner { text: "a\"b\n" data: "\000\377x" }
+            &[0x12, 0x0b, 0x0a, 0x04, b'a', b'"', b'b', b'\n'],
+            &[0x12, 0x03, 0x00, 0xff, b'x'],
+            // items { text: "one" } items { text: "two" }
+            &[0x1a, 0x05, 0x0a, 0x03, b'o', b'n', b'e'],
+            &[0x1a, 0x05, 0x0a, 0x03, b't', b'w', b'o'],
+            // numbers: [1, 2, 300], packed
+            &[0x22, 0x04, 0x01, 0x02, 0xac, 0x02],
+            // kind: FIRST
+            &[0x28, 0x01],
+            // tags: ["x", "y\t"]
+            &[0x32, 0x01, b'x'],
+            &[0x32, 0x02, b'y', b'\t'],
+            // Unknown field 15 with varint 7.
+            &[0x78, 0x07],
+        ]
+        .concat();
+
+        // The expected output is what `protoc --decode=test.Outer` prints for the data.
+        assert_eq!(
+            to_text_format(&data),
+            r#"id: 150
+inner {
+  text: "a\"b\n"
+  data: "\000\377x"
+}
+items {
+  text: "one"
+}
+items {
+  text: "two"
+}
+numbers: 1
+numbers: 2
+numbers: 300
+kind: FIRST
+tags: "x"
+tags: "y\t"
+15: 7
+"#
+        );
+    }
+
+    #[test]
+    fn escape_strings()
+    {
+        assert_eq!(escape(b""), r#""""#);
+        assert_eq!(escape("'\\\r".as_bytes()), r#""\'\\\r""#);
+        assert_eq!(escape("\u{7f}\u{1}".as_bytes()), r#""\177\001""#);
+
+        // Valid UTF-8 is kept while invalid sequences are escaped byte by byte.
+        assert_eq!(escape("höh".as_bytes()), r#""höh""#);
+        assert_eq!(escape(&[b'a', 0xc3, 0xff, b'b']), r#""a\303\377b""#);
+    }
+}
@@ -0,0 +1,430 @@
+use clap::{App, Arg};
+use snafu::ResultExt;
+use tui::style::{Color, Style};
+use tui::text::{Span, Text};
+
+use super::{ConfigurationValueError, Decoder, DecoderArgs, DecoderFactory, Result, TextBuilder};
+use crate::session::{MessageData, RequestData};
+
+/// Shortest string value that is considered to be base64 encoded binary data.
+///
+/// Short strings consisting of letters and digits are common in ordinary JSON so these would
+/// produce too many false positives.
+const MIN_BASE64_LENGTH: usize = 64;
+
+/// Number of decoded bytes shown after the base64 annotation.
+const BASE64_PREVIEW_SIZE: usize = 16;
+
+pub fn setup_args(app: App) -> App
+{
+    app.arg(Arg::with_name("decode-base64").long("decode-base64").help(
+        "Show long base64 string values in JSON bodies as their decoded size and a hex preview",
+    ))
+    .arg(
+        Arg::with_name(decoder_option!("json", "max-depth"))
+            .long(decoder_option!("json", "max-depth"))
+            .value_name("DEPTH")
+            .help("Collapse the JSON objects and arrays nested deeper than the given depth")
+            .takes_value(true),
+    )
+}
+
+pub fn initialize(args: &DecoderArgs) -> Result<Option<Box<dyn DecoderFactory>>>
+{
+    let max_depth = match args.value_of("max-depth") {
+        Some(depth) => Some(
+            depth
+                .parse::<usize>()
+                .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
+                .context(ConfigurationValueError {
+                    option: decoder_option!("json", "max-depth"),
+                    msg: format!("Invalid depth '{}'", depth),
+                })?,
+        ),
+        None => None,
+    };
+
+    // The base64 option predates the decoder option namespaces.
+    Ok(Some(Box::new(JsonDecoderFactory {
+        decode_base64: args.matches().is_present("decode-base64"),
+        max_depth,
+    })))
+}
+
+pub struct JsonDecoderFactory
+{
+    decode_base64: bool,
+    max_depth: Option<usize>,
+}
+
+impl DecoderFactory for JsonDecoderFactory
+{
+    fn try_create(&self, _request: &RequestData, msg: &MessageData) -> Option<Box<dyn Decoder>>
+    {
+        let content_type = msg.headers.get("content-type")?.to_str().ok()?;
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match mime == "application/json"
+            || (mime.starts_with("application/") && mime.ends_with("+json"))
+        {
+            true => Some(Box::new(JsonDecoder {
+                decode_base64: self.decode_base64,
+                max_depth: self.max_depth,
+            })),
+            false => None,
+        }
+    }
+}
+
+pub struct JsonDecoder
+{
+    decode_base64: bool,
+
+    /// Objects and arrays nested deeper than this are collapsed.
+    max_depth: Option<usize>,
+}
+
+impl Decoder for JsonDecoder
+{
+    fn name(&self) -> &'static str
+    {
+        "json"
+    }
+
+    fn decode(&self, msg: &MessageData) -> Text<'_>
+    {
+        let mut builder = TextBuilder::default();
+        if !msg.headers.is_empty() {
+            builder.push(Span::raw("Headers\n"));
+            for (k, v) in &msg.headers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        // Streaming responses may be incomplete so fall back to the raw text if the content isn't
+        // valid JSON.
+        match serde_json::from_slice::<serde::de::IgnoredAny>(&msg.content) {
+            Ok(_) => pretty_print(
+                &String::from_utf8_lossy(&msg.content),
+                self.decode_base64,
+                self.max_depth,
+                &mut builder,
+            ),
+            Err(_) => {
+                for line in String::from_utf8_lossy(&msg.content).lines() {
+                    builder.push(Span::raw(format!("{}\n", line)));
+                }
+            }
+        }
+
+        if !msg.trailers.is_empty() {
+            builder.push(Span::raw("\n"));
+            builder.push(Span::raw("\nTrailers\n"));
+            for (k, v) in &msg.trailers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+        }
+        builder.build()
+    }
+
+    fn index(&self, msg: &MessageData) -> Vec<String>
+    {
+        let mut output = vec![];
+        if let Ok(value) = serde_json::from_slice::<serde_json::Value>(&msg.content) {
+            flatten(&value, &mut output);
+        }
+        output
+    }
+}
+
+/// Collect the keys and the string values for the search index.
+fn flatten(value: &serde_json::Value, output: &mut Vec<String>)
+{
+    match value {
+        serde_json::Value::String(s) => output.push(s.clone()),
+        serde_json::Value::Array(items) => items.iter().for_each(|v| flatten(v, output)),
+        serde_json::Value::Object(map) => {
+            for (k, v) in map {
+                output.push(k.clone());
+                flatten(v, output);
+            }
+        }
+        serde_json::Value::Number(_) | serde_json::Value::Bool(_) | serde_json::Value::Null => {}
+    }
+}
+
+/// Pretty print already validated JSON.
+///
+/// The printing is done on the tokens of the original document instead of a parsed
+/// `serde_json::Value` to retain the original order of the object keys.
+///
+/// With `decode_base64` the string values that look like base64 are replaced with an annotation
+/// of their decoded size and the first bytes of the decoded data. Objects and arrays nested
+/// deeper than `max_depth` are shown as `{...}` and `[...]`.
+fn pretty_print<'a>(
+    json: &str,
+    decode_base64: bool,
+    max_depth: Option<usize>,
+    builder: &mut TextBuilder<'a>,
+)
+{
+    let key_style = Style::default().fg(Color::Cyan);
+    let string_style = Style::default().fg(Color::Green);
+    let literal_style = Style::default().fg(Color::Yellow);
+    let annotation_style = Style::default().fg(Color::Magenta);
+    let preview_style = Style::default().fg(Color::DarkGray);
+
+    let mut indent = 0;
+    let mut chars = json.chars().peekable();
+
+    // Object keys and values are both strings so we need to track whether the next string is a
+    // key. The stack contains true for objects and false for arrays.
+    let mut stack: Vec<bool> = vec![];
+    let mut expect_key = false;
+    let mut empty = false;
+
+    let newline = |builder: &mut TextBuilder<'a>, indent: usize| {
+        builder.push(Span::raw("\n"));
+        builder.push(Span::raw("  ".repeat(indent)));
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '{' | '[' if max_depth.map(|max| stack.len() >= max).unwrap_or(false) => {
+                // Skip the nested value, ignoring the brackets within its strings.
+                let mut depth = 1;
+                let mut in_string = false;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' if in_string => {
+                            chars.next();
+                        }
+                        '"' => in_string = !in_string,
+                        '{' | '[' if !in_string => depth += 1,
+                        '}' | ']' if !in_string => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                let collapsed = match c {
+                    '{' => "{...}",
+                    _ => "[...]",
+                };
+                builder.push(Span::styled(collapsed, preview_style));
+                expect_key = false;
+            }
+            '{' | '[' => {
+                builder.push(Span::raw(c.to_string()));
+                stack.push(c == '{');
+                expect_key = c == '{';
+
+                // Keep empty objects and arrays on a single line.
+                while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                    chars.next();
+                }
+                empty = matches!(chars.peek(), Some('}') | Some(']'));
+                if !empty {
+                    indent += 1;
+                    newline(builder, indent);
+                }
+            }
+            '}' | ']' => {
+                if !empty {
+                    indent = indent.saturating_sub(1);
+                    newline(builder, indent);
+                }
+                empty = false;
+                builder.push(Span::raw(c.to_string()));
+                stack.pop();
+                expect_key = false;
+            }
+            ',' => {
+                builder.push(Span::raw(","));
+                newline(builder, indent);
+                expect_key = stack.last().copied().unwrap_or(false);
+            }
+            ':' => {
+                builder.push(Span::raw(": "));
+                expect_key = false;
+            }
+            '"' => {
+                let mut s = String::from('"');
+                while let Some(c) = chars.next() {
+                    s.push(c);
+                    match c {
+                        '\\' => s.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                let decoded = match decode_base64 && !expect_key {
+                    true => decode_base64_value(&s[1..s.len().saturating_sub(1)]),
+                    false => None,
+                };
+                match decoded {
+                    Some(data) => {
+                        builder.push(Span::styled(
+                            format!("<base64, {}>", format_size(data.len())),
+                            annotation_style,
+                        ));
+                        let preview = data
+                            .iter()
+                            .take(BASE64_PREVIEW_SIZE)
+                            .map(|b| format!("{:02x}", b))
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        let ellipsis = match data.len() > BASE64_PREVIEW_SIZE {
+                            true => " ...",
+                            false => "",
+                        };
+                        builder.push(Span::styled(
+                            format!(" {}{}", preview, ellipsis),
+                            preview_style,
+                        ));
+                    }
+                    None => {
+                        let style = match expect_key {
+                            true => key_style,
+                            false => string_style,
+                        };
+                        builder.push(Span::styled(s, style));
+                    }
+                }
+            }
+            c => {
+                // Numbers and literals continue until the next structural character.
+                let mut s = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || ",:]}".contains(*c) {
+                        break;
+                    }
+                    s.extend(chars.next());
+                }
+                builder.push(Span::styled(s, literal_style));
+            }
+        }
+    }
+    builder.push(Span::raw("\n"));
+}
+
+/// Decode the contents of a JSON string if it looks like base64 encoded data.
+///
+/// Both the standard and the URL-safe alphabets are accepted with or without padding.
+fn decode_base64_value(value: &str) -> Option<Vec<u8>>
+{
+    // JSON allows escaping the forward slash, which is part of the standard alphabet. Any other
+    // escape means the value isn't base64.
+    let value = value.replace("\\/", "/");
+    let value = value.trim_end_matches('=');
+    if value.len() < MIN_BASE64_LENGTH || value.len() % 4 == 1 {
+        return None;
+    }
+
+    let standard = value
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/');
+    let url_safe = value
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    let config = match (standard, url_safe) {
+        (true, _) => base64::STANDARD_NO_PAD,
+        (false, true) => base64::URL_SAFE_NO_PAD,
+        (false, false) => return None,
+    };
+    base64::decode_config(value, config).ok()
+}
+
+fn format_size(size: usize) -> String
+{
+    match size {
+        s if s < 1024 => format!("{} B", s),
+        s if s < 1024 * 1024 => format!("{:.1} KB", s as f64 / 1024.0),
+        s => format!("{:.1} MB", s as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn to_string(text: &Text) -> String
+    {
+        text.lines
+            .iter()
+            .flat_map(|line| line.0.iter())
+            .map(|s| s.content.as_ref())
+            .collect()
+    }
+
+    #[test]
+    fn base64_values_are_annotated()
+    {
+        let blob = base64::encode(&(0..=255u8).cycle().take(1230).collect::<Vec<_>>());
+        let json = format!(
+            r#"{{"blob":"{}","name":"{}","{}":1}}"#,
+            blob,
+            "a".repeat(60),
+            blob
+        );
+
+        let mut builder = TextBuilder::default();
+        pretty_print(&json, true, None, &mut builder);
+        assert_eq!(
+            to_string(&builder.build()),
+            format!(
+                "{{\n  \"blob\": <base64, 1.2 KB> 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f ...,\n  \"name\": \"{}\",\n  \"{}\": 1\n}}\n",
+                "a".repeat(60),
+                blob
+            )
+        );
+
+        // Strings are left alone unless the option is given.
+        let mut builder = TextBuilder::default();
+        pretty_print(&json, false, None, &mut builder);
+        assert!(to_string(&builder.build()).contains(&blob));
+    }
+
+    #[test]
+    fn deep_values_are_collapsed()
+    {
+        let json = r#"{"a":{"b":[1,{"c":"}]"}],"e":{}},"d":[]}"#;
+        let mut builder = TextBuilder::default();
+        pretty_print(json, false, Some(2), &mut builder);
+        assert_eq!(
+            to_string(&builder.build()),
+            "{\n  \"a\": {\n    \"b\": [...],\n    \"e\": {...}\n  },\n  \"d\": []\n}\n"
+        );
+
+        let mut builder = TextBuilder::default();
+        pretty_print(json, false, Some(0), &mut builder);
+        assert_eq!(to_string(&builder.build()), "{...}\n");
+    }
+
+    #[test]
+    fn detect_base64_values()
+    {
+        let data: Vec<u8> = (0..100).collect();
+        assert_eq!(
+            decode_base64_value(&base64::encode(&data)),
+            Some(data.clone())
+        );
+        assert_eq!(
+            decode_base64_value(&base64::encode_config(&data, base64::URL_SAFE_NO_PAD)),
+            Some(data.clone())
+        );
+        assert_eq!(
+            decode_base64_value(&base64::encode(&data).replace('/', "\\/")),
+            Some(data)
+        );
+
+        assert_eq!(decode_base64_value("aGVsbG8gd29ybGQ="), None);
+        assert_eq!(decode_base64_value(&"a b ".repeat(20)), None);
+        assert_eq!(decode_base64_value(&"ab+_".repeat(20)), None);
+    }
+}
@@ -0,0 +1,306 @@
+use tui::style::{Color, Style};
+use tui::text::{Span, Text};
+
+use super::{hex_dump, Decoder, DecoderArgs, DecoderFactory, Result, TextBuilder};
+use crate::session::{MessageData, RequestData};
+
+/// Number of bytes shown of the binary parts.
+const PREVIEW_BYTES: usize = 64;
+
+pub fn initialize(_args: &DecoderArgs) -> Result<Option<Box<dyn DecoderFactory>>>
+{
+    Ok(Some(Box::new(MultipartDecoderFactory)))
+}
+
+pub struct MultipartDecoderFactory;
+impl DecoderFactory for MultipartDecoderFactory
+{
+    fn try_create(&self, _request: &RequestData, msg: &MessageData) -> Option<Box<dyn Decoder>>
+    {
+        let content_type = msg.headers.get("content-type")?.to_str().ok()?;
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        if mime != "multipart/form-data" {
+            return None;
+        }
+
+        let boundary = header_param(content_type, "boundary")?;
+        Some(Box::new(MultipartDecoder { boundary }))
+    }
+}
+
+/// Decoder that lists the parts of `multipart/form-data` content.
+pub struct MultipartDecoder
+{
+    boundary: String,
+}
+
+impl Decoder for MultipartDecoder
+{
+    fn name(&self) -> &'static str
+    {
+        "multipart"
+    }
+
+    fn decode(&self, msg: &MessageData) -> Text<'_>
+    {
+        let label_style = Style::default().fg(Color::Cyan);
+
+        let mut builder = TextBuilder::default();
+        if !msg.headers.is_empty() {
+            builder.push(Span::raw("Headers\n"));
+            for (k, v) in &msg.headers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        for (idx, part) in parse_parts(&msg.content, &self.boundary).iter().enumerate() {
+            let mut label = format!("[Part {}]", idx + 1);
+            if let Some(name) = &part.name {
+                label.push_str(&format!(" name={:?}", name));
+            }
+            if let Some(filename) = &part.filename {
+                label.push_str(&format!(" filename={:?}", filename));
+            }
+            builder.push(Span::styled(label, label_style));
+            builder.push(Span::raw("\n"));
+            for (k, v) in &part.headers {
+                builder.push(Span::raw(format!(" - {}: {}\n", k, v)));
+            }
+
+            match part.text() {
+                Some(text) => {
+                    for line in text.lines() {
+                        builder.push(Span::raw(format!("{}\n", line)));
+                    }
+                }
+                None => {
+                    builder.push(Span::raw(format!("{} bytes\n", part.body.len())));
+                    hex_dump(
+                        &part.body[..part.body.len().min(PREVIEW_BYTES)],
+                        &mut builder,
+                    );
+                }
+            }
+
+            if !part.complete {
+                builder.push(Span::styled(
+                    "<Incomplete part>\n",
+                    Style::default().fg(Color::LightRed),
+                ));
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        if !msg.trailers.is_empty() {
+            builder.push(Span::raw("\nTrailers\n"));
+            for (k, v) in &msg.trailers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+        }
+        builder.build()
+    }
+
+    fn index(&self, msg: &MessageData) -> Vec<String>
+    {
+        let mut output = vec![];
+        for part in parse_parts(&msg.content, &self.boundary) {
+            output.extend(part.name.clone());
+            output.extend(part.filename.clone());
+            output.extend(part.text());
+        }
+        output
+    }
+}
+
+struct Part<'a>
+{
+    headers: Vec<(String, String)>,
+    name: Option<String>,
+    filename: Option<String>,
+    content_type: Option<String>,
+    body: &'a [u8],
+
+    /// False if the content ended before the closing boundary of the part.
+    complete: bool,
+}
+
+impl<'a> Part<'a>
+{
+    /// The body as text if the part is textual.
+    fn text(&self) -> Option<String>
+    {
+        let is_text = match &self.content_type {
+            Some(mime) => {
+                let mime = mime
+                    .split(';')
+                    .next()
+                    .unwrap_or("")
+                    .trim()
+                    .to_ascii_lowercase();
+                mime.starts_with("text/") || mime.ends_with("json") || mime.ends_with("xml")
+            }
+
+            // Parts without a content type default to text/plain, but files without one are
+            // likely to be binary anyway so those need to pass the UTF-8 check.
+            None => self.filename.is_none() || std::str::from_utf8(self.body).is_ok(),
+        };
+        match is_text {
+            true => Some(String::from_utf8_lossy(self.body).into_owned()),
+            false => None,
+        }
+    }
+}
+
+/// Parse the parts of the multipart content.
+///
+/// The content may still be streaming in so the last part may be incomplete. Such part is
+/// returned with `complete` set to false.
+fn parse_parts<'a>(content: &'a [u8], boundary: &str) -> Vec<Part<'a>>
+{
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+
+    // Skip the preamble before the first delimiter.
+    let mut data = match find(content, delimiter) {
+        Some(idx) => &content[idx + delimiter.len()..],
+        None => return vec![],
+    };
+
+    let mut parts = vec![];
+    loop {
+        // The delimiter is followed by "--" on the closing delimiter or a line break otherwise.
+        if data.starts_with(b"--") {
+            break;
+        }
+        data = match data.iter().position(|&b| b == b'\n') {
+            Some(idx) => &data[idx + 1..],
+            None => break,
+        };
+
+        // The delimiter is preceded by a line break that belongs to the delimiter.
+        let (part, complete) = match find(data, delimiter) {
+            Some(idx) => {
+                let part = &data[..idx];
+                let part = part.strip_suffix(b"\n").unwrap_or(part);
+                let part = part.strip_suffix(b"\r").unwrap_or(part);
+                data = &data[idx + delimiter.len()..];
+                (part, true)
+            }
+            None => (data, false),
+        };
+        parts.push(parse_part(part, complete));
+
+        if !complete {
+            break;
+        }
+    }
+    parts
+}
+
+fn parse_part(data: &[u8], complete: bool) -> Part<'_>
+{
+    let (header_data, body, complete) = match find(data, b"\r\n\r\n") {
+        Some(idx) => (&data[..idx], &data[idx + 4..], complete),
+        None => (data, &data[data.len()..], false),
+    };
+
+    let headers: Vec<_> = String::from_utf8_lossy(header_data)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+        .collect();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+    let disposition = header("content-disposition");
+
+    Part {
+        name: disposition.and_then(|d| header_param(d, "name")),
+        filename: disposition.and_then(|d| header_param(d, "filename")),
+        content_type: header("content-type").map(String::from),
+        headers,
+        body,
+        complete,
+    }
+}
+
+/// Get a `key=value` parameter from a header value such as `form-data; name="file"`.
+fn header_param(value: &str, param: &str) -> Option<String>
+{
+    value.split(';').skip(1).find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        match k.trim().eq_ignore_ascii_case(param) {
+            true => Some(v.trim().trim_matches('"').to_string()),
+            false => None,
+        }
+    })
+}
+
+fn find(data: &[u8], needle: &[u8]) -> Option<usize>
+{
+    data.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    const BODY: &[u8] = b"preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Hello\r\nWorld\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+        Content-Type: application/octet-stream\r\n\
+        \r\n\
+        \x00\x01\x02\r\n\
+        --XyZ--\r\n";
+
+    #[test]
+    fn parse_multipart()
+    {
+        let parts = parse_parts(BODY, "XyZ");
+        assert_eq!(parts.len(), 2);
+
+        assert_eq!(parts[0].name.as_deref(), Some("title"));
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(parts[0].text().as_deref(), Some("Hello\r\nWorld"));
+        assert!(parts[0].complete);
+
+        assert_eq!(parts[1].name.as_deref(), Some("file"));
+        assert_eq!(parts[1].filename.as_deref(), Some("a.bin"));
+        assert_eq!(
+            parts[1].content_type.as_deref(),
+            Some("application/octet-stream")
+        );
+        assert_eq!(parts[1].body, b"\x00\x01\x02");
+        assert_eq!(parts[1].text(), None);
+        assert!(parts[1].complete);
+    }
+
+    #[test]
+    fn incomplete_multipart()
+    {
+        let parts = parse_parts(&BODY[..149], "XyZ");
+        assert_eq!(parts.len(), 2);
+        assert!(parts[0].complete);
+        assert_eq!(parts[1].name.as_deref(), Some("file"));
+        assert!(!parts[1].complete);
+    }
+
+    #[test]
+    fn boundary_from_content_type()
+    {
+        assert_eq!(
+            header_param("multipart/form-data; boundary=\"a b\"", "boundary").as_deref(),
+            Some("a b")
+        );
+        assert_eq!(header_param("multipart/form-data", "boundary"), None);
+    }
+}
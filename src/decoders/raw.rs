@@ -1,36 +1,100 @@
-use clap::ArgMatches;
+use std::fmt::Write;
+use std::ops::Range;
 
 use super::*;
 
-pub fn initialize(_args: &ArgMatches) -> Result<Option<Box<dyn DecoderFactory>>>
+/// Number of bytes on each line of the hex dump.
+const BYTES_PER_LINE: usize = 16;
+
+pub fn initialize(_args: &DecoderArgs) -> Result<Option<Box<dyn DecoderFactory>>>
 {
-    Ok(Some(Box::new(RawDecoderFactory)))
+    Ok(Some(Box::new(RawHexDecoderFactory)))
 }
 
-pub struct RawDecoderFactory;
-impl DecoderFactory for RawDecoderFactory
+pub struct RawHexDecoderFactory;
+impl DecoderFactory for RawHexDecoderFactory
 {
     fn try_create(&self, _: &RequestData, _: &MessageData) -> Option<Box<dyn Decoder>>
     {
-        Some(Box::new(RawDecoder))
+        Some(Box::new(RawHexDecoder))
     }
 }
 
-pub struct RawDecoder;
-impl Decoder for RawDecoder
+/// Decoder that displays the content as an `offset | hex bytes | ascii` dump.
+pub struct RawHexDecoder;
+impl Decoder for RawHexDecoder
 {
     fn name(&self) -> &'static str
     {
-        "raw"
+        "hex"
     }
 
     fn decode(&self, msg: &MessageData) -> Text
     {
-        Text::raw(format!("{:?}", msg.content))
+        self.decode_lines(msg, 0..self.line_count(msg).unwrap_or(0))
     }
 
     fn index(&self, _msg: &MessageData) -> Vec<String>
     {
         vec![]
     }
+
+    fn line_count(&self, msg: &MessageData) -> Option<usize>
+    {
+        Some(msg.content.len().div_ceil(BYTES_PER_LINE))
+    }
+
+    fn decode_lines(&self, msg: &MessageData, lines: Range<usize>) -> Text<'_>
+    {
+        let start = (lines.start * BYTES_PER_LINE).min(msg.content.len());
+        let end = (lines.end * BYTES_PER_LINE).min(msg.content.len());
+        let mut output = String::new();
+        for (idx, chunk) in msg.content[start..end].chunks(BYTES_PER_LINE).enumerate() {
+            let _ = write!(output, "{:08x} |", start + idx * BYTES_PER_LINE);
+            for b in chunk {
+                let _ = write!(output, " {:02x}", b);
+            }
+
+            // Pad the short last line to keep the ASCII column aligned.
+            output.push_str(&"   ".repeat(BYTES_PER_LINE - chunk.len()));
+            output.push_str(" | ");
+            output.extend(chunk.iter().map(|&b| match b {
+                0x20..=0x7e => b as char,
+                _ => '.',
+            }));
+            output.push('\n');
+        }
+        Text::raw(output)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::session::RequestPart;
+
+    #[test]
+    fn hex_dump_lines()
+    {
+        let mut msg = MessageData::new(RequestPart::Request);
+        msg.content
+            .extend_from_slice(b"Hello, Proxide!\n\x00\x01\x7fEnd");
+        assert_eq!(RawHexDecoder.line_count(&msg), Some(2));
+
+        let text = RawHexDecoder.decode(&msg);
+        assert_eq!(
+            text.lines[0].0[0].content,
+            "00000000 | 48 65 6c 6c 6f 2c 20 50 72 6f 78 69 64 65 21 0a | Hello, Proxide!."
+        );
+        assert_eq!(
+            text.lines[1].0[0].content,
+            format!("00000010 | 00 01 7f 45 6e 64{} | ...End", "   ".repeat(10))
+        );
+
+        // Only the requested lines are formatted.
+        let text = RawHexDecoder.decode_lines(&msg, 1..5);
+        assert_eq!(text.lines.len(), 1);
+        assert!(text.lines[0].0[0].content.starts_with("00000010 |"));
+    }
 }
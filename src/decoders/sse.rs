@@ -0,0 +1,227 @@
+use tui::style::{Color, Style};
+use tui::text::{Span, Text};
+
+use super::{Decoder, DecoderArgs, DecoderFactory, Result, TextBuilder};
+use crate::session::{MessageData, RequestData};
+
+pub fn initialize(_args: &DecoderArgs) -> Result<Option<Box<dyn DecoderFactory>>>
+{
+    Ok(Some(Box::new(SseDecoderFactory)))
+}
+
+pub struct SseDecoderFactory;
+impl DecoderFactory for SseDecoderFactory
+{
+    fn try_create(&self, _request: &RequestData, msg: &MessageData) -> Option<Box<dyn Decoder>>
+    {
+        let content_type = msg.headers.get("content-type")?.to_str().ok()?;
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match mime == "text/event-stream" {
+            true => Some(Box::new(SseDecoder)),
+            false => None,
+        }
+    }
+}
+
+/// Decoder for server-sent events.
+///
+/// The whole stream is parsed again whenever new data arrives so the event still being received
+/// is shown as incomplete until the blank line that ends it.
+pub struct SseDecoder;
+impl Decoder for SseDecoder
+{
+    fn name(&self) -> &'static str
+    {
+        "sse"
+    }
+
+    fn decode(&self, msg: &MessageData) -> Text<'_>
+    {
+        let mut builder = TextBuilder::default();
+        if !msg.headers.is_empty() {
+            builder.push(Span::raw("Headers\n"));
+            for (k, v) in &msg.headers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        let label_style = Style::default().fg(Color::Cyan);
+        let content = String::from_utf8_lossy(&msg.content);
+        let (events, partial) = parse_events(&content);
+        for event in &events {
+            builder.push(Span::styled(event.label(), label_style));
+            builder.push(Span::raw("\n"));
+            for line in event.data.lines() {
+                builder.push(Span::raw(format!("{}\n", line)));
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        if let Some(event) = partial {
+            builder.push(Span::styled(
+                format!("{} <incomplete>", event.label()),
+                Style::default().fg(Color::LightRed),
+            ));
+            builder.push(Span::raw("\n"));
+            for line in event.data.lines() {
+                builder.push(Span::raw(format!("{}\n", line)));
+            }
+        }
+
+        builder.build()
+    }
+
+    fn index(&self, msg: &MessageData) -> Vec<String>
+    {
+        let content = String::from_utf8_lossy(&msg.content);
+        let (events, partial) = parse_events(&content);
+        events
+            .into_iter()
+            .chain(partial)
+            .map(|e| e.data)
+            .filter(|data| !data.is_empty())
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Event
+{
+    event: Option<String>,
+
+    /// The `data` fields joined with newlines.
+    data: String,
+    id: Option<String>,
+    retry: Option<u64>,
+
+    /// Whether the event has any `data` fields. Separate from `data` since the data may be empty.
+    has_data: bool,
+}
+
+impl Event
+{
+    fn is_empty(&self) -> bool
+    {
+        self.event.is_none() && self.id.is_none() && self.retry.is_none() && !self.has_data
+    }
+
+    fn apply(&mut self, line: &str)
+    {
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+                self.has_data = true;
+            }
+            "id" => self.id = Some(value.to_string()),
+            "retry" => self.retry = value.parse().ok().or(self.retry),
+
+            // Lines starting with a colon are comments.
+            _ => {}
+        }
+    }
+
+    fn label(&self) -> String
+    {
+        let mut label = format!("[{}]", self.event.as_deref().unwrap_or("message"));
+        if let Some(id) = &self.id {
+            label.push_str(&format!(" id: {}", id));
+        }
+        if let Some(retry) = self.retry {
+            label.push_str(&format!(" retry: {} ms", retry));
+        }
+        label
+    }
+}
+
+/// Parse the complete events of the stream.
+///
+/// Returns the event that hasn't been terminated by a blank line yet separately. Comments and
+/// unknown fields are skipped.
+fn parse_events(content: &str) -> (Vec<Event>, Option<Event>)
+{
+    let mut events = vec![];
+    let mut current = Event::default();
+    let mut rest = content;
+    while let Some(end) = rest.find(['\n', '\r']) {
+        let line = &rest[..end];
+        rest = match rest[end..].strip_prefix("\r\n") {
+            Some(r) => r,
+            None => &rest[end + 1..],
+        };
+
+        if line.is_empty() {
+            if !current.is_empty() {
+                events.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        current.apply(line);
+    }
+
+    // A line still being received belongs to the incomplete event as well.
+    if !rest.is_empty() {
+        current.apply(rest);
+    }
+
+    match current.is_empty() {
+        true => (events, None),
+        false => (events, Some(current)),
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn parse_event_stream()
+    {
+        let stream = ": keep-alive\n\n\
+            data: first\r\n\r\n\
+            event: update\nid: 2\nretry: 1000\ndata: {\"a\": 1,\ndata:  \"b\": 2}\n\n\
+            data\n\n\
+            event: update\ndata: part";
+        let (events, partial) = parse_events(stream);
+        assert_eq!(
+            events,
+            vec![
+                Event {
+                    data: "first".to_string(),
+                    has_data: true,
+                    ..Default::default()
+                },
+                Event {
+                    event: Some("update".to_string()),
+                    data: "{\"a\": 1,\n \"b\": 2}".to_string(),
+                    id: Some("2".to_string()),
+                    retry: Some(1000),
+                    has_data: true,
+                },
+                Event {
+                    has_data: true,
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(
+            partial,
+            Some(Event {
+                event: Some("update".to_string()),
+                data: "part".to_string(),
+                has_data: true,
+                ..Default::default()
+            })
+        );
+    }
+}
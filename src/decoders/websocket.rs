@@ -0,0 +1,293 @@
+use std::convert::TryFrom;
+use tui::style::{Color, Style};
+use tui::text::{Span, Text};
+
+use super::{hex_dump, Decoder, DecoderArgs, DecoderFactory, Result, TextBuilder};
+use crate::session::{MessageData, RequestData};
+
+pub fn initialize(_args: &DecoderArgs) -> Result<Option<Box<dyn DecoderFactory>>>
+{
+    Ok(Some(Box::new(WebSocketDecoderFactory)))
+}
+
+pub struct WebSocketDecoderFactory;
+impl DecoderFactory for WebSocketDecoderFactory
+{
+    fn try_create(&self, _request: &RequestData, msg: &MessageData) -> Option<Box<dyn Decoder>>
+    {
+        // Both the upgrade request and the 101 response carry the Upgrade header.
+        let upgrade = msg.headers.get("upgrade")?.to_str().ok()?;
+        match upgrade.trim().eq_ignore_ascii_case("websocket") {
+            true => Some(Box::new(WebSocketDecoder)),
+            false => None,
+        }
+    }
+}
+
+pub struct WebSocketDecoder;
+impl Decoder for WebSocketDecoder
+{
+    fn name(&self) -> &'static str
+    {
+        "websocket"
+    }
+
+    fn decode(&self, msg: &MessageData) -> Text<'_>
+    {
+        let mut builder = TextBuilder::default();
+        if !msg.headers.is_empty() {
+            builder.push(Span::raw("Headers\n"));
+            for (k, v) in &msg.headers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        let label_style = Style::default().fg(Color::Cyan);
+        let (messages, remainder) = parse_messages(&msg.content);
+        for message in messages {
+            let label = match message.fragments {
+                1 => format!("[{}]", message.opcode.name()),
+                n => format!("[{}, {} fragments]", message.opcode.name(), n),
+            };
+            builder.push(Span::styled(label, label_style));
+
+            match message.opcode {
+                Opcode::Text => {
+                    builder.push(Span::raw("\n"));
+                    for line in String::from_utf8_lossy(&message.payload).lines() {
+                        builder.push(Span::raw(format!("{}\n", line)));
+                    }
+                }
+                Opcode::Close if message.payload.len() >= 2 => {
+                    let code = u16::from_be_bytes([message.payload[0], message.payload[1]]);
+                    builder.push(Span::raw(format!(
+                        " {} {}\n",
+                        code,
+                        String::from_utf8_lossy(&message.payload[2..])
+                    )));
+                }
+                _ if message.payload.is_empty() => builder.push(Span::raw("\n")),
+                _ => {
+                    builder.push(Span::raw(format!(" {} bytes\n", message.payload.len())));
+                    hex_dump(&message.payload, &mut builder);
+                }
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        if remainder > 0 {
+            builder.push(Span::styled(
+                format!("<Incomplete frame: {} bytes>\n", remainder),
+                Style::default().fg(Color::LightRed),
+            ));
+        }
+
+        builder.build()
+    }
+
+    fn index(&self, msg: &MessageData) -> Vec<String>
+    {
+        parse_messages(&msg.content)
+            .0
+            .into_iter()
+            .filter(|m| m.opcode == Opcode::Text)
+            .map(|m| String::from_utf8_lossy(&m.payload).into_owned())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Opcode
+{
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+    Reserved(u8),
+}
+
+impl Opcode
+{
+    fn from_u8(opcode: u8) -> Self
+    {
+        match opcode {
+            0x0 => Opcode::Continuation,
+            0x1 => Opcode::Text,
+            0x2 => Opcode::Binary,
+            0x8 => Opcode::Close,
+            0x9 => Opcode::Ping,
+            0xA => Opcode::Pong,
+            other => Opcode::Reserved(other),
+        }
+    }
+
+    fn name(&self) -> String
+    {
+        match self {
+            Opcode::Continuation => "Continuation".to_string(),
+            Opcode::Text => "Text".to_string(),
+            Opcode::Binary => "Binary".to_string(),
+            Opcode::Close => "Close".to_string(),
+            Opcode::Ping => "Ping".to_string(),
+            Opcode::Pong => "Pong".to_string(),
+            Opcode::Reserved(op) => format!("Opcode {:#x}", op),
+        }
+    }
+}
+
+struct Frame
+{
+    fin: bool,
+    opcode: Opcode,
+    payload: Vec<u8>,
+}
+
+struct WebSocketMessage
+{
+    opcode: Opcode,
+    fragments: usize,
+    payload: Vec<u8>,
+}
+
+/// Parse the complete messages in the data.
+///
+/// Returns the messages and the amount of bytes left over at the end of the data.
+fn parse_messages(mut data: &[u8]) -> (Vec<WebSocketMessage>, usize)
+{
+    let mut messages = vec![];
+    let mut partial: Option<WebSocketMessage> = None;
+    while let Some((frame, rest)) = parse_frame(data) {
+        data = rest;
+
+        // Control frames may be interleaved between the fragments of a data message.
+        if frame.opcode != Opcode::Continuation && !frame.fin {
+            partial = Some(WebSocketMessage {
+                opcode: frame.opcode,
+                fragments: 1,
+                payload: frame.payload,
+            });
+            continue;
+        }
+
+        let message = match (frame.opcode, partial.take()) {
+            (Opcode::Continuation, Some(mut message)) => {
+                message.fragments += 1;
+                message.payload.extend(frame.payload);
+                if !frame.fin {
+                    partial = Some(message);
+                    continue;
+                }
+                message
+            }
+            (opcode, previous) => {
+                partial = previous;
+                WebSocketMessage {
+                    opcode,
+                    fragments: 1,
+                    payload: frame.payload,
+                }
+            }
+        };
+        messages.push(message);
+    }
+
+    // A message that is still being fragmented is shown as is.
+    messages.extend(partial);
+    (messages, data.len())
+}
+
+/// Parse a single frame. See RFC 6455, section 5.2.
+fn parse_frame(data: &[u8]) -> Option<(Frame, &[u8])>
+{
+    if data.len() < 2 {
+        return None;
+    }
+
+    let fin = data[0] & 0x80 != 0;
+    let opcode = Opcode::from_u8(data[0] & 0x0f);
+    let masked = data[1] & 0x80 != 0;
+    let (payload_len, mut offset) = match data[1] & 0x7f {
+        126 => (u16::from_be_bytes([*data.get(2)?, *data.get(3)?]) as u64, 4),
+        127 => {
+            let mut len = [0_u8; 8];
+            len.copy_from_slice(data.get(2..10)?);
+            (u64::from_be_bytes(len), 10)
+        }
+        len => (u64::from(len), 2),
+    };
+
+    // Frames sent by the client are masked with a 4 byte key.
+    let mask = match masked {
+        true => {
+            let mask = data.get(offset..offset + 4)?;
+            offset += 4;
+            Some(mask)
+        }
+        false => None,
+    };
+
+    let end = offset.checked_add(usize::try_from(payload_len).ok()?)?;
+    let mut payload = data.get(offset..end)?.to_vec();
+    if let Some(mask) = mask {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+    }
+
+    Some((
+        Frame {
+            fin,
+            opcode,
+            payload,
+        },
+        &data[end..],
+    ))
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn parse_masked_text_frame()
+    {
+        // "Hello" masked with the key from RFC 6455, section 5.7.
+        let data = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let (messages, remainder) = parse_messages(&data);
+        assert_eq!(remainder, 0);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].opcode, Opcode::Text);
+        assert_eq!(messages[0].payload, b"Hello");
+    }
+
+    #[test]
+    fn parse_fragmented_message()
+    {
+        // "Hel" + ping + "lo" from RFC 6455, section 5.7.
+        let data = [
+            0x01, 0x03, 0x48, 0x65, 0x6c, 0x89, 0x00, 0x80, 0x02, 0x6c, 0x6f,
+        ];
+        let (messages, remainder) = parse_messages(&data);
+        assert_eq!(remainder, 0);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].opcode, Opcode::Ping);
+        assert_eq!(messages[1].opcode, Opcode::Text);
+        assert_eq!(messages[1].fragments, 2);
+        assert_eq!(messages[1].payload, b"Hello");
+    }
+
+    #[test]
+    fn incomplete_frame_is_left_over()
+    {
+        let data = [0x82, 0x7e, 0x01, 0x00, 0x00];
+        let (messages, remainder) = parse_messages(&data);
+        assert!(messages.is_empty());
+        assert_eq!(remainder, 5);
+    }
+}
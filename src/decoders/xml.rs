@@ -0,0 +1,334 @@
+use tui::style::{Color, Style};
+use tui::text::{Span, Text};
+
+use super::{Decoder, DecoderArgs, DecoderFactory, Result, TextBuilder};
+use crate::session::{MessageData, RequestData};
+
+pub fn initialize(_args: &DecoderArgs) -> Result<Option<Box<dyn DecoderFactory>>>
+{
+    Ok(Some(Box::new(XmlDecoderFactory)))
+}
+
+pub struct XmlDecoderFactory;
+impl DecoderFactory for XmlDecoderFactory
+{
+    fn try_create(&self, _request: &RequestData, msg: &MessageData) -> Option<Box<dyn Decoder>>
+    {
+        let content_type = msg.headers.get("content-type")?.to_str().ok()?;
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match mime == "application/xml"
+            || mime == "text/xml"
+            || (mime.starts_with("application/") && mime.ends_with("+xml"))
+        {
+            true => Some(Box::new(XmlDecoder)),
+            false => None,
+        }
+    }
+}
+
+pub struct XmlDecoder;
+impl Decoder for XmlDecoder
+{
+    fn name(&self) -> &'static str
+    {
+        "xml"
+    }
+
+    fn decode(&self, msg: &MessageData) -> Text<'_>
+    {
+        let mut builder = TextBuilder::default();
+        if !msg.headers.is_empty() {
+            builder.push(Span::raw("Headers\n"));
+            for (k, v) in &msg.headers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+            builder.push(Span::raw("\n"));
+        }
+
+        // Streaming responses may be incomplete so fall back to the raw text if the content isn't
+        // well-formed XML.
+        let content = String::from_utf8_lossy(&msg.content);
+        match tokenize(&content) {
+            Some(tokens) => pretty_print(&tokens, &mut builder),
+            None => {
+                for line in content.lines() {
+                    builder.push(Span::raw(format!("{}\n", line)));
+                }
+            }
+        }
+
+        if !msg.trailers.is_empty() {
+            builder.push(Span::raw("\nTrailers\n"));
+            for (k, v) in &msg.trailers {
+                builder.push(Span::raw(format!(" - {}: {:?}\n", k, v)));
+            }
+        }
+        builder.build()
+    }
+
+    fn index(&self, msg: &MessageData) -> Vec<String>
+    {
+        let content = String::from_utf8_lossy(&msg.content);
+        tokenize(&content)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|t| match t {
+                Token::Start { name, .. } => Some(name.to_string()),
+                Token::Text(text) | Token::CData(text) => Some(text.to_string()),
+                Token::End(_) | Token::Comment(_) | Token::Declaration(_) => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a>
+{
+    Start
+    {
+        name: &'a str,
+
+        /// Attribute names and their values including the quotes.
+        attributes: Vec<(&'a str, &'a str)>,
+        self_closing: bool,
+    },
+    End(&'a str),
+    Text(&'a str),
+    CData(&'a str),
+    Comment(&'a str),
+
+    /// Processing instructions and DOCTYPE declarations.
+    Declaration(&'a str),
+}
+
+/// Split the XML document into tokens.
+///
+/// Returns `None` if the document isn't well-formed, such as when it is still being received.
+fn tokenize(xml: &str) -> Option<Vec<Token<'_>>>
+{
+    let mut tokens = vec![];
+    let mut open = vec![];
+    let mut rest = xml;
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix("<!--") {
+            let end = r.find("-->")?;
+            tokens.push(Token::Comment(&rest[..end + 7]));
+            rest = &r[end + 3..];
+        } else if let Some(r) = rest.strip_prefix("<![CDATA[") {
+            let end = r.find("]]>")?;
+            tokens.push(Token::CData(&r[..end]));
+            rest = &r[end + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = rest.find('>')?;
+            tokens.push(Token::Declaration(&rest[..=end]));
+            rest = &rest[end + 1..];
+        } else if let Some(r) = rest.strip_prefix("</") {
+            let end = r.find('>')?;
+            let name = r[..end].trim();
+            if open.pop()? != name {
+                return None;
+            }
+            tokens.push(Token::End(name));
+            rest = &r[end + 1..];
+        } else if let Some(r) = rest.strip_prefix('<') {
+            let end = tag_end(r)?;
+            let (tag, self_closing) = match r[..end].strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (&r[..end], false),
+            };
+            let (name, attributes) = parse_tag(tag)?;
+            if !self_closing {
+                open.push(name);
+            }
+            tokens.push(Token::Start {
+                name,
+                attributes,
+                self_closing,
+            });
+            rest = &r[end + 1..];
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..end].trim();
+            if !text.is_empty() {
+                tokens.push(Token::Text(text));
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    // Require at least one element so plain text isn't considered a valid document.
+    let has_root = tokens.iter().any(|t| matches!(t, Token::Start { .. }));
+    match open.is_empty() && has_root {
+        true => Some(tokens),
+        false => None,
+    }
+}
+
+/// Find the `>` that ends the tag, ignoring the ones within attribute values.
+fn tag_end(tag: &str) -> Option<usize>
+{
+    let mut quote = None;
+    for (idx, c) in tag.char_indices() {
+        match (c, quote) {
+            ('"', None) | ('\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('>', None) => return Some(idx),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_tag(tag: &str) -> Option<(&str, Vec<(&str, &str)>)>
+{
+    let tag = tag.trim_end();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let name = &tag[..name_end];
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut attributes = vec![];
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let eq = rest.find('=')?;
+        let attribute = rest[..eq].trim();
+        let value = rest[eq + 1..].trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let end = value[1..].find(quote)? + 2;
+        attributes.push((attribute, &value[..end]));
+        rest = value[end..].trim_start();
+    }
+    Some((name, attributes))
+}
+
+/// Pretty print the tokens of a well-formed document.
+///
+/// Elements that contain only text are kept on a single line.
+fn pretty_print(tokens: &[Token], builder: &mut TextBuilder)
+{
+    let tag_style = Style::default().fg(Color::Cyan);
+    let attribute_style = Style::default().fg(Color::Yellow);
+    let value_style = Style::default().fg(Color::Green);
+    let comment_style = Style::default().fg(Color::DarkGray);
+
+    let start_tag = |builder: &mut TextBuilder, name: &str, attributes: &[(&str, &str)]| {
+        builder.push(Span::raw("<"));
+        builder.push(Span::styled(name.to_string(), tag_style));
+        for (attribute, value) in attributes {
+            builder.push(Span::raw(" "));
+            builder.push(Span::styled(attribute.to_string(), attribute_style));
+            builder.push(Span::raw("="));
+            builder.push(Span::styled(value.to_string(), value_style));
+        }
+    };
+    let end_tag = |builder: &mut TextBuilder, name: &str| {
+        builder.push(Span::raw("</"));
+        builder.push(Span::styled(name.to_string(), tag_style));
+        builder.push(Span::raw(">\n"));
+    };
+
+    let mut indent: usize = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        if let Token::End(_) = tokens[i] {
+            indent = indent.saturating_sub(1);
+        }
+        builder.push(Span::raw("  ".repeat(indent)));
+        match &tokens[i] {
+            Token::Start {
+                name,
+                attributes,
+                self_closing: true,
+            } => {
+                start_tag(builder, name, attributes);
+                builder.push(Span::raw("/>\n"));
+            }
+            Token::Start {
+                name, attributes, ..
+            } => {
+                start_tag(builder, name, attributes);
+                builder.push(Span::raw(">"));
+                match (tokens.get(i + 1), tokens.get(i + 2)) {
+                    (Some(Token::End(_)), _) => {
+                        end_tag(builder, name);
+                        i += 1;
+                    }
+                    (Some(Token::Text(text)), Some(Token::End(_))) if !text.contains('\n') => {
+                        builder.push(Span::raw(text.to_string()));
+                        end_tag(builder, name);
+                        i += 2;
+                    }
+                    _ => {
+                        builder.push(Span::raw("\n"));
+                        indent += 1;
+                    }
+                }
+            }
+            Token::End(name) => end_tag(builder, name),
+            Token::Text(text) => {
+                let mut lines = text.lines().map(str::trim);
+                builder.push(Span::raw(format!("{}\n", lines.next().unwrap_or(""))));
+                for line in lines {
+                    builder.push(Span::raw(format!("{}{}\n", "  ".repeat(indent), line)));
+                }
+            }
+            Token::CData(data) => {
+                builder.push(Span::raw(format!("<![CDATA[{}]]>\n", data)));
+            }
+            Token::Comment(text) | Token::Declaration(text) => {
+                builder.push(Span::styled(text.to_string(), comment_style));
+                builder.push(Span::raw("\n"));
+            }
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn to_string(text: &Text) -> String
+    {
+        text.lines
+            .iter()
+            .flat_map(|line| line.0.iter())
+            .map(|s| s.content.as_ref())
+            .collect()
+    }
+
+    #[test]
+    fn pretty_print_soap()
+    {
+        let xml = r#"<?xml version="1.0"?><soap:Envelope xmlns:soap="urn:x"><soap:Body><!-- c --><Get id='a>b'><Name>Jane</Name><Empty/><None></None></Get></soap:Body></soap:Envelope>"#;
+        let mut builder = TextBuilder::default();
+        pretty_print(&tokenize(xml).unwrap(), &mut builder);
+        assert_eq!(
+            to_string(&builder.build()),
+            r#"<?xml version="1.0"?>
+<soap:Envelope xmlns:soap="urn:x">
+  <soap:Body>
+    <!-- c -->
+    <Get id='a>b'>
+      <Name>Jane</Name>
+      <Empty/>
+      <None></None>
+    </Get>
+  </soap:Body>
+</soap:Envelope>
+"#
+        );
+    }
+
+    #[test]
+    fn malformed_xml_is_rejected()
+    {
+        assert!(tokenize("<a><b></a>").is_none());
+        assert!(tokenize("<a><b>partial").is_none());
+        assert!(tokenize("<a attr=unquoted></a>").is_none());
+        assert!(tokenize("just text").is_none());
+        assert!(tokenize("<a>text</a>").is_some());
+    }
+}
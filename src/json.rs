@@ -5,7 +5,7 @@ use std::collections::HashMap;
 use uuid::Uuid;
 
 use super::session;
-use super::{DecoderError, Error, SerializationError};
+use super::{DecoderError, Error};
 
 #[derive(Serialize)]
 pub struct JsonSessionOutput
@@ -29,8 +29,7 @@ pub struct JsonMessageData
 
 pub fn view(matches: &ArgMatches) -> Result<(), Error>
 {
-    let filename = matches.value_of("file").unwrap();
-    let session = session::serialization::read_file(&filename).context(SerializationError {})?;
+    let session = crate::read_view_files(matches)?;
 
     let decoders = crate::decoders::get_decoders(matches).context(DecoderError {})?;
 
@@ -52,7 +51,7 @@ pub fn view(matches: &ArgMatches) -> Result<(), Error>
     Ok(())
 }
 
-fn get_message_data(
+pub fn get_message_data(
     req: &session::RequestData,
     msg: &session::MessageData,
     decoders: &crate::decoders::Decoders,
@@ -16,20 +16,23 @@ use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpListener;
 use tokio::sync::oneshot;
 
 mod command_line;
 mod config;
+mod config_file;
 mod connection;
+mod control;
 mod decoders;
 mod error;
 mod json;
 mod search;
 mod session;
+mod signing;
 mod ui;
 
-use connection::run;
+use connection::{run, FaultOptions, HeaderRules};
 use session::Session;
 
 #[derive(Debug, Snafu)]
@@ -66,12 +69,50 @@ pub enum Error
     },
 }
 
+#[derive(Default)]
 pub struct ConnectionOptions
 {
     pub allow_remote: bool,
+
+    /// Addresses to listen on. Overrides the addresses chosen based on `allow_remote`.
+    pub bind: Vec<std::net::IpAddr>,
     pub listen_port: String,
+
+    /// Unix domain socket to listen on in addition to the TCP port.
+    pub listen_uds: Option<std::path::PathBuf>,
     pub target_server: Option<String>,
+
+    /// Target servers for the direct connections by their host name. Connections matching none of
+    /// the routes are redirected to `target_server`.
+    pub routes: Vec<Route>,
+    pub target_header_host: Option<String>,
+    pub h2_window_size: Option<u32>,
     pub proxy: Option<Vec<ProxyFilter>>,
+
+    /// Client addresses whose connections are decoded. Empty to decode all clients.
+    pub client_filter: Vec<ClientFilter>,
+    pub upstream_proxy: Option<UpstreamProxy>,
+    pub faults: Option<FaultOptions>,
+    pub throttle_kbps: Option<u64>,
+
+    /// Close HTTP/2 connections that haven't forwarded any data for the duration.
+    pub idle_timeout: Option<std::time::Duration>,
+
+    /// Fail HTTP/2 requests that take longer than the duration.
+    pub request_timeout: Option<std::time::Duration>,
+
+    /// Number of times a failed connection to the server is retried.
+    pub connect_retries: u32,
+
+    /// Delay before the first connection retry. Doubled after each retry.
+    pub connect_backoff: Option<std::time::Duration>,
+
+    /// Largest number of bytes of each message body kept for the UI.
+    pub max_body_bytes: Option<usize>,
+
+    /// Changes made to the headers of the forwarded requests and responses.
+    pub request_headers: HeaderRules,
+    pub response_headers: HeaderRules,
     pub ca: Option<CADetails>,
 }
 
@@ -84,7 +125,30 @@ pub struct CADetails
 pub struct ProxyFilter
 {
     pub host_filter: wildmatch::WildMatch,
-    pub port_filter: Option<std::num::NonZeroU16>,
+    pub port_filter: Option<std::ops::RangeInclusive<u16>>,
+
+    /// Connections matching the filter are excluded from decoding.
+    pub exclude: bool,
+}
+
+pub struct Route
+{
+    pub host: wildmatch::WildMatch,
+    pub target: String,
+}
+
+pub struct ClientFilter
+{
+    pub network: std::net::IpAddr,
+    pub prefix_len: u8,
+}
+
+pub struct UpstreamProxy
+{
+    pub address: String,
+
+    /// Value of the `Proxy-Authorization` header sent with the CONNECT requests.
+    pub authorization: Option<String>,
 }
 
 fn main()
@@ -121,10 +185,16 @@ fn proxide_main() -> Result<(), Error>
     // Parse the command line argument and handle the simple arguments that don't require Proxide
     // to set up the complex bits. Anything handled here should `return` out of the function to
     // prevent the more complex bits from being performed.
-    let matches = app.get_matches();
+    let args = config_file::apply(&app, std::env::args_os().collect())?;
+    let matches = app.get_matches_from(args);
     match matches.subcommand() {
         Some(("config", matches)) => return config::run(matches),
         Some(("view", matches)) if matches.is_present("json") => return json::view(matches),
+        Some(("view", matches)) if matches.is_present("har") => {
+            let session = read_view_files(matches)?;
+            return session::serialization::export_har(&session, matches.value_of("har").unwrap())
+                .context(SerializationError {});
+        }
         _ => (), // Ignore other subcommands for now.
     }
 
@@ -141,14 +211,33 @@ fn proxide_main() -> Result<(), Error>
     //
     // The subcommands are responsible for figuring out how the initial session is constructed as
     // well as for giving us back the argument matches so we can initialize the decoders with them.
-    let (session, matches) = match matches.subcommand() {
+    let (session, matches, max_requests) = match matches.subcommand() {
         Some(("monitor", sub_m)) => {
             // Monitor sets up the network tack.
             let options = ConnectionOptions::resolve(sub_m)?;
-            network_thread = Some(std::thread::spawn(move || {
-                tokio_main(options, abort_rx, ui_tx)
-            }));
-            (Session::default(), sub_m)
+            let max_requests = read_max_requests(sub_m)?;
+            let (ui_tx, control_port) = serve_control(sub_m, max_requests, ui_tx)?;
+            let ui_tx = match read_redaction(sub_m)? {
+                Some(redaction) => session::redaction::redact_events(redaction, ui_tx),
+                None => ui_tx,
+            };
+            let (port_tx, port_rx) = std::sync::mpsc::channel();
+            let (port, join_handle) = wait_for_port(
+                port_rx,
+                std::thread::spawn(move || tokio_main(options, abort_rx, ui_tx, port_tx)),
+            )?;
+
+            // The UI isn't running yet so the port is reported through a toast that will be
+            // visible once the UI starts.
+            ui::toast::show_message(match control_port {
+                Some(control_port) => format!(
+                    "Listening on port {}, control API on port {}",
+                    port, control_port
+                ),
+                None => format!("Listening on port {}", port),
+            });
+            network_thread = Some(join_handle);
+            (Session::default(), sub_m, max_requests)
         }
         Some(("capture", sub_m)) => {
             let filename = sub_m.value_of("file").map(String::from).unwrap_or_else(|| {
@@ -157,9 +246,10 @@ fn proxide_main() -> Result<(), Error>
                     chrono::Local::now().format("%Y-%m-%d_%H%M%S")
                 )
             });
-            let format = match sub_m.is_present("json") {
-                true => session::serialization::OutputFormat::Json,
-                false => session::serialization::OutputFormat::MessagePack,
+            let format = match (sub_m.is_present("json"), sub_m.value_of("format")) {
+                (true, _) | (_, Some("json")) => session::serialization::OutputFormat::Json,
+                (_, Some("jsonl")) => session::serialization::OutputFormat::JsonLines,
+                _ => session::serialization::OutputFormat::MessagePack,
             };
 
             let stdout_data = filename == "-";
@@ -195,8 +285,31 @@ fn proxide_main() -> Result<(), Error>
 
             // Monitor sets up the network tack.
             let options = ConnectionOptions::resolve(sub_m)?;
-            std::thread::spawn(move || tokio_main(options, abort_rx, ui_tx));
-            if !stdout_data {
+            let (ui_tx, control_port) = serve_control(sub_m, None, ui_tx)?;
+            let ui_tx = match read_capture_filter(sub_m) {
+                Some(filter) => session::capture_filter::filter_events(filter, ui_tx),
+                None => ui_tx,
+            };
+            let ui_tx = match read_redaction(sub_m)? {
+                Some(redaction) => session::redaction::redact_events(redaction, ui_tx),
+                None => ui_tx,
+            };
+            let (port_tx, port_rx) = std::sync::mpsc::channel();
+            let (port, _) = wait_for_port(
+                port_rx,
+                std::thread::spawn(move || tokio_main(options, abort_rx, ui_tx, port_tx)),
+            )?;
+            if stdout_data {
+                // Keep stdout clean for the data but still let the user know where to connect.
+                eprintln!("Listening on port {}", port);
+                if let Some(port) = control_port {
+                    eprintln!("Control API listening on port {}", port);
+                }
+            } else {
+                println!("Listening on port {}", port);
+                if let Some(port) = control_port {
+                    println!("Control API listening on port {}", port);
+                }
                 println!("Capturing to file: {}...", filename);
                 println!("\n... Waiting for connections.\n\n");
             }
@@ -205,12 +318,7 @@ fn proxide_main() -> Result<(), Error>
             )
             .context(SerializationError {});
         }
-        Some(("view", sub_m)) => {
-            let filename = sub_m.value_of("file").unwrap();
-            let session =
-                session::serialization::read_file(&filename).context(SerializationError {})?;
-            (session, sub_m)
-        }
+        Some(("view", sub_m)) => (read_view_files(sub_m)?, sub_m, None),
         _ => panic!("Sub command not handled!"),
     };
 
@@ -219,7 +327,9 @@ fn proxide_main() -> Result<(), Error>
     // Run the UI on the current thread.
     //
     // This function returns once the user has indicated they want to quit the app in the UI.
-    ui::main(session, decoders, ui_rx).context(UiError {})?;
+    let replay_target = matches.value_of("replay-target").map(String::from);
+    let osc52 = matches.is_present("osc52");
+    ui::main(session, decoders, ui_rx, replay_target, max_requests, osc52).context(UiError {})?;
 
     // Abort the network thread.
     abort_tx.send(()).unwrap();
@@ -230,6 +340,134 @@ fn proxide_main() -> Result<(), Error>
     Ok(())
 }
 
+/// Parse a duration given in milliseconds ('200ms') or seconds ('2s').
+///
+/// Values without a unit are treated as milliseconds.
+fn parse_duration(value: &str) -> Option<std::time::Duration>
+{
+    let (value, unit) = match value.strip_suffix("ms") {
+        Some(ms) => (ms, 1),
+        None => match value.strip_suffix('s') {
+            Some(s) => (s, 1000),
+            None => (value, 1),
+        },
+    };
+    let ms = value.parse::<u64>().ok()?;
+    Some(std::time::Duration::from_millis(ms * unit))
+}
+
+/// Resolve the `--redact-*` options.
+fn read_redaction(args: &ArgMatches) -> Result<Option<session::redaction::Redaction>, Error>
+{
+    let mut headers = vec![];
+    if args.is_present("redact-all-auth") {
+        headers.extend(
+            session::redaction::AUTH_HEADERS
+                .iter()
+                .map(|h| http::header::HeaderName::from_static(h)),
+        );
+    }
+    for header in args.values_of("redact-header").into_iter().flatten() {
+        headers.push(
+            http::header::HeaderName::from_bytes(header.as_bytes()).map_err(|_| {
+                Error::ArgumentError {
+                    msg: format!("Invalid header name '{}'", header),
+                }
+            })?,
+        );
+    }
+
+    let mut body_patterns = vec![];
+    for pattern in args.values_of("redact-body-pattern").into_iter().flatten() {
+        body_patterns.push(regex::bytes::Regex::new(pattern).map_err(|e| {
+            Error::ArgumentError {
+                msg: format!("Invalid pattern '{}': {}", pattern, e),
+            }
+        })?);
+    }
+
+    if headers.is_empty() && body_patterns.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(session::redaction::Redaction {
+        headers,
+        body_patterns,
+    }))
+}
+
+/// Start the control API if the user asked for it with `--control-listen`.
+///
+/// Returns the sender the events should be sent to and the port the API is listening to.
+fn serve_control(
+    args: &ArgMatches,
+    max_requests: Option<usize>,
+    ui_tx: Sender<session::events::SessionEvent>,
+) -> Result<(Sender<session::events::SessionEvent>, Option<u16>), Error>
+{
+    let port = match args.value_of("control-listen") {
+        Some(port) => port.parse::<u16>().expect("Port was invalid"), // Validated by clap.
+        None => return Ok((ui_tx, None)),
+    };
+    let (ui_tx, port) = control::serve(port, max_requests, args, ui_tx)?;
+    Ok((ui_tx, Some(port)))
+}
+
+/// Read the files given to the `view` command, merging them into a single session.
+fn read_view_files(args: &ArgMatches) -> Result<Session, Error>
+{
+    let mut session = Session::default();
+    for filename in args
+        .values_of("file")
+        .into_iter()
+        .flatten()
+        .chain(args.values_of("files").into_iter().flatten())
+    {
+        let file_session =
+            session::serialization::read_file(&filename).context(SerializationError {})?;
+        let duplicates = session.merge(file_session);
+        if duplicates > 0 {
+            log::warn!("Skipped {} duplicate items in '{}'", duplicates, filename);
+            eprintln!(
+                "Warning: Skipped {} connections or requests in '{}' that were already loaded",
+                duplicates, filename
+            );
+        }
+    }
+    Ok(session)
+}
+
+fn read_capture_filter(args: &ArgMatches) -> Option<session::capture_filter::CaptureFilter>
+{
+    let patterns = |name| {
+        args.values_of(name)
+            .into_iter()
+            .flatten()
+            .map(wildmatch::WildMatch::new)
+            .collect::<Vec<_>>()
+    };
+    let paths = patterns("only-path");
+    let hosts = patterns("only-host");
+    if paths.is_empty() && hosts.is_empty() {
+        return None;
+    }
+    Some(session::capture_filter::CaptureFilter::new(paths, hosts))
+}
+
+fn read_max_requests(args: &ArgMatches) -> Result<Option<usize>, Error>
+{
+    match args.value_of("max-requests") {
+        Some(max) => Ok(Some(
+            max.parse::<usize>()
+                .ok()
+                .filter(|max| *max > 0)
+                .ok_or_else(|| Error::ArgumentError {
+                    msg: format!("Invalid request limit '{}'", max),
+                })?,
+        )),
+        None => Ok(None),
+    }
+}
+
 impl ConnectionOptions
 {
     fn resolve(args: &ArgMatches) -> Result<Arc<Self>, Error>
@@ -242,16 +480,189 @@ impl ConnectionOptions
             None => None,
         };
 
-        if target_server.is_none() && proxy.is_none() {
+        let routes: Vec<_> = args
+            .values_of("route")
+            .into_iter()
+            .flatten()
+            .map(Route::parse)
+            .collect::<Result<_, _>>()?;
+
+        if target_server.is_none() && routes.is_empty() && proxy.is_none() {
             proxy = Some(vec![]);
         }
 
+        // HTTP/2 limits the window size to 2^31-1.
+        let h2_window_size = match args.value_of("h2-window-size") {
+            Some(size) => Some(
+                size.parse::<u32>()
+                    .ok()
+                    .filter(|size| *size > 0 && *size <= i32::MAX as u32)
+                    .ok_or_else(|| Error::ArgumentError {
+                        msg: format!("Invalid HTTP/2 window size '{}'", size),
+                    })?,
+            ),
+            None => None,
+        };
+
+        let upstream_proxy = args
+            .value_of("upstream-proxy")
+            .map(|address| UpstreamProxy {
+                address: address.to_string(),
+                authorization: args
+                    .value_of("upstream-proxy-auth")
+                    .map(|auth| format!("Basic {}", base64::encode(auth))),
+            });
+
+        let bind = args
+            .values_of("bind")
+            .into_iter()
+            .flatten()
+            .map(|addr| {
+                addr.parse().map_err(|_| Error::ArgumentError {
+                    msg: format!("Invalid bind address '{}'", addr),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        let client_filter = args
+            .values_of("client-filter")
+            .into_iter()
+            .flatten()
+            .map(ClientFilter::parse)
+            .collect::<Result<_, _>>()?;
+
+        let faults = Self::read_faults(args)?;
+        let throttle_kbps = match args.value_of("throttle-kbps") {
+            Some(kbps) => Some(
+                kbps.parse::<u64>()
+                    .ok()
+                    .filter(|kbps| *kbps > 0)
+                    .ok_or_else(|| Error::ArgumentError {
+                        msg: format!("Invalid throttle rate '{}'", kbps),
+                    })?,
+            ),
+            None => None,
+        };
+
+        let idle_timeout = Self::read_timeout(args, "idle-timeout")?;
+        let request_timeout = Self::read_timeout(args, "request-timeout")?;
+        let connect_retries = match args.value_of("connect-retries") {
+            Some(retries) => retries.parse::<u32>().map_err(|_| Error::ArgumentError {
+                msg: format!("Invalid retry count '{}'", retries),
+            })?,
+            None => 0,
+        };
+        let connect_backoff = Self::read_timeout(args, "connect-backoff")?;
+        let max_body_bytes = match args.value_of("max-body-bytes") {
+            Some(bytes) => Some(bytes.parse::<usize>().map_err(|_| Error::ArgumentError {
+                msg: format!("Invalid body size '{}'", bytes),
+            })?),
+            None => None,
+        };
+
         Ok(Arc::new(Self {
             allow_remote: args.is_present("allow-remote"),
+            bind,
             listen_port: args.value_of("listen").unwrap().to_string(),
+            listen_uds: args.value_of("listen-uds").map(std::path::PathBuf::from),
             ca: ca_details,
             target_server,
+            routes,
+            target_header_host: args.value_of("target-header-host").map(ToString::to_string),
+            h2_window_size,
             proxy,
+            client_filter,
+            upstream_proxy,
+            faults,
+            throttle_kbps,
+            idle_timeout,
+            request_timeout,
+            connect_retries,
+            connect_backoff,
+            max_body_bytes,
+            request_headers: Self::read_header_rules(args, "request")?,
+            response_headers: Self::read_header_rules(args, "response")?,
+        }))
+    }
+
+    fn read_timeout(args: &ArgMatches, name: &str) -> Result<Option<std::time::Duration>, Error>
+    {
+        match args.value_of(name) {
+            Some(timeout) => match parse_duration(timeout) {
+                Some(d) if d > std::time::Duration::from_secs(0) => Ok(Some(d)),
+                _ => Err(Error::ArgumentError {
+                    msg: format!("Invalid timeout '{}'", timeout),
+                }),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Read the `--set-<part>-header` and `--remove-<part>-header` options.
+    fn read_header_rules(args: &ArgMatches, part: &str) -> Result<HeaderRules, Error>
+    {
+        HeaderRules::parse(
+            args.values_of(format!("set-{}-header", part))
+                .into_iter()
+                .flatten(),
+            args.values_of(format!("remove-{}-header", part))
+                .into_iter()
+                .flatten(),
+        )
+        .map_err(|msg| Error::ArgumentError { msg })
+    }
+
+    fn read_faults(args: &ArgMatches) -> Result<Option<FaultOptions>, Error>
+    {
+        let latency = match args.value_of("inject-latency") {
+            Some(latency) => Some(parse_duration(latency).ok_or_else(|| Error::ArgumentError {
+                msg: format!("Invalid latency '{}'", latency),
+            })?),
+            None => None,
+        };
+        let status = match args.value_of("inject-status") {
+            Some(status) => Some(
+                status
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|s| (400..600).contains(s))
+                    .ok_or_else(|| Error::ArgumentError {
+                        msg: format!("Invalid status '{}', expected a 4xx or 5xx status", status),
+                    })?,
+            ),
+            None => None,
+        };
+
+        // A status without an error rate fails every request.
+        let error_rate = match args.value_of("inject-error-rate") {
+            Some(rate) => rate
+                .parse::<f64>()
+                .ok()
+                .filter(|r| (0.0..=1.0).contains(r))
+                .ok_or_else(|| Error::ArgumentError {
+                    msg: format!("Invalid error rate '{}'", rate),
+                })?,
+            None if status.is_some() => 1.0,
+            None => 0.0,
+        };
+
+        if latency.is_none() && error_rate == 0.0 {
+            return Ok(None);
+        }
+
+        let seed = match args.value_of("inject-seed") {
+            Some(seed) => seed.parse::<u64>().map_err(|_| Error::ArgumentError {
+                msg: format!("Invalid seed '{}'", seed),
+            })?,
+            None => uuid::Uuid::new_v4().as_u128() as u64,
+        };
+
+        Ok(Some(FaultOptions {
+            latency,
+            error_rate,
+            status: status.unwrap_or(503),
+            seed,
+            ..Default::default()
         }))
     }
 
@@ -293,82 +704,307 @@ impl ProxyFilter
     {
         data.split(',')
             .map(|part| {
-                // Split into parts and process the host and port separately.
-                let mut split = part.split(':');
-                let host = split.next().ok_or_else(|| Error::ArgumentError {
+                let invalid = || Error::ArgumentError {
                     msg: format!("Invalid proxy filter '{}'", part),
-                })?;
-
-                // The port is optional.
-                let port = split
-                    .next()
-                    .map(|p| p.parse::<u16>())
-                    .transpose()
-                    .map_err(|_| Error::ArgumentError {
-                        msg: format!("Invalid proxy filter '{}'", part),
-                    })?
-                    .and_then(std::num::NonZeroU16::new);
+                };
+
+                // Filters starting with '!' exclude the matching connections.
+                let (exclude, filter) = match part.strip_prefix('!') {
+                    Some(filter) => (true, filter),
+                    None => (false, part),
+                };
+
+                // Split into parts and process the host and port separately.
+                let mut split = filter.split(':');
+                let host = split.next().ok_or_else(invalid)?;
+
+                // The port is optional and may be either a single port or a range of ports.
+                let port = match split.next() {
+                    None => None,
+                    Some(p) => {
+                        let mut range = p.splitn(2, '-');
+                        let start = range
+                            .next()
+                            .unwrap()
+                            .parse::<u16>()
+                            .map_err(|_| invalid())?;
+                        let end = match range.next() {
+                            Some(end) => end.parse::<u16>().map_err(|_| invalid())?,
+                            None => start,
+                        };
+                        match (start, end) {
+                            (0, 0) => None,
+                            (start, end) if start <= end => Some(start..=end),
+                            _ => return Err(invalid()),
+                        }
+                    }
+                };
 
                 // There should be no more data after the port.
                 if split.next().is_some() {
-                    return Err(Error::ArgumentError {
-                        msg: format!("Invalid proxy filter '{}'", part),
-                    });
+                    return Err(invalid());
                 }
 
                 Ok(ProxyFilter {
                     host_filter: wildmatch::WildMatch::new(host),
                     port_filter: port,
+                    exclude,
                 })
             })
             .collect()
     }
 }
 
+impl Route
+{
+    fn parse(data: &str) -> Result<Route, Error>
+    {
+        match data.split_once('=') {
+            Some((host, target)) if !host.is_empty() && !target.is_empty() => Ok(Route {
+                host: wildmatch::WildMatch::new(host),
+                target: target.to_string(),
+            }),
+            _ => Err(Error::ArgumentError {
+                msg: format!("Invalid route '{}'", data),
+            }),
+        }
+    }
+
+    /// Find the target server for the host name.
+    pub fn find<'a>(routes: &'a [Route], host: &str) -> Option<&'a str>
+    {
+        routes
+            .iter()
+            .find(|r| r.host.is_match(host))
+            .map(|r| r.target.as_str())
+    }
+}
+
+impl ClientFilter
+{
+    fn parse(data: &str) -> Result<ClientFilter, Error>
+    {
+        let invalid = || Error::ArgumentError {
+            msg: format!("Invalid client filter '{}'", data),
+        };
+
+        // A plain address is treated as a network containing only that address.
+        let mut split = data.splitn(2, '/');
+        let network: std::net::IpAddr = split.next().unwrap().parse().map_err(|_| invalid())?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match split.next() {
+            Some(len) => len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(invalid)?,
+            None => max_len,
+        };
+
+        Ok(ClientFilter {
+            network,
+            prefix_len,
+        })
+    }
+
+    pub fn contains(&self, addr: std::net::IpAddr) -> bool
+    {
+        use std::net::IpAddr;
+
+        // Sockets listening on IPv6 addresses see IPv4 clients as IPv4-mapped addresses.
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(addr),
+            addr => addr,
+        };
+
+        let (network, addr, bits) = match (self.network, addr) {
+            (IpAddr::V4(n), IpAddr::V4(a)) => (u32::from(n) as u128, u32::from(a) as u128, 32),
+            (IpAddr::V6(n), IpAddr::V6(a)) => (u128::from(n), u128::from(a), 128),
+            _ => return false,
+        };
+        let shift = bits - u32::from(self.prefix_len);
+        shift == bits || (network >> shift) == (addr >> shift)
+    }
+}
+
+/// Waits for the network thread to report the port it is listening on.
+///
+/// If the network thread fails to bind the listening sockets, its error is returned instead.
+fn wait_for_port(
+    port_rx: std::sync::mpsc::Receiver<u16>,
+    network_thread: std::thread::JoinHandle<Result<(), Error>>,
+) -> Result<(u16, std::thread::JoinHandle<Result<(), Error>>), Error>
+{
+    match port_rx.recv() {
+        Ok(port) => Ok((port, network_thread)),
+        Err(_) => {
+            network_thread.join().unwrap()?;
+            Err(Error::RuntimeError {
+                msg: "Network thread exited before binding the listening sockets".to_string(),
+            })
+        }
+    }
+}
+
 #[tokio::main]
 async fn tokio_main(
     options: Arc<ConnectionOptions>,
     abort_rx: oneshot::Receiver<()>,
     ui_tx: Sender<session::events::SessionEvent>,
+    port_tx: std::sync::mpsc::Sender<u16>,
 ) -> Result<(), Error>
 {
-    launch_proxide(options, abort_rx, ui_tx).await?;
+    let listeners = bind_listeners(&options).await?;
+    let _ = port_tx.send(listeners.port);
+    launch_proxide(listeners, options, abort_rx, ui_tx).await?;
     Ok(())
 }
 
-async fn launch_proxide(
-    options: Arc<ConnectionOptions>,
-    abort_rx: oneshot::Receiver<()>,
-    ui_tx: Sender<session::events::SessionEvent>,
-) -> Result<(), Error>
+/// The sockets Proxide listens to for incoming connections.
+struct Listeners
+{
+    sockets: Vec<TcpListener>,
+
+    /// The socket given with `--listen-uds`.
+    #[cfg(unix)]
+    unix_socket: Option<UnixSocket>,
+
+    /// The port the sockets are bound to. If the user requested port 0, this is the port the OS
+    /// assigned for the sockets.
+    port: u16,
+}
+
+/// Unix domain socket listener that removes the socket file once it is no longer listened to.
+#[cfg(unix)]
+struct UnixSocket
+{
+    listener: tokio::net::UnixListener,
+    path: std::path::PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocket
+{
+    fn bind(path: &Path) -> Result<Self, Error>
+    {
+        // Binding fails if the file exists. Removing it could break another process using the
+        // socket so the user needs to do that.
+        if path.exists() {
+            return Err(Error::RuntimeError {
+                msg: format!(
+                    "Could not bind to {}: The file already exists",
+                    path.display()
+                ),
+            });
+        }
+
+        let listener = tokio::net::UnixListener::bind(path).map_err(|e| Error::RuntimeError {
+            msg: format!("Could not bind to {}: {}", path.display(), e),
+        })?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixSocket
+{
+    fn drop(&mut self)
+    {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn bind_listeners(options: &ConnectionOptions) -> Result<Listeners, Error>
 {
     // We'll want to listen for both IPv4 and IPv6. These days 'localhost' will first resolve to the
     // IPv6 address if that is available. If we did not bind to it, all the connections would first
     // need to timeout there before the Ipv4 would be attempted as a fallback.
-    let addresses = match options.allow_remote {
-        true => vec!["0.0.0.0", "[::]"],
-        false => vec!["127.0.0.1", "[::1]"],
+    let addresses = match (options.bind.is_empty(), options.allow_remote) {
+        (false, _) => options
+            .bind
+            .iter()
+            .map(|ip| match ip {
+                std::net::IpAddr::V4(ip) => ip.to_string(),
+                std::net::IpAddr::V6(ip) => format!("[{}]", ip),
+            })
+            .collect(),
+        (true, true) => vec!["0.0.0.0".to_string(), "[::]".to_string()],
+        (true, false) => vec!["127.0.0.1".to_string(), "[::1]".to_string()],
     };
 
+    // With port 0 the OS assigns the port when binding the first socket. The rest of the sockets
+    // must reuse the same port so the clients can connect using either of the addresses.
+    let mut port = options.listen_port.clone();
     let mut sockets: Vec<_> = Vec::new();
+    let mut failures = Vec::new();
     for addr in addresses {
-        let addr = format!("{}:{}", addr, &options.listen_port);
+        let addr = format!("{}:{}", addr, port);
         match TcpListener::bind(&addr).await {
-            Err(_) => log::error!("Could not bind to {}", addr),
-            Ok(s) => sockets.push(s),
+            Err(e) => {
+                log::error!("Could not bind to {}: {}", addr, e);
+                failures.push(format!("{}: {}", addr, e));
+            }
+            Ok(s) => {
+                if let Ok(local) = s.local_addr() {
+                    port = local.port().to_string();
+                }
+                sockets.push(s)
+            }
         }
     }
 
-    // Ensure we bound at least one of the sockets.
-    if sockets.is_empty() {
+    // The addresses given explicitly must all be bound. Otherwise it's enough to bind either the
+    // IPv4 or the IPv6 address.
+    if sockets.is_empty() || (!options.bind.is_empty() && !failures.is_empty()) {
         return Err(Error::RuntimeError {
-            msg: "Could not bind to either IPv4 or IPv6 address".to_string(),
+            msg: format!("Could not bind to:\n{}", failures.join("\n")),
         });
     }
 
-    for s in sockets {
+    let port = sockets[0]
+        .local_addr()
+        .map_err(|e| Error::RuntimeError {
+            msg: format!("Could not resolve the listening port: {}", e),
+        })?
+        .port();
+
+    #[cfg(unix)]
+    let unix_socket = match &options.listen_uds {
+        Some(path) => Some(UnixSocket::bind(path)?),
+        None => None,
+    };
+    #[cfg(not(unix))]
+    if options.listen_uds.is_some() {
+        return Err(Error::RuntimeError {
+            msg: "Unix domain sockets are not supported on this platform".to_string(),
+        });
+    }
+
+    Ok(Listeners {
+        sockets,
+        #[cfg(unix)]
+        unix_socket,
+        port,
+    })
+}
+
+async fn launch_proxide(
+    listeners: Listeners,
+    options: Arc<ConnectionOptions>,
+    abort_rx: oneshot::Receiver<()>,
+    ui_tx: Sender<session::events::SessionEvent>,
+) -> Result<(), Error>
+{
+    for s in listeners.sockets {
         spawn_accept(s, options.clone(), ui_tx.clone())
     }
+    #[cfg(unix)]
+    if let Some(s) = listeners.unix_socket {
+        spawn_unix_accept(s, options.clone(), ui_tx.clone())
+    }
 
     // Wait for an abort event to quit the thread.
     //
@@ -393,11 +1029,35 @@ fn spawn_accept(
     });
 }
 
-fn new_connection(
-    tx: Sender<session::events::SessionEvent>,
-    result: Result<(TcpStream, SocketAddr), std::io::Error>,
+#[cfg(unix)]
+fn spawn_unix_accept(
+    socket: UnixSocket,
     options: Arc<ConnectionOptions>,
+    ui_tx: Sender<session::events::SessionEvent>,
 )
+{
+    // The Unix socket clients don't have an IP address. They are on the local machine so they
+    // are shown with the loopback address.
+    let client_addr = SocketAddr::from(([127, 0, 0, 1], 0));
+    tokio::spawn(async move {
+        loop {
+            let ui_tx = ui_tx.clone();
+            let result = socket.listener.accept().await;
+            new_connection(
+                ui_tx,
+                result.map(|(s, _)| (s, client_addr)),
+                options.clone(),
+            );
+        }
+    });
+}
+
+fn new_connection<T>(
+    tx: Sender<session::events::SessionEvent>,
+    result: Result<(T, SocketAddr), std::io::Error>,
+    options: Arc<ConnectionOptions>,
+) where
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
 {
     // Process the new connection by spawning a new tokio task. This allows the original task to
     // process more connections.
@@ -419,7 +1079,6 @@ mod test
     use log::SetLoggerError;
     use serial_test::serial;
     use std::io::{ErrorKind, Write};
-    use std::str::FromStr;
     use std::sync::{Arc, Mutex};
     use std::time::Duration;
     use tokio::sync::broadcast::error::TryRecvError;
@@ -459,8 +1118,9 @@ mod test
         let options = get_proxide_options(&server);
         let (abort_tx, abort_rx) = tokio::sync::oneshot::channel::<()>();
         let (ui_tx, ui_rx_std) = std::sync::mpsc::channel();
-        let proxide_port = u16::from_str(&options.listen_port.to_string()).unwrap();
-        let proxide = tokio::spawn(crate::launch_proxide(options, abort_rx, ui_tx));
+        let listeners = crate::bind_listeners(&options).await.unwrap();
+        let proxide_port = listeners.port;
+        let proxide = tokio::spawn(crate::launch_proxide(listeners, options, abort_rx, ui_tx));
 
         // Message generator and tester.
         let tester = grpc_tester::GrpcTester::with_proxide(
@@ -503,12 +1163,18 @@ mod test
                 .expect("Getting free port for proxide failed.")
                 .to_string(),
             target_server: Some("Invalid address".to_string()),
-            proxy: None,
+            ..Default::default()
         };
         let (abort_tx, abort_rx) = oneshot::channel::<()>();
         let (ui_tx, _) = std::sync::mpsc::channel();
-        let proxide_port = u16::from_str(&options.listen_port.to_string()).unwrap();
-        let proxide = tokio::spawn(crate::launch_proxide(Arc::new(options), abort_rx, ui_tx));
+        let listeners = crate::bind_listeners(&options).await.unwrap();
+        let proxide_port = listeners.port;
+        let proxide = tokio::spawn(crate::launch_proxide(
+            listeners,
+            Arc::new(options),
+            abort_rx,
+            ui_tx,
+        ));
 
         // Request proxide to connect to the dummy server. This triggers the expected failure.
         let mut generator =
@@ -533,6 +1199,30 @@ mod test
             .expect("Waiting for proxide to stop failed.");
     }
 
+    #[test]
+    fn client_filter_matches_networks()
+    {
+        let contains = |filter: &str, addr: &str| {
+            crate::ClientFilter::parse(filter)
+                .unwrap()
+                .contains(addr.parse().unwrap())
+        };
+
+        assert!(contains("10.0.0.0/24", "10.0.0.42"));
+        assert!(!contains("10.0.0.0/24", "10.0.1.42"));
+        assert!(contains("10.0.0.5", "10.0.0.5"));
+        assert!(!contains("10.0.0.5", "10.0.0.6"));
+        assert!(contains("0.0.0.0/0", "192.168.1.1"));
+        assert!(contains("10.0.0.0/8", "::ffff:10.1.2.3"));
+        assert!(contains("fd00::/64", "fd00::1234"));
+        assert!(!contains("fd00::/64", "fd00:0:0:1::1234"));
+        assert!(!contains("fd00::/64", "10.0.0.1"));
+        assert!(contains("::/0", "::1"));
+
+        assert!(crate::ClientFilter::parse("10.0.0.0/33").is_err());
+        assert!(crate::ClientFilter::parse("10.0.0/24").is_err());
+    }
+
     /// Gets options for launching proxide.
     fn get_proxide_options(server: &GrpcServer) -> Arc<ConnectionOptions>
     {
@@ -543,7 +1233,7 @@ mod test
                 .expect("Getting free port for proxide failed.")
                 .to_string(),
             target_server: Some(server.address().to_string()),
-            proxy: None,
+            ..Default::default()
         };
         Arc::new(options)
     }
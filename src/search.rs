@@ -1,3 +1,5 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
@@ -28,6 +30,69 @@ struct IndexedMessage
 {
     // part: RequestPart,
     data: Vec<String>,
+    headers: Vec<String>,
+}
+
+/// Options controlling how the search patterns match the indexed text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions
+{
+    pub ignore_case: bool,
+
+    /// Match the header and trailer names and values in addition to the decoded content.
+    pub headers: bool,
+}
+
+impl Default for SearchOptions
+{
+    fn default() -> Self
+    {
+        Self {
+            ignore_case: true,
+            headers: false,
+        }
+    }
+}
+
+/// A compiled search pattern.
+///
+/// Patterns prefixed with `re:` are treated as regular expressions.
+pub enum Pattern
+{
+    Text(String),
+    Regex(Regex),
+}
+
+impl Pattern
+{
+    pub fn new(pattern: &str, options: &SearchOptions) -> Result<Self, regex::Error>
+    {
+        Ok(match (pattern.strip_prefix("re:"), options.ignore_case) {
+            (Some(re), ignore_case) => Pattern::Regex(
+                RegexBuilder::new(re)
+                    .case_insensitive(ignore_case)
+                    .build()?,
+            ),
+
+            // The index text isn't lowercased up front so plain patterns that ignore case are
+            // matched as an escaped regex instead.
+            (None, true) => Pattern::Regex(
+                RegexBuilder::new(&regex::escape(pattern))
+                    .case_insensitive(true)
+                    .build()?,
+            ),
+            (None, false) => Pattern::Text(pattern.to_string()),
+        })
+    }
+
+    fn is_match(&self, text: &str) -> bool
+    {
+        match self {
+            Pattern::Text(pattern) => text.contains(pattern.as_str()),
+            Pattern::Regex(re) => re.is_match(text),
+        }
+    }
 }
 
 impl SearchIndex
@@ -51,16 +116,22 @@ impl SearchIndex
         idx
     }
 
-    pub fn is_match(&self, request: Uuid, pattern: &str) -> bool
+    /// Drop the requests that are no longer part of the session.
+    pub fn prune(&mut self, session: &Session)
+    {
+        self.requests
+            .retain(|uuid, _| session.requests.get_by_uuid(*uuid).is_some());
+    }
+
+    pub fn is_match(&self, request: Uuid, pattern: &Pattern, options: &SearchOptions) -> bool
     {
         self.requests
             .get(&request)
             .map(|r| {
-                r.request_msg.data.iter().any(|text| text.contains(pattern))
-                    || r.response_msg
-                        .data
-                        .iter()
-                        .any(|text| text.contains(pattern))
+                [&r.request_msg, &r.response_msg].iter().any(|msg| {
+                    msg.data.iter().any(|text| pattern.is_match(text))
+                        || options.headers && msg.headers.iter().any(|text| pattern.is_match(text))
+                })
             })
             .unwrap_or(false)
     }
@@ -99,6 +170,12 @@ impl SearchIndex
         };
 
         msg.data = decoders.index(&session_request.request_data, data);
+        msg.headers = data
+            .headers
+            .iter()
+            .chain(data.trailers.iter())
+            .map(|(k, v)| format!("{}: {}", k, String::from_utf8_lossy(v.as_bytes())))
+            .collect();
     }
 }
 
@@ -121,6 +198,41 @@ impl IndexedMessage
         Self {
             // part,
             data: Default::default(),
+            headers: Default::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn patterns_ignore_case_by_default()
+    {
+        let options = SearchOptions::default();
+        assert!(Pattern::new("HELLO", &options)
+            .unwrap()
+            .is_match("say hello"));
+        assert!(Pattern::new("re:h.L+o", &options)
+            .unwrap()
+            .is_match("HELLO"));
+        assert!(Pattern::new("a.b", &options).unwrap().is_match("A.B"));
+        assert!(!Pattern::new("a.b", &options).unwrap().is_match("axb"));
+
+        let options = SearchOptions {
+            ignore_case: false,
+            ..options
+        };
+        assert!(!Pattern::new("HELLO", &options)
+            .unwrap()
+            .is_match("say hello"));
+        assert!(!Pattern::new("re:h.L+o", &options)
+            .unwrap()
+            .is_match("HELLO"));
+        assert!(Pattern::new("re:h.l+o", &options)
+            .unwrap()
+            .is_match("hello"));
+    }
+}
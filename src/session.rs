@@ -2,11 +2,13 @@ use bytes::BytesMut;
 use chrono::prelude::*;
 use http::{HeaderMap, Method, Uri};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use uuid::Uuid;
 
+pub mod capture_filter;
 pub mod events;
+pub mod redaction;
 pub mod serialization;
 
 #[derive(Serialize, Deserialize, Default)]
@@ -23,12 +25,13 @@ pub struct IndexedVec<T>
     pub items_by_uuid: HashMap<Uuid, usize>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Protocol
 {
     Connect,
     Tls,
     Http2,
+    Http1,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -40,6 +43,71 @@ pub struct ConnectionData
     pub start_timestamp: DateTime<Local>,
     pub end_timestamp: Option<DateTime<Local>>,
     pub status: Status,
+
+    /// The HTTP/2 error the connection was closed with.
+    #[serde(default)]
+    pub reason: Option<H2Reason>,
+
+    /// The HTTP/2 settings of the connection. Missing for other protocols.
+    #[serde(default)]
+    pub h2_settings: Option<H2Settings>,
+
+    /// Name given to the connection with `:alias`.
+    #[serde(default)]
+    pub alias: Option<String>,
+
+    /// Number of attempts it took to connect to the server. Zero if unknown.
+    #[serde(default)]
+    pub connect_attempts: u32,
+
+    /// Data forwarded from the client to the server.
+    #[serde(default)]
+    pub request_throughput: Throughput,
+
+    /// Data forwarded from the server to the client.
+    #[serde(default)]
+    pub response_throughput: Throughput,
+}
+
+/// Data forwarded in one direction of a connection.
+///
+/// The rate includes the time spent waiting on `--throttle-kbps` so it shows the rate the
+/// throttling achieved.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Throughput
+{
+    pub bytes: u64,
+
+    /// Time from forwarding the first data to forwarding the last.
+    pub duration: std::time::Duration,
+}
+
+/// HTTP/2 settings and flow-control state as known to Proxide.
+///
+/// `h2` doesn't expose all the settings the peers send. The stream limits are the ones the peers
+/// sent while the initial window size is the one Proxide is configured with. The header table
+/// sizes aren't known at all and the flow-control windows only for the individual streams.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct H2Settings
+{
+    /// SETTINGS_INITIAL_WINDOW_SIZE Proxide advertises to both the client and the server.
+    pub initial_window_size: u32,
+
+    /// SETTINGS_MAX_CONCURRENT_STREAMS of the client. `None` if the client didn't limit them.
+    pub client_max_concurrent_streams: Option<usize>,
+
+    /// SETTINGS_MAX_CONCURRENT_STREAMS of the server. `None` if the server didn't limit them.
+    pub server_max_concurrent_streams: Option<usize>,
+
+    /// Data the client may still send on its most recent stream before Proxide updates the
+    /// window. `None` until the client has sent data.
+    #[serde(default)]
+    pub client_stream_window: Option<usize>,
+
+    /// Data the server may still send on its most recent stream before Proxide updates the
+    /// window. `None` until the server has sent data.
+    #[serde(default)]
+    pub server_stream_window: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -57,6 +125,25 @@ pub struct RequestData
     pub start_timestamp: DateTime<Local>,
     pub end_timestamp: Option<DateTime<Local>>,
     pub status: Status,
+
+    #[serde(default)]
+    pub response_status: Option<u16>,
+
+    /// The response was delayed or replaced by the fault injection.
+    #[serde(default)]
+    pub injected_fault: bool,
+
+    /// The request was failed by `--request-timeout` or `--idle-timeout`.
+    #[serde(default)]
+    pub timed_out: bool,
+
+    /// The HTTP/2 error the stream was reset with.
+    #[serde(default)]
+    pub reason: Option<H2Reason>,
+
+    /// Name given to the request with `:alias`.
+    #[serde(default)]
+    pub alias: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -82,6 +169,15 @@ pub struct MessageData
     pub start_timestamp: Option<DateTime<Local>>,
     pub end_timestamp: Option<DateTime<Local>>,
     pub part: RequestPart,
+
+    /// The error the message failed with while it was being forwarded.
+    #[serde(default)]
+    pub error: Option<String>,
+
+    /// Number of bytes forwarded after `content` that weren't kept because of the
+    /// `--max-body-bytes` limit.
+    #[serde(default)]
+    pub truncated: usize,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
@@ -92,6 +188,16 @@ pub enum Status
     Failed,
 }
 
+/// HTTP/2 error code of a `RST_STREAM` or a `GOAWAY` frame.
+///
+/// The last-stream-id of a `GOAWAY` frame isn't recorded as the `h2` library doesn't expose it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct H2Reason
+{
+    pub code: u32,
+    pub go_away: bool,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum RequestPart
 {
@@ -99,6 +205,60 @@ pub enum RequestPart
     Response,
 }
 
+impl ConnectionData
+{
+    /// Describes the protocol stack, such as "CONNECT → TLS → HTTP/2".
+    ///
+    /// Connections that were not intercepted over TLS are marked as plaintext.
+    pub fn protocol_description(&self) -> String
+    {
+        let stack = self
+            .protocol_stack
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" → ");
+        match self
+            .protocol_stack
+            .iter()
+            .any(|p| matches!(p, Protocol::Tls))
+        {
+            true => stack,
+            false if stack.is_empty() => "(Unknown)".to_string(),
+            false => format!("{} (plaintext)", stack),
+        }
+    }
+
+    /// The alias of the connection followed by the client address or just the address if the
+    /// connection has no alias.
+    pub fn display_name(&self) -> String
+    {
+        match &self.alias {
+            Some(alias) => format!("{} ({})", alias, self.client_addr),
+            None => self.client_addr.to_string(),
+        }
+    }
+}
+
+impl RequestData
+{
+    /// The percent-decoded parameters of the query string in their original order.
+    ///
+    /// Repeated parameters are listed once for each value.
+    pub fn query(&self) -> Vec<(String, String)>
+    {
+        let query = self.uri.query().unwrap_or_default();
+        crate::decoders::form::parse_pairs(query.as_bytes()).collect()
+    }
+
+    /// Whether the requests are occurrences of the same call, such as retries, based on their
+    /// method and path.
+    pub fn is_similar(&self, other: &RequestData) -> bool
+    {
+        self.method == other.method && self.uri.path() == other.uri.path()
+    }
+}
+
 impl MessageData
 {
     pub fn new(part: RequestPart) -> Self
@@ -110,6 +270,8 @@ impl MessageData
             start_timestamp: None,
             end_timestamp: None,
             part,
+            error: None,
+            truncated: 0,
         }
     }
 
@@ -150,6 +312,49 @@ impl<T> IndexedVec<T>
         let idx = self.items_by_uuid.get(&uuid)?;
         self.items.get_mut(*idx)
     }
+
+    /// Remove the items at the given indices while keeping the UUID index up to date.
+    ///
+    /// The indices must be in ascending order.
+    pub fn remove_indices(&mut self, removed: &[usize])
+    {
+        if removed.is_empty() {
+            return;
+        }
+
+        let mut idx = 0;
+        self.items.retain(|_| {
+            let keep = removed.binary_search(&idx).is_err();
+            idx += 1;
+            keep
+        });
+
+        // The position a retained index would have among the removed indices is the number of
+        // removed items before it.
+        self.items_by_uuid
+            .retain(|_, idx| match removed.binary_search(idx) {
+                Ok(_) => false,
+                Err(shift) => {
+                    *idx -= shift;
+                    true
+                }
+            });
+    }
+}
+
+impl<T: HasKey> IndexedVec<T>
+{
+    /// Sort the items while keeping the UUID index up to date.
+    pub fn sort_by_key<K: Ord, F: FnMut(&T) -> K>(&mut self, f: F)
+    {
+        self.items.sort_by_key(f);
+        self.items_by_uuid = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| (item.key(), idx))
+            .collect();
+    }
 }
 
 impl<T> std::ops::Deref for IndexedVec<T>
@@ -185,6 +390,81 @@ impl HasKey for EncodedRequest
     }
 }
 
+impl HasKey for ConnectionData
+{
+    fn key(&self) -> Uuid
+    {
+        self.uuid
+    }
+}
+
+impl Session
+{
+    /// Append the connections and requests of another session, ordering them by their start
+    /// time.
+    ///
+    /// The UUIDs should be unique across sessions, but if they are not, the items of the other
+    /// session are skipped. Returns the number of skipped items.
+    pub fn merge(&mut self, other: Session) -> usize
+    {
+        let mut duplicates = 0;
+        for conn in other.connections.items {
+            match self.connections.get_by_uuid(conn.uuid) {
+                Some(_) => duplicates += 1,
+                None => self.connections.push(conn.uuid, conn),
+            }
+        }
+        for request in other.requests.items {
+            match self.requests.get_by_uuid(request.key()) {
+                Some(_) => duplicates += 1,
+                None => self.requests.push(request.key(), request),
+            }
+        }
+
+        self.connections.sort_by_key(|c| c.start_timestamp);
+        self.requests
+            .sort_by_key(|r| r.request_data.start_timestamp);
+        duplicates
+    }
+
+    /// Drop the oldest requests so at most `max` requests remain.
+    ///
+    /// The closed connections without requests are dropped as well, including the ones that never
+    /// carried any requests. Connections that are still open are kept since they may carry more
+    /// requests later.
+    pub fn evict_requests(&mut self, max: usize) -> Vec<events::SessionChange>
+    {
+        let count = self.requests.len().saturating_sub(max);
+        if count == 0 {
+            return vec![];
+        }
+
+        let requests: Vec<_> = (0..count).collect();
+        self.requests.remove_indices(&requests);
+
+        let remaining_connections: HashSet<_> = self
+            .requests
+            .iter()
+            .map(|r| r.request_data.connection_uuid)
+            .collect();
+        let connections: Vec<_> = self
+            .connections
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                c.status != Status::InProgress && !remaining_connections.contains(&c.uuid)
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        self.connections.remove_indices(&connections);
+
+        vec![events::SessionChange::Removed {
+            requests,
+            connections,
+        }]
+    }
+}
+
 impl std::fmt::Display for Protocol
 {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
@@ -196,11 +476,24 @@ impl std::fmt::Display for Protocol
                 Protocol::Connect => "CONNECT",
                 Protocol::Tls => "TLS",
                 Protocol::Http2 => "HTTP/2",
+                Protocol::Http1 => "HTTP/1.1",
             },
         )
     }
 }
 
+impl std::fmt::Display for H2Reason
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        let reason = h2::Reason::from(self.code);
+        match self.go_away {
+            true => write!(f, "GOAWAY {:?} (last stream ID unknown)", reason),
+            false => write!(f, "RST_STREAM {:?}", reason),
+        }
+    }
+}
+
 mod serde_base64
 {
     use bytes::BytesMut;
@@ -230,3 +523,111 @@ mod serde_base64
         }
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use super::events::*;
+    use super::*;
+    use std::time::SystemTime;
+
+    fn connection(session: &mut Session) -> Uuid
+    {
+        let uuid = Uuid::new_v4();
+        session.handle(SessionEvent::NewConnection(NewConnectionEvent {
+            uuid,
+            protocol_stack: vec![],
+            client_addr: "127.0.0.1:1234".parse().unwrap(),
+            timestamp: SystemTime::now(),
+            connect_attempts: 1,
+        }));
+        uuid
+    }
+
+    fn request(session: &mut Session, connection_uuid: Uuid) -> Uuid
+    {
+        let uuid = Uuid::new_v4();
+        session.handle(SessionEvent::NewRequest(NewRequestEvent {
+            connection_uuid,
+            uuid,
+            uri: "http://example.com/".parse().unwrap(),
+            method: http::Method::GET,
+            headers: Default::default(),
+            timestamp: SystemTime::now(),
+            injected_fault: false,
+        }));
+        uuid
+    }
+
+    fn connection_done(session: &mut Session, uuid: Uuid)
+    {
+        session.handle(SessionEvent::ConnectionDone(ConnectionDoneEvent {
+            uuid,
+            status: Status::Succeeded,
+            timestamp: SystemTime::now(),
+            reason: None,
+        }));
+    }
+
+    #[test]
+    fn evict_oldest_requests()
+    {
+        let mut session = Session::default();
+        let closed = connection(&mut session);
+        let open = connection(&mut session);
+        let shared = connection(&mut session);
+        let empty = connection(&mut session);
+        let first = request(&mut session, closed);
+        let second = request(&mut session, open);
+        let third = request(&mut session, shared);
+        let fourth = request(&mut session, shared);
+        connection_done(&mut session, closed);
+        connection_done(&mut session, shared);
+        connection_done(&mut session, empty);
+
+        assert!(session.evict_requests(4).is_empty());
+        let changes = session.evict_requests(1);
+        assert!(matches!(
+            changes.as_slice(),
+            [SessionChange::Removed { requests, connections }]
+                if requests == &[0, 1, 2] && connections == &[0, 3]
+        ));
+
+        // The open connection is kept even without requests and the shared connection still
+        // has a request left.
+        assert!(session.requests.get_by_uuid(first).is_none());
+        assert!(session.requests.get_by_uuid(second).is_none());
+        assert!(session.requests.get_by_uuid(third).is_none());
+        assert_eq!(session.requests.get_index_by_uuid(fourth), Some(0));
+        assert!(session.connections.get_by_uuid(closed).is_none());
+        assert!(session.connections.get_by_uuid(empty).is_none());
+        assert_eq!(session.connections.get_index_by_uuid(open), Some(0));
+        assert_eq!(session.connections.get_index_by_uuid(shared), Some(1));
+        assert_eq!(session.requests.items_by_uuid.len(), 1);
+        assert_eq!(session.connections.items_by_uuid.len(), 2);
+    }
+
+    #[test]
+    fn parse_query_parameters()
+    {
+        let mut session = Session::default();
+        let conn = connection(&mut session);
+        let uuid = request(&mut session, conn);
+        let request = session.requests.get_mut_by_uuid(uuid).unwrap();
+        request.request_data.uri = "http://example.com/items?id=1&tag=a%20b&tag=c&q=x+y&flag"
+            .parse()
+            .unwrap();
+
+        let data = &request.request_data;
+        assert_eq!(
+            data.query(),
+            vec![
+                ("id".to_string(), "1".to_string()),
+                ("tag".to_string(), "a b".to_string()),
+                ("tag".to_string(), "c".to_string()),
+                ("q".to_string(), "x y".to_string()),
+                ("flag".to_string(), "".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use http::{HeaderMap, Uri};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Sender;
+use uuid::Uuid;
+use wildmatch::WildMatch;
+
+use super::events::{ConnectionSettingsEvent, NewConnectionEvent, SessionEvent};
+
+/// Drops the requests that don't match the `--only-path` and `--only-host` patterns from the
+/// captured traffic.
+///
+/// The path and host of a request are known from its first event so the requests can be
+/// accepted or discarded right away. The connection events are held back until the first
+/// request on the connection is accepted so connections without any matching requests are
+/// omitted completely.
+pub struct CaptureFilter
+{
+    pub paths: Vec<WildMatch>,
+    pub hosts: Vec<WildMatch>,
+
+    pending_connections: HashMap<Uuid, NewConnectionEvent>,
+    pending_settings: HashMap<Uuid, ConnectionSettingsEvent>,
+    connections: HashSet<Uuid>,
+    requests: HashSet<Uuid>,
+}
+
+impl CaptureFilter
+{
+    pub fn new(paths: Vec<WildMatch>, hosts: Vec<WildMatch>) -> Self
+    {
+        Self {
+            paths,
+            hosts,
+            pending_connections: Default::default(),
+            pending_settings: Default::default(),
+            connections: Default::default(),
+            requests: Default::default(),
+        }
+    }
+
+    /// Process the event, returning the events that should be passed on.
+    pub fn filter(&mut self, e: SessionEvent) -> Vec<SessionEvent>
+    {
+        match e {
+            SessionEvent::NewConnection(e) => {
+                self.pending_connections.insert(e.uuid, e);
+                vec![]
+            }
+            SessionEvent::NewRequest(e) => {
+                if !self.is_match(&e.uri, &e.headers) {
+                    return vec![];
+                }
+
+                let mut events = vec![];
+                if let Some(connection) = self.pending_connections.remove(&e.connection_uuid) {
+                    self.connections.insert(connection.uuid);
+                    events.push(SessionEvent::NewConnection(connection));
+                }
+                if let Some(settings) = self.pending_settings.remove(&e.connection_uuid) {
+                    events.push(SessionEvent::ConnectionSettings(settings));
+                }
+                self.requests.insert(e.uuid);
+                events.push(SessionEvent::NewRequest(e));
+                events
+            }
+            SessionEvent::NewResponse(ref r) if self.requests.contains(&r.uuid) => vec![e],
+            SessionEvent::MessageData(ref d) if self.requests.contains(&d.uuid) => vec![e],
+            SessionEvent::MessageDone(ref d) if self.requests.contains(&d.uuid) => vec![e],
+            SessionEvent::RequestDone(ref d) if self.requests.remove(&d.uuid) => vec![e],
+            SessionEvent::ConnectionDone(ref d) if self.connections.remove(&d.uuid) => vec![e],
+            SessionEvent::ConnectionDone(d) => {
+                self.pending_connections.remove(&d.uuid);
+                self.pending_settings.remove(&d.uuid);
+                vec![]
+            }
+            SessionEvent::ConnectionSettings(ref s) if self.connections.contains(&s.uuid) => {
+                vec![e]
+            }
+            SessionEvent::ConnectionSettings(s) => {
+                self.pending_settings.insert(s.uuid, s);
+                vec![]
+            }
+            SessionEvent::ConnectAttempt(ref a) if self.connections.contains(&a.uuid) => vec![e],
+            SessionEvent::ConnectAttempt(a) => {
+                if let Some(connection) = self.pending_connections.get_mut(&a.uuid) {
+                    connection.connect_attempts = a.connect_attempts;
+                }
+                vec![]
+            }
+
+            // The throughput is reported as totals so the ones sent before the connection was
+            // accepted can be dropped.
+            SessionEvent::ConnectionThroughput(ref t) if self.connections.contains(&t.uuid) => {
+                vec![e]
+            }
+            SessionEvent::NewResponse(..)
+            | SessionEvent::MessageData(..)
+            | SessionEvent::MessageDone(..)
+            | SessionEvent::RequestDone(..)
+            | SessionEvent::ConnectionThroughput(..) => vec![],
+        }
+    }
+
+    fn is_match(&self, uri: &Uri, headers: &HeaderMap) -> bool
+    {
+        // HTTP/1 requests usually carry the host only in the Host header.
+        let host = uri
+            .host()
+            .or_else(|| headers.get("host").and_then(|h| h.to_str().ok()))
+            .map(|h| match h.rsplit_once(':') {
+                Some((host, port)) if port.parse::<u16>().is_ok() => host,
+                _ => h,
+            })
+            .unwrap_or("");
+
+        (self.paths.is_empty() || self.paths.iter().any(|p| p.is_match(uri.path())))
+            && (self.hosts.is_empty() || self.hosts.iter().any(|p| p.is_match(host)))
+    }
+}
+
+/// Filter the events sent through the returned sender before passing them on to the target.
+pub fn filter_events(
+    mut filter: CaptureFilter,
+    target: Sender<SessionEvent>,
+) -> Sender<SessionEvent>
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        while let Ok(e) = rx.recv() {
+            for e in filter.filter(e) {
+                if target.send(e).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    tx
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::session::events::{ConnectionDoneEvent, NewRequestEvent, RequestDoneEvent};
+    use crate::session::Status;
+    use std::time::SystemTime;
+
+    fn connection(uuid: Uuid) -> SessionEvent
+    {
+        SessionEvent::NewConnection(NewConnectionEvent {
+            uuid,
+            protocol_stack: vec![],
+            client_addr: "127.0.0.1:1234".parse().unwrap(),
+            timestamp: SystemTime::now(),
+            connect_attempts: 1,
+        })
+    }
+
+    fn request(connection_uuid: Uuid, uuid: Uuid, uri: &str) -> SessionEvent
+    {
+        SessionEvent::NewRequest(NewRequestEvent {
+            connection_uuid,
+            uuid,
+            uri: uri.parse().unwrap(),
+            method: http::Method::GET,
+            headers: HeaderMap::new(),
+            timestamp: SystemTime::now(),
+            injected_fault: false,
+        })
+    }
+
+    fn request_done(uuid: Uuid) -> SessionEvent
+    {
+        SessionEvent::RequestDone(RequestDoneEvent {
+            uuid,
+            status: Status::Succeeded,
+            timestamp: SystemTime::now(),
+            timed_out: false,
+            reason: None,
+        })
+    }
+
+    fn connection_done(uuid: Uuid) -> SessionEvent
+    {
+        SessionEvent::ConnectionDone(ConnectionDoneEvent {
+            uuid,
+            status: Status::Succeeded,
+            timestamp: SystemTime::now(),
+            reason: None,
+        })
+    }
+
+    #[test]
+    fn filter_requests_and_connections()
+    {
+        let mut filter = CaptureFilter::new(
+            vec![WildMatch::new("/api/v1/*")],
+            vec![WildMatch::new("*.example.com")],
+        );
+
+        // A connection without matching requests is dropped entirely.
+        let (conn, req) = (Uuid::new_v4(), Uuid::new_v4());
+        assert!(filter.filter(connection(conn)).is_empty());
+        assert!(filter
+            .filter(request(conn, req, "https://foo.example.com/other"))
+            .is_empty());
+        assert!(filter.filter(request_done(req)).is_empty());
+        assert!(filter.filter(connection_done(conn)).is_empty());
+
+        // The connection is passed on with the first matching request.
+        let (conn, other, req) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        assert!(filter.filter(connection(conn)).is_empty());
+        assert!(filter
+            .filter(request(conn, other, "https://other.com/api/v1/x"))
+            .is_empty());
+        let events = filter.filter(request(conn, req, "https://foo.example.com:443/api/v1/x"));
+        assert!(matches!(
+            events.as_slice(),
+            [SessionEvent::NewConnection(_), SessionEvent::NewRequest(_)]
+        ));
+        assert!(filter.filter(request_done(other)).is_empty());
+        assert_eq!(filter.filter(request_done(req)).len(), 1);
+        assert_eq!(filter.filter(connection_done(conn)).len(), 1);
+    }
+}
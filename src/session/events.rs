@@ -4,7 +4,7 @@ use std::time::SystemTime;
 
 use super::*;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum SessionEvent
 {
     NewConnection(NewConnectionEvent),
@@ -14,18 +14,25 @@ pub enum SessionEvent
     MessageDone(MessageDoneEvent),
     RequestDone(RequestDoneEvent),
     ConnectionDone(ConnectionDoneEvent),
+    ConnectionSettings(ConnectionSettingsEvent),
+    ConnectAttempt(ConnectAttemptEvent),
+    ConnectionThroughput(ConnectionThroughputEvent),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NewConnectionEvent
 {
     pub uuid: Uuid,
     pub protocol_stack: Vec<Protocol>,
     pub client_addr: SocketAddr,
     pub timestamp: SystemTime,
+
+    /// Number of attempts it took to connect to the server. Zero if unknown.
+    #[serde(default)]
+    pub connect_attempts: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NewRequestEvent
 {
     pub connection_uuid: Uuid,
@@ -37,9 +44,12 @@ pub struct NewRequestEvent
     #[serde(with = "http_serde::header_map")]
     pub headers: HeaderMap,
     pub timestamp: SystemTime,
+
+    #[serde(default)]
+    pub injected_fault: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NewResponseEvent
 {
     pub connection_uuid: Uuid,
@@ -47,17 +57,26 @@ pub struct NewResponseEvent
     #[serde(with = "http_serde::header_map")]
     pub headers: HeaderMap,
     pub timestamp: SystemTime,
+
+    /// HTTP status code of the response. Missing from captures made with older versions.
+    #[serde(default)]
+    pub status: Option<u16>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MessageDataEvent
 {
     pub uuid: Uuid,
     pub data: bytes::Bytes,
     pub part: RequestPart,
+
+    /// Number of bytes received in addition to `data` that were left out because of the
+    /// `--max-body-bytes` limit.
+    #[serde(default)]
+    pub truncated: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MessageDoneEvent
 {
     pub uuid: Uuid,
@@ -66,22 +85,61 @@ pub struct MessageDoneEvent
     pub timestamp: SystemTime,
     #[serde(with = "super::serialization::opt_header_map")]
     pub trailers: Option<HeaderMap>,
+
+    #[serde(default)]
+    pub error: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RequestDoneEvent
 {
     pub uuid: Uuid,
     pub status: Status,
     pub timestamp: SystemTime,
+
+    #[serde(default)]
+    pub timed_out: bool,
+
+    #[serde(default)]
+    pub reason: Option<H2Reason>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConnectionDoneEvent
 {
     pub uuid: Uuid,
     pub status: Status,
     pub timestamp: SystemTime,
+
+    #[serde(default)]
+    pub reason: Option<H2Reason>,
+}
+
+/// The HTTP/2 settings of the connection were established or changed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConnectionSettingsEvent
+{
+    pub uuid: Uuid,
+    pub settings: H2Settings,
+}
+
+/// The connection to the server is being retried.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConnectAttemptEvent
+{
+    pub uuid: Uuid,
+
+    /// Number of the attempt that is starting, counting all the previous attempts.
+    pub connect_attempts: u32,
+}
+
+/// More data has been forwarded over the connection.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConnectionThroughputEvent
+{
+    pub uuid: Uuid,
+    pub request: Throughput,
+    pub response: Throughput,
 }
 
 pub enum SessionChange
@@ -110,6 +168,14 @@ pub enum SessionChange
     {
         connection: Uuid
     },
+
+    /// Items were removed from the session. The indices are the ones the items had before the
+    /// removal, in ascending order.
+    Removed
+    {
+        requests: Vec<usize>,
+        connections: Vec<usize>,
+    },
 }
 
 impl Session
@@ -124,6 +190,9 @@ impl Session
             SessionEvent::MessageDone(e) => self.on_message_done(e),
             SessionEvent::RequestDone(e) => self.on_request_done(e),
             SessionEvent::ConnectionDone(e) => self.on_connection_done(e),
+            SessionEvent::ConnectionSettings(e) => self.on_connection_settings(e),
+            SessionEvent::ConnectAttempt(e) => self.on_connect_attempt(e),
+            SessionEvent::ConnectionThroughput(e) => self.on_connection_throughput(e),
         }
     }
 
@@ -136,6 +205,12 @@ impl Session
             start_timestamp: e.timestamp.into(),
             end_timestamp: None,
             status: Status::InProgress,
+            reason: None,
+            h2_settings: None,
+            alias: None,
+            connect_attempts: e.connect_attempts,
+            request_throughput: Default::default(),
+            response_throughput: Default::default(),
         };
         self.connections.push(e.uuid, data);
         vec![SessionChange::NewConnection { connection: e.uuid }]
@@ -154,6 +229,11 @@ impl Session
                     status: Status::InProgress,
                     start_timestamp: e.timestamp.into(),
                     end_timestamp: None,
+                    response_status: None,
+                    injected_fault: e.injected_fault,
+                    timed_out: false,
+                    reason: None,
+                    alias: None,
                 },
                 request_msg: MessageData::new(RequestPart::Request)
                     .with_headers(e.headers)
@@ -177,6 +257,7 @@ impl Session
     {
         let request = self.requests.get_mut_by_uuid(e.uuid);
         if let Some(request) = request {
+            request.request_data.response_status = e.status;
             request.response_msg.headers = e.headers;
             request.response_msg.start_timestamp = Some(e.timestamp.into());
             vec![SessionChange::NewMessage {
@@ -197,6 +278,7 @@ impl Session
                 RequestPart::Response => &mut request.response_msg,
             };
             part_msg.content.extend(e.data);
+            part_msg.truncated += e.truncated;
             vec![SessionChange::Message {
                 request: e.uuid,
                 part: e.part,
@@ -215,6 +297,10 @@ impl Session
                 RequestPart::Response => &mut request.response_msg,
             };
             part_msg.end_timestamp = Some(e.timestamp.into());
+            part_msg.error = e.error;
+            if let Some(trailers) = e.trailers {
+                part_msg.trailers = trailers;
+            }
             vec![SessionChange::Message {
                 request: e.uuid,
                 part: e.part,
@@ -230,6 +316,8 @@ impl Session
         if let Some(request) = request {
             request.request_data.end_timestamp = Some(e.timestamp.into());
             request.request_data.status = e.status;
+            request.request_data.timed_out = e.timed_out;
+            request.request_data.reason = e.reason;
             vec![SessionChange::Request { request: e.uuid }]
         } else {
             vec![]
@@ -242,9 +330,44 @@ impl Session
         if let Some(conn) = conn {
             conn.end_timestamp = Some(e.timestamp.into());
             conn.status = e.status;
+            conn.reason = e.reason;
             vec![SessionChange::Connection { connection: e.uuid }]
         } else {
             vec![]
         }
     }
+
+    fn on_connection_settings(&mut self, e: ConnectionSettingsEvent) -> Vec<SessionChange>
+    {
+        match self.connections.get_mut_by_uuid(e.uuid) {
+            Some(conn) => {
+                conn.h2_settings = Some(e.settings);
+                vec![SessionChange::Connection { connection: e.uuid }]
+            }
+            None => vec![],
+        }
+    }
+
+    fn on_connect_attempt(&mut self, e: ConnectAttemptEvent) -> Vec<SessionChange>
+    {
+        match self.connections.get_mut_by_uuid(e.uuid) {
+            Some(conn) => {
+                conn.connect_attempts = e.connect_attempts;
+                vec![SessionChange::Connection { connection: e.uuid }]
+            }
+            None => vec![],
+        }
+    }
+
+    fn on_connection_throughput(&mut self, e: ConnectionThroughputEvent) -> Vec<SessionChange>
+    {
+        match self.connections.get_mut_by_uuid(e.uuid) {
+            Some(conn) => {
+                conn.request_throughput = e.request;
+                conn.response_throughput = e.response;
+                vec![SessionChange::Connection { connection: e.uuid }]
+            }
+            None => vec![],
+        }
+    }
 }
@@ -0,0 +1,141 @@
+use bytes::Bytes;
+use http::header::{HeaderName, HeaderValue};
+use http::HeaderMap;
+use regex::bytes::Regex;
+use std::sync::mpsc::Sender;
+
+use super::events::SessionEvent;
+
+pub const REDACTED: &str = "***REDACTED***";
+
+/// Headers redacted with `--redact-all-auth`.
+pub const AUTH_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-auth-token",
+    "x-amz-security-token",
+];
+
+/// Removes sensitive data from the session events before they are stored anywhere.
+pub struct Redaction
+{
+    pub headers: Vec<HeaderName>,
+    pub body_patterns: Vec<Regex>,
+}
+
+impl Redaction
+{
+    pub fn redact(&self, e: &mut SessionEvent)
+    {
+        match e {
+            SessionEvent::NewRequest(e) => self.redact_headers(&mut e.headers),
+            SessionEvent::NewResponse(e) => self.redact_headers(&mut e.headers),
+            SessionEvent::MessageDone(e) => {
+                if let Some(trailers) = &mut e.trailers {
+                    self.redact_headers(trailers);
+                }
+            }
+
+            // The patterns are matched against each data frame separately so a secret split
+            // between two frames is not caught.
+            SessionEvent::MessageData(e) => {
+                for pattern in &self.body_patterns {
+                    if pattern.is_match(&e.data) {
+                        e.data = Bytes::from(
+                            pattern
+                                .replace_all(&e.data, REDACTED.as_bytes())
+                                .into_owned(),
+                        );
+                    }
+                }
+            }
+            SessionEvent::NewConnection(..)
+            | SessionEvent::RequestDone(..)
+            | SessionEvent::ConnectionDone(..)
+            | SessionEvent::ConnectionSettings(..)
+            | SessionEvent::ConnectAttempt(..)
+            | SessionEvent::ConnectionThroughput(..) => {}
+        }
+    }
+
+    fn redact_headers(&self, headers: &mut HeaderMap)
+    {
+        for name in &self.headers {
+            if let http::header::Entry::Occupied(mut entry) = headers.entry(name) {
+                for value in entry.iter_mut() {
+                    *value = HeaderValue::from_static(REDACTED);
+                }
+            }
+        }
+    }
+}
+
+/// Redact the events sent through the returned sender before passing them on to the target.
+pub fn redact_events(redaction: Redaction, target: Sender<SessionEvent>) -> Sender<SessionEvent>
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        while let Ok(mut e) = rx.recv() {
+            redaction.redact(&mut e);
+            if target.send(e).is_err() {
+                break;
+            }
+        }
+    });
+    tx
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::session::events::{MessageDataEvent, NewRequestEvent};
+    use crate::session::RequestPart;
+
+    #[test]
+    fn redact_headers_and_body()
+    {
+        let redaction = Redaction {
+            headers: vec![HeaderName::from_static("authorization")],
+            body_patterns: vec![Regex::new("token=[a-z]+").unwrap()],
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer secret"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        let mut request = SessionEvent::NewRequest(NewRequestEvent {
+            connection_uuid: uuid::Uuid::new_v4(),
+            uuid: uuid::Uuid::new_v4(),
+            uri: "/".parse().unwrap(),
+            method: http::Method::GET,
+            headers,
+            timestamp: std::time::SystemTime::now(),
+            injected_fault: false,
+        });
+        redaction.redact(&mut request);
+        match request {
+            SessionEvent::NewRequest(e) => {
+                assert_eq!(e.headers["authorization"], REDACTED);
+                assert_eq!(e.headers["accept"], "*/*");
+            }
+            _ => unreachable!(),
+        }
+
+        let mut data = SessionEvent::MessageData(MessageDataEvent {
+            uuid: uuid::Uuid::new_v4(),
+            data: Bytes::from_static(b"a=1&token=abc&b=2"),
+            part: RequestPart::Request,
+            truncated: 0,
+        });
+        redaction.redact(&mut data);
+        match data {
+            SessionEvent::MessageData(e) => {
+                assert_eq!(&e.data[..], b"a=1&***REDACTED***&b=2".as_ref())
+            }
+            _ => unreachable!(),
+        }
+    }
+}
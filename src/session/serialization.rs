@@ -1,5 +1,5 @@
 use snafu::{ResultExt, Snafu};
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::Path;
 use std::sync::mpsc::{channel, Receiver};
 use std::sync::Mutex;
@@ -8,6 +8,8 @@ use tokio::sync::oneshot::Sender;
 use super::events::SessionEvent;
 use super::*;
 
+mod har;
+
 const TYPE_LENGTH: usize = 15; // "PROXIDE-SESSION", "PROXIDE-CAPTURE"
 const VERSION_LENGTH: usize = 3; // "v01"
 
@@ -49,19 +51,128 @@ pub struct CaptureStatus
     pub data: usize,
 }
 
+impl CaptureStatus
+{
+    /// Count the connections, requests and data already in the session.
+    pub fn from_session(session: &Session) -> Self
+    {
+        Self {
+            connections: session.connections.len(),
+            active_connections: session
+                .connections
+                .iter()
+                .filter(|c| c.status == Status::InProgress)
+                .count(),
+            requests: session.requests.len(),
+            active_requests: session
+                .requests
+                .iter()
+                .filter(|r| r.request_data.status == Status::InProgress)
+                .count(),
+            data: session
+                .requests
+                .iter()
+                .map(|r| {
+                    r.request_msg.content.len()
+                        + r.request_msg.truncated
+                        + r.response_msg.content.len()
+                        + r.response_msg.truncated
+                })
+                .sum(),
+        }
+    }
+
+    /// Update the counters with the event.
+    pub fn update(&mut self, event: &SessionEvent)
+    {
+        match event {
+            SessionEvent::NewConnection(_) => {
+                self.connections += 1;
+                self.active_connections += 1;
+            }
+            SessionEvent::NewRequest(_) => {
+                self.requests += 1;
+                self.active_requests += 1;
+            }
+            SessionEvent::ConnectionDone(_) => {
+                self.active_connections = self.active_connections.saturating_sub(1)
+            }
+            SessionEvent::RequestDone(_) => {
+                self.active_requests = self.active_requests.saturating_sub(1)
+            }
+            SessionEvent::MessageData(d) => self.data += d.data.len() + d.truncated,
+            _ => {}
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum OutputFormat
 {
     MessagePack,
     Json,
+
+    /// One self-contained JSON object per event, tagged with the event type. Unlike the plain
+    /// JSON capture, this can be read back with `read_file`.
+    JsonLines,
+}
+
+/// An event in the JSON Lines capture.
+///
+/// The event type is a field of its own so the lines can be filtered with tools such as
+/// `jq 'select(.type == "MessageData")'`.
+#[derive(Serialize, Deserialize)]
+struct JsonLine
+{
+    #[serde(rename = "type")]
+    event_type: String,
+    event: serde_json::Value,
+}
+
+impl JsonLine
+{
+    fn from_event(event: &SessionEvent) -> Result<Self, SerializationError>
+    {
+        // Events are serialized as single entry maps from the event type to the event data.
+        let value = serde_json::to_value(event)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
+            .context(FormatError {})?;
+        match value {
+            serde_json::Value::Object(map) if map.len() == 1 => {
+                let (event_type, event) = map.into_iter().next().unwrap();
+                Ok(Self { event_type, event })
+            }
+            _ => unreachable!("Session events are serialized as single entry maps"),
+        }
+    }
+
+    fn into_event(self) -> Result<SessionEvent, SerializationError>
+    {
+        let mut map = serde_json::Map::new();
+        map.insert(self.event_type, self.event);
+        serde_json::from_value(serde_json::Value::Object(map))
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
+            .context(FormatError {})
+    }
 }
 
 pub fn read_file<P: AsRef<Path> + ToString>(filename: &P) -> Result<Session, SerializationError>
 {
-    let mut file = std::fs::File::open(filename).context(IoError {
+    let file = std::fs::File::open(filename).context(IoError {
+        operation: "reading",
+        file: filename.to_string(),
+    })?;
+
+    // JSON Lines captures have no file header but always start with a JSON object while the
+    // binary files start with the file type.
+    let mut file = BufReader::new(file);
+    let first = file.fill_buf().context(IoError {
         operation: "reading",
         file: filename.to_string(),
     })?;
+    if first.first() == Some(&b'{') {
+        return read_json_lines_file(file);
+    }
 
     let mut header = [0; TYPE_LENGTH + VERSION_LENGTH];
     file.read_exact(&mut header)
@@ -100,9 +211,127 @@ impl Session
         let file = open_target_file(filename, format, b"PROXIDE-SESSIONv01")?;
         serialize_format(file, self, format)
     }
+
+    /// Describe the session as the events that would reproduce it.
+    ///
+    /// This allows writing the session into a capture file that is continued with live events.
+    pub fn to_events(&self) -> Vec<SessionEvent>
+    {
+        use super::events::*;
+
+        let mut events: Vec<_> = self
+            .connections
+            .iter()
+            .map(|c| {
+                SessionEvent::NewConnection(NewConnectionEvent {
+                    uuid: c.uuid,
+                    protocol_stack: c.protocol_stack.clone(),
+                    client_addr: c.client_addr,
+                    timestamp: c.start_timestamp.into(),
+                    connect_attempts: c.connect_attempts,
+                })
+            })
+            .collect();
+        events.extend(self.connections.iter().filter_map(|c| {
+            Some(SessionEvent::ConnectionSettings(ConnectionSettingsEvent {
+                uuid: c.uuid,
+                settings: c.h2_settings?,
+            }))
+        }));
+        events.extend(
+            self.connections
+                .iter()
+                .filter(|c| c.request_throughput.bytes > 0 || c.response_throughput.bytes > 0)
+                .map(|c| {
+                    SessionEvent::ConnectionThroughput(ConnectionThroughputEvent {
+                        uuid: c.uuid,
+                        request: c.request_throughput,
+                        response: c.response_throughput,
+                    })
+                }),
+        );
+
+        for r in self.requests.iter() {
+            let data = &r.request_data;
+            events.push(SessionEvent::NewRequest(NewRequestEvent {
+                connection_uuid: data.connection_uuid,
+                uuid: data.uuid,
+                uri: data.uri.clone(),
+                method: data.method.clone(),
+                headers: r.request_msg.headers.clone(),
+                timestamp: data.start_timestamp.into(),
+                injected_fault: data.injected_fault,
+            }));
+            if let Some(timestamp) = r.response_msg.start_timestamp {
+                events.push(SessionEvent::NewResponse(NewResponseEvent {
+                    connection_uuid: data.connection_uuid,
+                    uuid: data.uuid,
+                    headers: r.response_msg.headers.clone(),
+                    timestamp: timestamp.into(),
+                    status: data.response_status,
+                }));
+            }
+
+            for msg in &[&r.request_msg, &r.response_msg] {
+                if !msg.content.is_empty() || msg.truncated > 0 {
+                    events.push(SessionEvent::MessageData(MessageDataEvent {
+                        uuid: data.uuid,
+                        data: msg.content.clone().freeze(),
+                        part: msg.part,
+                        truncated: msg.truncated,
+                    }));
+                }
+                if let Some(end) = msg.end_timestamp {
+                    events.push(SessionEvent::MessageDone(MessageDoneEvent {
+                        uuid: data.uuid,
+                        part: msg.part,
+                        status: match msg.error {
+                            Some(_) => Status::Failed,
+                            None => Status::Succeeded,
+                        },
+                        timestamp: end.into(),
+                        trailers: match msg.trailers.is_empty() {
+                            true => None,
+                            false => Some(msg.trailers.clone()),
+                        },
+                        error: msg.error.clone(),
+                    }));
+                }
+            }
+
+            if let Some(end) = data.end_timestamp {
+                events.push(SessionEvent::RequestDone(RequestDoneEvent {
+                    uuid: data.uuid,
+                    status: data.status,
+                    timestamp: end.into(),
+                    timed_out: data.timed_out,
+                    reason: data.reason,
+                }));
+            }
+        }
+
+        events.extend(self.connections.iter().filter_map(|c| {
+            Some(SessionEvent::ConnectionDone(ConnectionDoneEvent {
+                uuid: c.uuid,
+                status: c.status,
+                timestamp: c.end_timestamp?.into(),
+                reason: c.reason,
+            }))
+        }));
+        events
+    }
+}
+
+/// Export the session in the HAR 1.2 format used by browser developer tools.
+pub fn export_har(session: &Session, filename: &str) -> Result<(), SerializationError>
+{
+    let file = open_target_file(filename, OutputFormat::Json, b"PROXIDE-SESSIONv01")?;
+    serde_json::to_writer_pretty(file, &har::Har::from_session(session))
+        .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
+        .context(FormatError {})
 }
 
-pub fn read_session_file(file: std::fs::File) -> Result<Session, SerializationError>
+pub fn read_session_file(file: impl Read) -> Result<Session, SerializationError>
 {
     rmp_serde::from_read(file)
         .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
@@ -165,63 +394,16 @@ pub fn capture_to_file<F: FnMut(&CaptureStatus) + Send + 'static>(
         }
     });
 
-    let mut file = open_target_file(filename, format, b"PROXIDE-CAPTUREv02")?;
-    let mut buffer: Vec<u8> = Vec::new();
+    let mut writer = CaptureWriter::new(filename, format)?;
     let mut status = CaptureStatus::default();
     while let Ok(event) = rx.recv() {
-        // Handle status updates with certain events.
-        match &event {
-            SessionEvent::NewConnection(_) => {
-                status.connections += 1;
-                status.active_connections += 1;
-            }
-            SessionEvent::NewRequest(_) => {
-                status.requests += 1;
-                status.active_requests += 1;
-            }
-            SessionEvent::ConnectionDone(_) => status.active_connections -= 1,
-            SessionEvent::RequestDone(_) => status.active_requests -= 1,
-            SessionEvent::MessageData(d) => status.data += d.data.len(),
-            _ => {}
-        }
+        status.update(&event);
 
         // Print errors out, but otherwise ignore them.
-        if let Err(e) = serialize_format(&mut buffer, event, format) {
-            eprintln!("{}", e);
-        } else {
-            match format {
-                OutputFormat::Json => writeln!(
-                    file,
-                    "{}",
-                    std::str::from_utf8(&buffer)
-                        .expect("JSON serialization produced invalid UTF-8")
-                )
-                .context(IoError {
-                    operation: "writing",
-                    file: filename.to_string(),
-                })?,
-                OutputFormat::MessagePack => {
-                    // Convert the data length as varint (each byte has 7 bytes of payload and the MSB
-                    // indicates whether the length continues in the next byte.
-                    let mut len_buffer: Vec<u8> = Vec::new();
-                    let mut len = buffer.len();
-                    while len >= 0x80 {
-                        len_buffer.push((len & 0x7f | 0x80) as u8);
-                        len >>= 7;
-                    }
-                    len_buffer.push(len as u8);
-
-                    // Write the event. Length followed by the payload.
-                    file.write_all(&len_buffer)
-                        .and_then(|_| file.write_all(&buffer))
-                        .context(IoError {
-                            operation: "writing",
-                            file: filename.to_string(),
-                        })?;
-                }
-            }
-            status_tx.send(StatusAction::Status(status)).unwrap();
-            buffer.clear();
+        match writer.write(&event) {
+            Err(e @ SerializationError::FormatError { .. }) => eprintln!("{}", e),
+            Err(e) => return Err(e),
+            Ok(_) => status_tx.send(StatusAction::Status(status)).unwrap(),
         }
     }
 
@@ -232,7 +414,93 @@ pub fn capture_to_file<F: FnMut(&CaptureStatus) + Send + 'static>(
     Ok(())
 }
 
-pub fn read_capture_file(mut file: std::fs::File) -> Result<Session, SerializationError>
+/// Writes session events into a capture file that can be read with `read_file`.
+pub struct CaptureWriter
+{
+    file: Box<dyn Write + Send>,
+    filename: String,
+    format: OutputFormat,
+    buffer: Vec<u8>,
+}
+
+impl CaptureWriter
+{
+    pub fn new(filename: &str, format: OutputFormat) -> Result<Self, SerializationError>
+    {
+        Ok(Self {
+            file: open_target_file(filename, format, b"PROXIDE-CAPTUREv02")?,
+            filename: filename.to_string(),
+            format,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, event: &SessionEvent) -> Result<(), SerializationError>
+    {
+        self.buffer.clear();
+        match self.format {
+            OutputFormat::JsonLines => JsonLine::from_event(event)
+                .and_then(|line| serialize_format(&mut self.buffer, line, self.format))?,
+            _ => serialize_format(&mut self.buffer, event, self.format)?,
+        };
+
+        let filename = &self.filename;
+        match self.format {
+            OutputFormat::Json | OutputFormat::JsonLines => writeln!(
+                self.file,
+                "{}",
+                std::str::from_utf8(&self.buffer)
+                    .expect("JSON serialization produced invalid UTF-8")
+            )
+            .context(IoError {
+                operation: "writing",
+                file: filename.to_string(),
+            }),
+            OutputFormat::MessagePack => {
+                // Convert the data length as varint (each byte has 7 bytes of payload and the MSB
+                // indicates whether the length continues in the next byte.
+                let mut len_buffer: Vec<u8> = Vec::new();
+                let mut len = self.buffer.len();
+                while len >= 0x80 {
+                    len_buffer.push((len & 0x7f | 0x80) as u8);
+                    len >>= 7;
+                }
+                len_buffer.push(len as u8);
+
+                // Write the event. Length followed by the payload.
+                let (file, buffer) = (&mut self.file, &self.buffer);
+                file.write_all(&len_buffer)
+                    .and_then(|_| file.write_all(buffer))
+                    .context(IoError {
+                        operation: "writing",
+                        file: filename.to_string(),
+                    })
+            }
+        }
+    }
+}
+
+/// Write the events sent through the returned sender into a capture file on a background thread.
+///
+/// The capture ends when the sender is dropped or when writing fails, in which case `on_error` is
+/// invoked with the error.
+pub fn capture_events<F: FnOnce(SerializationError) + Send + 'static>(
+    mut writer: CaptureWriter,
+    on_error: F,
+) -> std::sync::mpsc::Sender<SessionEvent>
+{
+    let (tx, rx) = channel::<SessionEvent>();
+    std::thread::spawn(move || {
+        while let Ok(event) = rx.recv() {
+            if let Err(e) = writer.write(&event) {
+                return on_error(e);
+            }
+        }
+    });
+    tx
+}
+
+pub fn read_capture_file(mut file: impl Read) -> Result<Session, SerializationError>
 {
     let mut session = Session::default();
 
@@ -284,13 +552,51 @@ pub fn read_capture_file(mut file: std::fs::File) -> Result<Session, Serializati
     }
 }
 
+/// Read a capture written with `OutputFormat::JsonLines`.
+pub fn read_json_lines_file(mut file: impl BufRead) -> Result<Session, SerializationError>
+{
+    let mut session = Session::default();
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let len = file
+            .read_line(&mut line)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
+            .context(FormatError {})?;
+        if len == 0 {
+            return Ok(session);
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let event = serde_json::from_str::<JsonLine>(&line)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
+            .context(FormatError {})
+            .and_then(JsonLine::into_event);
+        match event {
+            Ok(event) => {
+                session.handle(event);
+            }
+
+            // The capture may still be writing the last line.
+            Err(_) if !line.ends_with('\n') => {
+                log::error!("Incomplete input file");
+                return Ok(session);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 pub fn open_target_file(
     filename: &str,
     format: OutputFormat,
     filetype: &[u8; TYPE_LENGTH + VERSION_LENGTH],
-) -> Result<Box<dyn Write>, SerializationError>
+) -> Result<Box<dyn Write + Send>, SerializationError>
 {
-    let mut file: Box<dyn Write> = match filename {
+    let mut file: Box<dyn Write + Send> = match filename {
         "-" => Box::new(std::io::stdout()),
         _ => match std::fs::File::create(filename) {
             Ok(f) => Box::new(f),
@@ -368,9 +674,118 @@ fn serialize_format(
         OutputFormat::MessagePack => data
             .serialize(&mut rmp_serde::Serializer::new(file))
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>),
-        OutputFormat::Json => data
+        OutputFormat::Json | OutputFormat::JsonLines => data
             .serialize(&mut serde_json::Serializer::new(file))
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>),
     }
     .map_err(|source| SerializationError::FormatError { source })
 }
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::session::events::*;
+
+    #[test]
+    fn json_lines_round_trip()
+    {
+        let uuid = Uuid::new_v4();
+        let events = vec![
+            SessionEvent::NewConnection(NewConnectionEvent {
+                uuid,
+                protocol_stack: vec![Protocol::Http2],
+                client_addr: "127.0.0.1:1234".parse().unwrap(),
+                timestamp: std::time::SystemTime::now(),
+                connect_attempts: 1,
+            }),
+            SessionEvent::ConnectionDone(ConnectionDoneEvent {
+                uuid,
+                status: Status::Succeeded,
+                timestamp: std::time::SystemTime::now(),
+                reason: None,
+            }),
+        ];
+
+        let mut output = vec![];
+        for e in &events {
+            let line = serde_json::to_string(&JsonLine::from_event(e).unwrap()).unwrap();
+            output.extend(line.as_bytes());
+            output.push(b'\n');
+        }
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("{\"type\":\"NewConnection\",\"event\":{"));
+        assert_eq!(output.lines().count(), 2);
+
+        let session = read_json_lines_file(output.as_bytes()).unwrap();
+        let connection = session.connections.get_by_uuid(uuid).unwrap();
+        assert_eq!(connection.status, Status::Succeeded);
+        assert!(connection.end_timestamp.is_some());
+    }
+
+    #[test]
+    fn session_to_events_round_trip()
+    {
+        let (connection, request) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = std::time::SystemTime::now();
+        let mut session = Session::default();
+        session.handle(SessionEvent::NewConnection(NewConnectionEvent {
+            uuid: connection,
+            protocol_stack: vec![Protocol::Http1],
+            client_addr: "127.0.0.1:1234".parse().unwrap(),
+            timestamp: now,
+            connect_attempts: 1,
+        }));
+        session.handle(SessionEvent::NewRequest(NewRequestEvent {
+            connection_uuid: connection,
+            uuid: request,
+            uri: "http://example.com/".parse().unwrap(),
+            method: http::Method::POST,
+            headers: Default::default(),
+            timestamp: now,
+            injected_fault: false,
+        }));
+        session.handle(SessionEvent::MessageData(MessageDataEvent {
+            uuid: request,
+            data: bytes::Bytes::from_static(b"body"),
+            part: RequestPart::Request,
+            truncated: 0,
+        }));
+        session.handle(SessionEvent::MessageDone(MessageDoneEvent {
+            uuid: request,
+            part: RequestPart::Request,
+            status: Status::Succeeded,
+            timestamp: now,
+            trailers: None,
+            error: None,
+        }));
+        let throughput = Throughput {
+            bytes: 4,
+            duration: std::time::Duration::from_millis(10),
+        };
+        session.handle(SessionEvent::ConnectionThroughput(
+            ConnectionThroughputEvent {
+                uuid: connection,
+                request: throughput,
+                response: Default::default(),
+            },
+        ));
+
+        // The request is still waiting for the response.
+        let events = session.to_events();
+        assert_eq!(events.len(), 5);
+
+        let mut copy = Session::default();
+        for e in events {
+            copy.handle(e);
+        }
+        let copied = copy.requests.get_by_uuid(request).unwrap();
+        assert_eq!(&copied.request_msg.content[..], b"body");
+        assert!(copied.request_msg.end_timestamp.is_some());
+        assert!(copied.response_msg.start_timestamp.is_none());
+        assert_eq!(copied.request_data.status, Status::InProgress);
+        let copied = copy.connections.get_by_uuid(connection).unwrap();
+        assert_eq!(copied.status, Status::InProgress);
+        assert_eq!(copied.request_throughput, throughput);
+    }
+}
@@ -0,0 +1,263 @@
+//! HAR 1.2 export.
+//!
+//! See <http://www.softwareishard.com/blog/har-12-spec/> for the format specification.
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use super::super::*;
+
+#[derive(Serialize)]
+pub struct Har
+{
+    log: Log,
+}
+
+#[derive(Serialize)]
+struct Log
+{
+    version: &'static str,
+    creator: Creator,
+    entries: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct Creator
+{
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry
+{
+    started_date_time: String,
+    time: f64,
+    request: Request,
+    response: Response,
+    cache: Cache,
+    timings: Timings,
+    connection: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request
+{
+    method: String,
+    url: String,
+    http_version: &'static str,
+    cookies: Vec<NameValue>,
+    headers: Vec<NameValue>,
+    query_string: Vec<NameValue>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<Content>,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Response
+{
+    status: u16,
+    status_text: String,
+    http_version: &'static str,
+    cookies: Vec<NameValue>,
+    headers: Vec<NameValue>,
+    content: Content,
+
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+struct NameValue
+{
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Content
+{
+    size: usize,
+    mime_type: String,
+    text: String,
+    encoding: &'static str,
+}
+
+#[derive(Serialize)]
+struct Cache {}
+
+#[derive(Serialize)]
+struct Timings
+{
+    send: f64,
+    wait: f64,
+    receive: f64,
+}
+
+impl Har
+{
+    pub fn from_session(session: &Session) -> Self
+    {
+        Self {
+            log: Log {
+                version: "1.2",
+                creator: Creator {
+                    name: "Proxide",
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                entries: session
+                    .requests
+                    .iter()
+                    .map(|r| Entry::from_request(session, r))
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl Entry
+{
+    fn from_request(session: &Session, request: &EncodedRequest) -> Self
+    {
+        let data = &request.request_data;
+        let http_version = match session.connections.get_by_uuid(data.connection_uuid) {
+            Some(c)
+                if c.protocol_stack
+                    .iter()
+                    .any(|p| matches!(p, Protocol::Http1)) =>
+            {
+                "HTTP/1.1"
+            }
+            _ => "HTTP/2.0",
+        };
+
+        // HAR requires non-negative timings. Phases that never happened are recorded as zero.
+        let timings = Timings {
+            send: millis(
+                request.request_msg.start_timestamp,
+                request.request_msg.end_timestamp,
+            ),
+            wait: millis(
+                request.request_msg.end_timestamp,
+                request.response_msg.start_timestamp,
+            ),
+            receive: millis(
+                request.response_msg.start_timestamp,
+                request.response_msg.end_timestamp,
+            ),
+        };
+
+        Self {
+            started_date_time: data.start_timestamp.to_rfc3339(),
+            time: millis(Some(data.start_timestamp), data.end_timestamp),
+            request: Request {
+                method: data.method.to_string(),
+                url: data.uri.to_string(),
+                http_version,
+                cookies: vec![],
+                headers: headers(&request.request_msg.headers),
+                query_string: query_string(&data.uri),
+                post_data: match request.request_msg.content.is_empty() {
+                    true => None,
+                    false => Some(Content::from_message(&request.request_msg)),
+                },
+                headers_size: -1,
+                body_size: request.request_msg.content.len() as i64,
+            },
+            response: Response {
+                // HAR uses 0 for responses that were never received.
+                status: data.response_status.unwrap_or(0),
+                status_text: data
+                    .response_status
+                    .and_then(|s| http::StatusCode::from_u16(s).ok())
+                    .and_then(|s| s.canonical_reason())
+                    .unwrap_or_default()
+                    .to_string(),
+                http_version,
+                cookies: vec![],
+                headers: headers(&request.response_msg.headers),
+                content: Content::from_message(&request.response_msg),
+                redirect_url: String::new(),
+                headers_size: -1,
+                body_size: request.response_msg.content.len() as i64,
+            },
+            cache: Cache {},
+            timings,
+            connection: data.connection_uuid.to_string(),
+            comment: match data.status {
+                Status::Failed => Some("Request failed"),
+                Status::InProgress => Some("Request in progress"),
+                Status::Succeeded => None,
+            },
+        }
+    }
+}
+
+impl Content
+{
+    fn from_message(msg: &MessageData) -> Self
+    {
+        Self {
+            size: msg.content.len(),
+            mime_type: msg
+                .headers
+                .get(http::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string(),
+            text: base64::encode(&msg.content),
+            encoding: "base64",
+        }
+    }
+}
+
+fn headers(headers: &http::HeaderMap) -> Vec<NameValue>
+{
+    headers
+        .iter()
+        .map(|(k, v)| NameValue {
+            name: k.to_string(),
+            value: String::from_utf8_lossy(v.as_bytes()).into_owned(),
+        })
+        .collect()
+}
+
+fn query_string(uri: &http::Uri) -> Vec<NameValue>
+{
+    uri.query()
+        .map(|q| {
+            q.split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let mut split = pair.splitn(2, '=');
+                    NameValue {
+                        name: split.next().unwrap_or_default().to_string(),
+                        value: split.next().unwrap_or_default().to_string(),
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn millis(start: Option<DateTime<Local>>, end: Option<DateTime<Local>>) -> f64
+{
+    match (start, end) {
+        (Some(start), Some(end)) => {
+            ((end - start).num_microseconds().unwrap_or(0) as f64 / 1000.0).max(0.0)
+        }
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,354 @@
+//! Parsing of request signing metadata for display purposes.
+//!
+//! The signatures are never verified. The intent is to surface the structured components of the
+//! signing headers since these are usually the first thing to check when a cloud API responds with
+//! 401 or 403.
+
+use http::HeaderMap;
+
+/// A single human readable piece of signing metadata.
+pub struct SigningDetail
+{
+    pub label: &'static str,
+    pub value: String,
+}
+
+impl SigningDetail
+{
+    fn new<T: ToString>(label: &'static str, value: T) -> Self
+    {
+        Self {
+            label,
+            value: value.to_string(),
+        }
+    }
+}
+
+/// Extracts the signing metadata from the request headers.
+///
+/// Returns an empty list if the request doesn't carry any recognized signature.
+pub fn annotate(headers: &HeaderMap) -> Vec<SigningDetail>
+{
+    let authorization = match headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(a) => a.trim(),
+        None => return vec![],
+    };
+
+    let (scheme, params) = match authorization.find(' ') {
+        Some(idx) => (&authorization[..idx], authorization[idx..].trim()),
+        None => (authorization, ""),
+    };
+
+    if scheme.ends_with("4-HMAC-SHA256") {
+        hmac_v4(scheme, params, headers)
+    } else if scheme.eq_ignore_ascii_case("bearer") {
+        bearer(params)
+    } else {
+        vec![]
+    }
+}
+
+/// AWS Signature Version 4 and the compatible GCS HMAC scheme (GOOG4-HMAC-SHA256).
+fn hmac_v4(scheme: &str, params: &str, headers: &HeaderMap) -> Vec<SigningDetail>
+{
+    let mut details = vec![SigningDetail::new("Algorithm", scheme)];
+    for param in params.split(',') {
+        let mut split = param.trim().splitn(2, '=');
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(k), Some(v)) => (k, v),
+            _ => continue,
+        };
+
+        match key {
+            "Credential" => {
+                // Credential is of the form <key id>/<date>/<region>/<service>/<terminator>.
+                let mut parts = value.split('/');
+                let labels = ["Key ID", "Scope date", "Region", "Service", "Terminator"];
+                for label in labels.iter() {
+                    if let Some(part) = parts.next() {
+                        details.push(SigningDetail::new(label, part));
+                    }
+                }
+            }
+            "SignedHeaders" => details.push(SigningDetail::new(
+                "Signed headers",
+                value.split(';').collect::<Vec<_>>().join(", "),
+            )),
+            "Signature" => details.push(SigningDetail::new("Signature", value)),
+            _ => {}
+        }
+    }
+
+    for (header, label) in [
+        ("x-amz-date", "Request date"),
+        ("x-goog-date", "Request date"),
+        ("x-amz-content-sha256", "Content hash"),
+        ("x-goog-content-sha256", "Content hash"),
+    ]
+    .iter()
+    {
+        if let Some(value) = headers.get(*header).and_then(|v| v.to_str().ok()) {
+            details.push(SigningDetail::new(label, value));
+        }
+    }
+
+    if headers.contains_key("x-amz-security-token") {
+        details.push(SigningDetail::new("Session token", "present"));
+    }
+
+    details
+}
+
+/// Bearer tokens, such as the ones used by GCP.
+///
+/// JWT tokens have their claims displayed. Opaque tokens can't be parsed so only their kind is
+/// reported.
+fn bearer(token: &str) -> Vec<SigningDetail>
+{
+    let mut details = vec![SigningDetail::new("Algorithm", "Bearer")];
+
+    let parts: Vec<_> = token.split('.').collect();
+    if parts.len() != 3 {
+        let kind = match token.starts_with("ya29.") {
+            true => "Google OAuth2 access token (opaque)",
+            false => "Opaque token",
+        };
+        details.push(SigningDetail::new("Token", kind));
+        return details;
+    }
+
+    let decode = |part: &str| -> Option<serde_json::Value> {
+        let data = base64::decode_config(part, base64::URL_SAFE_NO_PAD).ok()?;
+        serde_json::from_slice(&data).ok()
+    };
+
+    match (decode(parts[0]), decode(parts[1])) {
+        (Some(header), Some(claims)) => {
+            details.push(SigningDetail::new("Token", "JWT"));
+            for (key, label) in [("alg", "JWT algorithm"), ("kid", "Key ID")].iter() {
+                if let Some(value) = header.get(*key) {
+                    details.push(SigningDetail::new(label, json_to_string(value)));
+                }
+            }
+
+            for (key, label) in [
+                ("iss", "Issuer"),
+                ("sub", "Subject"),
+                ("email", "Email"),
+                ("aud", "Audience"),
+                ("scope", "Scope"),
+            ]
+            .iter()
+            {
+                if let Some(value) = claims.get(*key) {
+                    details.push(SigningDetail::new(label, json_to_string(value)));
+                }
+            }
+
+            for (key, label) in [("iat", "Issued at"), ("exp", "Expires")].iter() {
+                if let Some(value) = claims.get(*key).and_then(|v| v.as_i64()) {
+                    details.push(SigningDetail::new(label, format_epoch(value)));
+                }
+            }
+        }
+        _ => details.push(SigningDetail::new("Token", "Malformed JWT")),
+    }
+
+    details
+}
+
+fn json_to_string(value: &serde_json::Value) -> String
+{
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn format_epoch(seconds: i64) -> String
+{
+    use chrono::TimeZone;
+    match chrono::Utc.timestamp_opt(seconds, 0) {
+        chrono::LocalResult::Single(t) => t.with_timezone(&chrono::Local).to_string(),
+        _ => seconds.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn annotate_headers(headers: &[(&'static str, &str)]) -> Vec<(&'static str, String)>
+    {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, value.parse().unwrap());
+        }
+        annotate(&map)
+            .into_iter()
+            .map(|d| (d.label, d.value))
+            .collect()
+    }
+
+    fn jwt(header: &str, claims: &str) -> String
+    {
+        format!(
+            "{}.{}.signature",
+            base64::encode_config(header, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(claims, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    #[test]
+    fn sigv4()
+    {
+        // The GET ListUsers example from the AWS Signature Version 4 documentation.
+        let details = annotate_headers(&[
+            (
+                "authorization",
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+                 SignedHeaders=content-type;host;x-amz-date, \
+                 Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7",
+            ),
+            ("x-amz-date", "20150830T123600Z"),
+            ("x-amz-security-token", "token"),
+        ]);
+        assert_eq!(
+            details,
+            vec![
+                ("Algorithm", "AWS4-HMAC-SHA256".to_string()),
+                ("Key ID", "AKIDEXAMPLE".to_string()),
+                ("Scope date", "20150830".to_string()),
+                ("Region", "us-east-1".to_string()),
+                ("Service", "iam".to_string()),
+                ("Terminator", "aws4_request".to_string()),
+                (
+                    "Signed headers",
+                    "content-type, host, x-amz-date".to_string()
+                ),
+                (
+                    "Signature",
+                    "5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7".to_string()
+                ),
+                ("Request date", "20150830T123600Z".to_string()),
+                ("Session token", "present".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn goog4()
+    {
+        let details = annotate_headers(&[
+            (
+                "authorization",
+                "GOOG4-HMAC-SHA256 Credential=GOOGTS7C7FUP3AIRVJTE2BCD/\
+                 20190424/us-central1/storage/goog4_request, \
+                 SignedHeaders=host;x-goog-content-sha256;x-goog-date, \
+                 Signature=1a2b3c",
+            ),
+            ("x-goog-date", "20190424T120000Z"),
+            ("x-goog-content-sha256", "UNSIGNED-PAYLOAD"),
+        ]);
+        assert_eq!(
+            details,
+            vec![
+                ("Algorithm", "GOOG4-HMAC-SHA256".to_string()),
+                ("Key ID", "GOOGTS7C7FUP3AIRVJTE2BCD".to_string()),
+                ("Scope date", "20190424".to_string()),
+                ("Region", "us-central1".to_string()),
+                ("Service", "storage".to_string()),
+                ("Terminator", "goog4_request".to_string()),
+                (
+                    "Signed headers",
+                    "host, x-goog-content-sha256, x-goog-date".to_string()
+                ),
+                ("Signature", "1a2b3c".to_string()),
+                ("Request date", "20190424T120000Z".to_string()),
+                ("Content hash", "UNSIGNED-PAYLOAD".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_hmac()
+    {
+        // Parameters without values are skipped and a short credential yields what it has.
+        let details = annotate_headers(&[(
+            "authorization",
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830, SignedHeaders, garbage",
+        )]);
+        assert_eq!(
+            details,
+            vec![
+                ("Algorithm", "AWS4-HMAC-SHA256".to_string()),
+                ("Key ID", "AKIDEXAMPLE".to_string()),
+                ("Scope date", "20150830".to_string()),
+            ]
+        );
+
+        let details = annotate_headers(&[("authorization", "AWS4-HMAC-SHA256")]);
+        assert_eq!(details, vec![("Algorithm", "AWS4-HMAC-SHA256".to_string())]);
+    }
+
+    #[test]
+    fn bearer_jwt()
+    {
+        let token = jwt(
+            r#"{"alg":"RS256","kid":"key-1"}"#,
+            concat!(
+                r#"{"iss":"https://accounts.google.com","sub":"1234","aud":["a","b"],"#,
+                r#""iat":1600000000,"exp":1600003600}"#
+            ),
+        );
+        let details = annotate_headers(&[("authorization", &format!("Bearer {}", token))]);
+        assert_eq!(
+            details,
+            vec![
+                ("Algorithm", "Bearer".to_string()),
+                ("Token", "JWT".to_string()),
+                ("JWT algorithm", "RS256".to_string()),
+                ("Key ID", "key-1".to_string()),
+                ("Issuer", "https://accounts.google.com".to_string()),
+                ("Subject", "1234".to_string()),
+                ("Audience", r#"["a","b"]"#.to_string()),
+                ("Issued at", format_epoch(1600000000)),
+                ("Expires", format_epoch(1600003600)),
+            ]
+        );
+    }
+
+    #[test]
+    fn bearer_malformed()
+    {
+        let details = annotate_headers(&[("authorization", "bearer ya29.a0AfH6SM")]);
+        assert_eq!(
+            details,
+            vec![
+                ("Algorithm", "Bearer".to_string()),
+                ("Token", "Google OAuth2 access token (opaque)".to_string()),
+            ]
+        );
+
+        let details = annotate_headers(&[("authorization", "Bearer opaque")]);
+        assert_eq!(details[1], ("Token", "Opaque token".to_string()));
+
+        // Three parts that don't decode into JSON are not a JWT.
+        let details = annotate_headers(&[("authorization", "Bearer a.b.c")]);
+        assert_eq!(details[1], ("Token", "Malformed JWT".to_string()));
+        let token = jwt(r#"{"alg":"RS256"}"#, "not json");
+        let details = annotate_headers(&[("authorization", &format!("Bearer {}", token))]);
+        assert_eq!(details[1], ("Token", "Malformed JWT".to_string()));
+    }
+
+    #[test]
+    fn unsigned_requests()
+    {
+        assert!(annotate_headers(&[]).is_empty());
+        assert!(annotate_headers(&[("authorization", "Basic dXNlcjpwYXNz")]).is_empty());
+    }
+}
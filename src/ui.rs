@@ -1,9 +1,16 @@
 use crossterm::{
-    event, execute,
+    cursor::Show,
+    event::{self, DisableMouseCapture, EnableMouseCapture},
+    execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use snafu::{ResultExt, Snafu};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 use std::{io::stdout, sync::mpsc};
 use tui::{backend::CrosstermBackend, Terminal};
 
@@ -11,8 +18,10 @@ use crate::decoders::Decoders;
 use crate::session::events::SessionEvent;
 
 mod chords;
+mod clipboard;
 mod commands;
 mod filters;
+mod layout;
 mod prelude;
 mod state;
 mod style;
@@ -45,18 +54,46 @@ pub fn main(
     session: crate::session::Session,
     decoders: Decoders,
     session_rx: mpsc::Receiver<SessionEvent>,
+    replay_target: Option<String>,
+    max_requests: Option<usize>,
+    osc52: bool,
 ) -> Result<()>
 {
+    clipboard::use_osc52(osc52);
     enable_raw_mode().context(TermError {})?;
-    execute!(stdout(), EnterAlternateScreen).context(TermError {})?;
+    execute!(stdout(), EnterAlternateScreen, EnableMouseCapture).context(TermError {})?;
+    install_panic_hook();
 
+    // The terminal is restored even if the UI fails so the error can be read. Taking the hook
+    // reinstalls the default one as the terminal no longer needs restoring.
+    let result = run(session, decoders, session_rx, replay_target, max_requests);
+    let _ = std::panic::take_hook();
+    restore_terminal()?;
+    result
+}
+
+fn run(
+    session: crate::session::Session,
+    decoders: Decoders,
+    session_rx: mpsc::Receiver<SessionEvent>,
+    replay_target: Option<String>,
+    max_requests: Option<usize>,
+) -> Result<()>
+{
     let backend = CrosstermBackend::new(stdout());
     let mut terminal = Terminal::new(backend).context(IoError {})?;
     terminal.hide_cursor().context(IoError {})?;
 
     let (ui_tx, ui_rx) = std::sync::mpsc::channel();
 
-    let mut state = ProxideUi::new(session, ui_tx.clone(), decoders, terminal.size().unwrap());
+    let mut state = ProxideUi::new(
+        session,
+        ui_tx.clone(),
+        decoders,
+        replay_target,
+        max_requests,
+        terminal.size().unwrap(),
+    );
 
     let toast_tx = ui_tx.clone();
     thread::spawn(move || {
@@ -68,13 +105,40 @@ pub fn main(
         }
     });
 
+    // The request rate in the status line changes even without new traffic.
+    let tick_tx = ui_tx.clone();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+        if tick_tx.send(UiEvent::Redraw).is_err() {
+            break;
+        }
+    });
+
+    // The input is read on its own thread, which must not steal the input from the external
+    // programs run with the UI suspended. The thread holds the lock while it is reading and
+    // stays away from the terminal while the input is paused.
+    let input = InputPause::default();
     let crossterm_tx = ui_tx.clone();
-    thread::spawn(move || {
-        while let Ok(e) = event::read() {
-            // If the send fails, the UI has stopped so we can exit the thread.
-            if crossterm_tx.send(UiEvent::Crossterm(e)).is_err() {
-                break;
-            }
+    let input_pause = input.clone();
+    thread::spawn(move || loop {
+        if input_pause.paused.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(50));
+            continue;
+        }
+
+        let _guard = input_pause.lock.lock().unwrap();
+        let e = match event::poll(Duration::from_millis(100)) {
+            Ok(true) => match event::read() {
+                Ok(e) => e,
+                Err(_) => break,
+            },
+            Ok(false) => continue,
+            Err(_) => break,
+        };
+
+        // If the send fails, the UI has stopped so we can exit the thread.
+        if crossterm_tx.send(UiEvent::Crossterm(e)).is_err() {
+            break;
         }
     });
 
@@ -108,6 +172,16 @@ pub fn main(
         match r {
             HandleResult::PushView(..) => unreachable!("PushView is handled by the state"),
             HandleResult::ExitView => unreachable!("ExitView is handled by the state"),
+            HandleResult::SelectRequest(..) => {
+                unreachable!("SelectRequest is handled by the state")
+            }
+            HandleResult::SelectConnection(..) => {
+                unreachable!("SelectConnection is handled by the state")
+            }
+            HandleResult::AddFilter(..) => unreachable!("AddFilter is handled by the state"),
+            HandleResult::SaveFilters(..) => unreachable!("SaveFilters is handled by the state"),
+            HandleResult::LoadFilters(..) => unreachable!("LoadFilters is handled by the state"),
+            HandleResult::Find(..) => unreachable!("Find is handled by the state"),
             HandleResult::ExitCommand(..) => unreachable!("ExitCommand is handled by the state"),
             HandleResult::Update => {
                 if !redraw_pending {
@@ -115,12 +189,74 @@ pub fn main(
                     ui_tx.send(UiEvent::Redraw).expect("The UI loop dropped RX");
                 }
             }
+            HandleResult::OpenExternal { command, file } => {
+                if let Err(e) = run_external(&command, &file, &input) {
+                    toast::show_error(format!("Could not run '{}'\n{}", command, e));
+                }
+                let _ = std::fs::remove_file(&file);
+
+                // The external program has drawn over the UI so the whole screen is redrawn.
+                terminal.clear().context(IoError {})?;
+                terminal.hide_cursor().context(IoError {})?;
+                state.draw(&mut terminal).context(IoError {})?;
+            }
             HandleResult::Quit => break,
         }
     }
 
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+struct InputPause
+{
+    paused: Arc<AtomicBool>,
+    lock: Arc<Mutex<()>>,
+}
+
+/// Run the program on the file with the terminal restored to its normal state.
+///
+/// The command may include arguments, such as `code --wait`.
+fn run_external(command: &str, file: &Path, input: &InputPause) -> std::io::Result<()>
+{
+    let mut parts = command.split_whitespace();
+    let program = parts.next().unwrap_or_default();
+
+    input.paused.store(true, Ordering::SeqCst);
+    let guard = input.lock.lock().unwrap();
+    let _ = restore_terminal();
+    let status = Command::new(program).args(parts).arg(file).status();
+    let _ = enable_raw_mode();
+    let _ = execute!(stdout(), EnterAlternateScreen, EnableMouseCapture);
+    drop(guard);
+    input.paused.store(false, Ordering::SeqCst);
+
+    match status? {
+        s if s.success() => Ok(()),
+        s => Err(std::io::Error::other(format!(
+            "The program exited with {}",
+            s
+        ))),
+    }
+}
+
+fn restore_terminal() -> Result<()>
+{
     disable_raw_mode().context(TermError {})?;
-    execute!(stdout(), LeaveAlternateScreen).context(TermError {})?;
+    execute!(stdout(), LeaveAlternateScreen, DisableMouseCapture, Show).context(TermError {})
+}
 
-    Ok(())
+/// Restore the terminal before the panic message is printed if the UI thread panics.
+///
+/// Panics on the other threads are left alone as they don't stop the UI.
+fn install_panic_hook()
+{
+    let ui_thread = thread::current().id();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if thread::current().id() == ui_thread {
+            let _ = restore_terminal();
+        }
+        default_hook(info);
+    }));
 }
@@ -0,0 +1,73 @@
+use chrono::prelude::*;
+use lazy_static::lazy_static;
+use std::error::Error;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::ui::toast;
+
+/// Terminals and multiplexers commonly drop OSC 52 sequences larger than this.
+const MAX_OSC52_LEN: usize = 100_000;
+
+static USE_OSC52: AtomicBool = AtomicBool::new(false);
+lazy_static! {
+    // The clipboard is kept around since on X11 the copied text is served by its owner.
+    static ref CLIPBOARD: Mutex<Option<arboard::Clipboard>> = Mutex::new(None);
+}
+
+/// Copy through the terminal with the OSC 52 escape sequence instead of the system clipboard.
+///
+/// This works over SSH as well, but requires support from the terminal emulator.
+pub fn use_osc52(enabled: bool)
+{
+    USE_OSC52.store(enabled, Ordering::Relaxed);
+}
+
+/// Copy the text to the system clipboard.
+pub fn copy(text: &str) -> Result<(), Box<dyn Error>>
+{
+    if USE_OSC52.load(Ordering::Relaxed) {
+        return copy_osc52(text);
+    }
+
+    let mut clipboard = CLIPBOARD.lock().unwrap();
+    let clipboard = match &mut *clipboard {
+        Some(clipboard) => clipboard,
+        None => clipboard.insert(arboard::Clipboard::new()?),
+    };
+    clipboard.set_text(text)?;
+    Ok(())
+}
+
+fn copy_osc52(text: &str) -> Result<(), Box<dyn Error>>
+{
+    if text.len() > MAX_OSC52_LEN {
+        return Err(format!("The text is too long for OSC 52 ({} bytes)", text.len()).into());
+    }
+
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64::encode(text))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Copy the text to the clipboard or write it into a temporary file if it can't be copied.
+///
+/// The `what` describes the text in the toast shown to the user.
+pub fn copy_or_save(text: &str, what: &str)
+{
+    match copy(text) {
+        Ok(_) => return toast::show_message(format!("{} copied to clipboard", what)),
+        Err(e) => log::warn!("Could not copy to clipboard: {}", e),
+    }
+
+    let path = std::env::temp_dir().join(format!(
+        "proxide-{}.txt",
+        Local::now().format("%Y-%m-%d_%H%M%S")
+    ));
+    match std::fs::write(&path, text) {
+        Ok(_) => toast::show_message(format!("{} written to '{}'", what, path.display())),
+        Err(e) => toast::show_error(format!("Could not write file '{}'\n{}", path.display(), e)),
+    }
+}
@@ -1,12 +1,16 @@
-use crate::ui::state::UiContext;
+use crate::ui::state::{UiContext, UiEvent};
 use crate::ui::toast;
 use chrono::prelude::*;
 use clap::{App, Arg, ArgMatches, SubCommand};
 use core::cell::RefCell;
 use tui::backend::Backend;
+use uuid::Uuid;
 
 use super::Executable;
-use crate::session;
+use crate::connection::{self, ReplayRequest};
+use crate::session::{self, EncodedRequest, Protocol};
+use crate::ui::clipboard;
+use crate::ui::filters::HeaderFilter;
 use crate::ui::state::HandleResult;
 
 thread_local! {
@@ -36,9 +40,113 @@ pub fn create_app() -> App<'static>
                         .possible_values(["msgpack", "json"]),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("save")
+                .arg(
+                    Arg::with_name("file")
+                        .index(1)
+                        .value_name("file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short('f')
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(["msgpack", "json"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("capture")
+                .arg(
+                    Arg::with_name("file")
+                        .index(1)
+                        .value_name("file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short('f')
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(["msgpack", "json", "jsonl"]),
+                )
+                .arg(Arg::with_name("session").short('s').long("session")),
+        )
+        .subcommand(SubCommand::with_name("stop-capture"))
+        .subcommand(
+            SubCommand::with_name("har").arg(
+                Arg::with_name("file")
+                    .index(1)
+                    .value_name("file")
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("curl").arg(
+                Arg::with_name("file")
+                    .index(1)
+                    .value_name("file")
+                    .required(false),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("filter-header")
+                .arg(
+                    Arg::with_name("name")
+                        .index(1)
+                        .value_name("name")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("value")
+                        .index(2)
+                        .value_name("value")
+                        .required(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("save-filters").arg(
+                Arg::with_name("file")
+                    .index(1)
+                    .value_name("file")
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("load-filters").arg(
+                Arg::with_name("file")
+                    .index(1)
+                    .value_name("file")
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("alias")
+                .arg(
+                    Arg::with_name("name")
+                        .index(1)
+                        .value_name("name")
+                        .required(false),
+                )
+                .arg(Arg::with_name("connection").short('c').long("connection")),
+        )
+        .subcommand(
+            SubCommand::with_name("replay").arg(
+                Arg::with_name("target")
+                    .index(1)
+                    .value_name("host:port")
+                    .required(false),
+            ),
+        )
+}
+
+pub struct ColonCommand
+{
+    /// The request selected at the time the command was started.
+    pub selected_request: Option<Uuid>,
 }
 
-pub struct ColonCommand;
 impl<B: Backend> Executable<B> for ColonCommand
 {
     fn execute(&self, cmd: &str, ctx: &mut UiContext) -> Option<HandleResult<B>>
@@ -60,17 +168,34 @@ impl<B: Backend> Executable<B> for ColonCommand
                     return None;
                 }
             };
-            execute_matches(matches, ctx)
+            execute_matches(matches, ctx, self.selected_request)
         })
     }
 }
 
-pub fn execute_matches<B: Backend>(s: ArgMatches, ctx: &mut UiContext) -> Option<HandleResult<B>>
+pub fn execute_matches<B: Backend>(
+    s: ArgMatches,
+    ctx: &mut UiContext,
+    selected_request: Option<Uuid>,
+) -> Option<HandleResult<B>>
 {
     match s.subcommand() {
         Some(("quit", _)) => Some(HandleResult::Quit),
         Some(("clear", _)) => clear_session(ctx),
-        Some(("export", m)) => export_session(ctx, m),
+        Some(("export", m)) | Some(("save", m)) => export_session(ctx, m),
+        Some(("capture", m)) => start_capture(ctx, m),
+        Some(("stop-capture", _)) => stop_capture(ctx),
+        Some(("har", m)) => export_har(ctx, m),
+        Some(("curl", m)) => export_curl(ctx, m, selected_request),
+        Some(("replay", m)) => replay_request(ctx, m, selected_request),
+        Some(("filter-header", m)) => filter_header(m),
+        Some(("alias", m)) => set_alias(ctx, m, selected_request),
+        Some(("save-filters", m)) => Some(HandleResult::SaveFilters(
+            m.value_of("file").unwrap().to_string(),
+        )),
+        Some(("load-filters", m)) => Some(HandleResult::LoadFilters(
+            m.value_of("file").unwrap().to_string(),
+        )),
         Some((cmd, _)) => {
             toast::show_error(format!("Unknown command: {}", cmd));
             None
@@ -86,9 +211,71 @@ pub fn clear_session<B: Backend>(ctx: &mut UiContext) -> Option<HandleResult<B>>
 {
     ctx.data.requests = Default::default();
     ctx.data.connections = Default::default();
+    ctx.runtime.unsaved_requests.set(false);
     Some(HandleResult::Update)
 }
 
+/// Name the selected request or, with `--connection`, its connection. Leaving out the name
+/// removes the alias.
+pub fn set_alias<B: Backend>(
+    ctx: &mut UiContext,
+    matches: &ArgMatches,
+    selected_request: Option<Uuid>,
+) -> Option<HandleResult<B>>
+{
+    let request = match selected_request.and_then(|r| ctx.data.requests.get_mut_by_uuid(r)) {
+        Some(r) => r,
+        None => {
+            toast::show_error("No request selected");
+            return None;
+        }
+    };
+
+    let alias = matches
+        .value_of("name")
+        .filter(|name| !name.is_empty())
+        .map(String::from);
+    if matches.is_present("connection") {
+        let connection = request.request_data.connection_uuid;
+        match ctx.data.connections.get_mut_by_uuid(connection) {
+            Some(conn) => conn.alias = alias,
+            None => {
+                toast::show_error("The connection of the request isn't part of the session");
+                return None;
+            }
+        }
+    } else {
+        request.request_data.alias = alias;
+    }
+
+    // The alias is session data that hasn't been saved yet.
+    ctx.runtime.unsaved_requests.set(true);
+    Some(HandleResult::Update)
+}
+
+pub fn filter_header<B: Backend>(matches: &ArgMatches) -> Option<HandleResult<B>>
+{
+    // HeaderName normalizes the name to lower case so the names are compared case-insensitively.
+    let name = match http::header::HeaderName::from_bytes(
+        matches.value_of("name").unwrap_or_default().as_bytes(),
+    ) {
+        Ok(name) => name,
+        Err(e) => {
+            toast::show_error(format!("Invalid header name:\n{}", e));
+            return None;
+        }
+    };
+    let value = match matches.value_of("value") {
+        None | Some("*") => None,
+        Some(value) => Some(value.to_string()),
+    };
+
+    Some(HandleResult::AddFilter(Box::new(HeaderFilter {
+        name,
+        value,
+    })))
+}
+
 pub fn export_session<B: Backend>(ctx: &UiContext, matches: &ArgMatches)
     -> Option<HandleResult<B>>
 {
@@ -97,15 +284,283 @@ pub fn export_session<B: Backend>(ctx: &UiContext, matches: &ArgMatches)
         .map(|f| f.to_string())
         .unwrap_or_else(|| format!("session-{}.bin", Local::now().format("%Y-%m-%d_%H%M%S")));
 
-    let format = match matches.value_of("format") {
-        Some("json") => session::serialization::OutputFormat::Json,
+    // Without an explicit format, a .json file gets JSON and anything else MessagePack.
+    let is_json = filename.to_ascii_lowercase().ends_with(".json");
+    let format = match (matches.value_of("format"), is_json) {
+        (Some("json"), _) | (None, true) => session::serialization::OutputFormat::Json,
         _ => session::serialization::OutputFormat::MessagePack,
     };
 
     match ctx.data.write_to_file(&filename, format) {
-        Ok(_) => toast::show_message(format!("Exported session to '{}'", filename)),
+        Ok(_) => {
+            ctx.runtime.unsaved_requests.set(false);
+            toast::show_message(format!("Exported session to '{}'", filename))
+        }
         Err(e) => toast::show_error(e.to_string()),
     }
 
     None
 }
+
+pub fn start_capture<B: Backend>(
+    ctx: &mut UiContext,
+    matches: &ArgMatches,
+) -> Option<HandleResult<B>>
+{
+    use session::serialization::{CaptureWriter, OutputFormat};
+
+    let filename = matches.value_of("file").unwrap();
+    let format = match matches.value_of("format") {
+        Some("json") => OutputFormat::Json,
+        Some("jsonl") => OutputFormat::JsonLines,
+        _ => OutputFormat::MessagePack,
+    };
+
+    let mut writer = match CaptureWriter::new(filename, format) {
+        Ok(w) => w,
+        Err(e) => {
+            toast::show_error(e.to_string());
+            return None;
+        }
+    };
+
+    // The existing session is written as the events that produced it so the file stays a
+    // regular capture that can be continued with the live events.
+    if matches.is_present("session") {
+        for e in ctx.data.to_events() {
+            if let Err(e) = writer.write(&e) {
+                toast::show_error(e.to_string());
+                return None;
+            }
+        }
+    }
+
+    let previous = ctx
+        .runtime
+        .capture
+        .replace(session::serialization::capture_events(writer, |e| {
+            toast::show_error(format!("Capture stopped\n{}", e))
+        }));
+    match previous {
+        Some(_) => toast::show_message(format!("Capturing to '{}' instead", filename)),
+        None => toast::show_message(format!("Capturing to '{}'", filename)),
+    }
+
+    None
+}
+
+pub fn stop_capture<B: Backend>(ctx: &mut UiContext) -> Option<HandleResult<B>>
+{
+    // Dropping the sender ends the capture thread once it has written the pending events.
+    match ctx.runtime.capture.take() {
+        Some(_) => toast::show_message("Capture stopped"),
+        None => toast::show_error("No capture in progress"),
+    }
+
+    None
+}
+
+pub fn export_har<B: Backend>(ctx: &UiContext, matches: &ArgMatches) -> Option<HandleResult<B>>
+{
+    let filename = matches.value_of("file").unwrap();
+    match session::serialization::export_har(&ctx.data, filename) {
+        Ok(_) => toast::show_message(format!("Exported HAR to '{}'", filename)),
+        Err(e) => toast::show_error(e.to_string()),
+    }
+
+    None
+}
+
+pub fn export_curl<B: Backend>(
+    ctx: &UiContext,
+    matches: &ArgMatches,
+    selected_request: Option<Uuid>,
+) -> Option<HandleResult<B>>
+{
+    let request = match selected_request.and_then(|r| ctx.data.requests.get_by_uuid(r)) {
+        Some(r) => r,
+        None => {
+            toast::show_error("No request selected");
+            return None;
+        }
+    };
+
+    let command = curl_command(ctx, request);
+    match matches.value_of("file") {
+        Some(filename) => match std::fs::write(filename, command + "\n") {
+            Ok(_) => toast::show_message(format!("curl command written to '{}'", filename)),
+            Err(e) => toast::show_error(format!("Could not write file '{}'\n{}", filename, e)),
+        },
+        None => match clipboard::copy(&command) {
+            Ok(_) => toast::show_message("curl command copied to clipboard"),
+            Err(e) => toast::show_error(format!("Could not copy to clipboard\n{}", e)),
+        },
+    }
+
+    None
+}
+
+pub fn replay_request<B: Backend>(
+    ctx: &UiContext,
+    matches: &ArgMatches,
+    selected_request: Option<Uuid>,
+) -> Option<HandleResult<B>>
+{
+    let request = match selected_request.and_then(|r| ctx.data.requests.get_by_uuid(r)) {
+        Some(r) => r,
+        None => {
+            toast::show_error("No request selected");
+            return None;
+        }
+    };
+
+    let data = &request.request_data;
+    let msg = &request.request_msg;
+    let protocol_stack = ctx
+        .data
+        .connections
+        .get_by_uuid(data.connection_uuid)
+        .map(|c| c.protocol_stack.as_slice())
+        .unwrap_or_default();
+    let tls = protocol_stack.iter().any(|p| matches!(p, Protocol::Tls))
+        || data.uri.scheme() == Some(&http::uri::Scheme::HTTPS);
+    let protocol = match protocol_stack.iter().any(|p| matches!(p, Protocol::Http2)) {
+        true => Protocol::Http2,
+        false => Protocol::Http1,
+    };
+
+    // Without an explicit target the request goes to the server it was originally meant for.
+    let target = matches
+        .value_of("target")
+        .map(String::from)
+        .or_else(|| ctx.runtime.replay_target.clone())
+        .or_else(|| {
+            let authority = data
+                .uri
+                .authority()
+                .map(|a| a.as_str().to_string())
+                .or_else(|| {
+                    msg.headers
+                        .get(http::header::HOST)
+                        .and_then(|h| h.to_str().ok())
+                        .map(String::from)
+                })?;
+            Some(match authority.contains(':') {
+                true => authority,
+                false => format!("{}:{}", authority, if tls { 443 } else { 80 }),
+            })
+        });
+    let target = match target {
+        Some(t) => t,
+        None => {
+            toast::show_error("No replay target specified");
+            return None;
+        }
+    };
+
+    let replay = ReplayRequest {
+        method: data.method.clone(),
+        uri: data.uri.clone(),
+        headers: msg.headers.clone(),
+        body: msg.content.clone().freeze(),
+        trailers: msg.trailers.clone(),
+        protocol,
+        tls,
+    };
+
+    // The replayed request is reported through the same events as the captured traffic so it
+    // shows up in the session as a new request.
+    let (session_tx, session_rx) = std::sync::mpsc::channel();
+    let ui_tx = ctx.runtime.tx.clone();
+    std::thread::spawn(move || {
+        while let Ok(e) = session_rx.recv() {
+            if ui_tx.send(UiEvent::SessionEvent(Box::new(e))).is_err() {
+                break;
+            }
+        }
+    });
+
+    toast::show_message(format!("Replaying request to {}", target));
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(r) => r,
+            Err(e) => return toast::show_error(format!("Replay failed\n{}", e)),
+        };
+        if let Err(e) = runtime.block_on(connection::replay(replay, &target, session_tx)) {
+            toast::show_error(format!("Replay failed\n{}", e));
+        }
+    });
+
+    None
+}
+
+/// Build a shell command that reproduces the request with curl.
+fn curl_command(ctx: &UiContext, request: &EncodedRequest) -> String
+{
+    let data = &request.request_data;
+    let msg = &request.request_msg;
+
+    // HTTP/2 carries the authority as a pseudo-header that ends up in the URI. Fall back to the
+    // Host header for HTTP/1.1 requests that use the origin form.
+    let uri = match (data.uri.authority(), msg.headers.get(http::header::HOST)) {
+        (None, Some(host)) => format!(
+            "http://{}{}",
+            host.to_str().unwrap_or_default(),
+            data.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/")
+        ),
+        _ => data.uri.to_string(),
+    };
+
+    let mut args = vec!["curl".to_string()];
+    let protocol_stack = ctx
+        .data
+        .connections
+        .get_by_uuid(data.connection_uuid)
+        .map(|c| c.protocol_stack.as_slice())
+        .unwrap_or_default();
+    if protocol_stack.iter().any(|p| matches!(p, Protocol::Http2)) {
+        match protocol_stack.iter().any(|p| matches!(p, Protocol::Tls)) {
+            true => args.push("--http2".to_string()),
+            false => args.push("--http2-prior-knowledge".to_string()),
+        }
+    }
+
+    args.push("-X".to_string());
+    args.push(data.method.to_string());
+    args.push(shell_words::quote(&uri).into_owned());
+
+    for (k, v) in &msg.headers {
+        // curl derives these from the URI and the body.
+        if k.as_str().starts_with(':') || k == http::header::CONTENT_LENGTH {
+            continue;
+        }
+        let header = format!("{}: {}", k, String::from_utf8_lossy(v.as_bytes()));
+        args.push("-H".to_string());
+        args.push(shell_words::quote(&header).into_owned());
+    }
+
+    if msg.content.is_empty() {
+        return args.join(" ");
+    }
+
+    // Binary bodies can't be passed on the command line so they are piped in through base64.
+    match std::str::from_utf8(&msg.content) {
+        Ok(body) => {
+            args.push("--data-binary".to_string());
+            args.push(shell_words::quote(body).into_owned());
+            args.join(" ")
+        }
+        Err(_) => {
+            args.push("--data-binary".to_string());
+            args.push("@-".to_string());
+            format!(
+                "echo '{}' | base64 -d | {}",
+                base64::encode(&msg.content),
+                args.join(" ")
+            )
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use crossterm::event::{Event as CTEvent, KeyCode, KeyModifiers};
 use tui::backend::Backend;
 
+use crate::search::SearchOptions;
 use crate::ui::state::HandleResult;
 use crate::ui::state::UiContext;
+use crate::ui::{filters::SearchFilter, toast};
 
 mod colon_command;
 pub use colon_command::ColonCommand;
@@ -32,6 +34,12 @@ impl<B: Backend> CommandState<B>
                 KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
                     return Some(HandleResult::ExitCommand(None))
                 }
+                KeyCode::Char(c) if key.modifiers == KeyModifiers::CONTROL => {
+                    match self.executable.control_key(c) {
+                        Some(help) => self.help = help,
+                        None => return None,
+                    }
+                }
                 _ if key.modifiers == KeyModifiers::CONTROL => return None,
                 KeyCode::Char(c) => self.insert(c),
                 KeyCode::Enter => {
@@ -93,23 +101,74 @@ impl<B: Backend> CommandState<B>
 pub trait Executable<B: Backend>
 {
     fn execute(&self, cmd: &str, ctx: &mut UiContext) -> Option<HandleResult<B>>;
+
+    /// Handle a Ctrl key pressed while typing the command. Returns the new help text if the
+    /// key was used.
+    fn control_key(&mut self, _key: char) -> Option<String>
+    {
+        None
+    }
+}
+
+pub struct SearchCommand
+{
+    pub options: SearchOptions,
+}
+
+impl SearchCommand
+{
+    pub fn help(&self) -> String
+    {
+        let on_off = |b| match b {
+            true => "on",
+            false => "off",
+        };
+        format!(
+            "Search, prefix with 're:' for a regular expression; [^T] Ignore case: {}; [^E] Search headers: {}",
+            on_off(self.options.ignore_case),
+            on_off(self.options.headers),
+        )
+    }
+}
+
+impl<B: Backend> Executable<B> for SearchCommand
+{
+    fn execute(&self, cmd: &str, ctx: &mut UiContext) -> Option<HandleResult<B>>
+    {
+        // The options are kept for the next search.
+        ctx.runtime.search_options = self.options;
+        if cmd.is_empty() {
+            return None;
+        }
+
+        match SearchFilter::new(cmd, self.options, ctx.runtime.search_index.clone()) {
+            Ok(filter) => Some(HandleResult::AddFilter(Box::new(filter))),
+            Err(e) => {
+                toast::show_error(format!("Invalid regular expression:\n{}", e));
+                None
+            }
+        }
+    }
+
+    fn control_key(&mut self, key: char) -> Option<String>
+    {
+        match key {
+            't' => self.options.ignore_case = !self.options.ignore_case,
+            'e' => self.options.headers = !self.options.headers,
+            _ => return None,
+        }
+        Some(self.help())
+    }
 }
 
-/*
-pub struct SearchCommand;
-impl Executable for SearchCommand
+pub struct FindCommand;
+impl<B: Backend> Executable<B> for FindCommand
 {
-    fn execute(&self, cmd: &str, ctx: &mut UiContext)
+    fn execute(&self, cmd: &str, _ctx: &mut UiContext) -> Option<HandleResult<B>>
     {
-        ctx.data
-            .requests
-            .add_filter(Box::new(session::filters::SearchFilter {
-                pattern: cmd.to_string(),
-                index: ctx.runtime.search_index.clone(),
-            }))
+        Some(HandleResult::Find(cmd.to_string()))
     }
 }
-*/
 
 pub fn export_session<B: Backend>(ctx: &UiContext) -> Option<HandleResult<B>>
 {
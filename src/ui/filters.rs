@@ -1,13 +1,17 @@
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::rc::Rc;
 use uuid::Uuid;
 
-use crate::search::SearchIndex;
+use crate::search::{Pattern, SearchIndex, SearchOptions};
 use crate::session::{EncodedRequest, Status};
 use crate::ui::state::UiContext;
 
+/// Maximum number of filter changes that can be undone.
+const UNDO_DEPTH: usize = 50;
+
 pub struct FilterState<T>
 {
     last_count: usize,
@@ -16,6 +20,7 @@ pub struct FilterState<T>
     filtered_items: Vec<usize>,
     filtered_items_set: HashSet<usize>,
     pub filters: FilterMap<T>,
+    undo_stack: VecDeque<FilterUndo<T>>,
     // These are waiting for highlight/search implementation.
     /*
     highlighted_items: HashSet<usize>,
@@ -44,6 +49,44 @@ pub struct SingleFilterState<T>
     filter: Box<dyn ItemFilter<T>>,
 }
 
+/// An operation that reverts a single change made to the filters.
+///
+/// The filters themselves are trait objects that can't be cloned so instead of snapshotting the
+/// whole filter map on every change, the undo entries take ownership of whatever the change
+/// removed.
+enum FilterUndo<T>
+{
+    /// Undo adding a new filter.
+    Remove
+    {
+        filter_type: FilterType,
+        key: String,
+    },
+
+    /// Undo removing or replacing a single filter.
+    Restore
+    {
+        filter_type: FilterType,
+        key: String,
+        filter: SingleFilterState<T>,
+        group_enabled: bool,
+    },
+
+    /// Undo removing a whole filter group.
+    RestoreGroup(FilterGroupState<T>),
+
+    /// Undo toggling a filter or a filter group.
+    RestoreEnabled
+    {
+        filter_type: FilterType,
+        group_enabled: bool,
+        filters: Vec<(String, bool)>,
+    },
+
+    /// Undo clearing all filters.
+    RestoreAll(FilterMap<T>),
+}
+
 impl<T> FilterState<T>
 {
     pub fn len_filtered(&mut self, items: &[T]) -> usize
@@ -152,41 +195,189 @@ impl<T> FilterState<T>
 
     pub fn add_filter(&mut self, filter: Box<dyn ItemFilter<T>>) -> (FilterType, String)
     {
-        let pair = (filter.filter_type(), filter.key().to_string());
-        self.filters
-            .add(filter.filter_type(), filter.key().to_string(), filter);
+        let (filter_type, key) = (filter.filter_type(), filter.key().to_string());
+        let group_enabled = self.group_enabled(filter_type);
+        let undo = match self.filters.add(filter_type, key.clone(), filter) {
+            Some(previous) => FilterUndo::Restore {
+                filter_type,
+                key: key.clone(),
+                filter: previous,
+                group_enabled,
+            },
+            None => FilterUndo::Remove {
+                filter_type,
+                key: key.clone(),
+            },
+        };
+        self.push_undo(undo);
         self.refilter();
-        pair
+        (filter_type, key)
     }
 
     pub fn remove_filter(&mut self, filter_type: FilterType, key: &str)
     {
-        self.filters.remove_filter(filter_type, key);
+        let group_enabled = self.group_enabled(filter_type);
+        if let Some(filter) = self.filters.remove_filter(filter_type, key) {
+            self.push_undo(FilterUndo::Restore {
+                filter_type,
+                key: key.to_string(),
+                filter,
+                group_enabled,
+            });
+        }
         self.refilter();
     }
 
     pub fn remove_filter_group(&mut self, filter_type: FilterType)
     {
-        self.filters.remove_group(filter_type);
+        if let Some(group) = self.filters.remove_group(filter_type) {
+            self.push_undo(FilterUndo::RestoreGroup(group));
+        }
         self.refilter();
     }
 
     pub fn toggle_filter(&mut self, filter_type: FilterType, key: &str)
     {
+        if self
+            .filters
+            .get(filter_type)
+            .and_then(|g| g.get(key))
+            .is_none()
+        {
+            return;
+        }
+        self.push_enabled_undo(filter_type);
         self.filters.toggle_filter(filter_type, key);
         self.refilter();
     }
 
     pub fn toggle_filter_group(&mut self, filter_type: FilterType)
     {
+        self.push_enabled_undo(filter_type);
         self.filters.toggle_group(filter_type);
         self.refilter();
     }
 
     pub fn clear_filters(&mut self)
     {
-        self.filters.clear();
+        if self.filters.map.is_empty() {
+            return;
+        }
+        let previous = std::mem::take(&mut self.filters);
+        self.push_undo(FilterUndo::RestoreAll(previous));
+        self.refilter();
+    }
+
+    /// Reverts the latest filter change.
+    ///
+    /// Returns `false` if there was nothing to undo.
+    pub fn undo(&mut self) -> bool
+    {
+        let undo = match self.undo_stack.pop_back() {
+            Some(u) => u,
+            None => return false,
+        };
+
+        match undo {
+            FilterUndo::Remove { filter_type, key } => {
+                self.filters.remove_filter(filter_type, &key);
+            }
+            FilterUndo::Restore {
+                filter_type,
+                key,
+                filter,
+                group_enabled,
+            } => {
+                self.filters.insert(filter_type, key, filter);
+                if let Some(group) = self.filters.map.get_mut(&filter_type) {
+                    group.enabled = group_enabled;
+                }
+            }
+            FilterUndo::RestoreGroup(group) => {
+                self.filters.map.insert(group.filter_type, group);
+            }
+            FilterUndo::RestoreEnabled {
+                filter_type,
+                group_enabled,
+                filters,
+            } => {
+                if let Some(group) = self.filters.map.get_mut(&filter_type) {
+                    group.enabled = group_enabled;
+                    for (key, enabled) in filters {
+                        if let Some(f) = group.filters.get_mut(&key) {
+                            f.enabled = enabled;
+                        }
+                    }
+                }
+            }
+            FilterUndo::RestoreAll(filters) => self.filters = filters,
+        }
+
         self.refilter();
+        true
+    }
+
+    /// Whether the filter group is enabled. Groups are created enabled so missing groups are
+    /// reported as enabled.
+    fn group_enabled(&self, filter_type: FilterType) -> bool
+    {
+        self.filters
+            .get(filter_type)
+            .map(|g| g.enabled)
+            .unwrap_or(true)
+    }
+
+    fn push_enabled_undo(&mut self, filter_type: FilterType)
+    {
+        let group = match self.filters.get(filter_type) {
+            Some(g) => g,
+            None => return,
+        };
+
+        let undo = FilterUndo::RestoreEnabled {
+            filter_type,
+            group_enabled: group.enabled,
+            filters: group
+                .filters
+                .iter()
+                .map(|(key, f)| (key.clone(), f.enabled))
+                .collect(),
+        };
+        self.push_undo(undo);
+    }
+
+    fn push_undo(&mut self, undo: FilterUndo<T>)
+    {
+        if self.undo_stack.len() >= UNDO_DEPTH {
+            self.undo_stack.pop_front();
+        }
+        self.undo_stack.push_back(undo);
+    }
+
+    /// Index of the item in the content for the given index in the filtered items.
+    pub fn item_index(&self, idx: usize) -> Option<usize>
+    {
+        match self.use_filter {
+            true => self.filtered_items.get(idx).copied(),
+            false => Some(idx),
+        }
+    }
+
+    /// Update the filtered indices after the items at the given indices were removed.
+    pub fn remove_items(&mut self, removed: &[usize])
+    {
+        self.filtered_items = self
+            .filtered_items
+            .iter()
+            .filter_map(|idx| match removed.binary_search(idx) {
+                Ok(_) => None,
+                Err(shift) => Some(idx - shift),
+            })
+            .collect();
+        self.filtered_items_set = self.filtered_items.iter().copied().collect();
+        self.last_count -= match removed.binary_search(&self.last_count) {
+            Ok(shift) | Err(shift) => shift,
+        };
     }
 
     pub fn toggle(&mut self)
@@ -215,6 +406,67 @@ impl<T> FilterState<T>
     }
 }
 
+impl FilterState<EncodedRequest>
+{
+    pub fn save(&self) -> SavedFilters
+    {
+        SavedFilters {
+            groups: self
+                .filters
+                .iter()
+                .map(|group| SavedFilterGroup {
+                    enabled: group.enabled,
+                    filters: group
+                        .iter()
+                        .map(|f| SavedFilter {
+                            enabled: f.enabled,
+                            filter: f.filter.definition(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Replaces the current filters with the saved ones.
+    ///
+    /// Filters that can't be recreated are skipped. Returns the reasons for skipping them.
+    pub fn load(&mut self, saved: SavedFilters, ctx: &UiContext) -> Vec<String>
+    {
+        let mut filters = FilterMap::default();
+        let mut skipped = vec![];
+        for group in saved.groups {
+            for saved_filter in group.filters {
+                let filter = match saved_filter.filter.into_filter(ctx) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        skipped.push(e);
+                        continue;
+                    }
+                };
+
+                let (filter_type, key) = (filter.filter_type(), filter.key().to_string());
+                filters.insert(
+                    filter_type,
+                    key,
+                    SingleFilterState {
+                        enabled: saved_filter.enabled,
+                        filter,
+                    },
+                );
+                if let Some(g) = filters.map.get_mut(&filter_type) {
+                    g.enabled = group.enabled;
+                }
+            }
+        }
+
+        let previous = std::mem::replace(&mut self.filters, filters);
+        self.push_undo(FilterUndo::RestoreAll(previous));
+        self.refilter();
+        skipped
+    }
+}
+
 impl<T> Default for FilterState<T>
 {
     fn default() -> Self
@@ -225,6 +477,7 @@ impl<T> Default for FilterState<T>
             filtered_items: Default::default(),
             filtered_items_set: Default::default(),
             filters: Default::default(),
+            undo_stack: Default::default(),
             /*
             highlighted_items: Default::default(),
             highlights: Default::default(),
@@ -274,6 +527,99 @@ pub trait ItemFilter<T>
     fn key(&self) -> Cow<str>;
     fn filter(&self, item: &T) -> bool;
     fn to_string(&self, ctx: &UiContext) -> String;
+    fn definition(&self) -> FilterDefinition;
+}
+
+/// Serializable description of a filter used when saving the filters to a file.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterDefinition
+{
+    Connection
+    {
+        connection: Uuid
+    },
+    Path
+    {
+        path: String
+    },
+    Method
+    {
+        method: String
+    },
+    Header
+    {
+        name: String, value: Option<String>
+    },
+    Search
+    {
+        pattern: String,
+        #[serde(default)]
+        options: SearchOptions,
+    },
+    Status
+    {
+        status: Status
+    },
+    ResponseCode
+    {
+        class: u16
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedFilters
+{
+    pub groups: Vec<SavedFilterGroup>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedFilterGroup
+{
+    pub enabled: bool,
+    pub filters: Vec<SavedFilter>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedFilter
+{
+    pub enabled: bool,
+
+    #[serde(flatten)]
+    pub filter: FilterDefinition,
+}
+
+impl FilterDefinition
+{
+    /// Recreate the filter. Fails if the filter doesn't apply to the current session.
+    pub fn into_filter(self, ctx: &UiContext)
+        -> Result<Box<dyn ItemFilter<EncodedRequest>>, String>
+    {
+        Ok(match self {
+            FilterDefinition::Connection { connection } => {
+                if ctx.data.connections.get_by_uuid(connection).is_none() {
+                    return Err(format!("Connection {} is not in the session", connection));
+                }
+                Box::new(ConnectionFilter { connection })
+            }
+            FilterDefinition::Path { path } => Box::new(PathFilter { path }),
+            FilterDefinition::Method { method } => Box::new(MethodFilter {
+                method: http::Method::from_bytes(method.as_bytes())
+                    .map_err(|e| format!("Invalid method '{}': {}", method, e))?,
+            }),
+            FilterDefinition::Header { name, value } => Box::new(HeaderFilter {
+                name: http::header::HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|e| format!("Invalid header name '{}': {}", name, e))?,
+                value,
+            }),
+            FilterDefinition::Search { pattern, options } => Box::new(
+                SearchFilter::new(&pattern, options, ctx.runtime.search_index.clone())
+                    .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?,
+            ),
+            FilterDefinition::Status { status } => Box::new(StatusFilter { status }),
+            FilterDefinition::ResponseCode { class } => Box::new(ResponseCodeFilter { class }),
+        })
+    }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
@@ -281,8 +627,11 @@ pub enum FilterType
 {
     Connection,
     Path,
+    Method,
+    Header,
     Search,
     Status,
+    ResponseCode,
 }
 
 impl FilterType
@@ -292,8 +641,11 @@ impl FilterType
         match self {
             FilterType::Connection => "Connection",
             FilterType::Path => "Path",
+            FilterType::Method => "Method",
+            FilterType::Header => "Header",
             FilterType::Search => "Text",
             FilterType::Status => "Status",
+            FilterType::ResponseCode => "Response code",
         }
     }
 }
@@ -306,12 +658,50 @@ impl std::fmt::Display for FilterType
     }
 }
 
+/// Filter by the text in the search index.
+///
+/// Patterns prefixed with `re:` are treated as regular expressions.
 pub struct SearchFilter
 {
     pub pattern: String,
+    pub options: SearchOptions,
+    pub compiled: Pattern,
     pub index: Rc<RefCell<SearchIndex>>,
 }
 
+impl SearchFilter
+{
+    pub fn new(
+        pattern: &str,
+        options: SearchOptions,
+        index: Rc<RefCell<SearchIndex>>,
+    ) -> Result<Self, regex::Error>
+    {
+        Ok(Self {
+            pattern: pattern.to_string(),
+            compiled: Pattern::new(pattern, &options)?,
+            options,
+            index,
+        })
+    }
+
+    /// The pattern with the options that differ from the defaults.
+    fn label(&self) -> String
+    {
+        let mut flags = vec![];
+        if !self.options.ignore_case {
+            flags.push("match case");
+        }
+        if self.options.headers {
+            flags.push("headers");
+        }
+        match flags.is_empty() {
+            true => self.pattern.clone(),
+            false => format!("{} [{}]", self.pattern, flags.join(", ")),
+        }
+    }
+}
+
 impl ItemFilter<EncodedRequest> for SearchFilter
 {
     fn filter_type(&self) -> FilterType
@@ -321,19 +711,27 @@ impl ItemFilter<EncodedRequest> for SearchFilter
 
     fn key(&self) -> Cow<str>
     {
-        Cow::from(&self.pattern)
+        Cow::from(self.label())
     }
 
     fn filter(&self, item: &EncodedRequest) -> bool
     {
         self.index
             .borrow()
-            .is_match(item.request_data.uuid, &self.pattern)
+            .is_match(item.request_data.uuid, &self.compiled, &self.options)
     }
 
     fn to_string(&self, _ctx: &UiContext) -> String
     {
-        self.pattern.clone()
+        self.label()
+    }
+
+    fn definition(&self) -> FilterDefinition
+    {
+        FilterDefinition::Search {
+            pattern: self.pattern.clone(),
+            options: self.options,
+        }
     }
 }
 
@@ -363,7 +761,14 @@ impl ItemFilter<EncodedRequest> for ConnectionFilter
     {
         match ctx.data.connections.get_by_uuid(self.connection) {
             None => format!("Unknown connection ({:?})", self.connection),
-            Some(conn) => format!("{}", conn.client_addr),
+            Some(conn) => conn.display_name(),
+        }
+    }
+
+    fn definition(&self) -> FilterDefinition
+    {
+        FilterDefinition::Connection {
+            connection: self.connection,
         }
     }
 }
@@ -394,6 +799,94 @@ impl ItemFilter<EncodedRequest> for PathFilter
     {
         self.path.clone()
     }
+
+    fn definition(&self) -> FilterDefinition
+    {
+        FilterDefinition::Path {
+            path: self.path.clone(),
+        }
+    }
+}
+
+pub struct MethodFilter
+{
+    pub method: http::Method,
+}
+
+impl ItemFilter<EncodedRequest> for MethodFilter
+{
+    fn filter_type(&self) -> FilterType
+    {
+        FilterType::Method
+    }
+
+    fn key(&self) -> Cow<'_, str>
+    {
+        Cow::from(self.method.as_str())
+    }
+
+    fn filter(&self, item: &EncodedRequest) -> bool
+    {
+        item.request_data.method == self.method
+    }
+
+    fn to_string(&self, _ctx: &UiContext) -> String
+    {
+        self.method.to_string()
+    }
+
+    fn definition(&self) -> FilterDefinition
+    {
+        FilterDefinition::Method {
+            method: self.method.to_string(),
+        }
+    }
+}
+
+/// Filter by a request or response header. A `None` value matches any value of the header.
+pub struct HeaderFilter
+{
+    pub name: http::header::HeaderName,
+    pub value: Option<String>,
+}
+
+impl ItemFilter<EncodedRequest> for HeaderFilter
+{
+    fn filter_type(&self) -> FilterType
+    {
+        FilterType::Header
+    }
+
+    fn key(&self) -> Cow<'_, str>
+    {
+        format!("{}: {}", self.name, self.value.as_deref().unwrap_or("*")).into()
+    }
+
+    fn filter(&self, item: &EncodedRequest) -> bool
+    {
+        item.request_msg
+            .headers
+            .get_all(&self.name)
+            .iter()
+            .chain(item.response_msg.headers.get_all(&self.name))
+            .any(|v| match &self.value {
+                None => true,
+                Some(value) => v.as_bytes() == value.as_bytes(),
+            })
+    }
+
+    fn to_string(&self, _ctx: &UiContext) -> String
+    {
+        self.key().into_owned()
+    }
+
+    fn definition(&self) -> FilterDefinition
+    {
+        FilterDefinition::Header {
+            name: self.name.to_string(),
+            value: self.value.clone(),
+        }
+    }
 }
 
 pub struct StatusFilter
@@ -422,6 +915,47 @@ impl ItemFilter<EncodedRequest> for StatusFilter
     {
         self.status.to_string()
     }
+
+    fn definition(&self) -> FilterDefinition
+    {
+        FilterDefinition::Status {
+            status: self.status,
+        }
+    }
+}
+
+/// Filter by the class of the HTTP response status code, such as 4xx or 5xx.
+pub struct ResponseCodeFilter
+{
+    pub class: u16,
+}
+
+impl ItemFilter<EncodedRequest> for ResponseCodeFilter
+{
+    fn filter_type(&self) -> FilterType
+    {
+        FilterType::ResponseCode
+    }
+
+    fn key(&self) -> Cow<'_, str>
+    {
+        format!("{}xx", self.class).into()
+    }
+
+    fn filter(&self, item: &EncodedRequest) -> bool
+    {
+        item.request_data.response_status.map(|s| s / 100) == Some(self.class)
+    }
+
+    fn to_string(&self, _ctx: &UiContext) -> String
+    {
+        format!("{}xx", self.class)
+    }
+
+    fn definition(&self) -> FilterDefinition
+    {
+        FilterDefinition::ResponseCode { class: self.class }
+    }
 }
 
 impl<T> FilterMap<T>
@@ -431,7 +965,12 @@ impl<T> FilterMap<T>
         self.map.values().next()
     }
 
-    pub fn add(&mut self, ft: FilterType, key: String, filter: Box<dyn ItemFilter<T>>)
+    pub fn add(
+        &mut self,
+        ft: FilterType,
+        key: String,
+        filter: Box<dyn ItemFilter<T>>,
+    ) -> Option<SingleFilterState<T>>
     {
         self.map
             .entry(ft)
@@ -443,12 +982,16 @@ impl<T> FilterMap<T>
                     enabled: true,
                     filter,
                 },
-            );
+            )
     }
 
-    pub fn clear(&mut self)
+    fn insert(&mut self, ft: FilterType, key: String, filter: SingleFilterState<T>)
     {
-        self.map.clear();
+        self.map
+            .entry(ft)
+            .or_insert_with(|| FilterGroupState::new(ft))
+            .filters
+            .insert(key, filter);
     }
 
     pub fn get(&self, ft: FilterType) -> Option<&FilterGroupState<T>>
@@ -476,22 +1019,20 @@ impl<T> FilterMap<T>
         self.map.values()
     }
 
-    pub fn remove_filter(&mut self, ft: FilterType, key: &str)
+    pub fn remove_filter(&mut self, ft: FilterType, key: &str) -> Option<SingleFilterState<T>>
     {
-        let group = match self.map.get_mut(&ft) {
-            None => return,
-            Some(g) => g,
-        };
+        let group = self.map.get_mut(&ft)?;
 
-        group.filters.remove(key);
+        let removed = group.filters.remove(key);
         if group.filters.is_empty() {
             self.map.remove(&ft);
         }
+        removed
     }
 
-    pub fn remove_group(&mut self, ft: FilterType)
+    pub fn remove_group(&mut self, ft: FilterType) -> Option<FilterGroupState<T>>
     {
-        self.map.remove(&ft);
+        self.map.remove(&ft)
     }
 
     pub fn toggle_filter(&mut self, ft: FilterType, key: &str)
@@ -592,3 +1133,161 @@ impl<T> SingleFilterState<T>
         self.filter.filter(t)
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    /// Filter that accepts the numbers divisible by the divisor.
+    struct DivisibleFilter
+    {
+        filter_type: FilterType,
+        divisor: u32,
+    }
+
+    impl ItemFilter<u32> for DivisibleFilter
+    {
+        fn filter_type(&self) -> FilterType
+        {
+            self.filter_type
+        }
+
+        fn key(&self) -> Cow<str>
+        {
+            Cow::Owned(self.divisor.to_string())
+        }
+
+        fn filter(&self, item: &u32) -> bool
+        {
+            item.rem_euclid(self.divisor) == 0
+        }
+
+        fn to_string(&self, _ctx: &UiContext) -> String
+        {
+            format!("Divisible by {}", self.divisor)
+        }
+
+        fn definition(&self) -> FilterDefinition
+        {
+            unimplemented!("Test filters are not saved")
+        }
+    }
+
+    fn divisible(filter_type: FilterType, divisor: u32) -> Box<dyn ItemFilter<u32>>
+    {
+        Box::new(DivisibleFilter {
+            filter_type,
+            divisor,
+        })
+    }
+
+    fn state() -> FilterState<u32>
+    {
+        FilterState {
+            use_filter: true,
+            ..Default::default()
+        }
+    }
+
+    const ITEMS: &[u32] = &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+
+    #[test]
+    fn undo_reverts_changes_in_order()
+    {
+        let mut state = state();
+        state.add_filter(divisible(FilterType::Path, 2));
+        assert_eq!(state.len_filtered(ITEMS), 6);
+        state.add_filter(divisible(FilterType::Method, 3));
+        assert_eq!(state.len_filtered(ITEMS), 2);
+        state.remove_filter(FilterType::Path, "2");
+        assert_eq!(state.len_filtered(ITEMS), 4);
+        state.clear_filters();
+        assert_eq!(state.len_filtered(ITEMS), 12);
+
+        assert!(state.undo());
+        assert_eq!(state.len_filtered(ITEMS), 4);
+        assert!(state.undo());
+        assert_eq!(state.len_filtered(ITEMS), 2);
+        assert!(state.undo());
+        assert_eq!(state.len_filtered(ITEMS), 6);
+        assert!(state.undo());
+        assert_eq!(state.len_filtered(ITEMS), 12);
+        assert!(!state.undo());
+    }
+
+    #[test]
+    fn unchanged_filters_are_not_pushed()
+    {
+        let mut state = state();
+        state.clear_filters();
+        state.remove_filter(FilterType::Path, "2");
+        state.remove_filter_group(FilterType::Path);
+        state.toggle_filter(FilterType::Path, "2");
+        state.toggle_filter_group(FilterType::Path);
+        assert!(!state.undo());
+
+        state.add_filter(divisible(FilterType::Path, 2));
+        state.toggle_filter(FilterType::Path, "3");
+        assert!(state.undo());
+        assert!(!state.undo());
+    }
+
+    #[test]
+    fn undo_depth_is_bounded()
+    {
+        let mut state = state();
+        for divisor in 1..=(UNDO_DEPTH as u32 + 10) {
+            state.add_filter(divisible(FilterType::Path, divisor));
+        }
+
+        let mut undone = 0;
+        while state.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, UNDO_DEPTH);
+
+        // The oldest changes can't be undone anymore.
+        assert_eq!(
+            state.filters.get(FilterType::Path).unwrap().iter().count(),
+            10
+        );
+    }
+
+    #[test]
+    fn undo_restores_group_enabled()
+    {
+        // The filters within a group accept any item matching one of them.
+        let mut state = state();
+        state.add_filter(divisible(FilterType::Path, 2));
+        state.add_filter(divisible(FilterType::Path, 3));
+        state.toggle_filter_group(FilterType::Path);
+        assert_eq!(state.len_filtered(ITEMS), 12);
+
+        // Removing the whole group and restoring it keeps it disabled.
+        state.remove_filter_group(FilterType::Path);
+        assert!(state.undo());
+        assert!(!state.filters.get(FilterType::Path).unwrap().enabled);
+        assert_eq!(state.len_filtered(ITEMS), 12);
+
+        // The same goes for a group that was removed along with its last filter.
+        state.remove_filter(FilterType::Path, "2");
+        state.remove_filter(FilterType::Path, "3");
+        assert!(state.filters.get(FilterType::Path).is_none());
+        assert!(state.undo());
+        assert!(!state.filters.get(FilterType::Path).unwrap().enabled);
+        assert!(state.undo());
+        assert_eq!(
+            state.filters.get(FilterType::Path).unwrap().iter().count(),
+            2
+        );
+        assert_eq!(state.len_filtered(ITEMS), 12);
+
+        // Undoing the toggle enables the group and its filters again.
+        assert!(state.undo());
+        let group = state.filters.get(FilterType::Path).unwrap();
+        assert!(group.enabled);
+        assert!(group.iter().all(|f| f.enabled));
+        assert_eq!(state.len_filtered(ITEMS), 8);
+    }
+}
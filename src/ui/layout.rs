@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::ui::toast;
+
+/// Layout chosen by the user in the main view. Restored on the next launch.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedLayout
+{
+    /// Width of the request list.
+    pub requests_width: Option<u16>,
+
+    /// Widths of the request list columns by the column title.
+    pub column_widths: BTreeMap<String, u16>,
+}
+
+impl SavedLayout
+{
+    /// Read the layout from the config dir. Falls back to the default layout if the file is
+    /// missing or invalid.
+    pub fn load() -> Self
+    {
+        let path = match layout_file() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(_) => return Self::default(),
+        };
+        serde_json::from_slice(&data).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid layout file '{}': {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self)
+    {
+        let path = match layout_file() {
+            Some(path) => path,
+            None => return toast::show_error("Could not resolve the config directory"),
+        };
+        let result = serde_json::to_vec_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                std::fs::write(&path, json).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            toast::show_error(format!("Could not write file '{}'\n{}", path.display(), e));
+        }
+    }
+}
+
+fn layout_file() -> Option<PathBuf>
+{
+    Some(config_dir()?.join("proxide").join("layout.json"))
+}
+
+#[cfg(windows)]
+fn config_dir() -> Option<PathBuf>
+{
+    std::env::var_os("APPDATA").map(PathBuf::from)
+}
+
+#[cfg(target_os = "macos")]
+fn config_dir() -> Option<PathBuf>
+{
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn config_dir() -> Option<PathBuf>
+{
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
@@ -35,3 +35,21 @@ pub fn format_duration(d: Duration) -> String
         t => format!("{} ms", t.num_milliseconds()),
     }
 }
+
+/// Format the rate the given number of bytes were transferred at.
+pub fn format_throughput(bytes: usize, d: Duration) -> String
+{
+    match d.num_milliseconds() {
+        ms if ms > 0 => format!("{:.1} kB/s", bytes as f64 / ms as f64),
+        _ => "-".to_string(),
+    }
+}
+
+pub fn format_size(size: usize) -> String
+{
+    match size {
+        s if s < 1024 => format!("{} B", s),
+        s if s < 1024 * 1024 => format!("{:.1} KB", s as f64 / 1024.0),
+        s => format!("{:.1} MB", s as f64 / (1024.0 * 1024.0)),
+    }
+}
@@ -1,7 +1,9 @@
 use crossterm::event::{Event as CTEvent, KeyCode};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
 use tui::backend::Backend;
 use tui::buffer::Buffer;
 use tui::layout::Rect;
@@ -15,10 +17,17 @@ use super::toast::ToastEvent;
 use crate::decoders::Decoders;
 use crate::search;
 use crate::session::events::SessionEvent;
+use crate::session::serialization::CaptureStatus;
 use crate::session::*;
 use crate::ui::commands;
+use crate::ui::filters::ItemFilter;
+use crate::ui::layout;
+use crate::ui::prelude::format_size;
 use crate::ui::views::{self, View};
 
+/// Time over which the request rate in the status line is averaged.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
 pub enum UiEvent
 {
     Redraw,
@@ -46,7 +55,29 @@ pub struct Runtime
 {
     pub decoders: Decoders,
     pub search_index: Rc<RefCell<search::SearchIndex>>,
+
+    /// Options of the last search, used as the defaults of the next one.
+    pub search_options: search::SearchOptions,
     pub tx: Sender<UiEvent>,
+
+    /// The default server for the `:replay` command.
+    pub replay_target: Option<String>,
+
+    /// Largest number of requests kept in the session. The oldest requests are dropped first.
+    pub max_requests: Option<usize>,
+
+    /// Receives the session events while a `:capture` is active.
+    pub capture: Option<Sender<SessionEvent>>,
+
+    /// Traffic counters shown in the status line.
+    pub status: CaptureStatus,
+
+    /// Arrival times of the requests within the `RATE_WINDOW`.
+    pub request_times: VecDeque<Instant>,
+
+    /// Whether there are requests that haven't been saved since the last `:save`. Quitting asks
+    /// for confirmation in that case.
+    pub unsaved_requests: Cell<bool>,
 }
 
 pub struct UiContext
@@ -62,12 +93,32 @@ pub enum HandleResult<B: Backend>
     Quit,
     PushView(Box<dyn View<B>>),
     ExitView,
+    SelectRequest(Uuid),
+    SelectConnection(Uuid),
+    AddFilter(Box<dyn ItemFilter<EncodedRequest>>),
+    SaveFilters(String),
+    LoadFilters(String),
+    Find(String),
     ExitCommand(Option<Box<HandleResult<B>>>),
+
+    /// Suspend the UI to run an external program, such as `$EDITOR`, on the file.
+    OpenExternal
+    {
+        command: String,
+        file: std::path::PathBuf,
+    },
 }
 
 impl<B: Backend> ProxideUi<B>
 {
-    pub fn new(session: Session, tx: Sender<UiEvent>, decoders: Decoders, size: Rect) -> Self
+    pub fn new(
+        session: Session,
+        tx: Sender<UiEvent>,
+        decoders: Decoders,
+        replay_target: Option<String>,
+        max_requests: Option<usize>,
+        size: Rect,
+    ) -> Self
     {
         Self {
             context: UiContext {
@@ -75,13 +126,22 @@ impl<B: Backend> ProxideUi<B>
                     search_index: Rc::new(RefCell::new(search::SearchIndex::new(
                         &session, &decoders,
                     ))),
+                    search_options: Default::default(),
                     decoders,
                     tx,
+                    replay_target,
+                    max_requests,
+                    capture: None,
+                    status: CaptureStatus::from_session(&session),
+                    request_times: VecDeque::new(),
+                    unsaved_requests: Cell::new(false),
                 },
                 data: session,
                 size,
             },
-            ui_stack: vec![Box::<views::MainView>::default()],
+            ui_stack: vec![Box::new(
+                views::MainView::default().with_layout(&layout::SavedLayout::load()),
+            )],
             toasts: vec![],
             input_command: None,
         }
@@ -102,6 +162,19 @@ impl<B: Backend> ProxideUi<B>
                     _ => None,
                 };
 
+                self.context.runtime.status.update(&e);
+                if let SessionEvent::NewRequest(_) = &*e {
+                    self.context.runtime.request_times.push_back(Instant::now());
+                    self.context.runtime.unsaved_requests.set(true);
+                }
+
+                // A capture that failed to write has already reported the error.
+                if let Some(capture) = &self.context.runtime.capture {
+                    if capture.send((*e).clone()).is_err() {
+                        self.context.runtime.capture = None;
+                    }
+                }
+
                 if let Some(ixreq) = index_request {
                     self.context.runtime.search_index.borrow_mut().index(
                         &self.context.data,
@@ -110,14 +183,35 @@ impl<B: Backend> ProxideUi<B>
                     );
                 }
 
-                let mut results_iter = self.context.data.handle(*e).into_iter().map(|change| {
+                let changes = self.context.data.handle(*e);
+                let mut update = changes.into_iter().any(|change| {
                     self.ui_stack
                         .last_mut()
                         .unwrap()
                         .on_change(&self.context, &change)
                 });
 
-                match results_iter.any(|b| b) {
+                if let Some(max) = self.context.runtime.max_requests {
+                    let removed = self.context.data.evict_requests(max);
+                    if !removed.is_empty() {
+                        self.context
+                            .runtime
+                            .search_index
+                            .borrow_mut()
+                            .prune(&self.context.data);
+                        update = true;
+                    }
+
+                    // The views keep track of the items by their indices so all of them need to
+                    // know about the removal, not just the visible one.
+                    for change in &removed {
+                        for view in &mut self.ui_stack {
+                            view.on_change(&self.context, change);
+                        }
+                    }
+                }
+
+                match update {
                     true => Some(HandleResult::Update),
                     false => None,
                 }
@@ -140,13 +234,47 @@ impl<B: Backend> ProxideUi<B>
     fn handle_result(&mut self, result: HandleResult<B>) -> Option<HandleResult<B>>
     {
         match result {
-            r @ HandleResult::Update | r @ HandleResult::Quit => return Some(r),
+            r @ HandleResult::Update
+            | r @ HandleResult::Quit
+            | r @ HandleResult::OpenExternal { .. } => return Some(r),
             HandleResult::PushView(v) => {
                 self.ui_stack.push(v);
             }
             HandleResult::ExitView => {
                 self.ui_stack.pop();
             }
+            HandleResult::SelectRequest(request) => {
+                self.ui_stack.pop();
+                if let Some(view) = self.ui_stack.last_mut() {
+                    view.select_request(&self.context, request);
+                }
+            }
+            HandleResult::SelectConnection(connection) => {
+                self.ui_stack.pop();
+                if let Some(view) = self.ui_stack.last_mut() {
+                    view.select_connection(&self.context, connection);
+                }
+            }
+            HandleResult::AddFilter(filter) => {
+                if let Some(view) = self.ui_stack.last_mut() {
+                    view.add_filter(&self.context, filter);
+                }
+            }
+            HandleResult::SaveFilters(file) => {
+                if let Some(view) = self.ui_stack.last_mut() {
+                    view.save_filters(&self.context, &file);
+                }
+            }
+            HandleResult::LoadFilters(file) => {
+                if let Some(view) = self.ui_stack.last_mut() {
+                    view.load_filters(&self.context, &file);
+                }
+            }
+            HandleResult::Find(pattern) => {
+                if let Some(view) = self.ui_stack.last_mut() {
+                    view.find(&self.context, &pattern);
+                }
+            }
             HandleResult::ExitCommand(cmd) => {
                 self.input_command = None;
                 return cmd.and_then(|r| self.handle_result(*r));
@@ -183,29 +311,60 @@ impl<B: Backend> ProxideUi<B>
             }
             CTEvent::Key(key) => match key.code {
                 KeyCode::Char(':') => {
+                    let context = &self.context;
+                    let selected_request = self
+                        .ui_stack
+                        .iter_mut()
+                        .rev()
+                        .find_map(|v| v.selected_request(context));
                     self.input_command = Some(commands::CommandState {
                         help: "Enter command".to_string(),
                         prompt: ":".to_string(),
                         input: Default::default(),
                         text_cursor: 0,
                         display_cursor: 0,
-                        executable: Box::new(commands::ColonCommand),
+                        executable: Box::new(commands::ColonCommand { selected_request }),
+                    });
+                    HandleResult::Update
+                }
+                KeyCode::Char('/') if self.ui_stack.last().unwrap().can_find() => {
+                    self.input_command = Some(commands::CommandState {
+                        help: "Find in the message, leave empty to clear".to_string(),
+                        prompt: "/".to_string(),
+                        input: Default::default(),
+                        text_cursor: 0,
+                        display_cursor: 0,
+                        executable: Box::new(commands::FindCommand),
                     });
                     HandleResult::Update
                 }
-                /*
                 KeyCode::Char('/') => {
+                    let command = commands::SearchCommand {
+                        options: self.context.runtime.search_options,
+                    };
                     self.input_command = Some(commands::CommandState {
-                        help: "Search".to_string(),
+                        help: command.help(),
                         prompt: "/".to_string(),
                         input: Default::default(),
                         text_cursor: 0,
                         display_cursor: 0,
-                        executable: Box::new(commands::SearchCommand),
+                        executable: Box::new(command),
                     });
                     HandleResult::Update
                 }
-                */
+                KeyCode::Char('?') => {
+                    let bindings = self.ui_stack.last().unwrap().key_bindings();
+                    self.ui_stack.push(Box::new(views::HelpView::new(bindings)));
+                    HandleResult::Update
+                }
+                // Requests that are being captured to a file are safe already.
+                KeyCode::Char('Q')
+                    if self.context.runtime.unsaved_requests.get()
+                        && self.context.runtime.capture.is_none() =>
+                {
+                    self.ui_stack.push(Box::new(views::QuitView));
+                    HandleResult::Update
+                }
                 KeyCode::Char('Q') => HandleResult::Quit,
                 KeyCode::Esc => {
                     if self.ui_stack.len() > 1 {
@@ -261,17 +420,28 @@ impl<B: Backend> ProxideUi<B>
             view.help_text(&self.context, self.context.size)
         };
 
+        // The traffic status is kept on the right side of the first line with the help text
+        // wrapping around it on the left.
+        let status = self.status_text();
+        let status_width = (status.len() as u16).min(chunk.width.saturating_sub(2));
         let text_chunk = Rect {
             x: 1,
             y: chunk.height - 2,
-            width: chunk.width - 2,
+            width: chunk.width - 2 - status_width,
             height: 2,
         };
+        let status_chunk = Rect {
+            x: chunk.width - 1 - status_width,
+            y: chunk.height - 2,
+            width: status_width,
+            height: 1,
+        };
 
         f.render_widget(
             Paragraph::new(Text::raw(&help_text)).wrap(Wrap { trim: false }),
             text_chunk,
         );
+        f.render_widget(TextLine(&status), status_chunk);
 
         // Draw toasts on top of everything.
         let mut offset = 1;
@@ -281,6 +451,30 @@ impl<B: Backend> ProxideUi<B>
     }
 }
 
+impl<B: Backend> ProxideUi<B>
+{
+    /// Requests, active requests, data and the request rate over the last `RATE_WINDOW`.
+    fn status_text(&mut self) -> String
+    {
+        let runtime = &mut self.context.runtime;
+        while let Some(time) = runtime.request_times.front() {
+            match time.elapsed() > RATE_WINDOW {
+                true => runtime.request_times.pop_front(),
+                false => break,
+            };
+        }
+        let rate = runtime.request_times.len() as f64 / RATE_WINDOW.as_secs_f64();
+
+        format!(
+            " {} req ({} active) | {} | {:.1} req/s ",
+            runtime.status.requests,
+            runtime.status.active_requests,
+            format_size(runtime.status.data),
+            rate
+        )
+    }
+}
+
 impl Toast
 {
     fn draw<B: Backend>(&self, offset: u16, f: &mut Frame<B>) -> u16
@@ -315,21 +509,6 @@ impl Toast
     }
 }
 
-pub struct ProxideTable<T>
-{
-    phantom: std::marker::PhantomData<T>,
-}
-
-impl<T> Default for ProxideTable<T>
-{
-    fn default() -> Self
-    {
-        Self {
-            phantom: std::marker::PhantomData::<T>,
-        }
-    }
-}
-
 impl std::fmt::Display for Status
 {
     fn fmt(&self, w: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error>
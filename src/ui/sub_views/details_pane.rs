@@ -1,15 +1,48 @@
+use http::header::{HeaderMap, HeaderName};
 use tui::layout::{Constraint, Direction, Layout};
+use tui::style::{Color, Style};
 use tui::text::{Span, Spans, Text};
 use tui::widgets::Paragraph;
 use uuid::Uuid;
 
 use crate::ui::prelude::*;
 
-use crate::session::{EncodedRequest, RequestPart};
+use crate::decoders::grpc;
+use crate::session::{EncodedRequest, MessageData, RequestPart};
+use crate::signing;
 use crate::ui::views::MessageView;
 
 #[derive(Clone, Default)]
-pub struct DetailsPane;
+pub struct DetailsPane
+{
+    /// Display the signing metadata of the request, such as AWS SigV4 or bearer token details.
+    show_signing: bool,
+
+    /// Display the request headers that changed since the previous request with the same method
+    /// and path.
+    pub show_header_changes: bool,
+}
+
+/// Difference in a request header between two requests.
+#[derive(Debug, PartialEq)]
+enum HeaderChange
+{
+    Added
+    {
+        name: String, value: String
+    },
+    Removed
+    {
+        name: String, value: String
+    },
+    Changed
+    {
+        name: String,
+        previous: String,
+        value: String,
+    },
+}
+
 impl DetailsPane
 {
     pub fn on_input<B: Backend>(
@@ -22,6 +55,10 @@ impl DetailsPane
             match key.code {
                 KeyCode::Char('q') => self.create_message_view(req, RequestPart::Request),
                 KeyCode::Char('e') => self.create_message_view(req, RequestPart::Response),
+                KeyCode::Char('a') => {
+                    self.show_signing = !self.show_signing;
+                    Some(HandleResult::Update)
+                }
                 _ => None,
             }
         } else {
@@ -52,10 +89,55 @@ impl DetailsPane
 
         let block = create_block("Details");
 
+        let signing = match self.show_signing {
+            true => Some(signing::annotate(&request.request_msg.headers)),
+            false => None,
+        };
+        let signing_lines = match &signing {
+            Some(details) => 2 + details.len().max(1) as u16,
+            None => 0,
+        };
+        let header_changes = match self.show_header_changes {
+            true => previous_similar(ctx, request).map(|previous| {
+                (
+                    previous.request_data.start_timestamp,
+                    header_changes(&previous.request_msg.headers, &request.request_msg.headers),
+                )
+            }),
+            false => None,
+        };
+        let header_change_lines = match &header_changes {
+            Some((_, changes)) => 2 + changes.len().max(1) as u16,
+            None => 0,
+        };
+        let query = request.request_data.query();
+        let query_lines = match query.is_empty() {
+            true => 0,
+            false => 2 + query.len() as u16,
+        };
+        let timeout = grpc::request_timeout(request);
+        let reason_lines = request.request_data.reason.is_some() as u16
+            + timeout.is_some() as u16
+            + conn.reason.is_some() as u16
+            + request.request_msg.error.is_some() as u16
+            + request.response_msg.error.is_some() as u16;
+
         let details_chunks = Layout::default()
             .direction(Direction::Vertical)
             .margin(0)
-            .constraints([Constraint::Length(6), Constraint::Percentage(50)].as_ref())
+            .constraints(
+                [
+                    Constraint::Length(
+                        7 + request.request_data.injected_fault as u16
+                            + reason_lines
+                            + query_lines
+                            + header_change_lines
+                            + signing_lines,
+                    ),
+                    Constraint::Percentage(50),
+                ]
+                .as_ref(),
+            )
             .split(block.inner(chunk));
         let mut c = details_chunks[1];
         c.x -= 1;
@@ -73,47 +155,127 @@ impl DetailsPane
             None => "(Pending)".to_string(),
             Some(end) => format_duration(end - request.request_data.start_timestamp),
         };
+        let status = match request.request_data.timed_out {
+            true => format!("{} (timed out)", request.request_data.status),
+            false => request.request_data.status.to_string(),
+        };
 
-        let spans = vec![
+        let mut spans = vec![
             Span::raw("\n"),
             Span::raw(format!(
                 " Request:    {} {}\n",
                 request.request_data.method, request.request_data.uri
             )),
-            Span::raw(format!(
-                " Protocol:   {}\n",
-                conn.protocol_stack
-                    .iter()
-                    .map(ToString::to_string)
-                    .collect::<Vec<_>>()
-                    .join(" -> ")
-            )),
+            Span::raw(format!(" Protocol:   {}\n", conn.protocol_description())),
             Span::raw(format!(
                 " Timestamp:  {}\n",
                 request.request_data.start_timestamp
             )),
+            Span::raw(match request.request_data.response_status {
+                Some(code) => format!(" Status:     {}, HTTP {} (in {})\n", status, code, duration),
+                None => format!(" Status:     {} (in {})\n", status, duration),
+            }),
             Span::raw(format!(
-                " Status:     {} (in {})\n",
-                request.request_data.status, duration
+                " Throughput: {} up, {} down\n",
+                throughput(&request.request_msg),
+                throughput(&request.response_msg)
             )),
         ];
+        if let Some(timeout) = timeout {
+            let deadline = match grpc::deadline_usage(request) {
+                Some(usage) if usage > 1.0 => format!(" (exceeded, {:.0}% used)", usage * 100.0),
+                Some(usage) => format!(" ({:.0}% used)", usage * 100.0),
+                None => String::new(),
+            };
+            spans.push(Span::raw(format!(
+                " Deadline:   {}{}\n",
+                format_duration(timeout),
+                deadline
+            )));
+        }
+        if request.request_data.injected_fault {
+            spans.push(Span::raw(" Fault:      Injected by Proxide\n"));
+        }
+        if let Some(reason) = request.request_data.reason {
+            spans.push(Span::raw(format!(" Cause:      {}\n", reason)));
+        }
+        for (label, msg) in &[
+            ("Request", &request.request_msg),
+            ("Response", &request.response_msg),
+        ] {
+            if let Some(error) = &msg.error {
+                spans.push(Span::raw(format!(" Error:      {}: {}\n", label, error)));
+            }
+        }
+        if let Some(reason) = conn.reason {
+            spans.push(Span::raw(format!(" Connection: Closed with {}\n", reason)));
+        }
+        if !query.is_empty() {
+            spans.push(Span::raw("\n"));
+            spans.push(Span::raw(" Query:\n"));
+            for (key, value) in &query {
+                spans.push(Span::raw(format!("   {} = {}\n", key, value)));
+            }
+        }
+        if let Some((timestamp, changes)) = header_changes {
+            spans.push(Span::raw("\n"));
+            spans.push(Span::raw(format!(
+                " Header changes since {}:\n",
+                timestamp.format("%H:%M:%S%.3f")
+            )));
+            if changes.is_empty() {
+                spans.push(Span::raw("   (No changes)\n"));
+            }
+            for change in changes {
+                spans.push(match change {
+                    HeaderChange::Added { name, value } => Span::styled(
+                        format!("   + {}: {}\n", name, value),
+                        Style::default().fg(Color::Green),
+                    ),
+                    HeaderChange::Removed { name, value } => Span::styled(
+                        format!("   - {}: {}\n", name, value),
+                        Style::default().fg(Color::Red),
+                    ),
+                    HeaderChange::Changed {
+                        name,
+                        previous,
+                        value,
+                    } => Span::styled(
+                        format!("   ~ {}: {} → {}\n", name, previous, value),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                });
+            }
+        }
+        if let Some(details) = signing {
+            spans.push(Span::raw("\n"));
+            spans.push(Span::raw(" Signing:\n"));
+            if details.is_empty() {
+                spans.push(Span::raw("   (No recognized signature)\n"));
+            }
+            for detail in details {
+                spans.push(Span::raw(format!(
+                    "   {:<16}{}\n",
+                    format!("{}:", detail.label),
+                    detail.value
+                )));
+            }
+        }
         let details = Paragraph::new(Text::from(
             spans.into_iter().map(Spans::from).collect::<Vec<_>>(),
         ));
         f.render_widget(details, details_chunks[0]);
 
-        MessageView {
-            request: request.request_data.uuid,
-            part: RequestPart::Request,
-            offset: 0,
-        }
-        .draw(ctx, f, req_resp_chunks[0]);
-        MessageView {
-            request: request.request_data.uuid,
-            part: RequestPart::Response,
-            offset: 0,
-        }
-        .draw(ctx, f, req_resp_chunks[1]);
+        MessageView::new(request.request_data.uuid, RequestPart::Request).draw(
+            ctx,
+            f,
+            req_resp_chunks[0],
+        );
+        MessageView::new(request.request_data.uuid, RequestPart::Response).draw(
+            ctx,
+            f,
+            req_resp_chunks[1],
+        );
     }
 
     fn create_message_view<B: Backend>(
@@ -122,10 +284,131 @@ impl DetailsPane
         part: RequestPart,
     ) -> Option<HandleResult<B>>
     {
-        Some(HandleResult::PushView(Box::new(MessageView {
-            request: req.request_data.uuid,
+        Some(HandleResult::PushView(Box::new(MessageView::new(
+            req.request_data.uuid,
             part,
-            offset: 0,
-        })))
+        ))))
+    }
+}
+
+/// The rate the message data was transferred at.
+fn throughput(msg: &MessageData) -> String
+{
+    match (msg.start_timestamp, msg.end_timestamp) {
+        (Some(start), Some(end)) => {
+            format_throughput(msg.content.len() + msg.truncated, end - start)
+        }
+        _ => "-".to_string(),
+    }
+}
+
+/// The latest request before the given one with the same method and path.
+fn previous_similar<'a>(ctx: &'a UiContext, request: &EncodedRequest)
+    -> Option<&'a EncodedRequest>
+{
+    let idx = ctx
+        .data
+        .requests
+        .get_index_by_uuid(request.request_data.uuid)?;
+    ctx.data.requests[..idx]
+        .iter()
+        .rev()
+        .find(|r| r.request_data.is_similar(&request.request_data))
+}
+
+/// Compare the headers of two requests. Headers with multiple values are compared as a whole.
+fn header_changes(previous: &HeaderMap, current: &HeaderMap) -> Vec<HeaderChange>
+{
+    let joined = |headers: &HeaderMap, name: &HeaderName| {
+        headers
+            .get_all(name)
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut changes = vec![];
+    for name in current.keys() {
+        let value = joined(current, name);
+        match previous.contains_key(name) {
+            false => changes.push(HeaderChange::Added {
+                name: name.to_string(),
+                value,
+            }),
+            true => {
+                let previous = joined(previous, name);
+                if previous != value {
+                    changes.push(HeaderChange::Changed {
+                        name: name.to_string(),
+                        previous,
+                        value,
+                    });
+                }
+            }
+        }
+    }
+    for name in previous.keys().filter(|name| !current.contains_key(*name)) {
+        changes.push(HeaderChange::Removed {
+            name: name.to_string(),
+            value: joined(previous, name),
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap
+    {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, http::HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    #[test]
+    fn header_changes_between_retries()
+    {
+        let previous = headers(&[
+            ("authorization", "Bearer a"),
+            ("x-request-id", "1"),
+            ("accept", "*/*"),
+            ("x-retry", "0"),
+        ]);
+        let current = headers(&[
+            ("authorization", "Bearer b"),
+            ("accept", "*/*"),
+            ("x-retry", "0"),
+            ("x-retry", "1"),
+            ("x-attempt", "2"),
+        ]);
+        assert_eq!(
+            header_changes(&previous, &current),
+            vec![
+                HeaderChange::Changed {
+                    name: "authorization".to_string(),
+                    previous: "Bearer a".to_string(),
+                    value: "Bearer b".to_string(),
+                },
+                HeaderChange::Changed {
+                    name: "x-retry".to_string(),
+                    previous: "0".to_string(),
+                    value: "0, 1".to_string(),
+                },
+                HeaderChange::Added {
+                    name: "x-attempt".to_string(),
+                    value: "2".to_string(),
+                },
+                HeaderChange::Removed {
+                    name: "x-request-id".to_string(),
+                    value: "1".to_string(),
+                },
+            ]
+        );
     }
 }
@@ -10,8 +10,8 @@ use crate::session::EncodedRequest;
 use crate::session::Status;
 use crate::ui::chords::{ChordResult, ChordState};
 use crate::ui::filters::{
-    ConnectionFilter, FilterGroupState, FilterState, FilterType, ItemFilter, PathFilter,
-    StatusFilter,
+    ConnectionFilter, FilterGroupState, FilterState, FilterType, ItemFilter, MethodFilter,
+    PathFilter, ResponseCodeFilter, StatusFilter,
 };
 use crate::ui::style;
 
@@ -51,6 +51,11 @@ impl FilterPane
                     let r = match s {
                         "ss" => self.on_state_filter(Status::Succeeded, filter),
                         "sf" => self.on_state_filter(Status::Failed, filter),
+                        "r1" | "r2" | "r3" | "r4" | "r5" => {
+                            let class = u16::from(s.as_bytes()[1] - b'0');
+                            self.add_remove_filter(filter, ResponseCodeFilter { class });
+                            Some(HandleResult::Update)
+                        }
                         other => {
                             toast::show_error(format!("Unknown chord '{}'", other));
                             Some(HandleResult::Update)
@@ -73,11 +78,20 @@ impl FilterPane
                     return Some(HandleResult::ExitView);
                 }
                 KeyCode::Char('X') => filter.clear_filters(),
+                KeyCode::Char('u') => {
+                    if !filter.undo() {
+                        toast::show_message("Nothing to undo");
+                    }
+                }
                 KeyCode::Char('s') => self.chord = Some(ChordState::new('s')),
+                KeyCode::Char('r') => self.chord = Some(ChordState::new('r')),
                 KeyCode::Char('c') => return self.on_connection_filter(filter, request),
                 KeyCode::Char('p') => return self.on_path_filter(filter, request),
+                KeyCode::Char('M') => return self.on_method_filter(filter, request),
                 _ => return None,
             }
+        } else {
+            return None;
         }
 
         Some(HandleResult::Update)
@@ -105,6 +119,8 @@ impl FilterPane
                 },
                 _ => return None,
             }
+        } else {
+            return None;
         }
 
         Some(HandleResult::Update)
@@ -240,7 +256,7 @@ impl FilterPane
         let sub_chunks = Layout::default()
             .direction(Direction::Vertical)
             .margin(0)
-            .constraints([Constraint::Length(10), Constraint::Percentage(100)].as_ref())
+            .constraints([Constraint::Length(14), Constraint::Percentage(100)].as_ref())
             .split(block_rect);
 
         let mut keys_text = vec![Spans::from(Span::raw("\n"))];
@@ -259,7 +275,8 @@ impl FilterPane
 
                 keys_text.push(Spans::from(Span::raw(format!(
                     "[c]: {} filter by connection: {}\n",
-                    enable_disable, conn.client_addr
+                    enable_disable,
+                    conn.display_name()
                 ))));
             }
 
@@ -275,15 +292,30 @@ impl FilterPane
                 enable_disable,
                 request.request_data.uri.path()
             ))));
+
+            let enable_disable = match filter.has_filter(&MethodFilter {
+                method: request.request_data.method.clone(),
+            }) {
+                false => "Enable",
+                true => "Disable",
+            };
+
+            keys_text.push(Spans::from(Span::raw(format!(
+                "[M]: {} filter by method: {}\n",
+                enable_disable, request.request_data.method
+            ))));
         }
         keys_text.extend(vec![
             Spans::from(Span::raw("[s?]: Toggle filter by status\n")),
             Spans::from(Span::raw(" - [ss]: Status Success\n")),
             Spans::from(Span::raw(" - [sf]: Status Fail\n")),
+            Spans::from(Span::raw("[r?]: Toggle filter by response code\n")),
+            Spans::from(Span::raw(" - [r1]-[r5]: 1xx-5xx responses\n")),
             Spans::from(Span::raw("\n")),
             Spans::from(Span::raw("[t]: Toggle selected filter or filter group\n")),
             Spans::from(Span::raw("[x]: Remove selected filter or filter group\n")),
             Spans::from(Span::raw("[X]: Remove all filters\n")),
+            Spans::from(Span::raw("[u]: Undo the last filter change\n")),
         ]);
 
         let keys_paragraph = Paragraph::new(Text::from(keys_text));
@@ -360,6 +392,19 @@ impl FilterPane
         })
     }
 
+    fn on_method_filter<B: Backend>(
+        &mut self,
+        filter: &mut FilterState<EncodedRequest>,
+        request: Option<&EncodedRequest>,
+    ) -> Option<HandleResult<B>>
+    {
+        request.map(|req| {
+            let method = req.request_data.method.clone();
+            self.add_remove_filter(filter, MethodFilter { method });
+            HandleResult::Update
+        })
+    }
+
     fn on_state_filter<B: Backend>(
         &mut self,
         status: Status,
@@ -1,5 +1,6 @@
-use crossterm::event::KeyModifiers;
+use crossterm::event::{KeyModifiers, MouseButton, MouseEventKind};
 use std::borrow::Cow;
+use std::cmp::Ordering;
 use tui::backend::Backend;
 use tui::layout::Constraint;
 use tui::style::{Modifier, Style};
@@ -8,8 +9,13 @@ use uuid::Uuid;
 
 use super::super::prelude::*;
 use crate::session::IndexedVec;
+use crate::ui::chords::{ChordResult, ChordState};
 use crate::ui::filters::{FilterState, FilterType};
 
+/// Limits for the fixed column widths so a column can't be resized out of view.
+const MIN_COLUMN_WIDTH: u16 = 3;
+const MAX_COLUMN_WIDTH: u16 = 40;
+
 pub struct TableView<T>
 {
     title: Cow<'static, str>,
@@ -18,18 +24,75 @@ pub struct TableView<T>
     user_selected: Option<usize>,
     locked: Option<Uuid>,
 
+    /// Lock toggled by the user. Moves along with the user selection instead of being cleared.
+    pinned: bool,
+
+    /// Keep the last item selected as new items arrive. Cleared when the user moves the selection.
+    follow: bool,
+
     group_filter: fn(&T, &T) -> bool,
 
     columns: Vec<Column<T>>,
 
+    /// Orderings the table can be sorted by.
+    sorts: Vec<Sort<T>>,
+
+    /// The active sort as an index to `sorts` and whether it is ascending.
+    sort: Option<(usize, bool)>,
+
+    /// Indices of the filtered items in display order while the table is sorted.
+    ///
+    /// Items that arrived after the order was last updated have not been sorted yet. They are
+    /// displayed at the end in their filtered order.
+    order: Vec<usize>,
+
     filter: FilterState<T>,
+
+    chord: Option<ChordState>,
+
+    /// Area and the index of the first visible row from the last draw for mapping mouse clicks
+    /// to rows.
+    chunk: Rect,
+    scroll_offset: usize,
 }
 
 struct Column<T>
 {
     title: &'static str,
     width: Option<u16>,
-    map: fn(&T) -> String,
+    map: ColumnMap<T>,
+
+    /// The active format as an index to the formats of `ColumnMap::Formats`.
+    format: usize,
+}
+
+/// Column format that receives the first item of the table as the second parameter.
+type FormatFn<T> = fn(&T, &T) -> String;
+
+enum ColumnMap<T>
+{
+    Item(fn(&T) -> String),
+
+    /// Alternative formats by their name and width. The formats receive the first item of the
+    /// table as the second parameter for displaying values relative to it.
+    Formats(Vec<(&'static str, u16, FormatFn<T>)>),
+}
+
+impl<T> Column<T>
+{
+    fn value(&self, item: &T, first: &T) -> String
+    {
+        match &self.map {
+            ColumnMap::Item(map) => map(item),
+            ColumnMap::Formats(formats) => (formats[self.format].2)(item, first),
+        }
+    }
+}
+
+struct Sort<T>
+{
+    title: &'static str,
+    compare: fn(&T, &T) -> Ordering,
 }
 
 enum Dir
@@ -52,9 +115,17 @@ impl<T: crate::session::HasKey> TableView<T>
             tui_state: state,
             user_selected: Default::default(),
             locked: None,
+            pinned: false,
+            follow: false,
             group_filter: |_, _| true,
             columns: Default::default(),
+            sorts: Default::default(),
+            sort: None,
+            order: Default::default(),
             filter: Default::default(),
+            chord: None,
+            chunk: Rect::default(),
+            scroll_offset: 0,
         }
     }
 
@@ -65,7 +136,81 @@ impl<T: crate::session::HasKey> TableView<T>
         map: fn(&T) -> String,
     ) -> Self
     {
-        self.columns.push(Column { title, width, map });
+        self.columns.push(Column {
+            title,
+            width,
+            map: ColumnMap::Item(map),
+            format: 0,
+        });
+        self
+    }
+
+    /// Add an alternative format to a fixed width column, adding the column if it doesn't exist
+    /// yet. The formats are cycled with `cycle_column_format` and the first one is used by
+    /// default.
+    pub fn with_column_format(
+        mut self,
+        title: &'static str,
+        name: &'static str,
+        width: u16,
+        map: FormatFn<T>,
+    ) -> Self
+    {
+        match self.columns.iter_mut().find(|c| c.title == title) {
+            Some(Column {
+                map: ColumnMap::Formats(formats),
+                ..
+            }) => formats.push((name, width, map)),
+            Some(_) => panic!("Column '{}' doesn't support formats", title),
+            None => self.columns.push(Column {
+                title,
+                width: Some(width),
+                map: ColumnMap::Formats(vec![(name, width, map)]),
+                format: 0,
+            }),
+        }
+        self
+    }
+
+    /// Switch the column to its next format, resizing it to fit the format.
+    ///
+    /// Returns the name of the new format or `None` if the column has no alternative formats.
+    pub fn cycle_column_format(&mut self, title: &str) -> Option<&'static str>
+    {
+        let column = self.columns.iter_mut().find(|c| c.title == title)?;
+        let formats = match &column.map {
+            ColumnMap::Formats(formats) => formats,
+            ColumnMap::Item(_) => return None,
+        };
+        column.format = (column.format + 1) % formats.len();
+        let (name, width, _) = formats[column.format];
+        column.width = Some(width.clamp(MIN_COLUMN_WIDTH, MAX_COLUMN_WIDTH));
+        Some(name)
+    }
+
+    /// Titles and widths of the columns that have a fixed width.
+    pub fn column_widths(&self) -> Vec<(&'static str, u16)>
+    {
+        self.columns
+            .iter()
+            .filter_map(|c| c.width.map(|w| (c.title, w)))
+            .collect()
+    }
+
+    /// Set the width of a fixed width column. The flexible column always takes the remaining
+    /// space.
+    pub fn set_column_width(&mut self, title: &str, width: u16)
+    {
+        if let Some(column) = self.columns.iter_mut().find(|c| c.title == title) {
+            if column.width.is_some() {
+                column.width = Some(width.clamp(MIN_COLUMN_WIDTH, MAX_COLUMN_WIDTH));
+            }
+        }
+    }
+
+    pub fn with_sort(mut self, title: &'static str, compare: fn(&T, &T) -> Ordering) -> Self
+    {
+        self.sorts.push(Sort { title, compare });
         self
     }
 
@@ -75,6 +220,13 @@ impl<T: crate::session::HasKey> TableView<T>
         self
     }
 
+    /// Replace the filter that decides which items are highlighted and moved between with
+    /// `[Shift]` along with the selected item.
+    pub fn set_group_filter(&mut self, group_filter: fn(&T, &T) -> bool)
+    {
+        self.group_filter = group_filter;
+    }
+
     pub fn on_active_input<B: Backend>(
         &mut self,
         content: &IndexedVec<T>,
@@ -82,8 +234,28 @@ impl<T: crate::session::HasKey> TableView<T>
         _size: Rect,
     ) -> Option<HandleResult<B>>
     {
+        if let Some(chord) = &mut self.chord {
+            match chord.handle(e) {
+                ChordResult::State(s) => {
+                    match s {
+                        "gg" => self.user_jump(content, Dir::Previous),
+                        other => toast::show_error(format!("Unknown chord '{}'", other)),
+                    }
+                    self.chord = None;
+                    return Some(HandleResult::Update);
+                }
+                ChordResult::Cancel => {
+                    self.chord = None;
+                    return Some(HandleResult::Update);
+                }
+                ChordResult::Ignore => (),
+            }
+        }
+
         match e {
             CTEvent::Key(key) => match key.code {
+                KeyCode::Char('g') => self.chord = Some(ChordState::new('g')),
+                KeyCode::Char('G') => self.user_jump(content, Dir::Next),
                 KeyCode::Char('k') | KeyCode::Char('K') | KeyCode::Up => {
                     self.user_move(content, key.modifiers == KeyModifiers::SHIFT, Dir::Previous)
                 }
@@ -93,6 +265,12 @@ impl<T: crate::session::HasKey> TableView<T>
                 KeyCode::Esc => self.user_select(content, None),
                 _ => return None,
             },
+            CTEvent::Mouse(mouse) => match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) => self.user_click(content, mouse.row)?,
+                MouseEventKind::ScrollUp => self.user_move(content, false, Dir::Previous),
+                MouseEventKind::ScrollDown => self.user_move(content, false, Dir::Next),
+                _ => return None,
+            },
             _ => return None,
         };
         Some(HandleResult::Update)
@@ -100,6 +278,8 @@ impl<T: crate::session::HasKey> TableView<T>
 
     fn user_move(&mut self, content: &IndexedVec<T>, by_group: bool, dir: Dir)
     {
+        self.follow = false;
+
         // If there's no content, there should be no reason to move.
         // We'd just end up panicing on the calculations.
         if self.filter.is_empty_filtered(content) {
@@ -114,7 +294,8 @@ impl<T: crate::session::HasKey> TableView<T>
             }
             Some(idx) => idx.min(self.filter.len_filtered(content) - 1),
         };
-        let (current_item, _) = self.filter.get(idx, content).unwrap();
+        let current_idx = self.filtered_index(idx);
+        let (current_item, _) = self.filter.get(current_idx, content).unwrap();
 
         // Loop until we'll find an item that matches the filter.
         loop {
@@ -131,18 +312,56 @@ impl<T: crate::session::HasKey> TableView<T>
                 },
             };
 
-            let (candidate_item, _) = self.filter.get(idx, content).unwrap();
+            let candidate_idx = self.filtered_index(idx);
+            let (candidate_item, _) = self.filter.get(candidate_idx, content).unwrap();
             if !by_group || (self.group_filter)(current_item, candidate_item) {
                 return self.user_select(content, Some(idx));
             }
         }
     }
 
+    /// Select the row at the clicked terminal row. Returns `None` if the click missed the rows.
+    fn user_click(&mut self, content: &IndexedVec<T>, row: u16) -> Option<()>
+    {
+        // The rows start after the top border and the header.
+        let first_row = self.chunk.y + 2;
+        if row < first_row || row >= self.chunk.bottom().saturating_sub(1) {
+            return None;
+        }
+
+        let idx = self.scroll_offset + usize::from(row - first_row);
+        if idx >= self.filter.len_filtered(content) {
+            return None;
+        }
+
+        self.follow = false;
+        self.user_select(content, Some(idx));
+        Some(())
+    }
+
+    /// Select the first or the last row that is visible with the current filter.
+    fn user_jump(&mut self, content: &IndexedVec<T>, dir: Dir)
+    {
+        self.follow = false;
+        if self.filter.is_empty_filtered(content) {
+            return;
+        }
+
+        match dir {
+            Dir::Previous => self.user_select(content, Some(0)),
+            Dir::Next => {
+                let last = self.filter.len_filtered(content) - 1;
+                self.user_select(content, Some(last))
+            }
+        }
+    }
+
     pub fn user_select(&mut self, content: &IndexedVec<T>, idx: Option<usize>)
     {
         self.unlock();
         match idx {
             None => {
+                self.pinned = false;
                 self.user_selected = None;
                 if self.filter.is_empty_filtered(content) {
                     self.tui_state.select(Some(usize::MAX));
@@ -157,12 +376,39 @@ impl<T: crate::session::HasKey> TableView<T>
                 }
                 self.user_selected = Some(idx);
                 self.tui_state.select(self.user_selected);
+                if self.pinned {
+                    self.lock(content);
+                }
             }
         }
     }
 
+    pub fn is_following(&self) -> bool
+    {
+        self.follow
+    }
+
+    pub fn toggle_follow(&mut self, content: &IndexedVec<T>)
+    {
+        self.follow = !self.follow;
+        if self.follow {
+            self.user_select(content, None);
+        }
+    }
+
     pub fn auto_select(&mut self, content: &IndexedVec<T>, idx: Option<usize>)
     {
+        // The user has explicitly locked the selection.
+        if self.pinned {
+            return;
+        }
+
+        // Following overrides whatever the user had selected previously.
+        if self.follow {
+            self.user_selected = None;
+            self.unlock();
+        }
+
         // If the user has selected something, skip the auto select. The user select will override
         // this.
         if self.user_selected.is_some() {
@@ -183,10 +429,8 @@ impl<T: crate::session::HasKey> TableView<T>
 
     pub fn selected<'a>(&mut self, content: &'a IndexedVec<T>) -> Option<&'a T>
     {
-        self.tui_state
-            .selected()
-            .and_then(|idx| self.filter.get(idx, content))
-            .map(|(item, _)| item)
+        let idx = self.filtered_index(self.tui_state.selected()?);
+        self.filter.get(idx, content).map(|(item, _)| item)
     }
 
     fn ensure_current_selection<'a>(&mut self, content: &'a IndexedVec<T>) -> Option<&'a T>
@@ -216,7 +460,8 @@ impl<T: crate::session::HasKey> TableView<T>
 
         let idx = content
             .get_index_by_uuid(lock)
-            .map(|idx| self.filter.find_filtered_index(idx, content));
+            .map(|idx| self.filter.find_filtered_index(idx, content))
+            .map(|idx| self.display_index(idx));
         self.user_selected = idx;
         self.tui_state.select(idx);
         self.selected(content)
@@ -231,25 +476,44 @@ impl<T: crate::session::HasKey> TableView<T>
         chunk: Rect,
     )
     {
+        if self.sort.is_some() {
+            self.update_order(content);
+        }
         let currently_selected = self.ensure_current_selection(content);
-        let block = create_control_block(&self.title, is_active);
+        let mut title = self.title.to_string();
+        if let Some((sort, ascending)) = self.sort {
+            let direction = match ascending {
+                true => "ascending",
+                false => "descending",
+            };
+            title.push_str(&format!(" (by {}, {})", self.sorts[sort].title, direction));
+        }
+        if self.pinned {
+            title.push_str(" [Locked]");
+        }
+        let block = create_control_block(&title, is_active);
 
         // Get a borrow of columns to avoid having to use `self` within the closure below.
         let columns = &self.columns;
         let group_filter = &self.group_filter;
+        let first = content.first();
 
         let total_width: u16 = columns.iter().filter_map(|c| c.width).sum();
-        let remainder = chunk.width - total_width
-            - 2  // Columns spacing.
-            - 2  // Borders.
-            - 2; // Highlight marker.
+        let remainder = chunk
+            .width
+            .saturating_sub(total_width)
+            .saturating_sub((columns.len() as u16).saturating_sub(1)) // Columns spacing.
+            .saturating_sub(2) // Borders.
+            .saturating_sub(2); // Highlight marker.
         let widths = columns
             .iter()
             .map(|c| Constraint::Length(c.width.unwrap_or(remainder)))
             .collect::<Vec<_>>();
-        let mut table = Table::new(self.filter.iter(content, highlight_filter).map(
-            |(item, is_filtered, selected_filter)| {
-                let closure = move |c: &Column<T>| (c.map)(item);
+        let mut rows: Vec<_> = self
+            .filter
+            .iter(content, highlight_filter)
+            .map(|(item, is_filtered, selected_filter)| {
+                let closure = move |c: &Column<T>| c.value(item, first.unwrap_or(item));
 
                 let is_group = if let Some(cs) = currently_selected {
                     (group_filter)(cs, item)
@@ -263,13 +527,19 @@ impl<T: crate::session::HasKey> TableView<T>
                     is_group,
                     selected_filter,
                 );
-                Row::new(columns.iter().map(closure)).style(style)
-            },
-        ))
-        .header(Row::new(columns.iter().map(|c| c.title)))
-        .block(block)
-        .widths(&widths)
-        .highlight_symbol("> ");
+                Some(Row::new(columns.iter().map(closure)).style(style))
+            })
+            .collect();
+        let rows = display_order(&self.order, rows.len())
+            .filter_map(|idx| rows[idx].take())
+            .collect::<Vec<_>>();
+        let row_count = rows.len();
+
+        let mut table = Table::new(rows)
+            .header(Row::new(columns.iter().map(|c| c.title)))
+            .block(block)
+            .widths(&widths)
+            .highlight_symbol("> ");
         if is_active {
             table = table.highlight_style(Style {
                 add_modifier: Modifier::BOLD,
@@ -279,7 +549,167 @@ impl<T: crate::session::HasKey> TableView<T>
             table = table.highlight_style(Style::default());
         }
 
-        f.render_stateful_widget(table, chunk, &mut self.tui_state)
+        f.render_stateful_widget(table, chunk, &mut self.tui_state);
+        self.update_scroll_offset(row_count, chunk);
+    }
+
+    /// Track the scroll offset the same way the table widget does. The widget doesn't expose the
+    /// offset it keeps in the state.
+    fn update_scroll_offset(&mut self, row_count: usize, chunk: Rect)
+    {
+        self.chunk = chunk;
+        if row_count == 0 {
+            self.scroll_offset = 0;
+            return;
+        }
+
+        let height = usize::from(chunk.height.saturating_sub(3)).max(1);
+        let selected = self.tui_state.selected().unwrap_or(0).min(row_count - 1);
+        self.scroll_offset = self.scroll_offset.min(row_count - 1);
+        if selected >= self.scroll_offset + height {
+            self.scroll_offset = selected + 1 - height;
+        } else if selected < self.scroll_offset {
+            self.scroll_offset = selected;
+        }
+    }
+
+    /// Keys of the items currently listed in the table in display order.
+    pub fn visible_keys(&mut self, content: &IndexedVec<T>) -> Vec<Uuid>
+    {
+        let keys: Vec<_> = self
+            .filter
+            .iter(content, None)
+            .map(|(item, ..)| item.key())
+            .collect();
+        display_order(&self.order, keys.len())
+            .map(|idx| keys[idx])
+            .collect()
+    }
+
+    pub fn select_key(&mut self, content: &IndexedVec<T>, key: Uuid)
+    {
+        self.follow = false;
+        if let Some(idx) = content.get_index_by_uuid(key) {
+            let idx = self.filter.find_filtered_index(idx, content);
+            let idx = self.display_index(idx);
+            self.user_select(content, Some(idx));
+        }
+    }
+
+    /// Update the table after the items at the given indices were removed from the content.
+    ///
+    /// The indices are the ones the items had before the removal, in ascending order.
+    pub fn remove_items(&mut self, content: &IndexedVec<T>, removed: &[usize])
+    {
+        // A lock on a removed item can't be restored anymore.
+        if let Some(lock) = self.locked {
+            if content.get_index_by_uuid(lock).is_none() {
+                self.pinned = false;
+                self.unlock();
+            }
+        }
+
+        // Resolve the user selection before the removal shifts the indices.
+        let selected = self
+            .user_selected
+            .and_then(|idx| self.filter.item_index(self.filtered_index(idx)));
+
+        self.filter.remove_items(removed);
+        self.order.clear();
+        if self.sort.is_some() {
+            self.user_selected = None;
+            self.update_order(content);
+        }
+
+        match selected {
+            Some(idx) if removed.binary_search(&idx).is_err() => {
+                let shift = removed.partition_point(|r| *r < idx);
+                let idx = self.filter.find_filtered_index(idx - shift, content);
+                let idx = self.display_index(idx);
+                self.user_selected = Some(idx);
+                self.tui_state.select(Some(idx));
+            }
+
+            // The selected item was removed so the selection moves to the oldest remaining one.
+            Some(_) if !self.filter.is_empty_filtered(content) => {
+                self.user_selected = Some(0);
+                self.tui_state.select(Some(0));
+            }
+            _ => {
+                self.user_selected = None;
+                self.auto_select(content, None);
+            }
+        }
+    }
+
+    /// Switch to sorting by the next ordering. Sorting is turned off after the last ordering.
+    pub fn cycle_sort(&mut self, content: &IndexedVec<T>)
+    {
+        self.sort = match self.sort {
+            None if !self.sorts.is_empty() => Some((0, true)),
+            Some((sort, ascending)) if sort + 1 < self.sorts.len() => Some((sort + 1, ascending)),
+            _ => None,
+        };
+        self.update_order(content);
+    }
+
+    pub fn reverse_sort(&mut self, content: &IndexedVec<T>)
+    {
+        if let Some((_, ascending)) = &mut self.sort {
+            *ascending = !*ascending;
+        }
+        self.update_order(content);
+    }
+
+    /// Sort the filtered items again.
+    ///
+    /// The items move around when they are sorted so the selection made by the user is restored
+    /// by its key afterwards.
+    fn update_order(&mut self, content: &IndexedVec<T>)
+    {
+        let selected = match self.user_selected {
+            Some(_) => self.selected(content).map(|item| item.key()),
+            None => None,
+        };
+
+        self.order.clear();
+        if let Some((sort, ascending)) = self.sort {
+            let compare = self.sorts[sort].compare;
+            let items: Vec<_> = self
+                .filter
+                .iter(content, None)
+                .map(|(item, ..)| item)
+                .collect();
+            let mut order: Vec<_> = (0..items.len()).collect();
+
+            // The sort is stable so equal items are kept in their arrival order.
+            order.sort_by(|a, b| match ascending {
+                true => compare(items[*a], items[*b]),
+                false => compare(items[*b], items[*a]),
+            });
+            self.order = order;
+        }
+
+        if let Some(key) = selected {
+            if let Some(idx) = content.get_index_by_uuid(key) {
+                let idx = self.filter.find_filtered_index(idx, content);
+                let idx = self.display_index(idx);
+                self.user_selected = Some(idx);
+                self.tui_state.select(Some(idx));
+            }
+        }
+    }
+
+    /// Index in the filtered items for the given display index.
+    fn filtered_index(&self, idx: usize) -> usize
+    {
+        self.order.get(idx).copied().unwrap_or(idx)
+    }
+
+    /// Display index for the given index in the filtered items.
+    fn display_index(&self, idx: usize) -> usize
+    {
+        self.order.iter().position(|i| *i == idx).unwrap_or(idx)
     }
 
     pub fn get_filter(&self) -> &FilterState<T>
@@ -293,6 +723,23 @@ impl<T: crate::session::HasKey> TableView<T>
         &mut self.filter
     }
 
+    pub fn is_locked(&self) -> bool
+    {
+        self.pinned
+    }
+
+    pub fn toggle_lock(&mut self, content: &IndexedVec<T>)
+    {
+        if self.pinned {
+            self.pinned = false;
+            self.unlock();
+        } else if self.selected(content).is_some() {
+            self.follow = false;
+            self.pinned = true;
+            self.lock(content);
+        }
+    }
+
     pub fn unlock(&mut self)
     {
         log::info!("Unlocking");
@@ -309,3 +756,13 @@ impl<T: crate::session::HasKey> TableView<T>
         }
     }
 }
+
+/// Indices of the filtered items in display order.
+fn display_order(order: &[usize], len: usize) -> impl Iterator<Item = usize> + '_
+{
+    order
+        .iter()
+        .copied()
+        .filter(move |idx| *idx < len)
+        .chain(order.len()..len)
+}
@@ -1,10 +1,23 @@
 pub mod prelude;
 use prelude::*;
 
+use crate::session::EncodedRequest;
+use crate::ui::filters::ItemFilter;
+
+mod connections_view;
+pub use connections_view::ConnectionsView;
+mod diff_view;
+pub use diff_view::DiffView;
+mod help_view;
+pub use help_view::HelpView;
 mod main_view;
 pub use main_view::MainView;
 mod message_view;
 pub use message_view::MessageView;
+mod quit_view;
+pub use quit_view::QuitView;
+mod timeline_view;
+pub use timeline_view::TimelineView;
 
 pub trait View<B: Backend>
 {
@@ -12,6 +25,54 @@ pub trait View<B: Backend>
     fn on_input(&mut self, ctx: &UiContext, e: &CTEvent, size: Rect) -> Option<HandleResult<B>>;
     fn on_change(&mut self, ctx: &UiContext, change: &SessionChange) -> bool;
     fn help_text(&self, state: &UiContext, size: Rect) -> String;
+
+    /// The keys of the view and what they do, listed in the help opened with `?`.
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>
+    {
+        vec![]
+    }
+
+    /// Select a request in the view. Invoked when a view pushed on top of this one exits with
+    /// `HandleResult::SelectRequest`.
+    fn select_request(&mut self, _ctx: &UiContext, _request: uuid::Uuid) {}
+
+    /// Show the requests of a connection. Invoked when a view pushed on top of this one exits
+    /// with `HandleResult::SelectConnection`.
+    fn select_connection(&mut self, _ctx: &UiContext, _connection: uuid::Uuid) {}
+
+    /// Add a filter to the requests shown in the view. Used by the search command.
+    fn add_filter(&mut self, _ctx: &UiContext, _filter: Box<dyn ItemFilter<EncodedRequest>>)
+    {
+        toast::show_error("Search is not available in this view");
+    }
+
+    /// Write the filters of the view to a file.
+    fn save_filters(&mut self, _ctx: &UiContext, _file: &str)
+    {
+        toast::show_error("Filters are not available in this view");
+    }
+
+    /// Replace the filters of the view with ones read from a file.
+    fn load_filters(&mut self, _ctx: &UiContext, _file: &str)
+    {
+        toast::show_error("Filters are not available in this view");
+    }
+
+    /// Whether `/` searches within the view instead of filtering the requests.
+    fn can_find(&self) -> bool
+    {
+        false
+    }
+
+    /// Search for the text within the view. An empty pattern ends the search.
+    fn find(&mut self, _ctx: &UiContext, _pattern: &str) {}
+
+    /// The request currently selected in the view. Used as the target of colon commands.
+    fn selected_request(&mut self, _ctx: &UiContext) -> Option<uuid::Uuid>
+    {
+        None
+    }
+
     fn transparent(&self) -> bool
     {
         false
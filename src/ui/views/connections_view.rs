@@ -0,0 +1,233 @@
+use chrono::Local;
+use tui::layout::{Constraint, Direction, Layout};
+use tui::style::{Modifier, Style};
+use tui::widgets::{Paragraph, Row, Table, TableState};
+use uuid::Uuid;
+
+use super::prelude::*;
+use crate::session::Throughput;
+
+/// List of the connections in the session. Selecting a connection filters the requests to it.
+pub struct ConnectionsView
+{
+    selected: usize,
+    tui_state: TableState,
+}
+
+impl ConnectionsView
+{
+    pub fn new(ctx: &UiContext, selected: Option<Uuid>) -> Self
+    {
+        let selected = selected
+            .and_then(|s| ctx.data.connections.get_index_by_uuid(s))
+            .unwrap_or_else(|| ctx.data.connections.len().saturating_sub(1));
+        Self {
+            selected,
+            tui_state: TableState::default(),
+        }
+    }
+
+    fn move_selection(&mut self, ctx: &UiContext, delta: isize)
+    {
+        let max = ctx.data.connections.len().saturating_sub(1);
+        self.selected = match delta < 0 {
+            true => self.selected.saturating_sub(delta.unsigned_abs()),
+            false => (self.selected + delta as usize).min(max),
+        };
+    }
+}
+
+impl<B: Backend> View<B> for ConnectionsView
+{
+    fn draw(&mut self, ctx: &UiContext, f: &mut Frame<B>, chunk: Rect)
+    {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Min(5),
+                    Constraint::Length(4),
+                    Constraint::Length(8),
+                ]
+                .as_ref(),
+            )
+            .split(chunk);
+
+        let block = create_block("Connections");
+        let connections = &ctx.data.connections;
+        self.selected = self.selected.min(connections.len().saturating_sub(1));
+
+        let rows = connections.iter().map(|conn| {
+            let requests = ctx
+                .data
+                .requests
+                .iter()
+                .filter(|r| r.request_data.connection_uuid == conn.uuid)
+                .count();
+            Row::new(vec![
+                conn.display_name(),
+                conn.protocol_description(),
+                conn.start_timestamp.format("%H:%M:%S").to_string(),
+                match conn.end_timestamp {
+                    Some(end) => format!("{:>9}", format_duration(end - conn.start_timestamp)),
+                    None => format!(
+                        "{:>9}",
+                        format_duration(Local::now() - conn.start_timestamp)
+                    ),
+                },
+                conn.status.to_string(),
+                match conn.connect_attempts {
+                    0 => String::new(),
+                    n => n.to_string(),
+                },
+                requests.to_string(),
+            ])
+        });
+
+        let table = Table::new(rows)
+            .header(
+                Row::new(vec![
+                    "Client", "Protocol", "Started", "Duration", "St.", "Tries", "Requests",
+                ])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(block)
+            .widths(&[
+                Constraint::Length(22),
+                Constraint::Min(20),
+                Constraint::Length(10),
+                Constraint::Length(9),
+                Constraint::Length(5),
+                Constraint::Length(5),
+                Constraint::Length(8),
+            ])
+            .highlight_symbol("> ")
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD));
+
+        match connections.is_empty() {
+            true => self.tui_state.select(None),
+            false => self.tui_state.select(Some(self.selected)),
+        }
+        f.render_stateful_widget(table, chunks[0], &mut self.tui_state);
+
+        // The duration includes the time spent throttled so the rate is the one achieved with
+        // --throttle-kbps.
+        let throughput = |t: Throughput| {
+            let duration =
+                chrono::Duration::from_std(t.duration).unwrap_or_else(|_| chrono::Duration::zero());
+            format!(
+                "{} in {} ({})",
+                format_size(t.bytes as usize),
+                format_duration(duration),
+                format_throughput(t.bytes as usize, duration)
+            )
+        };
+        let transferred = match connections.get(self.selected) {
+            Some(conn) => format!(
+                "Client to server: {}\n\
+                 Server to client: {}",
+                throughput(conn.request_throughput),
+                throughput(conn.response_throughput),
+            ),
+            None => String::new(),
+        };
+        let paragraph = Paragraph::new(transferred).block(create_block("Throughput"));
+        f.render_widget(paragraph, chunks[1]);
+
+        let streams = |streams: Option<usize>| match streams {
+            Some(n) => n.to_string(),
+            None => "unlimited".to_string(),
+        };
+        let window = |window: Option<usize>| match window {
+            Some(n) => n.to_string(),
+            None => "unknown".to_string(),
+        };
+        let settings = match connections.get(self.selected).map(|c| c.h2_settings) {
+            Some(Some(settings)) => format!(
+                "Initial window size:           {} (configured)\n\
+                 Header table size:             unavailable\n\
+                 Client max concurrent streams: {}\n\
+                 Server max concurrent streams: {}\n\
+                 Client stream window:          {}\n\
+                 Server stream window:          {}",
+                settings.initial_window_size,
+                streams(settings.client_max_concurrent_streams),
+                streams(settings.server_max_concurrent_streams),
+                window(settings.client_stream_window),
+                window(settings.server_stream_window),
+            ),
+            Some(None) => "Not an HTTP/2 connection".to_string(),
+            None => String::new(),
+        };
+        let paragraph = Paragraph::new(settings).block(create_block("HTTP/2 settings"));
+        f.render_widget(paragraph, chunks[2]);
+    }
+
+    fn on_input(&mut self, ctx: &UiContext, e: &CTEvent, size: Rect) -> Option<HandleResult<B>>
+    {
+        match e {
+            CTEvent::Key(key) => match key.code {
+                KeyCode::Char('k') | KeyCode::Up => self.move_selection(ctx, -1),
+                KeyCode::Char('j') | KeyCode::Down => self.move_selection(ctx, 1),
+                KeyCode::PageUp => self.move_selection(ctx, -(size.height as isize - 5)),
+                KeyCode::PageDown => self.move_selection(ctx, size.height as isize - 5),
+                KeyCode::Char('g') => self.selected = 0,
+                KeyCode::Char('G') => self.move_selection(ctx, isize::MAX),
+                KeyCode::Enter => {
+                    return ctx
+                        .data
+                        .connections
+                        .get(self.selected)
+                        .map(|conn| HandleResult::SelectConnection(conn.uuid))
+                }
+                KeyCode::Char('q') | KeyCode::Char('c') | KeyCode::Char('C') => {
+                    return Some(HandleResult::ExitView)
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(HandleResult::Update)
+    }
+
+    fn on_change(&mut self, _ctx: &UiContext, change: &SessionChange) -> bool
+    {
+        match change {
+            SessionChange::NewConnection { .. } | SessionChange::Connection { .. } => true,
+
+            // The request counts change.
+            SessionChange::NewRequest { .. } => true,
+            SessionChange::Request { .. }
+            | SessionChange::NewMessage { .. }
+            | SessionChange::Message { .. } => false,
+
+            // Keep the same connection selected while the older ones are removed.
+            SessionChange::Removed { connections, .. } => {
+                match connections.binary_search(&self.selected) {
+                    Ok(_) => self.selected = 0,
+                    Err(shift) => self.selected -= shift,
+                }
+                true
+            }
+        }
+    }
+
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>
+    {
+        vec![
+            ("Up/Down, j/k, PgUp/PgDn", "Select connection"),
+            ("g/G", "First/Last connection"),
+            ("Enter", "Show the requests of the connection"),
+            ("c/q/Esc", "Back to main view"),
+        ]
+    }
+
+    fn help_text(&self, _session: &UiContext, _size: Rect) -> String
+    {
+        format!(
+            "{}\n{}",
+            "[Up/Down, j/k, PgUp/PgDn]: Select connection; [g/G]: First/Last",
+            "[Enter]: Show the requests of the connection; [c/q/Esc]: Back to main view"
+        )
+    }
+}
@@ -0,0 +1,327 @@
+use crossterm::event::{KeyCode, MouseEventKind};
+use tui::layout::{Constraint, Direction, Layout};
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans, Text};
+use tui::widgets::Paragraph;
+use uuid::Uuid;
+
+use super::prelude::*;
+use crate::session::{EncodedRequest, MessageData, RequestPart};
+
+/// Upper limit for the size of the LCS table. Larger messages are compared line by line.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Side-by-side comparison of the same message in two requests.
+pub struct DiffView
+{
+    pub left: Uuid,
+    pub right: Uuid,
+    pub part: RequestPart,
+    pub offset: u16,
+}
+
+#[derive(Debug, PartialEq)]
+enum DiffLine
+{
+    Same(String),
+    Removed(String),
+    Added(String),
+    Changed(String, String),
+}
+
+impl DiffView
+{
+    pub fn new(left: Uuid, right: Uuid) -> Self
+    {
+        Self {
+            left,
+            right,
+            part: RequestPart::Request,
+            offset: 0,
+        }
+    }
+
+    fn get_lines(&self, request: &EncodedRequest, ctx: &UiContext) -> Vec<String>
+    {
+        let message = match self.part {
+            RequestPart::Request => &request.request_msg,
+            RequestPart::Response => &request.response_msg,
+        };
+
+        let mut lines: Vec<String> = message
+            .headers
+            .iter()
+            .map(|(k, v)| format!("{}: {}", k, String::from_utf8_lossy(v.as_bytes())))
+            .collect();
+        lines.push(String::new());
+        lines.extend(decode_lines(ctx, request, message));
+        if !message.trailers.is_empty() {
+            lines.push(String::new());
+            lines.extend(
+                message
+                    .trailers
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, String::from_utf8_lossy(v.as_bytes()))),
+            );
+        }
+        lines
+    }
+}
+
+impl<B: Backend> View<B> for DiffView
+{
+    fn draw(&mut self, ctx: &UiContext, f: &mut Frame<B>, chunk: Rect)
+    {
+        let (left, right) = match (
+            ctx.data.requests.get_by_uuid(self.left),
+            ctx.data.requests.get_by_uuid(self.right),
+        ) {
+            (Some(l), Some(r)) => (l, r),
+            _ => return,
+        };
+
+        let diff = diff_lines(self.get_lines(left, ctx), self.get_lines(right, ctx));
+        let removed = Style::default().fg(Color::Red);
+        let added = Style::default().fg(Color::Green);
+        let changed = Style::default().fg(Color::Yellow);
+        let mut left_text = Text::default();
+        let mut right_text = Text::default();
+        for line in diff {
+            let (l, r) = match line {
+                DiffLine::Same(s) => (Spans::from(s.clone()), Spans::from(s)),
+                DiffLine::Removed(s) => (Spans::from(Span::styled(s, removed)), Spans::default()),
+                DiffLine::Added(s) => (Spans::default(), Spans::from(Span::styled(s, added))),
+                DiffLine::Changed(l, r) => (
+                    Spans::from(Span::styled(l, changed)),
+                    Spans::from(Span::styled(r, changed)),
+                ),
+            };
+            left_text.lines.push(l);
+            right_text.lines.push(r);
+        }
+
+        let title = match self.part {
+            RequestPart::Request => "Request",
+            RequestPart::Response => "Response",
+        };
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(chunk);
+        for ((request, text), chunk) in vec![(left, left_text), (right, right_text)]
+            .into_iter()
+            .zip(chunks)
+        {
+            let block_title = format!(
+                "{} {} ({})",
+                title,
+                request.request_data.uri,
+                request.request_data.start_timestamp.format("%H:%M:%S")
+            );
+            let paragraph = Paragraph::new(text)
+                .block(create_block(&block_title))
+                .scroll((self.offset, 0));
+            f.render_widget(paragraph, chunk);
+        }
+    }
+
+    fn on_input(&mut self, _ctx: &UiContext, e: &CTEvent, size: Rect) -> Option<HandleResult<B>>
+    {
+        match e {
+            CTEvent::Key(key) => match key.code {
+                KeyCode::Char('k') | KeyCode::Up => self.offset = self.offset.saturating_sub(1),
+                KeyCode::Char('j') | KeyCode::Down => self.offset = self.offset.saturating_add(1),
+                KeyCode::PageDown => self.offset = self.offset.saturating_add(size.height - 5),
+                KeyCode::PageUp => self.offset = self.offset.saturating_sub(size.height - 5),
+                KeyCode::Tab => {
+                    self.offset = 0;
+                    self.part = match self.part {
+                        RequestPart::Request => RequestPart::Response,
+                        RequestPart::Response => RequestPart::Request,
+                    }
+                }
+                KeyCode::Char('q') => return Some(HandleResult::ExitView),
+                _ => return None,
+            },
+            CTEvent::Mouse(mouse) => match mouse.kind {
+                MouseEventKind::ScrollUp => self.offset = self.offset.saturating_sub(3),
+                MouseEventKind::ScrollDown => self.offset = self.offset.saturating_add(3),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(HandleResult::Update)
+    }
+
+    fn on_change(&mut self, _ctx: &UiContext, change: &SessionChange) -> bool
+    {
+        match change {
+            SessionChange::NewConnection { .. } => false,
+            SessionChange::Connection { .. } => false,
+            SessionChange::NewRequest { .. } => false,
+            SessionChange::Request { request } => *request == self.left || *request == self.right,
+            SessionChange::NewMessage { request, part }
+            | SessionChange::Message { request, part } => {
+                *part == self.part && (*request == self.left || *request == self.right)
+            }
+            SessionChange::Removed { .. } => true,
+        }
+    }
+
+    fn selected_request(&mut self, _ctx: &UiContext) -> Option<Uuid>
+    {
+        Some(self.right)
+    }
+
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>
+    {
+        vec![
+            ("Up/Down, j/k, PgUp/PgDn", "Scroll"),
+            ("Tab", "Switch between request and response"),
+            ("q/Esc", "Back to main view"),
+        ]
+    }
+
+    fn help_text(&self, _session: &UiContext, _size: Rect) -> String
+    {
+        "[Up/Down, j/k, PgUp/PgDn]: Scroll; [Tab]: Switch Request/Response; [q, Esc]: Back to main view".to_string()
+    }
+}
+
+/// Decode the message into plain text lines using the preferred decoder.
+fn decode_lines(ctx: &UiContext, request: &EncodedRequest, message: &MessageData) -> Vec<String>
+{
+    let decoders = &ctx.runtime.decoders;
+    let decoder = decoders
+        .get_decoders(&request.request_data, message)
+        .filter(|d| !d.is_opt_in() || decoders.is_default(d.name()))
+        .last()
+        .expect("Raw decoder should always be present");
+    decoder
+        .decode(message)
+        .lines
+        .into_iter()
+        .map(|spans| {
+            spans
+                .0
+                .iter()
+                .map(|s| s.content.as_ref())
+                .collect::<String>()
+        })
+        .collect()
+}
+
+/// Compute the line diff between the two texts.
+///
+/// Removals directly followed by additions are paired into changed lines.
+fn diff_lines(left: Vec<String>, right: Vec<String>) -> Vec<DiffLine>
+{
+    // Trim the common prefix and suffix to keep the LCS table small.
+    let prefix = left.iter().zip(&right).take_while(|(l, r)| l == r).count();
+    let suffix = left[prefix..]
+        .iter()
+        .rev()
+        .zip(right[prefix..].iter().rev())
+        .take_while(|(l, r)| l == r)
+        .count();
+    let l = &left[prefix..left.len() - suffix];
+    let r = &right[prefix..right.len() - suffix];
+
+    // Edit script of the middle part: `Some(true)` is a removal from the left side, `Some(false)`
+    // an addition from the right side and `None` a line common to both.
+    let mut script = vec![];
+    if l.len().saturating_mul(r.len()) > MAX_DIFF_CELLS {
+        script.resize(l.len(), Some(true));
+        script.resize(l.len() + r.len(), Some(false));
+    } else {
+        let mut lcs = vec![vec![0usize; r.len() + 1]; l.len() + 1];
+        for i in (0..l.len()).rev() {
+            for j in (0..r.len()).rev() {
+                lcs[i][j] = match l[i] == r[j] {
+                    true => lcs[i + 1][j + 1] + 1,
+                    false => lcs[i + 1][j].max(lcs[i][j + 1]),
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < l.len() || j < r.len() {
+            if i < l.len() && j < r.len() && l[i] == r[j] {
+                script.push(None);
+                i += 1;
+                j += 1;
+            } else if j == r.len() || (i < l.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                script.push(Some(true));
+                i += 1;
+            } else {
+                script.push(Some(false));
+                j += 1;
+            }
+        }
+    }
+
+    let mut output: Vec<DiffLine> = left[..prefix].iter().cloned().map(DiffLine::Same).collect();
+    let mut left_iter = l.iter().cloned();
+    let mut right_iter = r.iter().cloned();
+    let mut removed = vec![];
+    let mut added = vec![];
+    for op in script.into_iter().map(Some).chain(std::iter::once(None)) {
+        match op {
+            Some(Some(true)) => removed.push(left_iter.next().unwrap()),
+            Some(Some(false)) => added.push(right_iter.next().unwrap()),
+            _ => {
+                // A common line or the end of the script flushes the pending changes.
+                let changed = removed.len().min(added.len());
+                let mut removed_iter = removed.drain(..);
+                let mut added_iter = added.drain(..);
+                for _ in 0..changed {
+                    output.push(DiffLine::Changed(
+                        removed_iter.next().unwrap(),
+                        added_iter.next().unwrap(),
+                    ));
+                }
+                output.extend(removed_iter.map(DiffLine::Removed));
+                output.extend(added_iter.map(DiffLine::Added));
+
+                if op.is_some() {
+                    right_iter.next();
+                    output.push(DiffLine::Same(left_iter.next().unwrap()));
+                }
+            }
+        }
+    }
+    output.extend(
+        left[left.len() - suffix..]
+            .iter()
+            .cloned()
+            .map(DiffLine::Same),
+    );
+    output
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String>
+    {
+        text.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn diff_pairs_changed_lines()
+    {
+        let diff = diff_lines(lines("a b c d e"), lines("a x c e f"));
+        assert_eq!(
+            diff,
+            vec![
+                DiffLine::Same("a".to_string()),
+                DiffLine::Changed("b".to_string(), "x".to_string()),
+                DiffLine::Same("c".to_string()),
+                DiffLine::Removed("d".to_string()),
+                DiffLine::Same("e".to_string()),
+                DiffLine::Added("f".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use tui::style::{Modifier, Style};
+use tui::text::{Span, Spans, Text};
+use tui::widgets::{Clear, Paragraph};
+
+use super::prelude::*;
+
+/// Keys that work in every view.
+const GLOBAL_BINDINGS: &[(&str, &str)] = &[
+    (":", "Enter a command"),
+    ("/", "Search the requests or find in the message"),
+    ("?", "Show this help"),
+    ("Esc", "Back to the previous view"),
+    ("Shift-Q", "Quit"),
+];
+
+/// Overlay listing the key bindings of the view below it.
+pub struct HelpView
+{
+    bindings: Vec<(&'static str, &'static str)>,
+    offset: u16,
+}
+
+impl HelpView
+{
+    pub fn new(bindings: Vec<(&'static str, &'static str)>) -> Self
+    {
+        Self {
+            bindings,
+            offset: 0,
+        }
+    }
+}
+
+impl<B: Backend> View<B> for HelpView
+{
+    fn draw(&mut self, _ctx: &UiContext, f: &mut Frame<B>, chunk: Rect)
+    {
+        let key_width = self
+            .bindings
+            .iter()
+            .chain(GLOBAL_BINDINGS)
+            .map(|(keys, _)| keys.chars().count())
+            .max()
+            .unwrap_or(0);
+        let heading = Style::default().add_modifier(Modifier::BOLD);
+        let section = |title: &'static str, bindings: &[(&'static str, &'static str)]| {
+            let mut lines = vec![Spans::from(Span::styled(title, heading))];
+            lines.extend(bindings.iter().map(|(keys, description)| {
+                Spans::from(format!(
+                    " {:width$}  {}",
+                    keys,
+                    description,
+                    width = key_width
+                ))
+            }));
+            lines.push(Spans::default());
+            lines
+        };
+
+        let mut lines = vec![];
+        if !self.bindings.is_empty() {
+            lines.extend(section("View", &self.bindings));
+        }
+        lines.extend(section("Global", GLOBAL_BINDINGS));
+        let text = Text::from(lines);
+
+        // Leave a margin so the view below is still visible around the help.
+        let width = (text.width() as u16 + 2).min(chunk.width.saturating_sub(4));
+        let height = (text.height() as u16 + 2).min(chunk.height.saturating_sub(2));
+        let rect = Rect {
+            x: chunk.x + (chunk.width - width) / 2,
+            y: chunk.y + (chunk.height - height) / 2,
+            width,
+            height,
+        };
+
+        let max_offset = (text.height() as u16).saturating_sub(height.saturating_sub(2));
+        self.offset = self.offset.min(max_offset);
+
+        f.render_widget(Clear, rect);
+        f.render_widget(
+            Paragraph::new(text)
+                .block(create_block("Key bindings"))
+                .scroll((self.offset, 0)),
+            rect,
+        );
+    }
+
+    fn on_input(&mut self, _ctx: &UiContext, e: &CTEvent, size: Rect) -> Option<HandleResult<B>>
+    {
+        match e {
+            CTEvent::Key(key) => match key.code {
+                KeyCode::Char('k') | KeyCode::Up => self.offset = self.offset.saturating_sub(1),
+                KeyCode::Char('j') | KeyCode::Down => self.offset = self.offset.saturating_add(1),
+                KeyCode::PageUp => self.offset = self.offset.saturating_sub(size.height - 5),
+                KeyCode::PageDown => self.offset = self.offset.saturating_add(size.height - 5),
+                KeyCode::Char('?') | KeyCode::Char('q') | KeyCode::Esc => {
+                    return Some(HandleResult::ExitView)
+                }
+
+                // Keep the other keys from reaching the views below.
+                _ => {}
+            },
+            _ => return None,
+        }
+        Some(HandleResult::Update)
+    }
+
+    fn on_change(&mut self, _ctx: &UiContext, _change: &SessionChange) -> bool
+    {
+        false
+    }
+
+    fn help_text(&self, _session: &UiContext, _size: Rect) -> String
+    {
+        "[Up/Down, j/k, PgUp/PgDn]: Scroll; [?/q/Esc]: Close help".to_string()
+    }
+
+    fn transparent(&self) -> bool
+    {
+        true
+    }
+}
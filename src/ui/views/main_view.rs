@@ -1,10 +1,14 @@
 use tui::layout::{Constraint, Direction, Layout, Rect};
 
 use super::prelude::*;
+use crate::decoders::grpc;
 use crate::session::EncodedRequest;
 
 use crate::ui::commands;
+use crate::ui::filters::{ConnectionFilter, ItemFilter};
+use crate::ui::layout::SavedLayout;
 use crate::ui::sub_views::{DetailsPane, FilterPane, TableView};
+use crate::ui::views::{ConnectionsView, DiffView, TimelineView};
 
 pub struct MainView
 {
@@ -12,23 +16,58 @@ pub struct MainView
     requests_state: TableView<EncodedRequest>,
     filter_pane: Option<FilterPane>,
     filter_pane_active: bool,
+
+    /// The request marked as the left side of the diff view.
+    marked: Option<uuid::Uuid>,
+
+    /// Width of the request list. Adjusted with `<` and `>`.
+    requests_width: u16,
+
+    /// Index of the fixed width column resized with `{` and `}`.
+    resize_column: usize,
+
+    /// Group the requests by their method and path instead of their connection.
+    group_similar: bool,
 }
 
+const DEFAULT_REQUESTS_WIDTH: u16 = 70;
+
+/// The request list and the details are kept at least this wide when resizing the list.
+const MIN_REQUESTS_WIDTH: u16 = 30;
+const MIN_DETAILS_WIDTH: u16 = 20;
+
 impl Default for MainView
 {
     fn default() -> Self
     {
         Self {
-            details_view: DetailsPane {},
+            details_view: DetailsPane::default(),
             filter_pane: None,
             filter_pane_active: false,
+            marked: None,
+            requests_width: DEFAULT_REQUESTS_WIDTH,
+            resize_column: 0,
+            group_similar: false,
             requests_state: TableView::<EncodedRequest>::new("Requests")
-                .with_group_filter(|current, maybe| {
-                    current.request_data.connection_uuid == maybe.request_data.connection_uuid
-                })
+                .with_group_filter(same_connection)
                 .with_column("Requests", None, |item| {
+                    let fault = match item.request_data.injected_fault {
+                        true => "[F] ",
+                        false => "",
+                    };
+                    let deadline = match grpc::deadline_usage(item) {
+                        Some(usage) if usage >= grpc::DEADLINE_WARNING => "[D] ",
+                        _ => "",
+                    };
+                    let alias = match &item.request_data.alias {
+                        Some(alias) => format!("{}: ", alias),
+                        None => String::new(),
+                    };
                     format!(
-                        "{} {}",
+                        "{}{}{}{} {}",
+                        fault,
+                        deadline,
+                        alias,
                         item.request_data.method,
                         item.request_data
                             .uri
@@ -37,13 +76,71 @@ impl Default for MainView
                             .unwrap_or_else(|| "/".to_string())
                     )
                 })
-                .with_column("Timestamp", Some(10), |item| {
+                .with_column_format("Timestamp", "time", 10, |item, _| {
                     item.request_data
                         .start_timestamp
                         .format("%H:%M:%S")
                         .to_string()
                 })
-                .with_column("St.", Some(5), |item| item.request_data.status.to_string()),
+                .with_column_format("Timestamp", "time with milliseconds", 12, |item, _| {
+                    item.request_data
+                        .start_timestamp
+                        .format("%H:%M:%S%.3f")
+                        .to_string()
+                })
+                .with_column_format("Timestamp", "ISO-8601", 29, |item, _| {
+                    item.request_data
+                        .start_timestamp
+                        .format("%Y-%m-%dT%H:%M:%S%.3f%:z")
+                        .to_string()
+                })
+                .with_column_format(
+                    "Timestamp",
+                    "relative to the first request",
+                    12,
+                    |item, first| {
+                        let offset =
+                            item.request_data.start_timestamp - first.request_data.start_timestamp;
+                        format!("{:+.3}s", offset.num_milliseconds() as f64 / 1000.0)
+                    },
+                )
+                .with_column("Duration", Some(9), |item| {
+                    // Durations are right aligned to make them easier to compare.
+                    match duration(item) {
+                        Some(d) => format!("{:>9}", format_duration(d)),
+                        None => "(pending)".to_string(),
+                    }
+                })
+                .with_column("St.", Some(5), |item| match item.request_data.timed_out {
+                    true => "T/O".to_string(),
+                    false => item.request_data.status.to_string(),
+                })
+                .with_column("Code", Some(4), |item| {
+                    match item.request_data.response_status {
+                        Some(status) => status.to_string(),
+                        None => "-".to_string(),
+                    }
+                })
+                .with_sort("timestamp", |a, b| {
+                    a.request_data
+                        .start_timestamp
+                        .cmp(&b.request_data.start_timestamp)
+                })
+                .with_sort("duration", |a, b| duration(a).cmp(&duration(b)))
+                .with_sort("method", |a, b| {
+                    a.request_data
+                        .method
+                        .as_str()
+                        .cmp(b.request_data.method.as_str())
+                })
+                .with_sort("path", |a, b| {
+                    a.request_data.uri.path().cmp(b.request_data.uri.path())
+                })
+                .with_sort("status", |a, b| {
+                    a.request_data
+                        .response_status
+                        .cmp(&b.request_data.response_status)
+                }),
         }
     }
 }
@@ -57,8 +154,12 @@ impl<B: Backend> View<B> for MainView
             .margin(0)
             .constraints(
                 [
-                    // Constraint::Length(55),
-                    Constraint::Length(70),
+                    // The saved width may be too wide for the current terminal.
+                    Constraint::Length(
+                        self.requests_width
+                            .min(chunk.width.saturating_sub(MIN_DETAILS_WIDTH))
+                            .max(MIN_REQUESTS_WIDTH.min(chunk.width)),
+                    ),
                     Constraint::Percentage(100),
                 ]
                 .as_ref(),
@@ -132,19 +233,185 @@ impl<B: Backend> View<B> for MainView
                 .selected(&ctx.data.requests)
                 .map(|r| r.request_data.uuid == *req)
                 .unwrap_or(false),
+            SessionChange::Removed { requests, .. } => {
+                self.requests_state
+                    .remove_items(&ctx.data.requests, requests);
+                true
+            }
         }
     }
 
+    fn add_filter(&mut self, ctx: &UiContext, filter: Box<dyn ItemFilter<EncodedRequest>>)
+    {
+        self.requests_state
+            .get_filter_mut(&ctx.data.requests)
+            .add_filter(filter);
+    }
+
+    fn save_filters(&mut self, _ctx: &UiContext, file: &str)
+    {
+        let saved = self.requests_state.get_filter().save();
+        let result = serde_json::to_vec_pretty(&saved)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(file, json).map_err(|e| e.to_string()));
+        match result {
+            Ok(_) => toast::show_message(format!("Filters saved to '{}'", file)),
+            Err(e) => toast::show_error(format!("Could not write file '{}'\n{}", file, e)),
+        }
+    }
+
+    fn load_filters(&mut self, ctx: &UiContext, file: &str)
+    {
+        let saved = match std::fs::read(file)
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_json::from_slice(&data).map_err(|e| e.to_string()))
+        {
+            Ok(saved) => saved,
+            Err(e) => {
+                return toast::show_error(format!("Could not read filters from '{}'\n{}", file, e))
+            }
+        };
+
+        let skipped = self
+            .requests_state
+            .get_filter_mut(&ctx.data.requests)
+            .load(saved, ctx);
+        match skipped.is_empty() {
+            true => toast::show_message(format!("Filters loaded from '{}'", file)),
+            false => toast::show_error(format!(
+                "Skipped {} filters from '{}':\n{}",
+                skipped.len(),
+                file,
+                skipped.join("\n")
+            )),
+        }
+    }
+
+    fn selected_request(&mut self, ctx: &UiContext) -> Option<uuid::Uuid>
+    {
+        self.requests_state
+            .selected(&ctx.data.requests)
+            .map(|r| r.request_data.uuid)
+    }
+
     fn help_text(&self, _state: &UiContext, _size: Rect) -> String
     {
-        format!("{}\n{}",
-            "[Up/Down,j/k]: Previous/Next request ([Shift]: Follow connection); [Esc]: Stick to last; [F12]: Export session to file; [Shift-Q]: Quit",
-            "[f]: Manage filters; [Tab]: Change panel focus")
+        let follow = match self.requests_state.is_following() {
+            true => "on",
+            false => "off",
+        };
+        let group = match self.group_similar {
+            true => "method and path",
+            false => "connection",
+        };
+        format!("{}{}\n{}{}",
+            "[Up/Down,j/k]: Previous/Next request ([Shift]: Within group); [gg/G]: First/Last; [Esc]: Stick to last; [F12]: Export session to file; [Shift-Q]: Quit; [r]: Group by: ",
+            group,
+            "[f]: Manage filters; [Tab]: Change panel focus; [a]: Toggle request signing details; [w]: Timeline; [m/d]: Mark/Diff against marked; [L]: Lock selection; [o/O]: Sort/Reverse sort; [t]: Timestamp format; [</>]: Resize list; [|, {/}]: Select/Resize column; [c]: Connections; [?]: Help; [T]: Follow new requests: ",
+            follow)
+    }
+
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>
+    {
+        vec![
+            ("Up/Down, j/k", "Previous/Next request"),
+            (
+                "Shift-Up/Down, J/K",
+                "Previous/Next request within the group",
+            ),
+            ("gg/G", "First/Last request"),
+            ("Esc", "Stick to the last request"),
+            ("T", "Follow new requests"),
+            ("L", "Lock the selection"),
+            ("q/e", "Open the request/response data"),
+            ("a", "Toggle request signing details"),
+            ("r", "Group requests by connection or by method and path"),
+            ("o/O", "Sort/Reverse sort"),
+            ("t", "Change the timestamp format"),
+            ("</>", "Resize the request list"),
+            ("|, {/}", "Select/Resize column"),
+            ("m/d", "Mark request/Diff against the marked request"),
+            ("w", "Timeline"),
+            ("c", "Connections"),
+            ("Tab", "Change panel focus"),
+            ("F12", "Export session to file"),
+            ("f", "Show/Hide the filter panel"),
+            ("F", "Enable/Disable the filters"),
+            (
+                "c/p/M",
+                "Filter panel: Filter by the connection/path/method of the request",
+            ),
+            (
+                "ss/sf",
+                "Filter panel: Filter the succeeded/failed requests",
+            ),
+            ("r1-r5", "Filter panel: Filter by the response status class"),
+            (
+                "X/u",
+                "Filter panel: Clear the filters/Undo the last change",
+            ),
+            ("x/t", "Filter panel: Remove/Toggle the selected filter"),
+        ]
+    }
+
+    fn select_request(&mut self, ctx: &UiContext, request: uuid::Uuid)
+    {
+        self.requests_state.select_key(&ctx.data.requests, request);
+    }
+
+    fn select_connection(&mut self, ctx: &UiContext, connection: uuid::Uuid)
+    {
+        self.requests_state
+            .get_filter_mut(&ctx.data.requests)
+            .add_filter(Box::new(ConnectionFilter { connection }));
     }
 }
 
 impl MainView
 {
+    pub fn with_layout(mut self, layout: &SavedLayout) -> Self
+    {
+        if let Some(width) = layout.requests_width {
+            self.requests_width = width.max(MIN_REQUESTS_WIDTH);
+        }
+        for (title, width) in &layout.column_widths {
+            self.requests_state.set_column_width(title, *width);
+        }
+        self
+    }
+
+    fn save_layout(&self)
+    {
+        SavedLayout {
+            requests_width: Some(self.requests_width),
+            column_widths: self
+                .requests_state
+                .column_widths()
+                .into_iter()
+                .map(|(title, width)| (title.to_string(), width))
+                .collect(),
+        }
+        .save();
+    }
+
+    fn resize_requests(&mut self, ctx: &UiContext, delta: i32)
+    {
+        let max = ctx.size.width.saturating_sub(MIN_DETAILS_WIDTH);
+        let width = (i32::from(self.requests_width) + delta).clamp(0, i32::from(u16::MAX)) as u16;
+        self.requests_width = width.min(max).max(MIN_REQUESTS_WIDTH);
+        self.save_layout();
+    }
+
+    fn resize_column(&mut self, delta: i32)
+    {
+        let columns = self.requests_state.column_widths();
+        if let Some((title, width)) = columns.get(self.resize_column % columns.len().max(1)) {
+            let width = (i32::from(*width) + delta).clamp(0, i32::from(u16::MAX)) as u16;
+            self.requests_state.set_column_width(title, width);
+            self.save_layout();
+        }
+    }
+
     fn do_filter_input<B: Backend>(
         &mut self,
         ctx: &UiContext,
@@ -211,6 +478,122 @@ impl MainView
                     }
                     Some(HandleResult::Update)
                 }
+                KeyCode::Char('T') => {
+                    self.requests_state.toggle_follow(&ctx.data.requests);
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('o') => {
+                    self.requests_state.cycle_sort(&ctx.data.requests);
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('<') => {
+                    self.resize_requests(ctx, -2);
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('>') => {
+                    self.resize_requests(ctx, 2);
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('c') | KeyCode::Char('C') => {
+                    let selected = self
+                        .requests_state
+                        .selected(&ctx.data.requests)
+                        .map(|r| r.request_data.connection_uuid);
+                    Some(HandleResult::PushView(Box::new(ConnectionsView::new(
+                        ctx, selected,
+                    ))))
+                }
+                KeyCode::Char('|') => {
+                    let columns = self.requests_state.column_widths();
+                    self.resize_column = (self.resize_column + 1) % columns.len().max(1);
+                    if let Some((title, _)) = columns.get(self.resize_column) {
+                        toast::show_message(format!("Resizing column '{}' with [{{/}}]", title));
+                    }
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('{') => {
+                    self.resize_column(-1);
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('}') => {
+                    self.resize_column(1);
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('t') => {
+                    if let Some(format) = self.requests_state.cycle_column_format("Timestamp") {
+                        toast::show_message(format!("Timestamp format: {}", format));
+                    }
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('O') => {
+                    self.requests_state.reverse_sort(&ctx.data.requests);
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('L') => {
+                    self.requests_state.toggle_lock(&ctx.data.requests);
+                    match self.requests_state.is_locked() {
+                        true => toast::show_message("Selection locked"),
+                        false => toast::show_message("Selection unlocked"),
+                    }
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('r') => {
+                    self.group_similar = !self.group_similar;
+                    self.details_view.show_header_changes = self.group_similar;
+                    match self.group_similar {
+                        true => {
+                            self.requests_state.set_group_filter(same_call);
+                            toast::show_message("Grouping requests by method and path")
+                        }
+                        false => {
+                            self.requests_state.set_group_filter(same_connection);
+                            toast::show_message("Grouping requests by connection")
+                        }
+                    }
+                    Some(HandleResult::Update)
+                }
+                KeyCode::Char('w') => {
+                    let selected = self
+                        .requests_state
+                        .selected(&ctx.data.requests)
+                        .map(|r| r.request_data.uuid);
+                    let requests = self.requests_state.visible_keys(&ctx.data.requests);
+                    Some(HandleResult::PushView(Box::new(TimelineView::new(
+                        requests, selected,
+                    ))))
+                }
+                KeyCode::Char('m') => {
+                    let selected = self
+                        .requests_state
+                        .selected(&ctx.data.requests)
+                        .map(|r| r.request_data.uuid)?;
+                    match self.marked == Some(selected) {
+                        true => {
+                            self.marked = None;
+                            toast::show_message("Request unmarked");
+                        }
+                        false => {
+                            self.marked = Some(selected);
+                            toast::show_message("Request marked for diff");
+                        }
+                    }
+                    None
+                }
+                KeyCode::Char('d') => {
+                    let selected = self
+                        .requests_state
+                        .selected(&ctx.data.requests)
+                        .map(|r| r.request_data.uuid)?;
+                    match self.marked {
+                        Some(marked) if marked != selected => Some(HandleResult::PushView(
+                            Box::new(DiffView::new(marked, selected)),
+                        )),
+                        _ => {
+                            toast::show_error("Mark another request with [m] to diff against");
+                            None
+                        }
+                    }
+                }
                 KeyCode::F(12) => commands::export_session(ctx),
                 KeyCode::Tab => {
                     match self.filter_pane {
@@ -225,3 +608,24 @@ impl MainView
         }
     }
 }
+
+/// Duration of the request. Pending requests have no duration yet.
+fn duration(request: &EncodedRequest) -> Option<chrono::Duration>
+{
+    request
+        .request_data
+        .end_timestamp
+        .map(|end| end - request.request_data.start_timestamp)
+}
+
+/// Groups the requests of the same connection.
+fn same_connection(current: &EncodedRequest, maybe: &EncodedRequest) -> bool
+{
+    current.request_data.connection_uuid == maybe.request_data.connection_uuid
+}
+
+/// Groups the repeated calls, such as retries, with the same method and path.
+fn same_call(current: &EncodedRequest, maybe: &EncodedRequest) -> bool
+{
+    current.request_data.is_similar(&maybe.request_data)
+}
@@ -1,10 +1,17 @@
 use super::prelude::*;
+use crate::decoders::grpc::TEXT_FORMAT_DECODER;
 use crate::decoders::Decoder;
-use crossterm::event::KeyCode;
+use chrono::Local;
+use crossterm::event::{KeyCode, MouseEventKind};
+use std::ops::Range;
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans, Text};
 use tui::widgets::{Paragraph, Wrap};
 use uuid::Uuid;
 
-use crate::session::{MessageData, RequestData, RequestPart};
+use crate::session::{MessageData, RequestData, RequestPart, Status};
+use crate::ui::chords::{ChordResult, ChordState};
+use crate::ui::clipboard;
 use crate::ui::toast;
 
 pub struct MessageView
@@ -12,10 +19,84 @@ pub struct MessageView
     pub request: Uuid,
     pub part: RequestPart,
     pub offset: u16,
+
+    /// Horizontal scroll offset. Only used when the lines are not wrapped.
+    pub column: u16,
+
+    /// Display Protobuf messages in the `protoc --decode` text format.
+    pub text_format: bool,
+
+    /// Display the content as it was on the wire without decompressing it.
+    pub show_encoded: bool,
+
+    /// Display the content as a hex dump even if a structured decoder is available.
+    pub show_hex: bool,
+
+    /// Decoder chosen by cycling through the decoders. Used over the toggles if the decoder is
+    /// available for the message.
+    pub decoder: Option<&'static str>,
+
+    /// Wrap long lines instead of clipping them. Kept when switching between request and response.
+    pub wrap: bool,
+
+    pub chord: Option<ChordState>,
+
+    find: Option<Find>,
+}
+
+/// Text searched within the displayed message with `/`.
+struct Find
+{
+    /// The lowercase pattern. Matching ignores ASCII case.
+    pattern: String,
+
+    /// Index of the highlighted match.
+    current: usize,
+
+    /// Number of matches found on the last draw.
+    matches: usize,
+
+    /// Scroll to the current match on the next draw.
+    jump: bool,
 }
 
 impl MessageView
 {
+    pub fn new(request: Uuid, part: RequestPart) -> Self
+    {
+        Self {
+            request,
+            part,
+            offset: 0,
+            column: 0,
+            text_format: false,
+            show_encoded: false,
+            show_hex: false,
+            decoder: None,
+            wrap: true,
+            chord: None,
+            find: None,
+        }
+    }
+
+    /// Move to the next or the previous match of the search.
+    fn next_match(&mut self, forward: bool)
+    {
+        let find = match &mut self.find {
+            Some(f) => f,
+            None => return toast::show_error("No active search"),
+        };
+        if find.matches == 0 {
+            return toast::show_error(format!("No matches for '{}'", find.pattern));
+        }
+
+        find.current = match forward {
+            true => (find.current + 1) % find.matches,
+            false => (find.current + find.matches - 1) % find.matches,
+        };
+        find.jump = true;
+    }
+
     fn export(&self, ctx: &UiContext)
     {
         let (request, message) = match self.get_message(ctx) {
@@ -44,6 +125,101 @@ impl MessageView
         }
     }
 
+    /// Open the raw content of the message in `$EDITOR` or `$PAGER`.
+    ///
+    /// The content is written as it was received, without decompressing or decoding it.
+    fn open_external<B: Backend>(&self, ctx: &UiContext) -> Option<HandleResult<B>>
+    {
+        let (_, message) = match self.get_message(ctx) {
+            Some(t) => t,
+            None => {
+                toast::show_error("No active message!");
+                return None;
+            }
+        };
+
+        let command = match ["EDITOR", "PAGER"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|cmd| !cmd.trim().is_empty())
+        {
+            Some(cmd) => cmd,
+            None => {
+                toast::show_error("Set $EDITOR or $PAGER to open the message");
+                return None;
+            }
+        };
+
+        let extension = match std::str::from_utf8(&message.content) {
+            Ok(_) => "txt",
+            Err(_) => "bin",
+        };
+        let file = std::env::temp_dir().join(format!(
+            "proxide-{}-{}.{}",
+            self.request,
+            match self.part {
+                RequestPart::Request => "request",
+                RequestPart::Response => "response",
+            },
+            extension
+        ));
+        if let Err(e) = std::fs::write(&file, &message.content) {
+            toast::show_error(format!("Could not write file '{}'\n{}", file.display(), e));
+            return None;
+        }
+
+        Some(HandleResult::OpenExternal { command, file })
+    }
+
+    /// Copy the displayed message or, with `full`, both the request and the response.
+    fn yank(&self, ctx: &UiContext, full: bool)
+    {
+        let request = match ctx.data.requests.get_by_uuid(self.request) {
+            Some(r) => r,
+            None => return toast::show_error("No active message!"),
+        };
+
+        let request_data = &request.request_data;
+        if !full {
+            let message = match self.part {
+                RequestPart::Request => &request.request_msg,
+                RequestPart::Response => &request.response_msg,
+            };
+            let decoder = self.get_decoder(ctx, request_data, message);
+            let text = decoder.decode(message);
+            return clipboard::copy_or_save(&text_to_string(&text), "Message");
+        }
+
+        let mut output = format!("{} {}\n", request_data.method, request_data.uri);
+        for &(first_line, message) in &[
+            (None, &request.request_msg),
+            (request_data.response_status, &request.response_msg),
+        ] {
+            if let Some(status) = first_line {
+                output.push_str(&format!("\n{}\n", status));
+            }
+            for (k, v) in &message.headers {
+                output.push_str(&format!(
+                    "{}: {}\n",
+                    k,
+                    String::from_utf8_lossy(v.as_bytes())
+                ));
+            }
+            output.push('\n');
+            let decoder = self.get_decoder(ctx, request_data, message);
+            let text = decoder.decode(message);
+            output.push_str(&text_to_string(&text));
+            for (k, v) in &message.trailers {
+                output.push_str(&format!(
+                    "{}: {}\n",
+                    k,
+                    String::from_utf8_lossy(v.as_bytes())
+                ));
+            }
+        }
+        clipboard::copy_or_save(&output, "Request and response")
+    }
+
     fn get_message<'a>(&self, ctx: &'a UiContext) -> Option<(&'a RequestData, &'a MessageData)>
     {
         let request = match ctx.data.requests.get_by_uuid(self.request) {
@@ -66,12 +242,68 @@ impl MessageView
         message: &MessageData,
     ) -> Box<dyn Decoder>
     {
-        ctx.runtime
+        if self.show_encoded {
+            return Box::new(crate::decoders::raw::RawHexDecoder);
+        }
+
+        let mut decoders: Vec<_> = ctx
+            .runtime
             .decoders
             .get_decoders(request, message)
-            .last()
+            .collect();
+        if let Some(name) = self.decoder {
+            if let Some(idx) = decoders.iter().position(|d| d.name() == name) {
+                return decoders.swap_remove(idx);
+            }
+        }
+        if self.show_hex {
+            if let Some(idx) = decoders.iter().position(|d| d.name() == "hex") {
+                return decoders.swap_remove(idx);
+            }
+        }
+        if self.text_format {
+            if let Some(idx) = decoders
+                .iter()
+                .position(|d| d.name() == TEXT_FORMAT_DECODER)
+            {
+                return decoders.swap_remove(idx);
+            }
+        }
+
+        let defaults = &ctx.runtime.decoders;
+        decoders.retain(|d| !d.is_opt_in() || defaults.is_default(d.name()));
+        decoders
+            .pop()
             .expect("Raw decoder should always be present")
     }
+
+    /// Switch to the next or the previous decoder available for the message.
+    fn cycle_decoder(&mut self, ctx: &UiContext, forward: bool)
+    {
+        let (request, message) = match self.get_message(ctx) {
+            Some(t) => t,
+            None => return,
+        };
+
+        // The decoders are listed from the lowest priority so the order is reversed to start
+        // from the preferred decoder.
+        let mut names: Vec<_> = ctx
+            .runtime
+            .decoders
+            .get_decoders(request, message)
+            .map(|d| d.name())
+            .collect();
+        names.reverse();
+
+        let current = self.get_decoder(ctx, request, message).name();
+        let idx = names.iter().position(|n| *n == current).unwrap_or(0);
+        let next = match forward {
+            true => (idx + 1) % names.len(),
+            false => (idx + names.len() - 1) % names.len(),
+        };
+        self.decoder = Some(names[next]);
+        self.show_encoded = false;
+    }
 }
 
 impl<B: Backend> View<B> for MessageView
@@ -87,34 +319,167 @@ impl<B: Backend> View<B> for MessageView
             RequestPart::Request => ("Re[q]uest Data", &request.request_msg),
             RequestPart::Response => ("R[e]sponse Data", &request.response_msg),
         };
-        let title = format!("{} (offset {})", title, self.offset);
 
-        let duration = match (data.start_timestamp, data.end_timestamp) {
-            (Some(start), Some(end)) => format!(", {}", format_duration(end - start)),
-            _ => String::new(),
+        let (request_data, message) = match self.get_message(ctx) {
+            Some(t) => t,
+            None => return,
         };
+        let decoder = self.get_decoder(ctx, request_data, message);
+        let visible_lines = usize::from(chunk.height.saturating_sub(2));
 
-        let request_title = format!("{} ({} bytes{})", title, data.content.len(), duration);
-        let block = create_block(&request_title);
+        // Decoders that know their line count decode only the visible lines. Such output isn't
+        // wrapped as wrapping would require decoding the lines above the view.
+        let line_count = decoder.line_count(message);
+        let wrap = self.wrap && line_count.is_none();
+        let width = chunk.width.saturating_sub(2);
 
-        let (request, message) = match self.get_message(ctx) {
-            Some(t) => t,
-            None => return,
+        // The search covers the whole message even if only the visible lines are decoded.
+        let full_text = match (line_count, &self.find) {
+            (Some(_), None) => None,
+            _ => Some(decoder.decode(message)),
+        };
+        let matches = match (&self.find, &full_text) {
+            (Some(find), Some(text)) => find_matches(text, &find.pattern),
+            _ => vec![],
         };
-        let decoder = self.get_decoder(ctx, request, message);
-        let text = decoder.decode(message);
+        if let Some(find) = &mut self.find {
+            find.matches = matches.len();
+            find.current = find.current.min(matches.len().saturating_sub(1));
+            if let (true, Some((line, range)), Some(text)) =
+                (find.jump, matches.get(find.current), &full_text)
+            {
+                find.jump = false;
+                let row = match wrap {
+                    true => wrapped_height(&text.lines[..*line], width),
+                    false => *line,
+                };
+                self.offset = row
+                    .saturating_sub(visible_lines / 2)
+                    .min(usize::from(u16::MAX)) as u16;
+
+                // Unwrapped lines are scrolled sideways if the match is out of the view.
+                let column = line_string(&text.lines[*line])[..range.start]
+                    .chars()
+                    .count();
+                let columns =
+                    usize::from(self.column)..usize::from(self.column) + usize::from(width);
+                if !wrap && !columns.contains(&column) {
+                    self.column = column
+                        .saturating_sub(usize::from(width) / 2)
+                        .min(usize::from(u16::MAX)) as u16;
+                }
+            }
+        }
+        let current = self.find.as_ref().map(|f| f.current).unwrap_or(0);
+
+        let (text, scroll) = match (line_count, full_text) {
+            (Some(line_count), _) => {
+                let max_offset = line_count.saturating_sub(visible_lines);
+                self.offset = self
+                    .offset
+                    .min(max_offset.min(usize::from(u16::MAX)) as u16);
+                let start = usize::from(self.offset);
+                let text = decoder.decode_lines(message, start..start + visible_lines);
+                (highlight(text, &matches, start, current), 0)
+            }
+            (None, text) => {
+                let text = text.expect("Text is decoded without line count");
 
-        let request_data = Paragraph::new(text)
+                // Keep the last line at the bottom of the view when scrolling past the end.
+                let height = match wrap {
+                    true => wrapped_height(&text.lines, width),
+                    false => text.height(),
+                };
+                let max_offset = height.saturating_sub(visible_lines);
+                self.offset = self
+                    .offset
+                    .min(max_offset.min(usize::from(u16::MAX)) as u16);
+                (highlight(text, &matches, 0, current), self.offset)
+            }
+        };
+
+        // Keep at least some of the longest line visible.
+        let max_column = match wrap {
+            true => 0,
+            false => text.width().saturating_sub(usize::from(width)),
+        };
+        self.column = self
+            .column
+            .min(max_column.min(usize::from(u16::MAX)) as u16);
+
+        let mut title = format!("{} [{}] (offset {})", title, decoder.name(), self.offset);
+        if let Some(find) = &self.find {
+            title = match find.matches {
+                0 => format!("{} [/{}: no matches]", title, find.pattern),
+                n => format!("{} [/{}: {}/{}]", title, find.pattern, find.current + 1, n),
+            };
+        }
+
+        // Messages still being received show the data received so far. The title is updated
+        // whenever more data arrives.
+        let in_progress = request.request_data.status == Status::InProgress;
+        let elapsed = match (data.start_timestamp, data.end_timestamp) {
+            (Some(start), Some(end)) => Some(end - start),
+            (Some(start), None) if in_progress => Some(Local::now() - start),
+            _ => None,
+        };
+        let duration = match elapsed {
+            Some(elapsed) => format!(
+                ", {}, {}",
+                format_duration(elapsed),
+                format_throughput(data.content.len() + data.truncated, elapsed)
+            ),
+            None => String::new(),
+        };
+
+        let mut size = match data.truncated {
+            0 => format!("{} bytes", data.content.len()),
+            truncated => format!(
+                "truncated at {} bytes, {} total",
+                data.content.len(),
+                data.content.len() + truncated
+            ),
+        };
+        if in_progress && data.end_timestamp.is_none() {
+            size.push_str(" so far");
+        }
+        let request_title = format!("{} ({}{})", title, size, duration);
+        let block = create_block(&request_title);
+        let mut request_data = Paragraph::new(text)
             .block(block)
-            .wrap(Wrap { trim: false })
-            .scroll((self.offset, 0));
+            .scroll((scroll, self.column));
+        if wrap {
+            request_data = request_data.wrap(Wrap { trim: false });
+        }
         f.render_widget(request_data, chunk);
     }
 
     fn on_input(&mut self, ctx: &UiContext, e: &CTEvent, size: Rect) -> Option<HandleResult<B>>
     {
+        if let Some(chord) = &mut self.chord {
+            match chord.handle(e) {
+                ChordResult::State(s) => {
+                    match s {
+                        "gg" => self.offset = 0,
+                        other => toast::show_error(format!("Unknown chord '{}'", other)),
+                    }
+                    self.chord = None;
+                    return Some(HandleResult::Update);
+                }
+                ChordResult::Cancel => {
+                    self.chord = None;
+                    return Some(HandleResult::Update);
+                }
+                ChordResult::Ignore => (),
+            }
+        }
+
         match e {
             CTEvent::Key(key) => match key.code {
+                KeyCode::Char('g') => self.chord = Some(ChordState::new('g')),
+
+                // The offset is clamped to the last line when drawing.
+                KeyCode::Char('G') => self.offset = u16::MAX,
                 KeyCode::Char('k') | KeyCode::Up => self.offset = self.offset.saturating_sub(1),
                 KeyCode::Char('j') | KeyCode::Down => self.offset = self.offset.saturating_add(1),
                 KeyCode::PageDown => self.offset = self.offset.saturating_add(size.height - 5),
@@ -123,6 +488,34 @@ impl<B: Backend> View<B> for MessageView
                     self.export(ctx);
                     return None;
                 }
+                KeyCode::Char('y') => {
+                    self.yank(ctx, false);
+                    return None;
+                }
+                KeyCode::Char('Y') => {
+                    self.yank(ctx, true);
+                    return None;
+                }
+                KeyCode::Char('E') => return self.open_external(ctx),
+                KeyCode::Char('t') => {
+                    self.text_format = !self.text_format;
+                    self.decoder = None;
+                }
+                KeyCode::Char('z') => self.show_encoded = !self.show_encoded,
+                KeyCode::Char('x') => {
+                    self.show_hex = !self.show_hex;
+                    self.decoder = None;
+                }
+                KeyCode::Char('n') => self.next_match(true),
+                KeyCode::Char('N') => self.next_match(false),
+                KeyCode::Char(']') => self.cycle_decoder(ctx, true),
+                KeyCode::Char('[') => self.cycle_decoder(ctx, false),
+                KeyCode::Char('w') => {
+                    self.wrap = !self.wrap;
+                    self.column = 0;
+                }
+                KeyCode::Char('h') | KeyCode::Left => self.column = self.column.saturating_sub(4),
+                KeyCode::Char('l') | KeyCode::Right => self.column = self.column.saturating_add(4),
                 KeyCode::Char('q') => match self.part {
                     RequestPart::Request => return Some(HandleResult::ExitView),
                     RequestPart::Response => self.part = RequestPart::Request,
@@ -139,6 +532,11 @@ impl<B: Backend> View<B> for MessageView
                 }
                 _ => return None,
             },
+            CTEvent::Mouse(mouse) => match mouse.kind {
+                MouseEventKind::ScrollUp => self.offset = self.offset.saturating_sub(3),
+                MouseEventKind::ScrollDown => self.offset = self.offset.saturating_add(3),
+                _ => return None,
+            },
             _ => return None,
         };
         Some(HandleResult::Update)
@@ -155,15 +553,244 @@ impl<B: Backend> View<B> for MessageView
             | SessionChange::Message { request, part } => {
                 *part == self.part && *request == self.request
             }
+            SessionChange::Removed { .. } => true,
         }
     }
 
+    fn selected_request(&mut self, _ctx: &UiContext) -> Option<Uuid>
+    {
+        Some(self.request)
+    }
+
+    fn can_find(&self) -> bool
+    {
+        true
+    }
+
+    fn find(&mut self, _ctx: &UiContext, pattern: &str)
+    {
+        self.find = match pattern.is_empty() {
+            true => None,
+            false => Some(Find {
+                pattern: pattern.to_ascii_lowercase(),
+                current: 0,
+                matches: 0,
+                jump: true,
+            }),
+        };
+    }
+
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>
+    {
+        vec![
+            ("Up/Down, j/k, PgUp/PgDn", "Scroll"),
+            ("gg/G", "Top/Bottom"),
+            ("Left/Right, h/l", "Scroll unwrapped lines sideways"),
+            ("w", "Toggle line wrap"),
+            ("/, n/N", "Find, next/previous match"),
+            ("Tab", "Switch between request and response"),
+            ("q/e", "Show the request/response"),
+            ("[/]", "Previous/Next decoder"),
+            ("t", "Toggle Protobuf text format"),
+            ("z", "Toggle raw encoded content"),
+            ("x", "Toggle hex dump"),
+            ("y/Y", "Copy the message/request and response"),
+            ("E", "Open the raw content in $EDITOR"),
+            ("F12", "Export to file"),
+            ("Esc", "Back to main view"),
+        ]
+    }
+
     fn help_text(&self, _session: &UiContext, _size: Rect) -> String
     {
         format!(
             "{}\n{}",
-            "[Up/Down, j/k, PgUp/PgDn]: Scroll; [Tab]: Switch Request/Response; [F12]: Export to file",
-            "[q/e]: Toggle request/response, [Esc]: Back to main view"
+            "[Up/Down, j/k, PgUp/PgDn]: Scroll; [gg/G]: Top/Bottom; [Left/Right, h/l]: Scroll unwrapped lines; [Tab]: Switch Request/Response; [F12]: Export to file; [y/Y]: Copy message/request and response; [E]: Open raw content in $EDITOR; [/, n/N]: Find, next/previous match",
+            "[q/e]: Toggle request/response; [t]: Toggle Protobuf text format; [z]: Toggle raw encoded content; [x]: Toggle hex dump; [[/]]: Previous/Next decoder; [w]: Toggle line wrap; [Esc]: Back to main view"
         )
     }
 }
+
+/// Join the lines of the text. Some decoders keep the line breaks in the spans.
+fn text_to_string(text: &Text) -> String
+{
+    let mut output = String::new();
+    for spans in &text.lines {
+        for span in &spans.0 {
+            output.push_str(&span.content);
+        }
+        if !output.ends_with('\n') {
+            output.push('\n');
+        }
+    }
+    output
+}
+
+fn line_string(spans: &Spans) -> String
+{
+    spans.0.iter().map(|s| s.content.as_ref()).collect()
+}
+
+/// Find the pattern in the lines of the text. Returns the line index and the byte range of each
+/// match within the line.
+///
+/// Only ASCII is folded so the byte offsets are the same in the original line.
+fn find_matches(text: &Text, pattern: &str) -> Vec<(usize, Range<usize>)>
+{
+    let mut matches = vec![];
+    for (idx, spans) in text.lines.iter().enumerate() {
+        let line = line_string(spans).to_ascii_lowercase();
+        for (start, m) in line.match_indices(pattern) {
+            matches.push((idx, start..start + m.len()));
+        }
+    }
+    matches
+}
+
+/// Highlight the matches in the text. The text starts from `first_line` of the full output.
+fn highlight<'a>(
+    text: Text<'a>,
+    matches: &[(usize, Range<usize>)],
+    first_line: usize,
+    current: usize,
+) -> Text<'a>
+{
+    let match_style = Style::default().bg(Color::Yellow).fg(Color::Black);
+    let current_style = Style::default().bg(Color::LightRed).fg(Color::Black);
+
+    let lines = text
+        .lines
+        .into_iter()
+        .enumerate()
+        .map(|(idx, spans)| {
+            let line = first_line + idx;
+            let begin = matches.partition_point(|m| m.0 < line);
+            let ranges: Vec<_> = matches[begin..]
+                .iter()
+                .take_while(|m| m.0 == line)
+                .enumerate()
+                .map(|(i, m)| match begin + i == current {
+                    true => (m.1.clone(), current_style),
+                    false => (m.1.clone(), match_style),
+                })
+                .collect();
+            highlight_line(spans, &ranges)
+        })
+        .collect::<Vec<_>>();
+    Text::from(lines)
+}
+
+/// Split the spans at the byte ranges and patch the style of the ranges.
+fn highlight_line<'a>(spans: Spans<'a>, ranges: &[(Range<usize>, Style)]) -> Spans<'a>
+{
+    if ranges.is_empty() {
+        return spans;
+    }
+
+    let mut output = vec![];
+    let mut offset = 0;
+    for span in spans.0 {
+        let end = offset + span.content.len();
+        let mut pos = offset;
+        for (range, style) in ranges {
+            let start = range.start.max(pos);
+            let stop = range.end.min(end);
+            if start >= stop {
+                continue;
+            }
+            if start > pos {
+                output.push(Span::styled(
+                    span.content[pos - offset..start - offset].to_string(),
+                    span.style,
+                ));
+            }
+            output.push(Span::styled(
+                span.content[start - offset..stop - offset].to_string(),
+                span.style.patch(*style),
+            ));
+            pos = stop;
+        }
+
+        if pos == offset {
+            output.push(span);
+        } else if pos < end {
+            output.push(Span::styled(
+                span.content[pos - offset..].to_string(),
+                span.style,
+            ));
+        }
+        offset = end;
+    }
+    Spans::from(output)
+}
+
+/// Number of rows the lines take when word wrapped to the width.
+fn wrapped_height(lines: &[Spans], width: u16) -> usize
+{
+    let width = usize::from(width.max(1));
+    lines
+        .iter()
+        .map(|spans| {
+            let line = line_string(spans);
+            let mut rows = 1;
+            let mut column = 0;
+            for word in line.split_inclusive(' ') {
+                let len = word.chars().count();
+                let trimmed = word.trim_end().chars().count();
+                if column > 0 && column + trimmed > width {
+                    rows += 1;
+                    column = 0;
+                }
+
+                // Words longer than the whole row are broken up. Trailing spaces never wrap.
+                if column == 0 && trimmed > width {
+                    rows += (trimmed - 1) / width;
+                    column = (trimmed - 1) % width + 1;
+                } else {
+                    column += trimmed;
+                }
+                column += len - trimmed;
+            }
+            rows
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn matches_are_highlighted_across_spans()
+    {
+        let text = Text::from(vec![
+            Spans::from(vec![Span::raw("foo: "), Span::raw("\"Bar\"")]),
+            Spans::from("nothing here"),
+            Spans::from("bar bar"),
+        ]);
+        let matches = find_matches(&text, "o: \"b");
+        assert_eq!(matches, vec![(0, 2..7)]);
+
+        let matches = find_matches(&text, "bar");
+        assert_eq!(matches, vec![(0, 6..9), (2, 0..3), (2, 4..7)]);
+
+        let text = highlight(text, &matches, 0, 1);
+        let contents: Vec<Vec<&str>> = text
+            .lines
+            .iter()
+            .map(|spans| spans.0.iter().map(|s| s.content.as_ref()).collect())
+            .collect();
+        assert_eq!(
+            contents,
+            vec![
+                vec!["foo: ", "\"", "Bar", "\""],
+                vec!["nothing here"],
+                vec!["bar", " ", "bar"],
+            ]
+        );
+        assert_eq!(text.lines[0].0[2].style.bg, Some(Color::Yellow));
+        assert_eq!(text.lines[2].0[0].style.bg, Some(Color::LightRed));
+        assert_eq!(text.lines[2].0[2].style.bg, Some(Color::Yellow));
+    }
+}
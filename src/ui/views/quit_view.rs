@@ -0,0 +1,75 @@
+use tui::layout::Alignment;
+use tui::text::Text;
+use tui::widgets::{Clear, Paragraph};
+
+use super::prelude::*;
+use crate::ui::commands;
+
+/// Confirmation shown when quitting with requests that haven't been saved.
+pub struct QuitView;
+
+impl<B: Backend> View<B> for QuitView
+{
+    fn draw(&mut self, _ctx: &UiContext, f: &mut Frame<B>, chunk: Rect)
+    {
+        let text = "The session has unsaved changes.\n\n[s] Save and quit  [Q] Quit  [Esc] Cancel";
+        let width = 48.min(chunk.width);
+        let height = 5.min(chunk.height);
+        let rect = Rect {
+            x: chunk.x + (chunk.width - width) / 2,
+            y: chunk.y + (chunk.height - height) / 2,
+            width,
+            height,
+        };
+
+        f.render_widget(Clear, rect);
+        f.render_widget(
+            Paragraph::new(Text::raw(text))
+                .alignment(Alignment::Center)
+                .block(create_block("Quit")),
+            rect,
+        );
+    }
+
+    fn on_input(&mut self, ctx: &UiContext, e: &CTEvent, _size: Rect) -> Option<HandleResult<B>>
+    {
+        match e {
+            CTEvent::Key(key) => match key.code {
+                KeyCode::Char('s') => {
+                    // The save reports its own errors. Stay in the dialog if it failed.
+                    commands::export_session::<B>(ctx);
+                    match ctx.runtime.unsaved_requests.get() {
+                        true => Some(HandleResult::Update),
+                        false => Some(HandleResult::Quit),
+                    }
+                }
+                KeyCode::Char('Q') | KeyCode::Char('y') => Some(HandleResult::Quit),
+                KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('q') => {
+                    Some(HandleResult::ExitView)
+                }
+
+                // Keep the other keys from reaching the views below.
+                _ => Some(HandleResult::Update),
+            },
+            _ => None,
+        }
+    }
+
+    fn on_change(&mut self, _ctx: &UiContext, _change: &SessionChange) -> bool
+    {
+        false
+    }
+
+    fn help_text(&self, _session: &UiContext, _size: Rect) -> String
+    {
+        format!(
+            "{}\n{}",
+            "[s]: Save the session and quit; [Q/y]: Quit without saving", "[Esc/q/n]: Back"
+        )
+    }
+
+    fn transparent(&self) -> bool
+    {
+        true
+    }
+}
@@ -0,0 +1,280 @@
+use chrono::{DateTime, Local};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans, Text};
+use tui::widgets::Paragraph;
+use uuid::Uuid;
+
+use super::prelude::*;
+use crate::session::{EncodedRequest, Status};
+
+/// Width of the request label column on the left side of the bars.
+const LABEL_WIDTH: usize = 32;
+
+/// Maximum zoom level. Each level halves the visible time span.
+const MAX_ZOOM: u32 = 16;
+
+/// Waterfall visualization of the requests on a shared time axis.
+pub struct TimelineView
+{
+    /// The requests visible in the view. This is a snapshot of the filtered requests at the time
+    /// the view was opened.
+    requests: Vec<Uuid>,
+    selected: usize,
+    offset: usize,
+
+    /// Zoom level. The visible time span is the total span divided by 2^zoom.
+    zoom: u32,
+
+    /// Start of the visible time span in milliseconds from the start of the first request.
+    pan: i64,
+}
+
+impl TimelineView
+{
+    pub fn new(requests: Vec<Uuid>, selected: Option<Uuid>) -> Self
+    {
+        let selected = selected
+            .and_then(|s| requests.iter().position(|r| *r == s))
+            .unwrap_or_else(|| requests.len().saturating_sub(1));
+        Self {
+            requests,
+            selected,
+            offset: 0,
+            zoom: 0,
+            pan: 0,
+        }
+    }
+
+    fn get_requests<'a>(&self, ctx: &'a UiContext) -> Vec<&'a EncodedRequest>
+    {
+        self.requests
+            .iter()
+            .filter_map(|uuid| ctx.data.requests.get_by_uuid(*uuid))
+            .collect()
+    }
+
+    /// Resolve the start time and the total span of the timeline in milliseconds.
+    fn time_range(requests: &[&EncodedRequest], now: DateTime<Local>) -> (DateTime<Local>, i64)
+    {
+        let start = requests
+            .iter()
+            .map(|r| r.request_data.start_timestamp)
+            .min()
+            .unwrap_or(now);
+        let end = requests
+            .iter()
+            .map(|r| r.request_data.end_timestamp.unwrap_or(now))
+            .max()
+            .unwrap_or(now);
+
+        (start, (end - start).num_milliseconds().max(1))
+    }
+
+    fn window(&self, total: i64) -> i64
+    {
+        (total >> self.zoom).max(1)
+    }
+
+    fn pan_by(&mut self, ctx: &UiContext, steps: i64)
+    {
+        let requests = self.get_requests(ctx);
+        let (_, total) = Self::time_range(&requests, Local::now());
+        let window = self.window(total);
+        self.pan = (self.pan + steps * (window / 4).max(1)).clamp(0, (total - window).max(0));
+    }
+
+    /// Drop the requests that have been evicted from the session while keeping the same request
+    /// selected if it still exists.
+    fn remove_evicted(&mut self, ctx: &UiContext)
+    {
+        let exists = |uuid: &Uuid| ctx.data.requests.get_by_uuid(*uuid).is_some();
+        let removed_before = self.requests[..self.selected.min(self.requests.len())]
+            .iter()
+            .filter(|uuid| !exists(uuid))
+            .count();
+        self.requests.retain(exists);
+        self.selected = (self.selected - removed_before).min(self.requests.len().saturating_sub(1));
+        self.offset = self.offset.min(self.selected);
+    }
+
+    fn move_selection(&mut self, delta: isize)
+    {
+        if self.requests.is_empty() {
+            return;
+        }
+
+        let max = self.requests.len() - 1;
+        self.selected = match delta < 0 {
+            true => self.selected.saturating_sub(delta.unsigned_abs()),
+            false => (self.selected + delta as usize).min(max),
+        };
+    }
+}
+
+impl<B: Backend> View<B> for TimelineView
+{
+    fn draw(&mut self, ctx: &UiContext, f: &mut Frame<B>, chunk: Rect)
+    {
+        let block = create_block("Timeline");
+        let inner = block.inner(chunk);
+        f.render_widget(block, chunk);
+
+        let requests = self.get_requests(ctx);
+        let now = Local::now();
+        let (start, total) = Self::time_range(&requests, now);
+        let window = self.window(total);
+        self.pan = self.pan.clamp(0, (total - window).max(0));
+
+        let bar_width = (inner.width as usize)
+            .saturating_sub(LABEL_WIDTH + 3)
+            .max(1);
+
+        // Keep the selected row visible. The first row is reserved for the time axis.
+        let rows = (inner.height as usize).saturating_sub(1).max(1);
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + rows {
+            self.offset = self.selected + 1 - rows;
+        }
+
+        let axis_start = format!(
+            "+{}",
+            format_duration(chrono::Duration::milliseconds(self.pan))
+        );
+        let axis_end = format!(
+            "+{}",
+            format_duration(chrono::Duration::milliseconds(self.pan + window))
+        );
+        let mut lines = vec![Spans::from(vec![
+            Span::raw(format!(
+                "  {:<width$} ",
+                start.format("%H:%M:%S"),
+                width = LABEL_WIDTH
+            )),
+            Span::styled(
+                format!(
+                    "{:<width$}{}",
+                    axis_start,
+                    axis_end,
+                    width = bar_width.saturating_sub(axis_end.len())
+                ),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ])];
+
+        let column = |t: DateTime<Local>| -> i64 {
+            let ms = (t - start).num_milliseconds() - self.pan;
+            ms * bar_width as i64 / window
+        };
+
+        for (idx, request) in requests.iter().enumerate().skip(self.offset).take(rows) {
+            let data = &request.request_data;
+            let label = format!(
+                "{} {}",
+                data.method,
+                data.uri
+                    .path_and_query()
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| "/".to_string())
+            );
+            let label: String = label.chars().take(LABEL_WIDTH).collect();
+
+            let bar_start = column(data.start_timestamp);
+            let bar_end = column(data.end_timestamp.unwrap_or(now)).max(bar_start + 1);
+            let visible_start = bar_start.clamp(0, bar_width as i64) as usize;
+            let visible_end = bar_end.clamp(0, bar_width as i64) as usize;
+
+            let color = match data.status {
+                Status::InProgress => Color::Yellow,
+                Status::Succeeded => Color::Green,
+                Status::Failed => Color::Red,
+            };
+            let (marker, label_style) = match idx == self.selected {
+                true => ("> ", Style::default().add_modifier(Modifier::BOLD)),
+                false => ("  ", Style::default()),
+            };
+
+            lines.push(Spans::from(vec![
+                Span::styled(
+                    format!("{}{:<width$} ", marker, label, width = LABEL_WIDTH),
+                    label_style,
+                ),
+                Span::raw(" ".repeat(visible_start)),
+                Span::styled(
+                    "\u{2588}".repeat(visible_end - visible_start),
+                    Style::default().fg(color),
+                ),
+            ]));
+        }
+
+        f.render_widget(Paragraph::new(Text::from(lines)), inner);
+    }
+
+    fn on_input(&mut self, ctx: &UiContext, e: &CTEvent, size: Rect) -> Option<HandleResult<B>>
+    {
+        match e {
+            CTEvent::Key(key) => match key.code {
+                KeyCode::Char('k') | KeyCode::Up => self.move_selection(-1),
+                KeyCode::Char('j') | KeyCode::Down => self.move_selection(1),
+                KeyCode::PageUp => self.move_selection(-(size.height as isize - 5)),
+                KeyCode::PageDown => self.move_selection(size.height as isize - 5),
+                KeyCode::Char('h') | KeyCode::Left => self.pan_by(ctx, -1),
+                KeyCode::Char('l') | KeyCode::Right => self.pan_by(ctx, 1),
+                KeyCode::Char('+') => self.zoom = (self.zoom + 1).min(MAX_ZOOM),
+                KeyCode::Char('-') => self.zoom = self.zoom.saturating_sub(1),
+                KeyCode::Char('0') => {
+                    self.zoom = 0;
+                    self.pan = 0;
+                }
+                KeyCode::Enter => {
+                    return self
+                        .requests
+                        .get(self.selected)
+                        .map(|uuid| HandleResult::SelectRequest(*uuid))
+                }
+                KeyCode::Char('q') => return Some(HandleResult::ExitView),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(HandleResult::Update)
+    }
+
+    fn on_change(&mut self, ctx: &UiContext, change: &SessionChange) -> bool
+    {
+        match change {
+            SessionChange::Request { request } => self.requests.contains(request),
+            SessionChange::Removed { .. } => {
+                self.remove_evicted(ctx);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn selected_request(&mut self, _ctx: &UiContext) -> Option<Uuid>
+    {
+        self.requests.get(self.selected).copied()
+    }
+
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>
+    {
+        vec![
+            ("Up/Down, j/k, PgUp/PgDn", "Select request"),
+            ("Enter", "Jump to the request"),
+            ("Left/Right, h/l", "Scroll the time axis"),
+            ("+/-", "Zoom in/out"),
+            ("0", "Reset zoom"),
+            ("q/Esc", "Back to main view"),
+        ]
+    }
+
+    fn help_text(&self, _session: &UiContext, _size: Rect) -> String
+    {
+        format!(
+            "{}\n{}",
+            "[Up/Down, j/k, PgUp/PgDn]: Select request; [Enter]: Jump to request",
+            "[Left/Right, h/l]: Scroll time axis; [+/-]: Zoom; [0]: Reset zoom; [q/Esc]: Back to main view"
+        )
+    }
+}